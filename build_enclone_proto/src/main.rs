@@ -21,6 +21,17 @@ fn make_output(manifest_dir: &Path, out_dir: &Path) {
     std::env::set_var("OUT_DIR", out_dir.as_os_str());
     let mut config = Config::new();
     config.type_attribute(".", "#[derive(::serde::Serialize, ::serde::Deserialize)]");
+    // The bytes fields hold sequences, so serialize them as strings, as for JSON export.
+    for field in [
+        "nt_sequence",
+        "aa_sequence",
+        "universal_reference",
+        "donor_reference",
+        "aa_sequence_universal",
+        "aa_sequence_donor",
+    ] {
+        config.field_attribute(field, "#[serde(with = \"crate::types::bytes_as_string\")]");
+    }
     config
         .compile_protos(&[manifest_dir.join("types.proto")], &[manifest_dir])
         .unwrap();
@@ -23,7 +23,7 @@ pub fn find_alleles(
     ctl: &EncloneControl,
     exact_clonotypes: &[ExactClonotype],
 ) -> Vec<(usize, usize, DnaString, usize, bool)> {
-    // Derive consensus sequences for alternate alleles of V segments.
+    // Derive consensus sequences for alternate alleles of V and J segments.
     //
     // The priority of this algorithm is to reduce the likelihood of false positive joins.  It
     // has been tuned and optimized for this purpose, possibly at the expense of generating some
@@ -38,11 +38,15 @@ pub fn find_alleles(
     // Alternate alleles might correspond to duplicated segments, which is fine, as
     // for purposes of this code that's functionally equivalent to bona fide alternate alleles.
     //
-    // We do not attempt to calculate the last 15 bases of an alternate allele.  These
-    // bases are just copied.  If we want to really know these bases we may need to
-    // have actual genomic sequences which could also provide a control for these calculations.
+    // We do not attempt to calculate the last 15 bases of an alternate V allele, or the first
+    // 15 bases of an alternate J allele.  These bases are just copied.  If we want to really
+    // know these bases we may need to have actual genomic sequences which could also provide
+    // a control for these calculations.
     //
-    // Attempts to also do this for J segments were unsuccessful.
+    // Alternate J alleles are only found if ALT_J_ALLELES is specified.  For J segments, we
+    // only use contigs whose J alignment extends from the untrimmed part of the reference to its
+    // end, without indels.  The sequence that we use is the contig sequence laid out on
+    // reference coordinates, with reference bases filling in the trimmed start.
     //
     // Limitations and to do items:
     // 1. Hypothetically we could make a library of alternate alleles and use that
@@ -56,13 +60,14 @@ pub fn find_alleles(
 
     // Organize data by reference ID.  Note that we ignore exact subclonotypes having four chains.
 
-    let mut allxy =
-        vec![Vec::<(usize, Vec<u8>, Vec<usize>, usize, usize, String)>::new(); refdata.refs.len()];
+    let mut allxy = vec![
+        Vec::<(usize, Vec<u8>, Vec<usize>, usize, usize, String, usize)>::new();
+        refdata.refs.len()
+    ];
     for (m, x) in exact_clonotypes.iter().enumerate() {
         if x.share.len() >= 2 && x.share.len() <= 3 {
             for j in 0..x.share.len() {
                 let y = &x.share[j];
-                let id = y.v_ref_id;
 
                 // Find the partner chains.
 
@@ -72,19 +77,42 @@ pub fn find_alleles(
                         partner.push(x.share[ja].v_ref_id);
                     }
                 }
-                if !partner.is_empty()
-                    && y.seq_del.len() >= refdata.refs[id].len() - ctl.heur.ref_v_trim
+                if partner.is_empty() {
+                    continue;
+                }
+
+                // Define the V and J sequences, if we can use them.
+
+                let mut seqs = Vec::<(usize, Vec<u8>)>::new();
+                let vid = y.v_ref_id;
+                if y.seq_del.len() >= refdata.refs[vid].len() - ctl.heur.ref_v_trim {
+                    seqs.push((vid, y.seq_del.clone()));
+                }
+                let jid = y.j_ref_id;
+                let jref = refdata.refs[jid].to_ascii_vec();
+                if ctl.allele_alg_opt.j_alleles
+                    && y.j_start_ref <= ctl.heur.ref_j_trim
+                    && ctl.heur.ref_j_trim < jref.len()
+                    && y.j_stop - y.j_start == jref.len() - y.j_start_ref
                 {
+                    let mut s = jref[..ctl.heur.ref_j_trim].to_vec();
+                    s.extend(
+                        &y.full_seq[y.j_start + ctl.heur.ref_j_trim - y.j_start_ref..y.j_stop],
+                    );
+                    seqs.push((jid, s));
+                }
+                for (id, seq) in seqs {
                     for clone in &x.clones {
                         let donor = clone[j].donor_index;
                         if let Some(donor) = donor {
                             allxy[id].push((
                                 donor,
-                                y.seq_del.clone(),
+                                seq.clone(),
                                 partner.clone(),
                                 m,
                                 clone[j].dataset_index,
                                 clone[0].barcode.clone(),
+                                j,
                             ));
                         }
                     }
@@ -95,15 +123,15 @@ pub fn find_alleles(
 
     // Process each reference ID.
 
-    let mut vs = Vec::<usize>::new();
+    let mut ids = Vec::<usize>::new();
     for id in 0..refdata.refs.len() {
-        if refdata.is_v(id) {
-            vs.push(id);
+        if refdata.is_v(id) || refdata.is_j(id) {
+            ids.push(id);
         }
     }
     let mut results = Vec::<(usize, Vec<(usize, usize, DnaString, usize, bool)>)>::new();
-    for v in vs.iter() {
-        results.push((*v, Vec::new()));
+    for id in ids.iter() {
+        results.push((*id, Vec::new()));
     }
     results.par_iter_mut().for_each(|res| {
         let id = res.0;
@@ -112,7 +140,7 @@ pub fn find_alleles(
         // Divide by donor.
 
         allx.sort();
-        let mut alls = Vec::<Vec<(usize, Vec<u8>, Vec<usize>, usize, usize, String)>>::new();
+        let mut alls = Vec::<Vec<(usize, Vec<u8>, Vec<usize>, usize, usize, String, usize)>>::new();
         let mut i = 0;
         while i < allx.len() {
             // let j = next_diff1_6(&allx, i as i32) as usize;
@@ -131,10 +159,12 @@ pub fn find_alleles(
 
         for (di, all) in alls.iter().enumerate() {
             // Data here are given by "all", the relevant entries of which are:
-            // 1: V..J sequence for one chain of a given info entry
+            // 1: V..J sequence for one chain of a given info entry, or for J, the sequence
+            //    of the J segment on reference coordinates
             // 2: the reference ID(s) of the partner chain(s) -- possibly not used
             // 3: the index in exact_clonotypes
-            // 4: the dataset ID.
+            // 4: the dataset ID
+            // 6: the index of the chain in the exact subclonotype.
 
             let mut all = all.clone();
             let donor_id = all[0].0;
@@ -166,22 +196,19 @@ pub fn find_alleles(
                 for (i, item) in all.iter().enumerate() {
                     let u = item.3;
                     let ex = &exact_clonotypes[u];
-                    for j1 in 0..ex.share.len() {
-                        if ex.share[j1].seq_del == item.1 {
-                            for j2 in 0..ex.share.len() {
-                                let (s1, s2) = (&ex.share[j1], &ex.share[j2]);
-                                if s2.left != s1.left {
-                                    trace.push((
-                                        (
-                                            s1.cdr3_dna.len(),
-                                            s2.cdr3_dna.len(),
-                                            s2.v_ref_id,
-                                            s2.j_ref_id,
-                                        ),
-                                        i,
-                                    ));
-                                }
-                            }
+                    let j1 = item.6;
+                    for j2 in 0..ex.share.len() {
+                        let (s1, s2) = (&ex.share[j1], &ex.share[j2]);
+                        if s2.left != s1.left {
+                            trace.push((
+                                (
+                                    s1.cdr3_dna.len(),
+                                    s2.cdr3_dna.len(),
+                                    s2.v_ref_id,
+                                    s2.j_ref_id,
+                                ),
+                                i,
+                            ));
                         }
                     }
                 }
@@ -204,10 +231,15 @@ pub fn find_alleles(
                 erase_if(&mut all, &to_delete);
             }
 
-            // Traverse the positions in the reference V segment.
+            // Traverse the positions in the reference segment, excluding the trimmed part.
 
+            let (start, stop) = if refdata.is_j(id) {
+                (ctl.heur.ref_j_trim, refdata.refs[id].len())
+            } else {
+                (0, refdata.refs[id].len() - ctl.heur.ref_v_trim)
+            };
             let mut ps = Vec::<usize>::new();
-            for p in 0..refdata.refs[id].len() - ctl.heur.ref_v_trim {
+            for p in start..stop {
                 // Let bases = {(contig base, contig index, index of the other ref V segment)}.
                 // The other ref V segments are there only for diagnostic purposes.
 
@@ -502,7 +534,22 @@ pub fn find_alleles(
 
 // ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
 
-// Update reference sequences for V segments by substituting in alt alleles if better.
+// Count the mismatches between bases start..stop of a reference sequence and a contig
+// sequence, starting at position tig_start on the contig.  Gaps in the contig, which represent
+// deletions relative to the reference, are not counted.
+
+fn ref_diffs(r: &DnaString, start: usize, stop: usize, tig: &[u8], tig_start: usize) -> usize {
+    (start..stop)
+        .filter(|&l| {
+            let c = tig[tig_start + l - start];
+            c != b'-' && c != b"ACGT"[r.get(l) as usize]
+        })
+        .count()
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+// Update reference sequences for V and J segments by substituting in alt alleles if better.
 // Computational performance dubious because of full alt_refs traversal.
 
 pub fn sub_alts(
@@ -516,23 +563,11 @@ pub fn sub_alts(
     for i in 0..info.len() {
         for j in 0..info[i].vs.len() {
             if info[i].vs[j].len() - ctl.heur.ref_v_trim <= info[i].tigs[j].len() {
-                let mut errs = 0;
-                for l in 0..info[i].vs[j].len() - ctl.heur.ref_v_trim {
-                    let x = info[i].vs[j].get(l);
-                    let c;
-                    if x == 0 {
-                        c = b'A';
-                    } else if x == 1 {
-                        c = b'C';
-                    } else if x == 2 {
-                        c = b'G';
-                    } else {
-                        c = b'T';
-                    }
-                    if info[i].tigs[j][l] != c {
-                        errs += 1;
-                    }
-                }
+                // The contig is laid out on the reference (info.tigs), so a deletion shows up as
+                // gaps.  These are not counted, as they are not evidence against any allele.
+
+                let vlen = info[i].vs[j].len() - ctl.heur.ref_v_trim;
+                let errs = ref_diffs(&info[i].vs[j], 0, vlen, &info[i].tigs[j], 0);
                 let mut donors = Vec::<usize>::new();
                 let ex = &exact_clonotypes[info[i].clonotype_index];
                 for m in 0..ex.clones.len() {
@@ -547,30 +582,22 @@ pub fn sub_alts(
                             && refdata.name[alt_refs[m].1] == refdata.name[info[i].vsids[j]]
                             && alt_refs[m].2.len() - ctl.heur.ref_v_trim <= info[i].tigs[j].len()
                         {
-                            let mut alt_errs = 0;
-                            for l in 0..alt_refs[m].2.len() - ctl.heur.ref_v_trim {
-                                let x = alt_refs[m].2.get(l);
-                                let c;
-                                if x == 0 {
-                                    c = b'A';
-                                } else if x == 1 {
-                                    c = b'C';
-                                } else if x == 2 {
-                                    c = b'G';
-                                } else {
-                                    c = b'T';
-                                }
-                                if info[i].tigs[j][l] != c {
-                                    alt_errs += 1;
-                                }
-                            }
+                            let alt_vlen = alt_refs[m].2.len() - ctl.heur.ref_v_trim;
+                            let alt_errs =
+                                ref_diffs(&alt_refs[m].2, 0, alt_vlen, &info[i].tigs[j], 0);
                             if alt_errs < errs {
                                 info[i].vs[j] = alt_refs[m].2.clone();
                                 info[i].vsids[j] = alt_refs[m].1;
                                 info[i].dref[j] = Some(m); // not sure we're actually using this
+
+                                // Find the chain by seq_del, which equals info.tigs, rather than by
+                                // seq, which differs from it if the contig has a deletion, in
+                                // which case the donor allele would be used for the clonotype but
+                                // not recorded for the chain.
+
                                 let ex = &mut exact_clonotypes[info[i].clonotype_id];
                                 for z in 0..ex.share.len() {
-                                    if ex.share[z].seq == info[i].tigs[j] {
+                                    if ex.share[z].seq_del == info[i].tigs[j] {
                                         ex.share[z].v_ref_id = alt_refs[m].1;
                                         ex.share[z].v_ref_id_donor = Some(m);
                                         ex.share[z].v_ref_id_donor_donor = Some(donor);
@@ -593,7 +620,204 @@ pub fn sub_alts(
                     }
                 }
             }
+
+            // Now do the same for the J segment, which is aligned to the end of the contig.
+
+            let jlen = info[i].js[j].len();
+            let n = info[i].tigs[j].len();
+            if jlen > ctl.heur.ref_j_trim && jlen - ctl.heur.ref_j_trim <= n {
+                let trim = ctl.heur.ref_j_trim;
+                let start = n - (jlen - trim);
+                let errs = ref_diffs(&info[i].js[j], trim, jlen, &info[i].tigs[j], start);
+                let mut donors = Vec::<usize>::new();
+                let ex = &exact_clonotypes[info[i].clonotype_index];
+                for m in 0..ex.clones.len() {
+                    if ex.clones[m][0].donor_index.is_some() {
+                        donors.push(ex.clones[m][0].donor_index.unwrap());
+                    }
+                }
+                unique_sort(&mut donors);
+                for donor in donors {
+                    for m in 0..alt_refs.len() {
+                        if alt_refs[m].0 == donor
+                            && refdata.name[alt_refs[m].1] == refdata.name[info[i].jsids[j]]
+                            && alt_refs[m].2.len() == jlen
+                        {
+                            let alt_errs =
+                                ref_diffs(&alt_refs[m].2, trim, jlen, &info[i].tigs[j], start);
+                            if alt_errs < errs {
+                                info[i].js[j] = alt_refs[m].2.clone();
+                                info[i].jsids[j] = alt_refs[m].1;
+                                let ex = &mut exact_clonotypes[info[i].clonotype_id];
+                                for z in 0..ex.share.len() {
+                                    if ex.share[z].seq_del == info[i].tigs[j] {
+                                        ex.share[z].js = alt_refs[m].2.clone();
+                                        ex.share[z].j_ref_id = alt_refs[m].1;
+                                        ex.share[z].j_ref_id_donor = Some(m);
+                                        ex.share[z].j_ref_id_donor_donor = Some(donor);
+                                        let mut alts = 0;
+                                        let mut mm = m;
+                                        while mm >= 1 {
+                                            mm -= 1;
+                                            if alt_refs[mm].0 == donor
+                                                && alt_refs[mm].1 == alt_refs[m].1
+                                            {
+                                                alts += 1;
+                                            }
+                                        }
+                                        ex.share[z].j_ref_id_donor_alt_id = Some(alts);
+                                        break;
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
         }
     }
     ctl.perf_stats(&t, "substituting alt alleles");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::join_test_data::{
+        mutate, test_ctl, JoinTestData, TestChain, TestExact, HEAVY_V, LIGHT_V,
+    };
+
+    #[test]
+    fn test_ref_diffs() {
+        let r = DnaString::from_acgt_bytes(b"ACGTACGT");
+        assert_eq!(ref_diffs(&r, 0, 8, b"ACGTACGT", 0), 0);
+        assert_eq!(ref_diffs(&r, 0, 8, b"ACCTACGA", 0), 2);
+        assert_eq!(ref_diffs(&r, 0, 8, b"AC-TACGA", 0), 1);
+        assert_eq!(ref_diffs(&r, 4, 8, b"TTTTACGA", 4), 1);
+        assert_eq!(ref_diffs(&r, 4, 8, b"ACGA", 0), 1);
+    }
+
+    // Substitute a donor V allele for a heavy chain contig that has a deletion in its V segment.
+    // The substitution must be recorded for the chain, although its sequence (seq) differs from
+    // the contig laid out on the reference (info.tigs).
+
+    #[test]
+    fn test_sub_alts_v_deletion() {
+        let exact = TestExact {
+            heavy: TestChain {
+                v: HEAVY_V,
+                muts: vec![30],
+                ins: b"GGGTAT".to_vec(),
+            },
+            light: TestChain {
+                v: LIGHT_V,
+                muts: Vec::new(),
+                ins: b"CCA".to_vec(),
+            },
+            ncells: 2,
+        };
+        let mut td = JoinTestData::new(test_ctl(), &[exact]);
+        let ex = &mut td.exact_clonotypes[0];
+        for clone in &mut ex.clones {
+            for x in clone {
+                x.donor_index = Some(0);
+            }
+        }
+        let j = ex.share.iter().position(|x| x.left).unwrap();
+        let vid = ex.share[j].v_ref_id;
+        let mut tig = td.info[0].tigs[j].clone();
+        tig[10] = b'-';
+        td.info[0].tigs[j] = tig.clone();
+        ex.share[j].seq_del = tig.clone();
+        assert_ne!(ex.share[j].seq, tig);
+        let mut alt = td.refdata.refs[vid].to_ascii_vec();
+        alt[30] = tig[30];
+        let alt = DnaString::from_acgt_bytes(&alt);
+
+        let alt_refs = vec![(0, vid, alt.clone(), 4, false)];
+        sub_alts(
+            &td.refdata,
+            &td.ctl,
+            &alt_refs,
+            &mut td.info,
+            &mut td.exact_clonotypes,
+        );
+        assert_eq!(td.info[0].vs[j], alt);
+        assert_eq!(td.info[0].dref[j], Some(0));
+        let x = &td.exact_clonotypes[0].share[j];
+        assert_eq!(x.v_ref_id, vid);
+        assert_eq!(x.v_ref_id_donor, Some(0));
+        assert_eq!(x.v_ref_id_donor_donor, Some(0));
+        assert_eq!(x.v_ref_id_donor_alt_id, Some(0));
+        assert_eq!(x.j_ref_id_donor, None);
+    }
+
+    // Substitute a donor J allele for a heavy chain contig that has a gap in its V segment, as
+    // for a deletion.
+
+    #[test]
+    fn test_sub_alts_j() {
+        let exact = TestExact {
+            heavy: TestChain {
+                v: HEAVY_V,
+                muts: Vec::new(),
+                ins: b"GGGTAT".to_vec(),
+            },
+            light: TestChain {
+                v: LIGHT_V,
+                muts: Vec::new(),
+                ins: b"CCA".to_vec(),
+            },
+            ncells: 2,
+        };
+        let mut td = JoinTestData::new(test_ctl(), &[exact]);
+        let ex = &mut td.exact_clonotypes[0];
+        for clone in &mut ex.clones {
+            for x in clone {
+                x.donor_index = Some(0);
+            }
+        }
+        let j = ex.share.iter().position(|x| x.left).unwrap();
+        let jid = ex.share[j].j_ref_id;
+        let mut tig = td.info[0].tigs[j].clone();
+        let n = tig.len();
+        tig[10] = b'-';
+        tig[n - 5] = mutate(tig[n - 5]);
+        td.info[0].tigs[j] = tig.clone();
+        ex.share[j].seq_del = tig.clone();
+        let mut alt = td.refdata.refs[jid].to_ascii_vec();
+        let jlen = alt.len();
+        alt[jlen - 5] = tig[n - 5];
+        let alt = DnaString::from_acgt_bytes(&alt);
+
+        // The gap is not a mismatch, and the J mutation is a mismatch only to the reference.
+
+        let vlen = td.info[0].vs[j].len() - td.ctl.heur.ref_v_trim;
+        assert_eq!(ref_diffs(&td.info[0].vs[j], 0, vlen, &tig, 0), 0);
+        let trim = td.ctl.heur.ref_j_trim;
+        let start = n - (jlen - trim);
+        assert_eq!(ref_diffs(&td.info[0].js[j], trim, jlen, &tig, start), 1);
+        assert_eq!(ref_diffs(&alt, trim, jlen, &tig, start), 0);
+
+        let alt_refs = vec![(0, jid, alt.clone(), 4, false)];
+        sub_alts(
+            &td.refdata,
+            &td.ctl,
+            &alt_refs,
+            &mut td.info,
+            &mut td.exact_clonotypes,
+        );
+        assert_eq!(td.info[0].js[j], alt);
+        assert_eq!(td.info[0].jsids[j], jid);
+        let x = &td.exact_clonotypes[0].share[j];
+        assert_eq!(x.js, alt);
+        assert_eq!(x.j_ref_id_donor, Some(0));
+        assert_eq!(x.j_ref_id_donor_donor, Some(0));
+        assert_eq!(x.j_ref_id_donor_alt_id, Some(0));
+        assert_eq!(x.v_ref_id_donor, None);
+
+        // The light chain is unchanged.
+
+        let y = &td.exact_clonotypes[0].share[1 - j];
+        assert_eq!(y.j_ref_id_donor, None);
+    }
+}
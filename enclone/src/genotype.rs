@@ -0,0 +1,450 @@
+// Copyright (c) 2023 10X Genomics, Inc. All rights reserved.
+
+// This file provides functions to read a known donor genotype (DONOR_GENOTYPE), use it in
+// place of or in addition to inferred alternate alleles, and compare the two.
+//
+// The genotype may be given as FASTA or as TSV.  In FASTA form, each header is either
+// >donor:gene
+// or the header line written by DONOR_REF_FILE, namely
+// >ref id:donor:allele number:gene (...)
+// so that the donor reference from a previous run can be read back in.  In TSV form, the first
+// line must be
+// donor<tab>gene<tab>sequence
+// and each following line gives one allele.  Each donor must be a donor short name, each gene
+// must be the name of a V or J segment in the reference, and each sequence must have the same
+// length as that reference segment, so alleles having indels relative to the reference cannot be
+// given.  Alleles identical to the reference may be listed, and record that the donor carries
+// the reference allele.
+
+use crate::allele::find_alleles;
+use debruijn::dna_string::DnaString;
+use enclone_core::defs::{EncloneControl, ExactClonotype};
+use io_utils::fwriteln;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use vdj_ann::refx::RefData;
+
+// A donor allele from DONOR_GENOTYPE.
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GenotypeAllele {
+    pub donor: usize,  // index in ctl.origin_info.donor_list
+    pub ref_id: usize, // index of the V or J segment in the reference
+    pub seq: Vec<u8>,
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+pub fn read_donor_genotype(
+    ctl: &EncloneControl,
+    refdata: &RefData,
+) -> Result<Vec<GenotypeAllele>, String> {
+    let path = &ctl.gen_opt.donor_genotype;
+    let f = File::open(path)
+        .map_err(|e| format!("\nCould not open DONOR_GENOTYPE file {path}: {e}.\n"))?;
+    let mut lines = Vec::<String>::new();
+    for line in BufReader::new(f).lines() {
+        let line =
+            line.map_err(|e| format!("\nFailed to read DONOR_GENOTYPE file {path}: {e}.\n"))?;
+        lines.push(line);
+    }
+    parse_donor_genotype(path, &lines, &ctl.origin_info.donor_list, refdata)
+}
+
+// Parse the lines of a DONOR_GENOTYPE file.  The path is used only in error messages.
+
+fn parse_donor_genotype(
+    path: &str,
+    lines: &[String],
+    donors: &[String],
+    refdata: &RefData,
+) -> Result<Vec<GenotypeAllele>, String> {
+    let lines = lines
+        .iter()
+        .filter(|x| !x.trim().is_empty())
+        .map(|x| x.trim_end().to_string())
+        .collect::<Vec<_>>();
+
+    // Parse the records into {(line number, donor, gene, ref id, sequence)}.
+
+    let mut records = Vec::<(usize, String, String, Option<String>, String)>::new();
+    if lines.first().is_some_and(|x| x.starts_with('>')) {
+        let mut i = 0;
+        while i < lines.len() {
+            if !lines[i].starts_with('>') {
+                return Err(format!(
+                    "\nIn the DONOR_GENOTYPE file {path}, line {} was expected to be a \
+                     FASTA header.\n",
+                    i + 1
+                ));
+            }
+            let header = lines[i][1..].split_whitespace().next().unwrap_or("");
+            let fields = header.split(':').collect::<Vec<&str>>();
+            let (ref_id, donor, gene) = match fields.len() {
+                2 => (None, fields[0], fields[1]),
+                4 => (Some(fields[0].to_string()), fields[1], fields[3]),
+                _ => {
+                    return Err(format!(
+                        "\nIn the DONOR_GENOTYPE file {path}, the FASTA header on line {} \
+                         should have the form\n>donor:gene\nor\n>ref id:donor:allele \
+                         number:gene\n",
+                        i + 1
+                    ));
+                }
+            };
+            let mut seq = String::new();
+            let mut j = i + 1;
+            while j < lines.len() && !lines[j].starts_with('>') {
+                seq += lines[j].trim();
+                j += 1;
+            }
+            records.push((i + 1, donor.to_string(), gene.to_string(), ref_id, seq));
+            i = j;
+        }
+    } else {
+        if lines.first().map(String::as_str) != Some("donor\tgene\tsequence") {
+            return Err(format!(
+                "\nThe DONOR_GENOTYPE file {path} should either be a FASTA file, or a TSV \
+                 file whose first line is\ndonor<tab>gene<tab>sequence\n"
+            ));
+        }
+        for (i, line) in lines.iter().enumerate().skip(1) {
+            let fields = line.split('\t').collect::<Vec<&str>>();
+            if fields.len() != 3 {
+                return Err(format!(
+                    "\nIn the DONOR_GENOTYPE file {path}, line {} does not have three \
+                     fields.\n",
+                    i + 1
+                ));
+            }
+            records.push((
+                i + 1,
+                fields[0].to_string(),
+                fields[1].to_string(),
+                None,
+                fields[2].to_string(),
+            ));
+        }
+    }
+
+    // Translate the records into alleles.
+
+    let mut alleles = Vec::<GenotypeAllele>::new();
+    for (line, donor, gene, ref_id, seq) in records {
+        let seq = seq.to_ascii_uppercase().into_bytes();
+        if seq.is_empty() || seq.iter().any(|c| !b"ACGT".contains(c)) {
+            return Err(format!(
+                "\nIn the DONOR_GENOTYPE file {path}, the sequence for line {line} is empty or \
+                 has a character other than A, C, G or T.\n"
+            ));
+        }
+        let Some(d) = donors.iter().position(|x| *x == donor) else {
+            return Err(format!(
+                "\nIn the DONOR_GENOTYPE file {path}, line {line} refers to the donor \
+                 {donor}, but the known donors are\n{}\n",
+                donors.join(", ")
+            ));
+        };
+        let mut ids = (0..refdata.refs.len())
+            .filter(|&i| refdata.name[i] == gene && (refdata.is_v(i) || refdata.is_j(i)))
+            .collect::<Vec<_>>();
+        if ids.is_empty() {
+            return Err(format!(
+                "\nIn the DONOR_GENOTYPE file {path}, line {line} refers to the gene {gene}, \
+                 which is not a V or J segment in the reference.\n"
+            ));
+        }
+        if let Some(r) = ref_id {
+            if ids.iter().any(|&i| refdata.id[i].to_string() == r) {
+                ids.retain(|&i| refdata.id[i].to_string() == r);
+            }
+        }
+        let Some(id) = ids
+            .iter()
+            .copied()
+            .find(|&i| refdata.refs[i].len() == seq.len())
+        else {
+            return Err(format!(
+                "\nIn the DONOR_GENOTYPE file {path}, the sequence for line {line} has length \
+                 {}, but the reference sequence for {gene} has length {}.  Alleles having \
+                 insertions or deletions relative to the reference are not supported, because \
+                 donor alleles are compared to the reference base by base.\n",
+                seq.len(),
+                refdata.refs[ids[0]].len()
+            ));
+        };
+        alleles.push(GenotypeAllele {
+            donor: d,
+            ref_id: id,
+            seq,
+        });
+    }
+    alleles.sort();
+    alleles.dedup();
+    Ok(alleles)
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+// Define the alternate alleles, as {(donor, ref id, alt seq, support, is_ref)}.  If a donor
+// genotype was given, it is authoritative for every (donor, gene) that it covers, and inferred
+// alleles are used only for the other genes, unless DONOR_GENOTYPE_ONLY was specified, in
+// which case no alleles are inferred.  Alleles from the genotype have zero support.
+
+pub fn donor_alleles(
+    refdata: &RefData,
+    ctl: &EncloneControl,
+    exact_clonotypes: &[ExactClonotype],
+) -> Result<Vec<(usize, usize, DnaString, usize, bool)>, String> {
+    let mut alt_refs = Vec::<(usize, usize, DnaString, usize, bool)>::new();
+    if !ctl.gen_opt.no_alt_alleles && !ctl.gen_opt.donor_genotype_only {
+        alt_refs = find_alleles(refdata, ctl, exact_clonotypes);
+    }
+    if ctl.gen_opt.donor_genotype.is_empty() {
+        return Ok(alt_refs);
+    }
+    let genotype = read_donor_genotype(ctl, refdata)?;
+    if !ctl.gen_opt.donor_genotype_report.is_empty() {
+        write_genotype_report(ctl, refdata, &genotype, &alt_refs)?;
+    }
+    let covered = genotype
+        .iter()
+        .map(|x| (x.donor, &refdata.name[x.ref_id]))
+        .collect::<HashSet<_>>();
+    alt_refs.retain(|x| !covered.contains(&(x.0, &refdata.name[x.1])));
+    for x in genotype {
+        if x.seq != refdata.refs[x.ref_id].to_ascii_vec() {
+            alt_refs.push((
+                x.donor,
+                x.ref_id,
+                DnaString::from_acgt_bytes(&x.seq),
+                0,
+                false,
+            ));
+        }
+    }
+    alt_refs.sort();
+    Ok(alt_refs)
+}
+
+// Write a TSV file (DONOR_GENOTYPE_REPORT) that classifies each inferred allele as agreeing
+// with an allele in the donor genotype (match), disagreeing with every allele of the same gene
+// in the donor genotype (conflict), or having a gene that is not in the donor genotype for the
+// donor (absent).  Bases that are not inferred, namely the trimmed end of a V segment and the
+// trimmed start of a J segment, are ignored.
+
+pub fn write_genotype_report(
+    ctl: &EncloneControl,
+    refdata: &RefData,
+    genotype: &[GenotypeAllele],
+    alt_refs: &[(usize, usize, DnaString, usize, bool)],
+) -> Result<(), String> {
+    let path = &ctl.gen_opt.donor_genotype_report;
+    let f = File::create(path)
+        .map_err(|e| format!("\nCould not create DONOR_GENOTYPE_REPORT file {path}: {e}.\n"))?;
+    let mut f = BufWriter::new(f);
+    fwriteln!(f, "donor\tgene\tallele\tsupport\tstatus");
+    let mut allele = 0;
+    for (i, x) in alt_refs.iter().enumerate() {
+        let (donor, ref_id) = (x.0, x.1);
+        if i > 0 && (donor == alt_refs[i - 1].0 && ref_id == alt_refs[i - 1].1) {
+            allele += 1;
+        } else {
+            allele = 1;
+        }
+        let status = genotype_status(ctl, refdata, genotype, donor, ref_id, &x.2.to_ascii_vec());
+        fwriteln!(
+            f,
+            "{}\t{}\t{allele}\t{}\t{status}",
+            ctl.origin_info.donor_list[donor],
+            refdata.name[ref_id],
+            x.3
+        );
+    }
+    f.flush()
+        .map_err(|e| format!("\nFailed to write DONOR_GENOTYPE_REPORT file {path}: {e}.\n"))
+}
+
+// Classify an inferred allele of the given donor and reference segment as match, conflict or
+// absent, as described for write_genotype_report.
+
+fn genotype_status(
+    ctl: &EncloneControl,
+    refdata: &RefData,
+    genotype: &[GenotypeAllele],
+    donor: usize,
+    ref_id: usize,
+    alt: &[u8],
+) -> &'static str {
+    let mut status = "absent";
+    for g in genotype {
+        if g.donor == donor && refdata.name[g.ref_id] == refdata.name[ref_id] {
+            let n = alt.len().min(g.seq.len());
+            let same = if refdata.is_j(ref_id) {
+                let n = n.saturating_sub(ctl.heur.ref_j_trim);
+                alt[alt.len() - n..] == g.seq[g.seq.len() - n..]
+            } else {
+                let n = n.saturating_sub(ctl.heur.ref_v_trim);
+                alt[..n] == g.seq[..n]
+            };
+            if same {
+                return "match";
+            }
+            status = "conflict";
+        }
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::join_test_data::{mutate, test_ctl};
+
+    // A reference having a V segment of length 40 and a J segment of length 30, and a V segment
+    // of the same name but length 41.
+
+    const V: &str = "GATTACAGATTACAGATTACGATTACAGATTACAGATTAC";
+    const J: &str = "TGGGGCCAGGGAACCCTGGTCACCGTCTCC";
+
+    fn test_refdata() -> RefData {
+        let mut refdata = RefData::new();
+        for (id, name, segtype, seq) in [
+            (1, "IGHV3-23", "V", V.to_string()),
+            (2, "IGHJ4", "J", J.to_string()),
+            (3, "IGHV3-23", "V", format!("{V}A")),
+        ] {
+            refdata.refs.push(DnaString::from_dna_string(&seq));
+            refdata.name.push(name.to_string());
+            refdata.segtype.push(segtype.to_string());
+            refdata.id.push(id);
+        }
+        refdata
+    }
+
+    fn donors() -> Vec<String> {
+        vec!["d1".to_string(), "d2".to_string()]
+    }
+
+    fn parse(lines: &[&str]) -> Result<Vec<GenotypeAllele>, String> {
+        let lines = lines.iter().map(ToString::to_string).collect::<Vec<_>>();
+        parse_donor_genotype("genotype", &lines, &donors(), &test_refdata())
+    }
+
+    fn mutated(seq: &str, ps: &[usize]) -> Vec<u8> {
+        let mut seq = seq.as_bytes().to_vec();
+        for &p in ps {
+            seq[p] = mutate(seq[p]);
+        }
+        seq
+    }
+
+    // FASTA input, in both header forms, with a sequence split across lines, in lower case.
+    // The reference segment is chosen by length, or by ref id in the DONOR_REF_FILE form.
+
+    #[test]
+    fn test_parse_fasta() {
+        let v = String::from_utf8(mutated(V, &[3])).unwrap();
+        let alleles = parse(&[
+            ">d2:IGHV3-23",
+            &v[..20],
+            &v[20..].to_ascii_lowercase(),
+            "",
+            ">3:d1:1:IGHV3-23 (reference allele)",
+            &format!("{V}A"),
+            ">2:d1:2:IGHJ4",
+            J,
+        ])
+        .unwrap();
+        let expected = vec![
+            GenotypeAllele {
+                donor: 0,
+                ref_id: 1,
+                seq: J.as_bytes().to_vec(),
+            },
+            GenotypeAllele {
+                donor: 0,
+                ref_id: 2,
+                seq: format!("{V}A").into_bytes(),
+            },
+            GenotypeAllele {
+                donor: 1,
+                ref_id: 0,
+                seq: mutated(V, &[3]),
+            },
+        ];
+        assert!(alleles == expected);
+    }
+
+    // TSV input, in which a repeated allele is listed once.
+
+    #[test]
+    fn test_parse_tsv() {
+        let j = String::from_utf8(mutated(J, &[20])).unwrap();
+        let alleles = parse(&[
+            "donor\tgene\tsequence",
+            &format!("d1\tIGHJ4\t{j}"),
+            &format!("d1\tIGHV3-23\t{V}"),
+            &format!("d1\tIGHJ4\t{j}"),
+        ])
+        .unwrap();
+        assert_eq!(alleles.len(), 2);
+        assert_eq!((alleles[0].donor, alleles[0].ref_id), (0, 0));
+        assert_eq!((alleles[1].donor, alleles[1].ref_id), (0, 1));
+        assert!(alleles[1].seq == j.as_bytes());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = |lines: &[&str]| parse(lines).err().unwrap();
+        assert!(err(&["donor\tgene"]).contains("should either be a FASTA file"));
+        assert!(err(&["donor\tgene\tsequence", "d1\tIGHJ4"]).contains("three fields"));
+        assert!(err(&[">d1:IGHJ4:x", J]).contains("should have the form"));
+        assert!(err(&[">d3:IGHJ4", J]).contains("known donors are\nd1, d2"));
+        assert!(err(&[">d1:IGHD1", J]).contains("not a V or J segment"));
+        assert!(err(&[">d1:IGHJ4", "TGGNN"]).contains("other than A, C, G or T"));
+
+        // An allele having an indel relative to the reference is rejected.
+
+        let del = format!("{}{}", &J[..10], &J[11..]);
+        let msg = err(&[">d1:IGHJ4", &del]);
+        assert!(msg.contains("has length 29, but the reference sequence for IGHJ4 has length 30"));
+        assert!(msg.contains("insertions or deletions"));
+    }
+
+    // The last 15 bases of a V segment and the first 15 bases of a J segment are ignored.
+
+    #[test]
+    fn test_genotype_status() {
+        let ctl = test_ctl();
+        let refdata = test_refdata();
+        let genotype = vec![
+            GenotypeAllele {
+                donor: 0,
+                ref_id: 0,
+                seq: V.as_bytes().to_vec(),
+            },
+            GenotypeAllele {
+                donor: 0,
+                ref_id: 0,
+                seq: mutated(V, &[3]),
+            },
+            GenotypeAllele {
+                donor: 0,
+                ref_id: 1,
+                seq: mutated(J, &[20]),
+            },
+        ];
+        let status = |donor, ref_id, alt: Vec<u8>| {
+            genotype_status(&ctl, &refdata, &genotype, donor, ref_id, &alt)
+        };
+        assert_eq!(status(0, 0, mutated(V, &[3])), "match");
+        assert_eq!(status(0, 0, mutated(V, &[30])), "match");
+        assert_eq!(status(0, 0, mutated(V, &[5])), "conflict");
+        assert_eq!(status(0, 2, mutated(&format!("{V}A"), &[3])), "match");
+        assert_eq!(status(0, 1, mutated(J, &[5, 20])), "match");
+        assert_eq!(status(0, 1, J.as_bytes().to_vec()), "conflict");
+        assert_eq!(status(1, 0, mutated(V, &[3])), "absent");
+    }
+}
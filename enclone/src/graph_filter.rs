@@ -2,14 +2,13 @@
 
 // This file provides the single function graph_filter.
 
-use enclone_core::barcode_fate::BarcodeFate;
+use enclone_core::barcode_fate::{BarcodeFate, FateLedger};
 use enclone_core::defs::{EncloneControl, TigData};
 use graph_simple::GraphSimple;
 use io_utils::fwriteln;
 use petgraph::prelude::*;
 use rayon::prelude::*;
 use std::cmp::{max, min};
-use std::collections::HashMap;
 use std::io::Write;
 use string_utils::strme;
 use vector_utils::{bin_member, bin_position, erase_if, lower_bound, next_diff12_3, reverse_sort};
@@ -34,7 +33,7 @@ pub fn graph_filter(
     ctl: &EncloneControl,
     tig_bc: &mut Vec<Vec<TigData>>,
     graph: bool,
-    fate: &mut [HashMap<String, BarcodeFate>],
+    fate: &mut [FateLedger],
 ) {
     let mut ndels = 0;
     let mut seqs = Vec::<(&[u8], bool, &str, usize)>::new();
@@ -372,13 +371,17 @@ pub fn graph_filter(
             ndels += 1;
         }
     }
+    let removed = ctl.clono_filt_opt_def.delete(!ctl.gen_opt.ngraph_filter);
     for i in 0..tig_bc.len() {
         if to_delete[i] {
-            fate[tig_bc[i][0].dataset_index]
-                .insert(tig_bc[i][0].barcode.clone(), BarcodeFate::GraphFilter);
+            fate[tig_bc[i][0].dataset_index].insert(
+                tig_bc[i][0].barcode.clone(),
+                BarcodeFate::GraphFilter,
+                removed,
+            );
         }
     }
-    if !ctl.gen_opt.ngraph_filter {
+    if removed {
         erase_if(tig_bc, &to_delete);
     }
     if graph {
@@ -2,7 +2,7 @@
 
 // This file provides the single function build_info.
 
-use enclone_core::barcode_fate::BarcodeFate;
+use enclone_core::barcode_fate::{BarcodeFate, FateLedger};
 use vdj_ann::refx;
 
 use self::refx::RefData;
@@ -12,7 +12,6 @@ use debruijn::{dna_string::DnaString, Mer};
 use enclone_core::defs::{CloneInfo, EncloneControl, ExactClonotype};
 use enclone_core::print_tools::emit_codon_color_escape;
 use rayon::prelude::*;
-use std::collections::HashMap;
 use std::convert::TryInto;
 use std::fmt::Write;
 use string_utils::strme;
@@ -22,7 +21,7 @@ pub fn build_info(
     refdata: &RefData,
     ctl: &EncloneControl,
     exact_clonotypes: &mut [ExactClonotype],
-    fate: &mut [HashMap<String, BarcodeFate>],
+    fate: &mut [FateLedger],
 ) -> Vec<CloneInfo> {
     // Build info about clonotypes.  We create a data structure info.
     // An entry in info is a clonotype having appropriate properties.
@@ -329,7 +328,9 @@ pub fn build_info(
                 ));
             }
         }
-        if !placed && (shares.len() == 1 || ctl.merge_all_impropers) {
+        if !placed
+            && (shares.len() == 1 || ctl.merge_all_impropers || ctl.clono_filt_opt_def.mark_only)
+        {
             let mut exact_cols = Vec::<usize>::new();
             for i in 0..tigs.len() {
                 exact_cols.push(i);
@@ -359,11 +360,15 @@ pub fn build_info(
 
     // Cumulate info.  This is single threaded and could probably be speeded up.
 
+    // Improper exact subclonotypes are only removed if they were not incorporated above.
+
+    let removed = !(ctl.merge_all_impropers || ctl.clono_filt_opt_def.mark_only);
     for i in 0..results.len() {
         info.append(&mut results[i].1);
         exact_clonotypes[i] = results[i].2.clone();
         for j in 0..results[i].3.len() {
-            fate[results[i].3[j].0].insert(results[i].3[j].1.clone(), results[i].3[j].2.clone());
+            let x = &results[i].3[j];
+            fate[x.0].insert(x.1.clone(), x.2, removed);
         }
     }
 
@@ -12,9 +12,12 @@ use vdj_ann::{annotate, refx};
 use self::annotate::print_annotations;
 use self::refx::RefData;
 use crate::join2::finish_join;
+use crate::join_audit::{write_join_audit, JoinAudit};
 use crate::join_core::join_core;
+use crate::join_indel::{indel_candidates, join_indels, joins_indels};
 use debruijn::dna_string::DnaString;
 use enclone_core::defs::{CloneInfo, EncloneControl, ExactClonotype, PotentialJoin};
+use enclone_core::join_one::JoinRejection;
 use enclone_proto::types::DonorReferenceItem;
 use equiv::EquivRel;
 use io_utils::{fwrite, fwriteln};
@@ -38,7 +41,7 @@ pub fn join_exacts(
     raw_joins: &mut Vec<(i32, i32)>,
     sr: &[Vec<Double>],
     dref: &[DonorReferenceItem],
-) -> EquivRel {
+) -> Result<EquivRel, String> {
     //
     // Run special option for joining by barcode identity.
 
@@ -61,7 +64,7 @@ pub fn join_exacts(
             }
             i = j;
         }
-        return eq;
+        return Ok(eq);
     }
 
     // Find potential joins.
@@ -107,13 +110,16 @@ pub fn join_exacts(
     ctl.perf_stats(&timer1, "join setup");
     let timer2 = Instant::now();
 
-    let joinf = |r: &mut (
-        usize,
-        usize,
-        usize,
-        usize,
-        Vec<(usize, usize, bool, Vec<u8>)>,
-        Vec<(usize, usize)>,
+    let joinf = |(r, audit): (
+        &mut (
+            usize,
+            usize,
+            usize,
+            usize,
+            Vec<(usize, usize, bool, Vec<u8>)>,
+            Vec<(usize, usize)>,
+        ),
+        &mut Vec<JoinAudit>,
     )| {
         let (i, j) = (r.0, r.1);
         let joins = &mut r.2;
@@ -138,6 +144,7 @@ pub fn join_exacts(
             &mut pot,
             refdata,
             dref,
+            audit,
         );
 
         // Run two passes.
@@ -199,8 +206,20 @@ pub fn join_exacts(
                     }
                 }
             }
+            if !ctl.gen_opt.join_audit.is_empty() {
+                for (pj, pot) in pot.iter().enumerate() {
+                    if to_delete[pj] {
+                        audit.push(JoinAudit::new(pot, Some(JoinRejection::TwoCellBar)));
+                    }
+                }
+            }
             erase_if(&mut pot, &to_delete);
         }
+        if !ctl.gen_opt.join_audit.is_empty() {
+            for pj in &pot {
+                audit.push(JoinAudit::new(pj, None));
+            }
+        }
 
         // Analyze potential joins.
 
@@ -599,13 +618,64 @@ pub fn join_exacts(
         }
     };
 
-    results.par_iter_mut().for_each(joinf);
+    let mut audits = (0..results.len())
+        .map(|_| Vec::<JoinAudit>::new())
+        .collect::<Vec<_>>();
+    results
+        .par_iter_mut()
+        .zip(audits.par_iter_mut())
+        .for_each(joinf);
 
     ctl.perf_stats(&timer2, "in main part of join");
+
+    // Join across CDR3 indels, comparing entries in pairs of the blocks above.  The joins are
+    // saved as if they came from a block spanning all info entries.
+
+    if joins_indels(is_bcr, ctl) {
+        let timer = Instant::now();
+        let mut eq: EquivRel = EquivRel::new(info.len() as i32);
+        for r in &results {
+            for &(k1, k2) in &r.5 {
+                eq.join(k1 as i32, k2 as i32);
+            }
+        }
+        let blocks = results
+            .iter()
+            .map(|r| (r.0..r.1).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let candidates = indel_candidates(ctl, info, &blocks);
+        let mut audit = Vec::<JoinAudit>::new();
+        let (new_joins, errors) = join_indels(
+            ctl,
+            exact_clonotypes,
+            info,
+            to_bc,
+            sr,
+            refdata,
+            dref,
+            &candidates,
+            &mut eq,
+            &mut audit,
+        );
+        audits.push(audit);
+        results.push((
+            0,
+            info.len(),
+            new_joins.len(),
+            errors,
+            Vec::new(),
+            new_joins,
+        ));
+        ctl.perf_stats(&timer, "joining across CDR3 indels");
+    }
     for r in &results {
         for &j in &r.5 {
             raw_joins.push((j.0 as i32, j.1 as i32));
         }
     }
-    finish_join(ctl, info, &results, join_info)
+    if !ctl.gen_opt.join_audit.is_empty() {
+        let audits = audits.into_iter().flatten().collect::<Vec<_>>();
+        write_join_audit(ctl, exact_clonotypes, info, &audits)?;
+    }
+    Ok(finish_join(ctl, info, &results, join_info))
 }
@@ -0,0 +1,337 @@
+// Copyright (c) 2023 10X Genomics, Inc. All rights reserved.
+
+// Structured record of the pairs of exact subclonotypes examined by join_exacts, written if
+// JOIN_AUDIT is specified.  There is one record for each pair that join_one examined, saying
+// whether the pair was joined, and if not, which rule rejected it, together with the evidence
+// gathered up to that point.  Pairs that join_core did not examine, either because they could
+// not possibly be joined or because they were already joined transitively, are not listed.
+
+use enclone_core::defs::{CloneInfo, EncloneControl, ExactClonotype, PotentialJoin};
+use enclone_core::join_one::JoinRejection;
+use itertools::Itertools;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+pub struct JoinAudit {
+    pub k1: usize,
+    pub k2: usize,
+    pub rejection: Option<JoinRejection>,
+    pub nrefs: usize,
+    pub cd: isize,
+    pub diffs: usize,
+    pub score: f64,
+    pub p1: f64,
+    pub mult: f64,
+    pub shares: Vec<isize>,
+    pub indeps: Vec<isize>,
+    pub share_pos_v: Vec<Vec<usize>>,
+    pub share_pos_j: Vec<Vec<usize>>,
+    pub err: bool,
+}
+
+impl JoinAudit {
+    pub fn new(pj: &PotentialJoin, rejection: Option<JoinRejection>) -> JoinAudit {
+        JoinAudit {
+            k1: pj.k1,
+            k2: pj.k2,
+            rejection,
+            nrefs: pj.nrefs,
+            cd: pj.cd,
+            diffs: pj.diffs,
+            score: pj.score,
+            p1: pj.p1,
+            mult: pj.mult,
+            shares: pj.shares.clone(),
+            indeps: pj.indeps.clone(),
+            share_pos_v: pj.share_pos_v.clone(),
+            share_pos_j: pj.share_pos_j.clone(),
+            err: pj.err,
+        }
+    }
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+// An exact subclonotype is named by the barcode and dataset of its first cell.  The index is
+// the index of the exact subclonotype in this run, which is not stable across runs.
+
+#[derive(Serialize)]
+struct AuditRecord<'a> {
+    barcode1: &'a str,
+    dataset1: &'a str,
+    exact1: usize,
+    ncells1: usize,
+    barcode2: &'a str,
+    dataset2: &'a str,
+    exact2: usize,
+    ncells2: usize,
+    accepted: bool,
+    rule: Option<&'static str>,
+    nrefs: usize,
+    cd: isize,
+    diffs: usize,
+    score: f64,
+    p1: f64,
+    mult: f64,
+    shares: &'a [isize],
+    indeps: &'a [isize],
+    share_pos_v: &'a [Vec<usize>],
+    share_pos_j: &'a [Vec<usize>],
+    err: bool,
+}
+
+const TSV_FIELDS: [&str; 21] = [
+    "barcode1",
+    "dataset1",
+    "exact1",
+    "ncells1",
+    "barcode2",
+    "dataset2",
+    "exact2",
+    "ncells2",
+    "accepted",
+    "rule",
+    "nrefs",
+    "cd",
+    "diffs",
+    "score",
+    "p1",
+    "mult",
+    "shares",
+    "indeps",
+    "share_pos_v",
+    "share_pos_j",
+    "err",
+];
+
+impl<'a> AuditRecord<'a> {
+    fn new(
+        ctl: &'a EncloneControl,
+        exact_clonotypes: &'a [ExactClonotype],
+        info: &[CloneInfo],
+        a: &'a JoinAudit,
+    ) -> AuditRecord<'a> {
+        let (u1, u2) = (info[a.k1].clonotype_index, info[a.k2].clonotype_index);
+        let (ex1, ex2) = (&exact_clonotypes[u1], &exact_clonotypes[u2]);
+        AuditRecord {
+            barcode1: &ex1.clones[0][0].barcode,
+            dataset1: &ctl.origin_info.dataset_id[ex1.clones[0][0].dataset_index],
+            exact1: u1,
+            ncells1: ex1.ncells(),
+            barcode2: &ex2.clones[0][0].barcode,
+            dataset2: &ctl.origin_info.dataset_id[ex2.clones[0][0].dataset_index],
+            exact2: u2,
+            ncells2: ex2.ncells(),
+            accepted: a.rejection.is_none(),
+            rule: a.rejection.as_ref().map(JoinRejection::label),
+            nrefs: a.nrefs,
+            cd: a.cd,
+            diffs: a.diffs,
+            score: a.score,
+            p1: a.p1,
+            mult: a.mult,
+            shares: &a.shares,
+            indeps: &a.indeps,
+            share_pos_v: &a.share_pos_v,
+            share_pos_j: &a.share_pos_j,
+            err: a.err,
+        }
+    }
+
+    // Lists are comma-separated, and lists of lists are semicolon-separated lists of
+    // comma-separated lists.
+
+    fn tsv_line(&self) -> String {
+        let nested = |x: &[Vec<usize>]| x.iter().map(|y| y.iter().format(",")).join(";");
+        [
+            self.barcode1.to_string(),
+            self.dataset1.to_string(),
+            self.exact1.to_string(),
+            self.ncells1.to_string(),
+            self.barcode2.to_string(),
+            self.dataset2.to_string(),
+            self.exact2.to_string(),
+            self.ncells2.to_string(),
+            self.accepted.to_string(),
+            self.rule.unwrap_or("").to_string(),
+            self.nrefs.to_string(),
+            self.cd.to_string(),
+            self.diffs.to_string(),
+            self.score.to_string(),
+            self.p1.to_string(),
+            self.mult.to_string(),
+            self.shares.iter().format(",").to_string(),
+            self.indeps.iter().format(",").to_string(),
+            nested(self.share_pos_v),
+            nested(self.share_pos_j),
+            self.err.to_string(),
+        ]
+        .join("\t")
+    }
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+// Write the audit records to JOIN_AUDIT, as JSON lines if the file name ends in .jsonl, and
+// otherwise as TSV.
+
+pub fn write_join_audit(
+    ctl: &EncloneControl,
+    exact_clonotypes: &[ExactClonotype],
+    info: &[CloneInfo],
+    audits: &[JoinAudit],
+) -> Result<(), String> {
+    let path = &ctl.gen_opt.join_audit;
+    let err = |e: std::io::Error| format!("\nUnable to write JOIN_AUDIT file {path}: {e}.\n");
+    let mut f = BufWriter::new(File::create(path).map_err(err)?);
+    let json = path.ends_with(".jsonl");
+    if !json {
+        writeln!(f, "{}", TSV_FIELDS.join("\t")).map_err(err)?;
+    }
+    for a in audits {
+        let r = AuditRecord::new(ctl, exact_clonotypes, info, a);
+        if json {
+            serde_json::to_writer(&mut f, &r)
+                .map_err(std::io::Error::from)
+                .map_err(err)?;
+            writeln!(f).map_err(err)?;
+        } else {
+            writeln!(f, "{}", r.tsv_line()).map_err(err)?;
+        }
+    }
+    f.flush().map_err(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::join_test_data::{test_ctl, JoinTestData, TestChain, TestExact, HEAVY_V, LIGHT_V};
+
+    fn exact(ins: &[u8], ncells: usize) -> TestExact {
+        TestExact {
+            heavy: TestChain {
+                v: HEAVY_V,
+                muts: vec![10, 20],
+                ins: ins.to_vec(),
+            },
+            light: TestChain {
+                v: LIGHT_V,
+                muts: vec![5],
+                ins: b"CAG".to_vec(),
+            },
+            ncells,
+        }
+    }
+
+    // An accepted join, and a join of two cells rejected by the higher bar of join_exacts.
+
+    fn audits(d: &JoinTestData) -> Vec<JoinAudit> {
+        let (k0, k1, k2) = (d.info_index(0), d.info_index(1), d.info_index(2));
+        let audit = |k1, k2, rejection| JoinAudit {
+            k1,
+            k2,
+            rejection,
+            nrefs: 1,
+            cd: 1,
+            diffs: 2,
+            score: 0.5,
+            p1: 0.25,
+            mult: 3.0,
+            shares: vec![2, 0],
+            indeps: vec![0, 1],
+            share_pos_v: vec![vec![10, 20], Vec::new()],
+            share_pos_j: vec![Vec::new(), vec![3]],
+            err: false,
+        };
+        vec![
+            audit(k0, k1, None),
+            audit(k1, k2, Some(JoinRejection::TwoCellBar)),
+        ]
+    }
+
+    fn test_data(path: &str) -> JoinTestData {
+        let exacts = [
+            exact(b"CGAGGGTATAGC", 2),
+            exact(b"CGAGGGTATAGA", 1),
+            exact(b"CGAGGGTATAGG", 1),
+        ];
+        let mut d = JoinTestData::new(test_ctl(), &exacts);
+        d.ctl.origin_info.dataset_id = vec!["d1".to_string()];
+        d.ctl.gen_opt.join_audit = path.to_string();
+        d
+    }
+
+    fn temp_path(ext: &str) -> String {
+        let path = std::env::temp_dir().join(format!("join_audit_{}.{ext}", std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_tsv() {
+        let path = temp_path("tsv");
+        let d = test_data(&path);
+        write_join_audit(&d.ctl, &d.exact_clonotypes, &d.info, &audits(&d)).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], TSV_FIELDS.join("\t"));
+        assert_eq!(
+            lines[1],
+            "0-0-1\td1\t0\t2\t1-0-1\td1\t1\t1\ttrue\t\t1\t1\t2\t0.5\t0.25\t3\t2,0\t0,1\t10,20;\t;3\t\
+             false"
+        );
+        let fields = lines[2].split('\t').collect::<Vec<_>>();
+        assert_eq!(fields.len(), TSV_FIELDS.len());
+        assert_eq!(fields[0], "1-0-1");
+        assert_eq!(fields[4], "2-0-1");
+        assert_eq!(fields[6], "2");
+        assert_eq!(fields[8], "false");
+        assert_eq!(fields[9], "TWO_CELL_BAR");
+    }
+
+    #[test]
+    fn test_jsonl() {
+        let path = temp_path("jsonl");
+        let d = test_data(&path);
+        write_join_audit(&d.ctl, &d.exact_clonotypes, &d.info, &audits(&d)).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let records = text
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["accepted"], true);
+        assert!(records[0]["rule"].is_null());
+        assert_eq!(records[0]["barcode1"], "0-0-1");
+        assert_eq!(records[0]["ncells1"], 2);
+        assert_eq!(records[0]["share_pos_v"], serde_json::json!([[10, 20], []]));
+        assert_eq!(records[1]["accepted"], false);
+        assert_eq!(records[1]["rule"], "TWO_CELL_BAR");
+        assert_eq!(records[1]["exact1"], 1);
+        assert_eq!(records[1]["exact2"], 2);
+        assert_eq!(records[1]["dataset2"], "d1");
+        assert_eq!(records[1]["score"], 0.5);
+        // The JSON records have the same fields as the TSV.
+
+        let mut keys = records[1]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        keys.sort();
+        let mut fields = TSV_FIELDS.to_vec();
+        fields.sort_unstable();
+        assert_eq!(keys, fields);
+    }
+
+    #[test]
+    fn test_unwritable() {
+        let d = test_data("/nonexistent/join_audit.tsv");
+        let e = write_join_audit(&d.ctl, &d.exact_clonotypes, &d.info, &audits(&d)).unwrap_err();
+        assert!(e.contains("Unable to write JOIN_AUDIT file /nonexistent/join_audit.tsv"));
+    }
+}
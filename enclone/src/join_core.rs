@@ -1,7 +1,9 @@
 // Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
 
+use crate::join_audit::JoinAudit;
+use crate::join_index::JoinIndex;
 use enclone_core::defs::{CloneInfo, EncloneControl, ExactClonotype, PotentialJoin};
-use enclone_core::join_one::join_one;
+use enclone_core::join_one::join_one_explained;
 use enclone_proto::types::DonorReferenceItem;
 use equiv::EquivRel;
 use qd::Double;
@@ -20,10 +22,15 @@ pub fn join_core<'a>(
     pot: &mut Vec<PotentialJoin<'a>>,
     refdata: &RefData,
     dref: &[DonorReferenceItem],
+    audit: &mut Vec<JoinAudit>,
 ) {
+    // Only examine pairs that could be joined.  These are examined in the same order as if all
+    // pairs were examined, so the result is the same.
+
+    let index = JoinIndex::new(is_bcr, ctl, info, (i..j).collect());
     let mut eq: EquivRel = EquivRel::new((j - i) as i32);
     for k1 in i..j {
-        for k2 in k1 + 1..j {
+        for k2 in index.candidates(k1) {
             // Do nothing if join could have no effect on equivalence relation.
             // For certain samples, this hugely reduces run time.  That is the purpose of
             // having the equivalence relation.  Observed on MALT samples including 83808.
@@ -33,21 +40,178 @@ pub fn join_core<'a>(
             if !ctl.force && (eq.class_id((k1 - i) as i32) == eq.class_id((k2 - i) as i32)) {
                 continue;
             }
-            if join_one(
-                is_bcr,
+
+            // Rejected pairs are only recorded if JOIN_AUDIT was specified.  Accepted pairs are
+            // recorded by the caller, once it has decided whether to keep them.
+
+            let mut pj = PotentialJoin {
                 k1,
                 k2,
+                ..Default::default()
+            };
+            match join_one_explained(
+                is_bcr,
+                &info[k1],
+                &info[k2],
                 ctl,
                 exact_clonotypes,
-                info,
                 to_bc,
                 sr,
-                pot,
                 refdata,
                 dref,
+                &mut pj,
             ) {
-                eq.join((k1 - i) as i32, (k2 - i) as i32);
+                Ok(()) => {
+                    pot.push(pj);
+                    eq.join((k1 - i) as i32, (k2 - i) as i32);
+                }
+                Err(rule) => {
+                    if !ctl.gen_opt.join_audit.is_empty() {
+                        audit.push(JoinAudit::new(&pj, Some(rule)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::join_test_data::{
+        mutate, test_ctl, JoinTestData, TestChain, TestExact, HEAVY_V, LIGHT_V,
+    };
+
+    // Make an exact subclonotype having one cell, with the given junction insertions, and
+    // whose heavy chain has V segment mutations shared with the others, and one more.
+
+    fn exact(heavy_ins: &[u8], light_ins: &[u8], m: usize) -> TestExact {
+        TestExact {
+            heavy: TestChain {
+                v: HEAVY_V,
+                muts: vec![10, 20, 30, 40, 50 + m],
+                ins: heavy_ins.to_vec(),
+            },
+            light: TestChain {
+                v: LIGHT_V,
+                muts: vec![5, 15],
+                ins: light_ins.to_vec(),
+            },
+            ncells: 1,
+        }
+    }
+
+    // Compute the equivalence relation on info entries by examining every pair, as join_core
+    // did before it used a JoinIndex.
+
+    fn join_all_pairs(d: &JoinTestData) -> EquivRel {
+        let n = d.info.len();
+        let mut eq = EquivRel::new(n as i32);
+        for k1 in 0..n {
+            for k2 in k1 + 1..n {
+                if eq.class_id(k1 as i32) == eq.class_id(k2 as i32) {
+                    continue;
+                }
+                let mut pj = PotentialJoin::default();
+                if join_one_explained(
+                    true,
+                    &d.info[k1],
+                    &d.info[k2],
+                    &d.ctl,
+                    &d.exact_clonotypes,
+                    &d.to_bc,
+                    &d.sr,
+                    &d.refdata,
+                    &[],
+                    &mut pj,
+                )
+                .is_ok()
+                {
+                    eq.join(k1 as i32, k2 as i32);
+                }
             }
         }
+        eq
+    }
+
+    #[test]
+    fn test_join_core_index() {
+        // Allow at most two CDR3 differences, so that the CDR3s are cut into three pieces.
+        // Exact subclonotypes 1-4 have the same CDR3s, 5 and 6 differ from them at one base,
+        // in different pieces, and 0 differs from all of them in every piece.
+
+        let mut ctl = test_ctl();
+        ctl.join_alg_opt.max_cdr3_diffs = 2;
+        let heavy = b"CGAGGGTATAGC".to_vec();
+        let light = b"CAG".to_vec();
+        let mut heavy0 = heavy.clone();
+        heavy0.iter_mut().for_each(|b| *b = mutate(*b));
+        let mut light0 = light.clone();
+        light0.iter_mut().for_each(|b| *b = mutate(*b));
+        let mut heavy5 = heavy.clone();
+        heavy5[0] = mutate(heavy5[0]);
+        let mut heavy6 = heavy.clone();
+        heavy6[11] = mutate(heavy6[11]);
+        let mut exacts = vec![exact(&heavy0, &light0, 0)];
+        for m in 1..=4 {
+            exacts.push(exact(&heavy, &light, m));
+        }
+        exacts.push(exact(&heavy5, &light, 5));
+        exacts.push(exact(&heavy6, &light, 6));
+        let mut d = JoinTestData::new(ctl, &exacts);
+
+        // Order the info entries as the exact subclonotypes, which all have the same contig
+        // lengths, so that they form a single block for join_core.
+
+        d.info.sort_by_key(|x| x.clonotype_id);
+        let n = d.info.len();
+        assert_eq!(n, 7);
+        assert!(d.info.iter().all(|x| x.lens == d.info[0].lens));
+
+        // Entry 0 shares no CDR3 piece with any other entry, so it has no candidates.  Entry 1
+        // shares all three pieces with each of entries 2-4, and so has more candidates than
+        // there are later entries, and falls back to all of them.
+
+        let index = JoinIndex::new(true, &d.ctl, &d.info, (0..n).collect());
+        assert!(index.candidates(0).is_empty());
+        assert_eq!(index.candidates(1), (2..n).collect::<Vec<_>>());
+
+        // The equivalence relation given by join_core is the same as that given by examining
+        // every pair.
+
+        let mut pot = Vec::<PotentialJoin>::new();
+        join_core(
+            true,
+            0,
+            n,
+            &d.ctl,
+            &d.exact_clonotypes,
+            &d.info,
+            &d.to_bc,
+            &d.sr,
+            &mut pot,
+            &d.refdata,
+            &[],
+            &mut Vec::new(),
+        );
+        let mut eq = EquivRel::new(n as i32);
+        for pj in &pot {
+            eq.join(pj.k1 as i32, pj.k2 as i32);
+        }
+        let all = join_all_pairs(&d);
+        for k1 in 0..n {
+            for k2 in k1 + 1..n {
+                let same = eq.class_id(k1 as i32) == eq.class_id(k2 as i32);
+                let all_same = all.class_id(k1 as i32) == all.class_id(k2 as i32);
+                assert_eq!(same, all_same, "entries {k1} and {k2}");
+            }
+        }
+
+        // Entries 1-6 are joined, and entry 0 is not.
+
+        for k in 2..n {
+            assert_eq!(eq.class_id(1), eq.class_id(k as i32));
+        }
+        assert_ne!(eq.class_id(0), eq.class_id(1));
     }
 }
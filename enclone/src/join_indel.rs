@@ -0,0 +1,283 @@
+// Copyright (c) 2023 10X Genomics, Inc. All rights reserved.
+
+// Joining across CDR3 indels.  join_core only compares info entries whose contigs have the
+// same lengths, so two cells whose CDR3s differ by an indel arising from somatic hypermutation
+// would never be placed in the same clonotype.  Here we compare pairs of info entries whose
+// CDR3s differ in length in one chain, by at most MAX_CDR3_INDEL bases, using join_one_indel.
+// This is only done for BCR, and only for the default join algorithm.
+
+use crate::join_audit::JoinAudit;
+use enclone_core::defs::{CloneInfo, EncloneControl, ExactClonotype, PotentialJoin};
+use enclone_core::join_one::{cdr3_indel_chain, join_one_indel, JoinRejection};
+use enclone_proto::types::DonorReferenceItem;
+use equiv::EquivRel;
+use qd::Double;
+use std::collections::HashMap;
+use vdj_ann::refx::RefData;
+
+// Determine if joining across CDR3 indels is turned on.
+
+pub fn joins_indels(is_bcr: bool, ctl: &EncloneControl) -> bool {
+    let jopt = &ctl.join_alg_opt;
+    is_bcr
+        && jopt.max_cdr3_indel > 0
+        && jopt.basic.is_none()
+        && jopt.basic_h.is_none()
+        && !jopt.basicx
+        && !jopt.join_full_diff
+        && !jopt.bcjoin
+}
+
+// Find the pairs (k1, k2) of info entries, with k1 < k2, that could be joined across a CDR3
+// indel, in increasing order.  The entries are given in blocks, as in join_exacts, each block
+// consisting of the entries having given contig lengths.  An indel changes the contig length
+// of one chain, so each block is paired with the blocks whose contig lengths differ from its
+// own in one chain by an allowed amount.  Within a pair of blocks, only entries having the
+// same V and J reference segments and the same CDR3 lengths outside the indel are paired.
+
+type IndelKey<'a> = (&'a [usize], &'a [usize], [usize; 2]);
+
+fn indel_key(x: &CloneInfo) -> IndelKey<'_> {
+    (&x.vsids, &x.jsids, [x.cdr3s[0].len(), x.cdr3s[1].len()])
+}
+
+pub fn indel_candidates(
+    ctl: &EncloneControl,
+    info: &[CloneInfo],
+    blocks: &[Vec<usize>],
+) -> Vec<(usize, usize)> {
+    let blocks = blocks
+        .iter()
+        .filter(|b| !b.is_empty() && info[b[0]].cdr3s.len() == 2)
+        .collect::<Vec<_>>();
+    let by_lens = blocks
+        .iter()
+        .enumerate()
+        .map(|(i, b)| (info[b[0]].lens.as_slice(), i))
+        .collect::<HashMap<_, _>>();
+    let mut keyed = vec![None; blocks.len()];
+    let mut pairs = Vec::<(usize, usize)>::new();
+    for (i1, b1) in blocks.iter().enumerate() {
+        for z in 0..2 {
+            for g in (3..=ctl.join_alg_opt.max_cdr3_indel).step_by(3) {
+                let mut lens = info[b1[0]].lens.clone();
+                lens[z] += g;
+                let Some(&i2) = by_lens.get(lens.as_slice()) else {
+                    continue;
+                };
+
+                // Index the entries in the block having the longer contig, then look up the
+                // entries in the other block.
+
+                let index = keyed[i2].get_or_insert_with(|| {
+                    let mut index = HashMap::<IndelKey, Vec<usize>>::new();
+                    for &k in blocks[i2].iter() {
+                        if info[k].cdr3s.len() == 2 {
+                            index.entry(indel_key(&info[k])).or_default().push(k);
+                        }
+                    }
+                    index
+                });
+                for &k1 in b1.iter() {
+                    if info[k1].cdr3s.len() != 2 {
+                        continue;
+                    }
+                    let mut key = indel_key(&info[k1]);
+                    key.2[z] += g;
+                    for &k2 in index.get(&key).into_iter().flatten() {
+                        if cdr3_indel_chain(ctl, &info[k1], &info[k2]).is_some() {
+                            pairs.push((k1.min(k2), k1.max(k2)));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    pairs.sort_unstable();
+    pairs.dedup();
+    pairs
+}
+
+// Join the given candidate pairs of info entries across CDR3 indels, where eq describes the
+// joins already made, and is updated.  Return the new joins, together with the number of them
+// that cross donors.  As for join_core, pairs that are already joined are not examined unless
+// FORCE is specified.  Joins of two single cells are held to the same higher bar as in
+// join_exacts.
+
+pub fn join_indels(
+    ctl: &EncloneControl,
+    exact_clonotypes: &[ExactClonotype],
+    info: &[CloneInfo],
+    to_bc: &HashMap<(usize, usize), Vec<String>>,
+    sr: &[Vec<Double>],
+    refdata: &RefData,
+    dref: &[DonorReferenceItem],
+    candidates: &[(usize, usize)],
+    eq: &mut EquivRel,
+    audit: &mut Vec<JoinAudit>,
+) -> (Vec<(usize, usize)>, usize) {
+    let (mut new_joins, mut errors) = (Vec::<(usize, usize)>::new(), 0);
+    for &(k1, k2) in candidates {
+        if !ctl.force && eq.class_id(k1 as i32) == eq.class_id(k2 as i32) {
+            continue;
+        }
+        let mut pj = PotentialJoin {
+            k1,
+            k2,
+            ..Default::default()
+        };
+        let mut result = join_one_indel(
+            k1,
+            k2,
+            ctl,
+            exact_clonotypes,
+            info,
+            to_bc,
+            sr,
+            refdata,
+            dref,
+            &mut pj,
+        );
+        if result.is_ok() && !ctl.join_alg_opt.easy {
+            let ncells1 = exact_clonotypes[info[k1].clonotype_index].ncells();
+            let ncells2 = exact_clonotypes[info[k2].clonotype_index].ncells();
+            let min_shares = pj.shares.iter().min().unwrap();
+            if ncells1 == 1 && ncells2 == 1 && pj.cd > *min_shares / 2 {
+                result = Err(JoinRejection::TwoCellBar);
+            }
+        }
+        if !ctl.gen_opt.join_audit.is_empty() {
+            audit.push(JoinAudit::new(&pj, result.err()));
+        }
+        if result.is_ok() {
+            eq.join(k1 as i32, k2 as i32);
+            new_joins.push((k1, k2));
+            if pj.err {
+                errors += 1;
+            }
+        }
+    }
+    (new_joins, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::join_test_data::{
+        mutate, test_ctl, JoinTestData, TestChain, TestExact, HEAVY_V, HEAVY_V_ALT, LIGHT_V,
+    };
+
+    // Make an exact subclonotype having one cell, whose heavy chain has the given V segment
+    // and junction insertion, and which shares its V segment mutations with the others.
+
+    fn exact(v: usize, ins: &[u8]) -> TestExact {
+        TestExact {
+            heavy: TestChain {
+                v,
+                muts: vec![10, 20, 30, 40],
+                ins: ins.to_vec(),
+            },
+            light: TestChain {
+                v: LIGHT_V,
+                muts: vec![5, 15],
+                ins: b"CAGCAGTAT".to_vec(),
+            },
+            ncells: 1,
+        }
+    }
+
+    #[test]
+    fn test_join_indels() {
+        // Exact subclonotype 1 has a three base deletion in the heavy chain CDR3 relative to 0,
+        // and 2 has in addition four substitutions next to it.  Exact subclonotype 3 is as 1,
+        // but has a different heavy chain V segment.
+
+        let long = b"CGAGGGTATAGCAGCAGCTGGTAC".to_vec();
+        let mut short = long.clone();
+        short.drain(9..12);
+        let mut subst = short.clone();
+        for b in subst.iter_mut().take(4) {
+            *b = mutate(*b);
+        }
+        let d = JoinTestData::new(
+            test_ctl(),
+            &[
+                exact(HEAVY_V, &long),
+                exact(HEAVY_V, &short),
+                exact(HEAVY_V, &subst),
+                exact(HEAVY_V_ALT, &short),
+            ],
+        );
+        let k = (0..4).map(|e| d.info_index(e)).collect::<Vec<_>>();
+        let pair = |k1: usize, k2: usize| (k1.min(k2), k1.max(k2));
+
+        // Only pairs having the same V and J segments are candidates.
+
+        let mut blocks = HashMap::<&[usize], Vec<usize>>::new();
+        for (i, x) in d.info.iter().enumerate() {
+            blocks.entry(x.lens.as_slice()).or_default().push(i);
+        }
+        let blocks = blocks.into_values().collect::<Vec<_>>();
+        let candidates = indel_candidates(&d.ctl, &d.info, &blocks);
+        let mut expected = vec![pair(k[0], k[1]), pair(k[0], k[2])];
+        expected.sort_unstable();
+        assert_eq!(candidates, expected);
+
+        // The indel counts as one CDR3 difference, and not as a mutation from the reference.
+
+        let mut pj = PotentialJoin::default();
+        let result = join_one_indel(
+            k[0],
+            k[1],
+            &d.ctl,
+            &d.exact_clonotypes,
+            &d.info,
+            &d.to_bc,
+            &d.sr,
+            &d.refdata,
+            &[],
+            &mut pj,
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(pj.cd, 1);
+        assert_eq!(pj.shares, vec![6]);
+        assert_eq!(pj.indeps, vec![0]);
+
+        // Too many CDR3 differences, relative to the mutations outside the CDR3s.
+
+        let mut pj = PotentialJoin::default();
+        let result = join_one_indel(
+            k[0],
+            k[2],
+            &d.ctl,
+            &d.exact_clonotypes,
+            &d.info,
+            &d.to_bc,
+            &d.sr,
+            &d.refdata,
+            &[],
+            &mut pj,
+        );
+        assert_eq!(result, Err(JoinRejection::Cdr3Mult));
+        assert_eq!(pj.cd, 5);
+
+        // Only the first pair is joined.
+
+        let mut eq = EquivRel::new(d.info.len() as i32);
+        let (joins, _) = join_indels(
+            &d.ctl,
+            &d.exact_clonotypes,
+            &d.info,
+            &d.to_bc,
+            &d.sr,
+            &d.refdata,
+            &[],
+            &candidates,
+            &mut eq,
+            &mut Vec::new(),
+        );
+        assert_eq!(joins, vec![pair(k[0], k[1])]);
+        assert_eq!(eq.class_id(k[0] as i32), eq.class_id(k[1] as i32));
+        assert_ne!(eq.class_id(k[0] as i32), eq.class_id(k[2] as i32));
+    }
+}
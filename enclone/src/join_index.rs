@@ -0,0 +1,155 @@
+// Copyright (c) 2023 10X Genomics, Inc. All rights reserved.
+
+// Candidate generation for join_core.  Within a set of info entries having the same contig
+// lengths, join_one can only succeed for two entries whose CDR3s have the same lengths and
+// differ at a bounded number of positions.  The bound depends on the join algorithm (see
+// max_cdr3_diffs), and if it is d, then by the pigeonhole principle, if the CDR3s are cut into
+// d + 1 pieces, the two entries agree on at least one piece.  So we index the entries by their
+// pieces, and propose only pairs that share a piece.
+//
+// The candidates for an entry include every later entry that it could join to, and are
+// returned in increasing order, so that join_core examines the same pairs in the same order
+// as if it had examined all pairs, and the resulting equivalence relation is identical.
+
+use enclone_core::defs::{CloneInfo, EncloneControl};
+use std::collections::HashMap;
+
+// Find the largest d in 0..=n such that fails(d) is false, assuming that fails is monotone.
+// Return None if fails(0).
+
+fn largest_passing(n: usize, fails: impl Fn(usize) -> bool) -> Option<usize> {
+    (0..=n).take_while(|&d| !fails(d)).last()
+}
+
+// Determine the number of leading chains whose CDR3s are compared by join_one, and given the
+// lengths of the CDR3s, the maximum number of nucleotide differences between them that could
+// allow a join, or usize::MAX if there is no bound.  Return None if no join is possible.  The
+// cases are those of join_one, in the same order.
+
+fn max_cdr3_diffs(is_bcr: bool, ctl: &EncloneControl, lens: &[usize]) -> Option<(usize, usize)> {
+    let jopt = &ctl.join_alg_opt;
+    let total = |chains: usize| lens.iter().take(chains).sum::<usize>();
+    if jopt.basic.is_some() || jopt.basic_h.is_some() {
+        let (chains, limit) = match jopt.basic {
+            Some(basic) => (2, (100.0 - basic) / 100.0),
+            None => (1, (100.0 - jopt.basic_h.unwrap()) / 100.0),
+        };
+        let mut d = 0;
+        for &len in lens.iter().take(chains) {
+            d += largest_passing(len, |cd| cd as f64 / (len as f64) > limit)?;
+        }
+        Some((chains, d))
+    } else if jopt.basicx {
+        let total = total(2);
+        Some((
+            2,
+            largest_passing(total, |cd| cd as f64 / total as f64 > 0.1)?,
+        ))
+    } else if jopt.join_full_diff {
+        Some((2, usize::MAX))
+    } else if is_bcr {
+        let total = total(2);
+        let ident = jopt.join_cdr3_ident;
+        let d = largest_passing(total, |cd| cd as f64 / total as f64 > 1.0 - ident / 100.0)?;
+        if jopt.max_cdr3_diffs < 1000 {
+            Some((2, d.min(jopt.max_cdr3_diffs)))
+        } else {
+            Some((2, d))
+        }
+    } else {
+        Some((lens.len(), 0))
+    }
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+pub struct JoinIndex {
+    entries: Vec<usize>,            // the indexed entries, in increasing order
+    buckets: Vec<Vec<usize>>,       // entries sharing a piece, in increasing order
+    entry_buckets: Vec<Vec<usize>>, // for each indexed entry, its buckets
+}
+
+impl JoinIndex {
+    // Index the given info entries, which must be in increasing order.
+
+    pub fn new(
+        is_bcr: bool,
+        ctl: &EncloneControl,
+        info: &[CloneInfo],
+        entries: Vec<usize>,
+    ) -> Self {
+        let mut groups = HashMap::<Vec<usize>, (usize, Option<(usize, usize)>)>::new();
+        let mut ids = HashMap::<(usize, usize, Vec<u8>), usize>::new();
+        let mut buckets = Vec::<Vec<usize>>::new();
+        let mut entry_buckets = vec![Vec::<usize>::new(); entries.len()];
+        for (pos, &k) in entries.iter().enumerate() {
+            let x = &info[k];
+
+            // Find the group of entries having the same CDR3 lengths, and the bound for it.
+
+            let lens = x.cdr3s.iter().map(String::len).collect::<Vec<_>>();
+            let ngroups = groups.len();
+            let &mut (g, bound) = groups
+                .entry(lens)
+                .or_insert_with_key(|lens| (ngroups, max_cdr3_diffs(is_bcr, ctl, lens)));
+            let Some((chains, d)) = bound else {
+                continue;
+            };
+
+            // Cut the compared CDR3s into d + 1 pieces, or if d is at least their length, use
+            // a single empty piece, so that the entry is proposed with its whole group.
+
+            let seq = x
+                .cdr3s
+                .iter()
+                .take(chains)
+                .flat_map(String::as_bytes)
+                .copied()
+                .collect::<Vec<u8>>();
+            let n = seq.len();
+            let keys = if d >= n {
+                vec![(g, 0, Vec::new())]
+            } else {
+                (0..=d)
+                    .map(|p| (g, p, seq[p * n / (d + 1)..(p + 1) * n / (d + 1)].to_vec()))
+                    .collect()
+            };
+            for key in keys {
+                let nbuckets = ids.len();
+                let b = *ids.entry(key).or_insert(nbuckets);
+                if b == buckets.len() {
+                    buckets.push(Vec::new());
+                }
+                buckets[b].push(k);
+                entry_buckets[pos].push(b);
+            }
+        }
+        JoinIndex {
+            entries,
+            buckets,
+            entry_buckets,
+        }
+    }
+
+    // Return the indexed entries after the indexed entry k1 that k1 might be joined to, in
+    // increasing order.  If there are many, as can happen for a very large clonotype, give up
+    // and return all of them, so that the work done here is never much more than that of
+    // examining every pair.
+
+    pub fn candidates(&self, k1: usize) -> Vec<usize> {
+        let pos = self.entries.binary_search(&k1).unwrap();
+        let max = self.entries.len() - pos - 1;
+        let mut cands = Vec::<usize>::new();
+        for &b in &self.entry_buckets[pos] {
+            let members = &self.buckets[b];
+            let start = members.partition_point(|&k| k <= k1);
+            cands.extend(&members[start..]);
+            if cands.len() > max {
+                return self.entries[pos + 1..].to_vec();
+            }
+        }
+        cands.sort_unstable();
+        cands.dedup();
+        cands
+    }
+}
@@ -0,0 +1,200 @@
+// Copyright (c) 2023 10X Genomics, Inc. All rights reserved.
+
+// Synthetic BCR data for testing the join code.  Each exact subclonotype has a heavy and a light
+// chain, whose V..J sequence is a reference V segment, possibly mutated, followed by a junction
+// insertion and a reference J segment.  The CDR3 consists of the last nine bases of the V
+// segment, the insertion, and the first twelve bases of the J segment.
+//
+// This is also used by the tests of enclone_stuff, via the test_data feature.
+
+use crate::info::build_info;
+use crate::misc2::create_exact_subclonotype_core;
+use debruijn::dna_string::DnaString;
+use enclone_core::barcode_fate::FateLedger;
+use enclone_core::defs::{
+    CloneInfo, ClonotypeHeuristics, EncloneControl, ExactClonotype, TigData, TigData0, TigData1,
+};
+use enclone_core::join_one::stirling2_ratio_table_double;
+use qd::Double;
+use std::collections::HashMap;
+use vdj_ann::refx::RefData;
+
+// Reference segments, as (name, length).  The heavy chain V segment is either of the first two,
+// and the light chain V segment is the fourth.
+
+const REFS: [(&str, usize); 5] = [
+    ("IGHV3-23", 90),
+    ("IGHV1-2", 90),
+    ("IGHJ4", 45),
+    ("IGKV1-39", 90),
+    ("IGKJ1", 36),
+];
+pub const HEAVY_V: usize = 0;
+pub const HEAVY_V_ALT: usize = 1;
+const HEAVY_J: usize = 2;
+pub const LIGHT_V: usize = 3;
+const LIGHT_J: usize = 4;
+
+// A chain of a synthetic exact subclonotype: the V segment, the positions on it that are mutated,
+// and the junction insertion.
+
+pub struct TestChain {
+    pub v: usize,
+    pub muts: Vec<usize>,
+    pub ins: Vec<u8>,
+}
+
+// A synthetic exact subclonotype, having the given number of cells.
+
+pub struct TestExact {
+    pub heavy: TestChain,
+    pub light: TestChain,
+    pub ncells: usize,
+}
+
+pub struct JoinTestData {
+    pub ctl: EncloneControl,
+    pub refdata: RefData,
+    pub exact_clonotypes: Vec<ExactClonotype>,
+    pub info: Vec<CloneInfo>,
+    pub to_bc: HashMap<(usize, usize), Vec<String>>,
+    pub sr: Vec<Vec<Double>>,
+}
+
+// Generate a fixed pseudorandom DNA sequence.
+
+fn bases(n: usize, seed: u64) -> Vec<u8> {
+    let mut x = seed;
+    (0..n)
+        .map(|_| {
+            x = x
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            b"ACGT"[(x >> 62) as usize]
+        })
+        .collect()
+}
+
+// Change a base.
+
+pub fn mutate(b: u8) -> u8 {
+    match b {
+        b'A' => b'C',
+        b'C' => b'G',
+        b'G' => b'T',
+        _ => b'A',
+    }
+}
+
+// Make the join options and heuristics as set by default in proc_args.
+
+pub fn test_ctl() -> EncloneControl {
+    let mut ctl = EncloneControl {
+        heur: ClonotypeHeuristics {
+            max_diffs: 1_000_000,
+            max_degradation: 2,
+            ref_v_trim: 15,
+            ref_j_trim: 15,
+        },
+        silent: true,
+        ..Default::default()
+    };
+    let jopt = &mut ctl.join_alg_opt;
+    jopt.max_score = 100_000.0;
+    jopt.max_cdr3_diffs = 1000;
+    jopt.max_cdr3_indel = 3;
+    jopt.cdr3_mult = 5.0;
+    jopt.mult_pow = 80.0;
+    jopt.join_cdr3_ident = 85.0;
+    jopt.fwr1_cdr12_delta = 20.0;
+    jopt.cdr3_normal_len = 42;
+    jopt.auto_share = 15;
+    jopt.comp_filt = 8;
+    jopt.comp_filt_bound = 80;
+    ctl
+}
+
+impl JoinTestData {
+    pub fn new(ctl: EncloneControl, exacts: &[TestExact]) -> Self {
+        let mut refdata = RefData::new();
+        let mut segs = Vec::<Vec<u8>>::new();
+        for (i, (name, len)) in REFS.iter().enumerate() {
+            let seg = bases(*len, i as u64 + 1);
+            refdata.refs.push(DnaString::from_acgt_bytes(&seg));
+            refdata.name.push(name.to_string());
+            segs.push(seg);
+        }
+        let mut exact_clonotypes = Vec::<ExactClonotype>::new();
+        for (e, x) in exacts.iter().enumerate() {
+            let mut tig_bc = Vec::<Vec<TigData>>::new();
+            for c in 0..x.ncells {
+                let mut tigs = Vec::<TigData>::new();
+                for (chain, j, left) in [(&x.heavy, HEAVY_J, true), (&x.light, LIGHT_J, false)] {
+                    let mut seq = segs[chain.v].clone();
+                    for &p in &chain.muts {
+                        seq[p] = mutate(seq[p]);
+                    }
+                    let cdr3_start = seq.len() - 9;
+                    seq.extend(&chain.ins);
+                    seq.extend(&segs[j]);
+                    let cdr3_stop = seq.len() - segs[j].len() + 12;
+                    tigs.push(TigData {
+                        cdr3_dna: String::from_utf8(seq[cdr3_start..cdr3_stop].to_vec()).unwrap(),
+                        len: seq.len(),
+                        j_stop: seq.len(),
+                        v_ref_id: chain.v,
+                        j_ref_id: j,
+                        cdr3_start,
+                        quals: vec![40; seq.len()],
+                        full_quals: vec![40; seq.len()],
+                        full_seq: seq,
+                        barcode: format!("{e}-{c}-1"),
+                        left,
+                        umi_count: 10,
+                        chain_type: if left { "IGH" } else { "IGK" }.to_string(),
+                        ..Default::default()
+                    });
+                }
+                tig_bc.push(tigs);
+            }
+            let mut share = Vec::<TigData1>::new();
+            let mut clones = Vec::<Vec<TigData0>>::new();
+            let to_delete = vec![false; x.ncells];
+            create_exact_subclonotype_core(
+                &tig_bc,
+                0,
+                x.ncells,
+                &to_delete,
+                &mut share,
+                &mut clones,
+            );
+            exact_clonotypes.push(ExactClonotype { share, clones });
+        }
+        let mut fate = vec![FateLedger::default()];
+        let info = build_info(&refdata, &ctl, &mut exact_clonotypes, &mut fate);
+        let mut to_bc = HashMap::<(usize, usize), Vec<String>>::new();
+        for (i, ex) in exact_clonotypes.iter().enumerate() {
+            for clone in &ex.clones {
+                let x = &clone[0];
+                to_bc
+                    .entry((x.dataset_index, i))
+                    .or_default()
+                    .push(x.barcode.clone());
+            }
+        }
+        JoinTestData {
+            ctl,
+            refdata,
+            exact_clonotypes,
+            info,
+            to_bc,
+            sr: stirling2_ratio_table_double(200),
+        }
+    }
+
+    // Find the info entry for the given exact subclonotype.
+
+    pub fn info_index(&self, e: usize) -> usize {
+        self.info.iter().position(|x| x.clonotype_id == e).unwrap()
+    }
+}
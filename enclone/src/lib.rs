@@ -1,12 +1,18 @@
 // Copyright (c) 2021 10x Genomics, Inc. All rights reserved.
 
 pub mod allele;
+pub mod genotype;
 pub mod graph_filter;
 pub mod info;
 pub mod innate;
 pub mod join;
 pub mod join2;
+pub mod join_audit;
 pub mod join_core;
+pub mod join_indel;
+pub mod join_index;
+#[cfg(any(test, feature = "test_data"))]
+pub mod join_test_data;
 pub mod misc1;
 pub mod misc2;
 pub mod misc3;
@@ -3,7 +3,7 @@
 // Miscellaneous functions.
 
 use enclone_core::{
-    barcode_fate::BarcodeFate,
+    barcode_fate::{BarcodeFate, FateLedger},
     defs::{CloneInfo, EncloneControl, ExactClonotype, TigData},
 };
 use equiv::EquivRel;
@@ -11,7 +11,6 @@ use itertools::Itertools;
 #[cfg(not(target_os = "windows"))]
 use pager::Pager;
 use perf_stats::elapsed;
-use std::collections::HashMap;
 use std::time::Instant;
 use string_utils::stringme;
 use vector_utils::{
@@ -195,11 +194,7 @@ pub fn lookup_heavy_chain_reuse(
 // original cells that were drawn (perhaps breaking up in the process of drawing), and was
 // subsequently distintegrated.
 
-pub fn cross_filter(
-    ctl: &EncloneControl,
-    tig_bc: &mut Vec<Vec<TigData>>,
-    fate: &mut [HashMap<String, BarcodeFate>],
-) {
+pub fn cross_filter(ctl: &EncloneControl, tig_bc: &mut Vec<Vec<TigData>>, fate: &mut [FateLedger]) {
     // Get the list of dataset origins.  Here we allow the same origin name to have been used
     // for more than one donor, as we haven't explicitly prohibited that.
 
@@ -287,10 +282,15 @@ pub fn cross_filter(
     for (i, tigi) in tig_bc.iter().enumerate() {
         for tig in tigi {
             if tig.umi_count < UMIS_SAVE && bin_member(&blacklist, &tig.seq()) {
-                fate[tigi[0].dataset_index].insert(tigi[0].barcode.clone(), BarcodeFate::Cross);
-                if !ctl.clono_filt_opt_def.ncross {
-                    to_delete[i] = true;
-                }
+                let removed = ctl
+                    .clono_filt_opt_def
+                    .delete(!ctl.clono_filt_opt_def.ncross);
+                fate[tigi[0].dataset_index].insert(
+                    tigi[0].barcode.clone(),
+                    BarcodeFate::Cross,
+                    removed,
+                );
+                to_delete[i] = removed;
                 break;
             }
         }
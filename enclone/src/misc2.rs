@@ -6,12 +6,11 @@ use crate::innate::mark_innate;
 use crate::misc3::study_consensus;
 use amino::aa_seq;
 use debruijn::dna_string::DnaString;
-use enclone_core::barcode_fate::BarcodeFate;
+use enclone_core::barcode_fate::{BarcodeFate, FateLedger};
 use enclone_core::defs::{EncloneControl, ExactClonotype, Junction, TigData, TigData0, TigData1};
 use io_utils::{fwriteln, open_for_write_new};
 use rayon::prelude::*;
 use std::cmp::{max, min};
-use std::collections::HashMap;
 
 use std::fmt::Write as _;
 use std::io::Write;
@@ -32,7 +31,7 @@ use vector_utils::{
 pub fn filter_gelbead_contamination(
     ctl: &EncloneControl,
     clones: &mut Vec<Vec<TigData0>>,
-    fate: &mut Vec<(usize, String, BarcodeFate)>,
+    fate: &mut Vec<(usize, String, BarcodeFate, bool)>,
 ) {
     const GB_UMI_MULT: usize = 10;
     const GB_MIN_FRAC: f64 = 0.2;
@@ -73,16 +72,18 @@ pub fn filter_gelbead_contamination(
             m = n;
         }
     }
+    let removed = ctl.clono_filt_opt_def.delete(!ctl.gen_opt.nwhitef);
     for (&b, clone) in bad.iter().zip(clones.iter()) {
         if b {
             fate.push((
                 clone[0].dataset_index,
                 clone[0].barcode.clone(),
                 BarcodeFate::GelBeadContamination,
+                removed,
             ));
         }
     }
-    if !ctl.gen_opt.nwhitef {
+    if removed {
         erase_if(clones, &bad);
     }
 }
@@ -207,6 +208,9 @@ pub fn create_exact_subclonotype_core(
             v_ref_id_donor_donor: None,
             d_ref_id: tig_bc[r][m].d_ref_id,
             j_ref_id: tig_bc[r][m].j_ref_id,
+            j_ref_id_donor: None,
+            j_ref_id_donor_alt_id: None,
+            j_ref_id_donor_donor: None,
             c_ref_id: tig_bc[r][m].c_ref_id,
             fr1_start: tig_bc[r][m].fr1_start,
             fr2_start: tig_bc[r][m].fr2_start,
@@ -273,7 +277,7 @@ pub fn find_exact_subclonotypes(
     ctl: &EncloneControl,
     tig_bc: &[Vec<TigData>],
     refdata: &RefData,
-    fate: &mut [HashMap<String, BarcodeFate>],
+    fate: &mut [FateLedger],
 ) -> Vec<ExactClonotype> {
     let mut exact_clonotypes = Vec::<ExactClonotype>::new();
     let mut r = 0;
@@ -326,7 +330,7 @@ pub fn find_exact_subclonotypes(
     let mut results = Vec::<(
         usize,
         Vec<ExactClonotype>,
-        Vec<(usize, String, BarcodeFate)>,
+        Vec<(usize, String, BarcodeFate, bool)>,
     )>::new();
     for i in 0..groups.len() {
         results.push((i, Vec::new(), Vec::new()));
@@ -379,13 +383,13 @@ pub fn find_exact_subclonotypes(
             if j - i >= 2 {
                 for bck in &bc[i..j] {
                     let t = bck.1;
-                    if ctl.clono_filt_opt_def.bc_dup {
-                        to_delete[t - r] = true;
-                    }
+                    let removed = ctl.clono_filt_opt_def.delete(ctl.clono_filt_opt_def.bc_dup);
+                    to_delete[t - r] = removed;
                     res.2.push((
                         tig_bc[t][0].dataset_index,
                         tig_bc[t][0].barcode.clone(),
                         BarcodeFate::DuplicatedBarcode,
+                        removed,
                     ));
                 }
             }
@@ -432,7 +436,8 @@ pub fn find_exact_subclonotypes(
             exact_clonotypes.append(&mut results[i].1);
         }
         for j in 0..results[i].2.len() {
-            fate[results[i].2[j].0].insert(results[i].2[j].1.clone(), results[i].2[j].2.clone());
+            let x = &results[i].2[j];
+            fate[x.0].insert(x.1.clone(), x.2, x.3);
         }
     }
     if ctl.gen_opt.utr_con || ctl.gen_opt.con_con {
@@ -154,6 +154,7 @@ pub fn proc_args(ctl: &mut EncloneControl, args: &[String]) -> Result<(), String
     ctl.join_alg_opt.merge_onesies = true; // should just kill this as an option
     ctl.join_alg_opt.merge_onesies_ctl = true;
     ctl.join_alg_opt.max_cdr3_diffs = 1000;
+    ctl.join_alg_opt.max_cdr3_indel = 3;
     ctl.join_alg_opt.cdr3_mult = 5.0;
     ctl.join_alg_opt.mult_pow = 80.0;
     ctl.join_alg_opt.join_cdr3_ident = 85.0;
@@ -386,6 +387,7 @@ pub fn proc_args(ctl: &mut EncloneControl, args: &[String]) -> Result<(), String
             &mut ctl.gen_opt.align_jun_align_consistency,
         ),
         ("ALLOW_INCONSISTENT", &mut ctl.gen_opt.allow_inconsistent),
+        ("ALT_J_ALLELES", &mut ctl.allele_alg_opt.j_alleles),
         ("ANN", &mut ctl.join_print_opt.ann),
         ("ANN0", &mut ctl.join_print_opt.ann0),
         ("BARCODES", &mut ctl.clono_print_opt.barcodes),
@@ -405,6 +407,7 @@ pub fn proc_args(ctl: &mut EncloneControl, args: &[String]) -> Result<(), String
         ("DEBUG_TABLE_PRINTING", &mut ctl.debug_table_printing),
         ("DEL", &mut ctl.clono_filt_opt.del),
         ("DESCRIP", &mut ctl.gen_opt.descrip),
+        ("DONOR_GENOTYPE_ONLY", &mut ctl.gen_opt.donor_genotype_only),
         ("D_INCONSISTENT", &mut ctl.clono_filt_opt.d_inconsistent),
         ("D_NONE", &mut ctl.clono_filt_opt.d_none),
         ("D_SECOND", &mut ctl.clono_filt_opt.d_second),
@@ -441,7 +444,6 @@ pub fn proc_args(ctl: &mut EncloneControl, args: &[String]) -> Result<(), String
         ("MOUSE", &mut ctl.gen_opt.mouse),
         ("NCELL", &mut ctl.gen_opt.ncell),
         ("NCROSS", &mut ctl.clono_filt_opt_def.ncross),
-        ("NEWICK", &mut ctl.gen_opt.newick),
         ("NGEX", &mut ctl.clono_filt_opt_def.ngex),
         ("NOGRAY", &mut ctl.nogray),
         ("NGRAPH_FILTER", &mut ctl.gen_opt.ngraph_filter),
@@ -468,6 +470,7 @@ pub fn proc_args(ctl: &mut EncloneControl, args: &[String]) -> Result<(), String
         ("PRE_EVAL", &mut ctl.gen_opt.pre_eval),
         ("PRE_EVAL_SHOW", &mut ctl.gen_opt.pre_eval_show),
         ("PROTECT_BADS", &mut ctl.clono_filt_opt.protect_bads),
+        ("PROTO_STREAM", &mut ctl.gen_opt.proto_stream),
         ("QUAD_HIVE", &mut ctl.plot_opt.plot_quad),
         ("RE", &mut ctl.gen_opt.reannotate),
         ("REPROD", &mut ctl.gen_opt.reprod),
@@ -538,6 +541,7 @@ pub fn proc_args(ctl: &mut EncloneControl, args: &[String]) -> Result<(), String
         ("JUN_SHARE", &mut ctl.join_alg_opt.comp_filt),
         ("JUN_SHARE_BOUND", &mut ctl.join_alg_opt.comp_filt_bound),
         ("MAX_CDR3_DIFFS", &mut ctl.join_alg_opt.max_cdr3_diffs),
+        ("MAX_CDR3_INDEL", &mut ctl.join_alg_opt.max_cdr3_indel),
         ("MAX_DATASETS", &mut ctl.clono_filt_opt.max_datasets),
         ("MAX_DEGRADATION", &mut ctl.heur.max_degradation),
         ("MAX_DIFFS", &mut ctl.heur.max_diffs),
@@ -594,6 +598,7 @@ pub fn proc_args(ctl: &mut EncloneControl, args: &[String]) -> Result<(), String
             "AG_DIST_FORMULA",
             &mut ctl.clono_group_opt.asymmetric_dist_formula,
         ),
+        ("CHECKPOINT", &mut ctl.gen_opt.checkpoint),
         ("CLUSTAL_AA", &mut ctl.gen_opt.clustal_aa),
         ("CLUSTAL_DNA", &mut ctl.gen_opt.clustal_dna),
         ("CONFIG", &mut ctl.gen_opt.config_file),
@@ -607,17 +612,32 @@ pub fn proc_args(ctl: &mut EncloneControl, args: &[String]) -> Result<(), String
     // Define arguments that set something to a string that is an output file name.
 
     let set_string_writeable = [
+        ("AIRR_CELL", &mut ctl.gen_opt.airr_cell),
+        ("AIRR_REARRANGEMENT", &mut ctl.gen_opt.airr_rearrangement),
+        ("BENCHMARK", &mut ctl.gen_opt.benchmark),
         ("BINARY", &mut ctl.gen_opt.binary),
+        (
+            "DONOR_GENOTYPE_REPORT",
+            &mut ctl.gen_opt.donor_genotype_report,
+        ),
         ("DONOR_REF_FILE", &mut ctl.gen_opt.dref_file),
         ("FATE_FILE", &mut ctl.gen_opt.fate_file),
+        ("FATE_LEDGER", &mut ctl.gen_opt.fate_ledger),
+        ("FATE_SUMMARY", &mut ctl.gen_opt.fate_summary),
+        ("FILTER_IMPACT", &mut ctl.gen_opt.filter_impact),
+        ("GROUP_TABLE", &mut ctl.gen_opt.group_table),
         ("HONEY_OUT", &mut ctl.plot_opt.honey_out),
+        ("INCREMENTAL_REPORT", &mut ctl.gen_opt.incremental_report),
+        ("JOIN_AUDIT", &mut ctl.gen_opt.join_audit),
         ("PROTO", &mut ctl.gen_opt.proto),
+        ("PROTO_INDEX", &mut ctl.gen_opt.proto_index),
         ("SUBSET_JSON", &mut ctl.gen_opt.subset_json),
     ];
 
     // Define arguments that set something to a string that is an output file name or stdout.
 
     let set_string_writeable_or_stdout = [
+        ("NEWICK", &mut ctl.gen_opt.newick),
         ("PEER_GROUP", &mut ctl.gen_opt.peer_group_filename),
         ("PHYLIP_AA", &mut ctl.gen_opt.phylip_aa),
         ("PHYLIP_DNA", &mut ctl.gen_opt.phylip_dna),
@@ -640,7 +660,10 @@ pub fn proc_args(ctl: &mut EncloneControl, args: &[String]) -> Result<(), String
 
     let set_string_readable_plain = [
         ("BC_JOINT", &mut ctl.gen_opt.bc_joint),
+        ("BENCHMARK_TRUTH", &mut ctl.gen_opt.benchmark_truth),
+        ("DONOR_GENOTYPE", &mut ctl.gen_opt.donor_genotype),
         ("EXTERNAL_REF", &mut ctl.gen_opt.external_ref),
+        ("INCREMENTAL", &mut ctl.gen_opt.incremental),
         ("POST_FILTER", &mut ctl.gen_opt.post_filter),
         ("REF", &mut ctl.gen_opt.refname),
     ];
@@ -713,6 +736,12 @@ pub fn proc_args(ctl: &mut EncloneControl, args: &[String]) -> Result<(), String
             arg = "NIMPROPER".to_string();
         }
 
+        // NEWICK without a value prints the trees, as it did before it could take a file name.
+
+        if arg == *"NEWICK" {
+            arg = "NEWICK=stdout".to_string();
+        }
+
         // Strip out certain quoted expressions.
 
         if arg.contains("=\"") && arg.ends_with('\"') {
@@ -247,7 +247,14 @@ pub fn proc_args_post(
         &mut ctl.plot_opt.plot_file,
         &mut ctl.gen_opt.fasta_filename,
         &mut ctl.gen_opt.fasta_aa_filename,
+        &mut ctl.gen_opt.clustal_aa,
+        &mut ctl.gen_opt.clustal_dna,
+        &mut ctl.gen_opt.phylip_aa,
+        &mut ctl.gen_opt.phylip_dna,
         &mut ctl.gen_opt.dref_file,
+        &mut ctl.gen_opt.donor_genotype_report,
+        &mut ctl.gen_opt.airr_rearrangement,
+        &mut ctl.gen_opt.airr_cell,
         &mut ctl.parseable_opt.pout,
     ];
     for f in files.iter_mut() {
@@ -404,6 +411,14 @@ pub fn proc_args_post(
         }
     }
 
+    if !ctl.clono_group_opt.style.is_empty() && ctl.gen_opt.proto_stream {
+        return Err(
+            "\nGrouping options cannot be used with PROTO_STREAM, because clonotypes are\n\
+            written before they are grouped.\n"
+                .to_string(),
+        );
+    }
+
     // Sanity check other arguments (and more below).
 
     if !ctl.parseable_opt.pcols_show.is_empty()
@@ -485,6 +500,31 @@ pub fn proc_args_post(
             "\nIf the value of PHYLIP_DNA is not stdout, it must end in .tar.\n".to_string(),
         );
     }
+    if !ctl.gen_opt.filter_impact.is_empty() {
+        ctl.clono_filt_opt_def.mark_only = true;
+    }
+    if ctl.gen_opt.donor_genotype.is_empty()
+        && (ctl.gen_opt.donor_genotype_only || !ctl.gen_opt.donor_genotype_report.is_empty())
+    {
+        return Err(
+            "\nDONOR_GENOTYPE_ONLY and DONOR_GENOTYPE_REPORT may only be used with \
+            DONOR_GENOTYPE.\n"
+                .to_string(),
+        );
+    }
+    if !ctl.gen_opt.donor_genotype_report.is_empty()
+        && (ctl.gen_opt.donor_genotype_only || ctl.gen_opt.no_alt_alleles)
+    {
+        return Err(
+            "\nDONOR_GENOTYPE_REPORT compares inferred alleles to the donor genotype, so it \
+            cannot be used with\nDONOR_GENOTYPE_ONLY or NO_ALT_ALLELES, which turn off \
+            allele inference.\n"
+                .to_string(),
+        );
+    }
+    if !ctl.gen_opt.benchmark_truth.is_empty() && ctl.gen_opt.benchmark.is_empty() {
+        return Err("\nBENCHMARK_TRUTH may only be used with BENCHMARK.\n".to_string());
+    }
     if ctl.clono_filt_opt_def.umi_filt && ctl.clono_filt_opt_def.umi_filt_mark {
         return Err(
             "\nIf you use UMI_FILT_MARK, you should also use NUMI, to turn off \
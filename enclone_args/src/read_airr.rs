@@ -0,0 +1,576 @@
+// Copyright (c) 2023 10X Genomics, Inc. All rights reserved.
+
+// Read contigs from an AIRR Rearrangement TSV file (see https://docs.airr-community.org), for
+// data that were not assembled by Cell Ranger, e.g. from plate-based sequencing or from another
+// assembler.  Each line is converted to an entry in the all_contig_annotations.json schema
+// described in read_json.rs, and the entries are then parsed exactly as if they came from
+// Cell Ranger.
+//
+// Fields that are used:
+// • sequence_id
+// • sequence
+// • productive
+// • cell_id -- each distinct value is treated as a cell called by the VDJ pipeline
+// • rev_comp -- optional
+// • umi_count -- optional, else duplicate_count, else 1
+// • consensus_count -- optional, else duplicate_count, else the UMI count
+// • v_call, d_call, j_call, c_call -- optional
+// • v_sequence_start, v_sequence_end, v_germline_start, v_germline_end and likewise for d, j
+//   and c -- optional, except that the germline coordinates for c may be omitted
+// • junction and junction_aa -- optional.
+//
+// The annotations in the file are used if the V and J calls name genes in the reference that
+// enclone is using, if the coordinates span the full V and J segments without indels, and if
+// the aligned parts of the sequence nearly agree with those segments.  Otherwise, and in
+// reannotate mode, the sequence is annotated by vdj_ann.  There are no quality scores in the
+// format, so every base is assigned the same quality score.  Sequences that have a character
+// other than A, C, G or T are not used.  Malformed lines, e.g. having a count or coordinate that
+// is not an integer, are reported with their line number, and errors found when the entries are
+// parsed, with the byte offset of their line.
+
+use crate::read_json::parse_json_entries;
+use enclone_core::defs::{EncloneControl, OriginInfo, TigData};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use vdj_ann::refx::RefData;
+
+// The name of the file that is looked for in a dataset directory.
+
+pub const AIRR_REARRANGEMENT: &str = "airr_rearrangement.tsv";
+
+// The quality score assigned to every base.
+
+const AIRR_QUAL: u8 = 40;
+
+// An alignment of part of a contig to a reference segment, zero-based and half-open.
+
+struct AirrAlign {
+    ref_id: usize,
+    contig_start: usize,
+    contig_stop: usize,
+    ref_start: usize,
+    ref_stop: usize,
+}
+
+fn airr_bool(x: &str) -> bool {
+    matches!(x, "T" | "TRUE" | "True" | "true")
+}
+
+// Find the alignment of a contig to the reference segment named by an AIRR call, from one-based
+// inclusive coordinates.  If the call lists several genes, the first is used, and if several
+// reference segments have that name, the one having the fewest mismatches is used.  Return None
+// if the alignment is not usable.
+
+fn airr_align(
+    refdata: &RefData,
+    call: &str,
+    is_type: impl Fn(usize) -> bool,
+    seq: &[u8],
+    coords: [Option<usize>; 4],
+) -> Option<AirrAlign> {
+    let [Some(start), Some(stop), Some(ref_start), Some(ref_stop)] = coords else {
+        return None;
+    };
+    if start == 0 || ref_start == 0 || stop < start || stop > seq.len() {
+        return None;
+    }
+    if ref_stop < ref_start || stop - start != ref_stop - ref_start {
+        return None;
+    }
+    let gene = call.split(',').next()?.split('*').next()?.trim();
+    let (start, ref_start, len) = (start - 1, ref_start - 1, stop - start + 1);
+    let (mismatches, ref_id) = (0..refdata.refs.len())
+        .filter(|&i| refdata.name[i] == gene && is_type(i))
+        .filter(|&i| refdata.refs[i].len() >= ref_stop)
+        .map(|i| {
+            let r = refdata.refs[i].to_ascii_vec();
+            let m = (0..len)
+                .filter(|&k| seq[start + k] != r[ref_start + k])
+                .count();
+            (m, i)
+        })
+        .min()?;
+    if 10 * mismatches > len {
+        return None;
+    }
+    Some(AirrAlign {
+        ref_id,
+        contig_start: start,
+        contig_stop: start + len,
+        ref_start,
+        ref_stop,
+    })
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+pub fn read_airr(
+    origin_info: &OriginInfo,
+    li: usize,
+    path: &str,
+    refdata: &RefData,
+    to_ref_index: &HashMap<usize, usize>,
+    ctl: &EncloneControl,
+    vdj_cells: &mut Vec<String>,
+    gex_cells: &mut Vec<String>,
+    gex_cells_specified: &mut bool,
+) -> Result<Vec<Vec<TigData>>, String> {
+    let f = File::open(path).map_err(|e| format!("\nCould not open {path}: {e}.\n"))?;
+    let entries = airr_entries(BufReader::new(f), path, refdata, ctl.gen_opt.reannotate)?;
+    let mut cr_version = String::new();
+    parse_json_entries(
+        ctl.gen_opt.accept_inconsistent,
+        origin_info,
+        li,
+        path,
+        &entries,
+        refdata,
+        to_ref_index,
+        &mut cr_version,
+        ctl,
+        vdj_cells,
+        gex_cells,
+        gex_cells_specified,
+    )
+}
+
+// Convert the lines of an AIRR Rearrangement file to entries in the JSON schema, as
+// {(byte offset of the line, entry, whether it has to be reannotated)}, sorted by barcode.
+
+fn airr_entries(
+    mut f: impl BufRead,
+    path: &str,
+    refdata: &RefData,
+    reannotate_all: bool,
+) -> Result<Vec<(usize, Vec<u8>, bool)>, String> {
+    // Read the lines, as {(line number, byte offset, line)}.
+
+    let mut lines = Vec::<(usize, usize, String)>::new();
+    let (mut line, mut offset) = (String::new(), 0);
+    for number in 1.. {
+        line.clear();
+        let n = f
+            .read_line(&mut line)
+            .map_err(|e| format!("\nFailed to read {path}: {e}.\n"))?;
+        if n == 0 {
+            break;
+        }
+        if !line.trim().is_empty() {
+            lines.push((
+                number,
+                offset,
+                line.trim_end_matches(['\r', '\n']).to_string(),
+            ));
+        }
+        offset += n;
+    }
+    let Some((_, _, header)) = lines.first() else {
+        return Err(format!("\nThe AIRR Rearrangement file {path} is empty.\n"));
+    };
+    let cols = header
+        .split('\t')
+        .enumerate()
+        .map(|(i, x)| (x.trim().to_string(), i))
+        .collect::<HashMap<String, usize>>();
+    for field in ["sequence_id", "sequence", "productive", "cell_id"] {
+        if !cols.contains_key(field) {
+            return Err(format!(
+                "\nThe AIRR Rearrangement file {path} does not have the field {field}.\n"
+            ));
+        }
+    }
+
+    // Convert each line into a JSON entry, recording whether it has to be reannotated.
+
+    let mut entries = Vec::<(String, usize, Vec<u8>, bool)>::new();
+    let ncols = header.split('\t').count();
+    for (number, offset, line) in lines.iter().skip(1) {
+        let err = |msg: String| {
+            format!("\nIn the AIRR Rearrangement file {path}, line {number} {msg}.\n")
+        };
+        let fields = line.split('\t').collect::<Vec<&str>>();
+        if fields.len() > ncols {
+            return Err(err(format!(
+                "has {} fields, but the header has {ncols}",
+                fields.len()
+            )));
+        }
+        let get = |name: &str| {
+            cols.get(name)
+                .and_then(|&j| fields.get(j))
+                .map_or("", |x| x.trim())
+        };
+
+        // Get a field that is a count or a coordinate, which may be empty.
+
+        let get_usize = |name: &str| match get(name) {
+            "" => Ok(None),
+            x => x.parse::<usize>().map(Some).map_err(|_| {
+                err(format!(
+                    "has {name} = {x}, which is not a nonnegative integer"
+                ))
+            }),
+        };
+        let cell_id = get("cell_id");
+        if cell_id.is_empty() {
+            return Err(err("has no cell_id".to_string()));
+        }
+        let mut seq = get("sequence").to_ascii_uppercase().into_bytes();
+        if seq.is_empty() || seq.iter().any(|c| !b"ACGT".contains(c)) {
+            continue;
+        }
+        if airr_bool(get("rev_comp")) {
+            seq = seq
+                .iter()
+                .rev()
+                .map(|c| match c {
+                    b'A' => b'T',
+                    b'C' => b'G',
+                    b'G' => b'C',
+                    _ => b'A',
+                })
+                .collect();
+        }
+        let coords = |seg: &str| -> Result<[Option<usize>; 4], String> {
+            Ok([
+                get_usize(&format!("{seg}_sequence_start"))?,
+                get_usize(&format!("{seg}_sequence_end"))?,
+                get_usize(&format!("{seg}_germline_start"))?,
+                get_usize(&format!("{seg}_germline_end"))?,
+            ])
+        };
+        let duplicate_count = get_usize("duplicate_count")?;
+        let umi_count = get_usize("umi_count")?.or(duplicate_count).unwrap_or(1);
+        let read_count = get_usize("consensus_count")?
+            .or(duplicate_count)
+            .unwrap_or(umi_count);
+        let (v_coords, d_coords, j_coords, mut c_coords) =
+            (coords("v")?, coords("d")?, coords("j")?, coords("c")?);
+        let mut entry = json!({
+            "barcode": cell_id,
+            "is_cell": true,
+            "productive": airr_bool(get("productive")),
+            "high_confidence": true,
+            "contig_name": get("sequence_id"),
+            "sequence": String::from_utf8_lossy(&seq),
+            "quals": String::from_utf8(vec![33 + AIRR_QUAL; seq.len()]).unwrap(),
+            "umi_count": umi_count,
+            "read_count": read_count,
+        });
+
+        // Decide if the annotations in the file can be used.
+
+        let v = airr_align(refdata, get("v_call"), |i| refdata.is_v(i), &seq, v_coords);
+        let j = airr_align(refdata, get("j_call"), |i| refdata.is_j(i), &seq, j_coords);
+        let full_v = v.as_ref().is_some_and(|v| v.ref_start == 0);
+        let full_j = j
+            .as_ref()
+            .is_some_and(|j| j.ref_stop == refdata.refs[j.ref_id].len());
+        let reannotate = reannotate_all || !full_v || !full_j;
+        if !reannotate {
+            let (v, j) = (v.unwrap(), j.unwrap());
+            let d = airr_align(refdata, get("d_call"), |i| refdata.is_d(i), &seq, d_coords);
+            if let [Some(start), Some(stop), None, None] = c_coords {
+                if stop >= start {
+                    c_coords = [Some(start), Some(stop), Some(1), Some(stop - start + 1)];
+                }
+            }
+            let c = airr_align(refdata, get("c_call"), |i| refdata.is_c(i), &seq, c_coords);
+            let annotation = |a: &AirrAlign, region_type: &str| {
+                let name = &refdata.name[a.ref_id];
+                json!({
+                    "feature": {
+                        "feature_id": refdata.id[a.ref_id],
+                        "region_type": region_type,
+                        "gene_name": name,
+                        "chain": name.get(0..3).unwrap_or(""),
+                    },
+                    "contig_match_start": a.contig_start,
+                    "contig_match_end": a.contig_stop,
+                    "annotation_match_start": a.ref_start,
+                    "annotation_match_end": a.ref_stop,
+                    "annotation_length": refdata.refs[a.ref_id].len(),
+                    "cigar": format!(
+                        "{}S{}M{}S",
+                        a.contig_start,
+                        a.contig_stop - a.contig_start,
+                        seq.len() - a.contig_stop
+                    ),
+                })
+            };
+            let mut annotations = vec![annotation(&v, "L-REGION+V-REGION")];
+            if let Some(d) = d {
+                annotations.push(annotation(&d, "D-REGION"));
+            }
+            annotations.push(annotation(&j, "J-REGION"));
+            if let Some(c) = c {
+                annotations.push(annotation(&c, "C-REGION"));
+            }
+
+            // Locate the junction.  If it cannot be found, the CDR3 is left empty, which causes
+            // it to be recomputed.
+
+            let junction = get("junction").to_ascii_uppercase().into_bytes();
+            let mut cdr3 = (String::new(), String::new(), v.contig_start);
+            if !junction.is_empty() {
+                if let Some(p) = seq[v.contig_start..]
+                    .windows(junction.len())
+                    .position(|w| w == junction)
+                {
+                    cdr3 = (
+                        get("junction_aa").to_string(),
+                        String::from_utf8_lossy(&junction).to_string(),
+                        v.contig_start + p,
+                    );
+                }
+            }
+            entry["cdr3"] = Value::from(cdr3.0);
+            entry["cdr3_seq"] = Value::from(cdr3.1);
+            entry["cdr3_start"] = Value::from(cdr3.2);
+            entry["annotations"] = Value::Array(annotations);
+        }
+        entries.push((
+            cell_id.to_string(),
+            *offset,
+            entry.to_string().into_bytes(),
+            reannotate,
+        ));
+    }
+
+    // Sort the entries by barcode, as parse_json_entries requires.
+
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(entries
+        .into_iter()
+        .map(|(_, offset, x, r)| (offset, x, r))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use debruijn::dna_string::DnaString;
+
+    const V: &str = "GATTACAGATTACAGATTACGATTACAGATTACAGATTAC";
+    const J: &str = "TGGGGCCAGGGAACCCTGGTCACCGTCTCC";
+    const C: &str = "GCCTCCACCAAGGGCCCATC";
+    const INS: &str = "GGGAAA";
+    const JUNCTION: &str = "GATTACGGGAAATGGGGC";
+
+    fn test_refdata() -> RefData {
+        let mut refdata = RefData::new();
+        for (id, name, segtype, seq) in [
+            (1, "IGHV3-23", "V", V),
+            (2, "IGHJ4", "J", J),
+            (3, "IGHG1", "C", C),
+        ] {
+            refdata.refs.push(DnaString::from_dna_string(seq));
+            refdata.name.push(name.to_string());
+            refdata.segtype.push(segtype.to_string());
+            refdata.id.push(id);
+        }
+        refdata
+    }
+
+    fn contig() -> String {
+        format!("{V}{INS}{J}{C}")
+    }
+
+    fn revcomp(s: &str) -> String {
+        s.bytes()
+            .rev()
+            .map(|c| match c {
+                b'A' => 'T',
+                b'C' => 'G',
+                b'G' => 'C',
+                _ => 'A',
+            })
+            .collect()
+    }
+
+    const HEADER: &str = "sequence_id\tsequence\tproductive\tcell_id\trev_comp\tumi_count\t\
+        duplicate_count\tv_call\tv_sequence_start\tv_sequence_end\tv_germline_start\t\
+        v_germline_end\tj_call\tj_sequence_start\tj_sequence_end\tj_germline_start\t\
+        j_germline_end\tc_call\tc_sequence_start\tc_sequence_end\tjunction\tjunction_aa";
+
+    // A line having the full annotations of the contig, with the given cell, sequence,
+    // rev_comp and V call, and V coordinates.
+
+    fn line(cell: &str, seq: &str, rev_comp: &str, v_call: &str, v: &str) -> String {
+        format!(
+            "{cell}_contig_1\t{seq}\tT\t{cell}\t{rev_comp}\t3\t\t{v_call}\t{v}\t\
+             IGHJ4*01\t47\t76\t1\t30\tIGHG1\t77\t96\t{JUNCTION}\tCAR"
+        )
+    }
+
+    fn entries(text: &str, reannotate_all: bool) -> Result<Vec<(usize, Value, bool)>, String> {
+        let entries = airr_entries(text.as_bytes(), "airr.tsv", &test_refdata(), reannotate_all)?;
+        Ok(entries
+            .into_iter()
+            .map(|(offset, x, r)| (offset, serde_json::from_slice(&x).unwrap(), r))
+            .collect())
+    }
+
+    #[test]
+    fn test_annotations() {
+        let full_v = "1\t40\t1\t40";
+        let text = format!(
+            "{HEADER}\n{}\n{}\n",
+            line(
+                "b2",
+                &revcomp(&contig()),
+                "T",
+                "IGHV3-23*01,IGHV3-30*01",
+                full_v
+            ),
+            line("b1", &contig(), "F", "IGHV3-23*01", full_v),
+        );
+        let entries = entries(&text, false).unwrap();
+        assert_eq!(entries.len(), 2);
+
+        // The entries are sorted by barcode, and the reverse complemented sequence is restored
+        // to the orientation of the annotations.
+
+        let offset = HEADER.len() + 1;
+        assert_eq!(entries[1].0, offset);
+        for (_, entry, reannotate) in &entries {
+            assert!(!reannotate);
+            assert_eq!(entry["sequence"], contig());
+            assert_eq!(entry["umi_count"], 3);
+            assert_eq!(entry["read_count"], 3);
+            assert_eq!(entry["productive"], true);
+            assert_eq!(entry["cdr3_seq"], JUNCTION);
+            assert_eq!(entry["cdr3"], "CAR");
+            assert_eq!(entry["cdr3_start"], 34);
+            let ann = entry["annotations"].as_array().unwrap();
+            let coords = ann
+                .iter()
+                .map(|a| {
+                    (
+                        a["feature"]["region_type"].as_str().unwrap(),
+                        a["contig_match_start"].as_u64().unwrap(),
+                        a["contig_match_end"].as_u64().unwrap(),
+                        a["annotation_match_start"].as_u64().unwrap(),
+                        a["annotation_match_end"].as_u64().unwrap(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                coords,
+                [
+                    ("L-REGION+V-REGION", 0, 40, 0, 40),
+                    ("J-REGION", 46, 76, 0, 30),
+                    ("C-REGION", 76, 96, 0, 20),
+                ]
+            );
+            assert_eq!(ann[0]["cigar"], "0S40M56S");
+            assert_eq!(ann[0]["feature"]["gene_name"], "IGHV3-23");
+            assert_eq!(ann[0]["feature"]["chain"], "IGH");
+        }
+        assert_eq!(entries[0].1["barcode"], "b1");
+        assert_eq!(entries[1].1["barcode"], "b2");
+    }
+
+    #[test]
+    fn test_reannotate() {
+        // The V call is not in the reference, the V alignment does not start at the start of the
+        // V segment, or the V coordinates are missing or out of range, or reannotation is
+        // requested.
+
+        let full_v = "1\t40\t1\t40";
+        let text = format!(
+            "{HEADER}\n{}\n{}\n{}\n{}\n{}\n",
+            line("b1", &contig(), "F", "IGHV9-99*01", full_v),
+            line("b2", &contig(), "F", "IGHV3-23*01", "2\t40\t2\t40"),
+            line("b3", &contig(), "F", "IGHV3-23*01", "\t\t\t"),
+            line("b4", &contig(), "F", "IGHV3-23*01", "0\t200\t1\t200"),
+            line("b5", &contig(), "F", "IGHV3-23*01", "40\t1\t40\t1"),
+        );
+        let entries = entries(&text, false).unwrap();
+        assert_eq!(entries.len(), 5);
+        for (_, entry, reannotate) in &entries {
+            assert!(reannotate);
+            assert!(entry.get("annotations").is_none());
+            assert_eq!(entry["sequence"], contig());
+        }
+        let text = format!(
+            "{HEADER}\n{}\n",
+            line("b1", &contig(), "F", "IGHV3-23", full_v)
+        );
+        assert!(self::entries(&text, true).unwrap()[0].2);
+    }
+
+    #[test]
+    fn test_missing_optional_columns() {
+        let seq = contig();
+        let text = format!(
+            "cell_id\tsequence_id\tsequence\tproductive\n\
+             b1\tb1_contig_1\t{seq}\tF\n\
+             b1\tb1_contig_2\tACGTN\tT\n\
+             \n\
+             b2\tb2_contig_1\t{}\ttrue\n",
+            seq.to_ascii_lowercase()
+        );
+        let entries = entries(&text, false).unwrap();
+
+        // The sequence having an N is not used.
+
+        assert_eq!(entries.len(), 2);
+        for (_, entry, reannotate) in &entries {
+            assert!(reannotate);
+            assert_eq!(entry["sequence"], seq);
+            assert_eq!(entry["umi_count"], 1);
+            assert_eq!(entry["read_count"], 1);
+            assert_eq!(entry["quals"].as_str().unwrap().len(), seq.len());
+        }
+        assert_eq!(entries[0].1["productive"], false);
+        assert_eq!(entries[1].1["productive"], true);
+        assert_eq!(entries[1].1["contig_name"], "b2_contig_1");
+
+        // duplicate_count is used for both counts if the others are missing.
+
+        let text = format!(
+            "cell_id\tsequence_id\tsequence\tproductive\tduplicate_count\nb1\tc\t{seq}\tT\t7\n"
+        );
+        let entry = &self::entries(&text, false).unwrap()[0].1;
+        assert_eq!(entry["umi_count"], 7);
+        assert_eq!(entry["read_count"], 7);
+    }
+
+    #[test]
+    fn test_malformed() {
+        let full_v = "1\t40\t1\t40";
+        let good = line("b1", &contig(), "F", "IGHV3-23", full_v);
+        let cases = [
+            (
+                format!("{HEADER}\n{good}\n\n{}\n", good.replacen("b1", "", 2)),
+                "line 4 has no cell_id",
+            ),
+            (
+                format!("{HEADER}\n{}\n", good.replacen("\t3\t", "\tthree\t", 1)),
+                "line 2 has umi_count = three, which is not a nonnegative integer",
+            ),
+            (
+                format!(
+                    "{HEADER}\n{}\n",
+                    line("b1", &contig(), "F", "IGHV3-23", "1\t-40\t1\t40")
+                ),
+                "line 2 has v_sequence_end = -40, which is not a nonnegative integer",
+            ),
+            (
+                format!("{HEADER}\n{good}\n{good}\textra\n"),
+                "line 3 has 23 fields, but the header has 22",
+            ),
+        ];
+        for (text, msg) in cases {
+            let e = entries(&text, false).unwrap_err();
+            assert!(e.contains(msg), "{e}");
+        }
+        let e = entries("", false).unwrap_err();
+        assert!(e.contains("is empty"));
+        let e = entries("sequence_id\tsequence\tcell_id\n", false).unwrap_err();
+        assert!(e.contains("does not have the field productive"));
+    }
+}
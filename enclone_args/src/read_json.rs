@@ -21,20 +21,25 @@
 // • cdr3_seq, unless in reannotate mode
 // • cdr3_start, unless in reannotate mode
 // • annotations, unless in reannotate mode.
+//
+// If a dataset directory has no contig annotations file, but has an AIRR Rearrangement file
+// airr_rearrangement.tsv, the latter is used instead, see read_airr.rs.
 
 use self::annotate::{annotate_seq, get_cdr3_using_ann, print_some_annotations};
 use self::refx::RefData;
 use self::transcript::is_valid;
+use crate::read_airr::{read_airr, AIRR_REARRANGEMENT};
 use debruijn::dna_string::DnaString;
-use enclone_core::barcode_fate::BarcodeFate;
+use enclone_core::barcode_fate::{BarcodeFate, FateLedger};
 use enclone_core::defs::{EncloneControl, OriginInfo, TigData};
-use io_utils::{open_maybe_compressed, path_exists, read_vector_entry_from_json};
+use io_utils::{open_maybe_compressed, path_exists};
 use rand::Rng;
 use rayon::prelude::*;
-use serde_json::Value;
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::fmt::Write;
+use std::io::{BufRead, BufReader};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::{collections::HashMap, io::BufReader};
 use string_utils::{stringme, strme, TextUtils};
 use vdj_ann::{annotate, refx, transcript};
 use vector_utils::{bin_position, erase_if, unique_sort};
@@ -43,6 +48,7 @@ use vector_utils::{bin_position, erase_if, unique_sort};
 
 fn json_error(
     json: Option<&str>,
+    offset: usize,
     ctl: &EncloneControl,
     exiting: &AtomicBool,
     msg: &str,
@@ -59,6 +65,12 @@ fn json_error(
         } else {
             msgx += ".";
         }
+        write!(
+            msgx,
+            "\nThe problem is in the entry that starts at byte offset {offset} of the \
+             uncompressed file."
+        )
+        .unwrap();
         if ctl.gen_opt.internal_run {
             writeln!(msgx, "\n\npossibly relevant internal data: {msg}").unwrap();
         }
@@ -93,8 +105,55 @@ fn json_error(
 
 // ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
 
+// The fields of an entry in a JSON annotations file that are used.  Other fields are skipped
+// by the parser rather than stored.
+
+#[derive(Deserialize)]
+struct JsonContig {
+    barcode: String,
+    is_cell: Option<bool>,
+    is_asm_cell: Option<bool>,
+    is_gex_cell: Option<bool>,
+    productive: Option<bool>,
+    high_confidence: Option<bool>,
+    contig_name: String,
+    sequence: String,
+    version: Option<String>,
+    validated_umis: Option<Vec<String>>,
+    non_validated_umis: Option<Vec<String>>,
+    invalidated_umis: Option<Vec<String>>,
+    fraction_of_reads_for_this_barcode_provided_as_input_to_assembly: Option<f64>,
+    quals: String,
+    umi_count: usize,
+    read_count: usize,
+    cdr3: Option<String>,
+    cdr3_seq: Option<String>,
+    cdr3_start: Option<usize>,
+    annotations: Option<Vec<JsonAnnotation>>,
+}
+
+#[derive(Deserialize)]
+struct JsonAnnotation {
+    feature: JsonFeature,
+    contig_match_start: usize,
+    contig_match_end: usize,
+    annotation_match_start: usize,
+    annotation_match_end: usize,
+    annotation_length: usize,
+    cigar: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JsonFeature {
+    feature_id: usize,
+    region_type: String,
+    gene_name: String,
+    chain: Option<String>,
+}
+
 fn parse_vector_entry_from_json(
     x: &[u8],
+    offset: usize,
     json: &str,
     accept_inconsistent: bool,
     origin_info: &OriginInfo,
@@ -110,29 +169,29 @@ fn parse_vector_entry_from_json(
     tigs: &mut Vec<TigData>,
     exiting: &AtomicBool,
 ) -> Result<(), String> {
-    let v: Value = match serde_json::from_slice(x) {
-        Err(_) => {
+    let v: JsonContig = match serde_json::from_slice(x) {
+        Err(e) => {
             return Err(format!(
-                "\nInternal error, failed to parse a value from a string.  The string is:\n{}\n",
-                strme(x)
+                "\nFailed to parse the entry that starts at byte offset {offset} of the \
+                 uncompressed file\n{json}\nThe error is: {e}.\n"
             ));
         }
         Ok(v) => v,
     };
-    let barcode = v["barcode"].to_string().between("\"", "\"").to_string();
+    let barcode = v.barcode;
 
     // Get cell status.  Sometime after CR 4.0 was released, and before 4.1 was released,
     // we added new fields is_asm_cell and is_gex_cell to the json file.  The value of
     // is_asm_cell is the original determination of "cell" in the VDJ pipeline, whereas the
     // value of is_gex_cell is that for the GEX pipeline.
 
-    let mut is_cell = v["is_cell"].as_bool().unwrap_or(false);
-    let is_asm_cell = v["is_asm_cell"].as_bool().unwrap_or(false);
+    let mut is_cell = v.is_cell.unwrap_or(false);
+    let is_asm_cell = v.is_asm_cell.unwrap_or(false);
     if is_asm_cell {
         is_cell = true;
     }
 
-    let is_gex_cell = v["is_gex_cell"].as_bool();
+    let is_gex_cell = v.is_gex_cell;
     if is_gex_cell.is_some() {
         *gex_cells_specified = true;
     }
@@ -149,15 +208,14 @@ fn parse_vector_entry_from_json(
 
     // Proceed.
 
-    if !ctl.gen_opt.reprod && !v["productive"].as_bool().unwrap_or(false) {
+    if !ctl.gen_opt.reprod && !v.productive.unwrap_or(false) {
         return Ok(());
     }
-    if !ctl.gen_opt.reprod && !ctl.gen_opt.ncell && !v["high_confidence"].as_bool().unwrap_or(false)
-    {
+    if !ctl.gen_opt.reprod && !ctl.gen_opt.ncell && !v.high_confidence.unwrap_or(false) {
         return Ok(());
     }
-    let tigname = v["contig_name"].to_string().between("\"", "\"").to_string();
-    let full_seq = &v["sequence"].to_string().between("\"", "\"").to_string();
+    let tigname = v.contig_name;
+    let full_seq = &v.sequence;
     let mut left = false;
     let (mut v_ref_id, mut j_ref_id) = (1000000, 0);
     let mut d_ref_id: Option<usize> = None;
@@ -175,47 +233,15 @@ fn parse_vector_entry_from_json(
     let mut cdr3_aa: String;
     let mut cdr3_dna: String;
     let mut cdr3_start: usize;
-    if v.get("version").is_some() {
-        *cr_version = v["version"].to_string().between("\"", "\"").to_string();
-    }
-
-    // Read validated and non-validated UMIs.
-
-    let mut validated_umis = Vec::<String>::new();
-    let mut validated_umis_present = false;
-    let val = v["validated_umis"].as_array();
-    if let Some(val) = val {
-        validated_umis_present = true;
-        for vi in val {
-            validated_umis.push(vi.to_string().between("\"", "\"").to_string());
-        }
-    }
-    let mut non_validated_umis = Vec::<String>::new();
-    let mut non_validated_umis_present = false;
-    let non_val = v["non_validated_umis"].as_array();
-    if let Some(non_val) = non_val {
-        non_validated_umis_present = true;
-        for nv in non_val {
-            non_validated_umis.push(nv.to_string().between("\"", "\"").to_string());
-        }
-    }
-    let mut invalidated_umis = Vec::<String>::new();
-    let mut invalidated_umis_present = false;
-    let inval = v["invalidated_umis"].as_array();
-    if let Some(inval) = inval {
-        invalidated_umis_present = true;
-        for inv in inval {
-            invalidated_umis.push(inv.to_string().between("\"", "\"").to_string());
-        }
+    if let Some(version) = v.version {
+        *cr_version = version;
     }
 
     // Read fraction_of_reads_for_this_barcode_provided_as_input_to_assembly.
 
-    let mut frac_reads_used = None;
-    let f = v["fraction_of_reads_for_this_barcode_provided_as_input_to_assembly"].as_f64();
-    if let Some(f) = f {
-        frac_reads_used = Some((f * 1_000_000.0).round() as u32);
-    }
+    let frac_reads_used = v
+        .fraction_of_reads_for_this_barcode_provided_as_input_to_assembly
+        .map(|f| (f * 1_000_000.0).round() as u32);
 
     // Reannotate.
 
@@ -340,36 +366,34 @@ fn parse_vector_entry_from_json(
     } else {
         // Use annotations from json file.
 
-        cdr3_aa = v["cdr3"].to_string().between("\"", "\"").to_string();
-        cdr3_dna = v["cdr3_seq"].to_string().between("\"", "\"").to_string();
-        cdr3_start = v["cdr3_start"].as_u64().unwrap() as usize;
-        let ann = v["annotations"].as_array();
-        if ann.is_none() {
+        cdr3_aa = v.cdr3.unwrap_or_default();
+        cdr3_dna = v.cdr3_seq.unwrap_or_default();
+        let Some(start) = v.cdr3_start else {
+            return json_error(Some(json), offset, ctl, exiting, "cdr3_start is missing");
+        };
+        cdr3_start = start;
+        let Some(ann) = v.annotations else {
             return Err(format!(
                 "\nThe file\n{json}\ndoes not contain annotations.  To use enclone with it, \
                     please specify the argument BUILT_IN\nto force use of the internal \
                     reference and recompute annotations.\n"
             ));
-        }
-        let ann = ann.unwrap();
+        };
         let mut cigarv = String::new(); // cigar for V segment
         for a in ann {
-            let region_type = &a["feature"]["region_type"];
-            let feature_id = a["feature"]["feature_id"].as_u64().unwrap() as usize;
+            let region_type = a.feature.region_type.as_str();
+            let feature_id = a.feature.feature_id;
             if !to_ref_index.contains_key(&feature_id) {
                 continue;
             }
             let feature_idx = to_ref_index[&feature_id];
-            let ref_start = a["annotation_match_start"].as_u64().unwrap() as usize;
+            let ref_start = a.annotation_match_start;
             if region_type == "L-REGION+V-REGION" {
-                v_stop = a["contig_match_end"].as_i64().unwrap() as usize;
-                v_stop_ref = a["annotation_match_end"].as_i64().unwrap() as usize;
+                v_stop = a.contig_match_end;
+                v_stop_ref = a.annotation_match_end;
             }
-            let gene_name = a["feature"]["gene_name"]
-                .to_string()
-                .between("\"", "\"")
-                .to_string();
-            if refdata.name[feature_idx] != gene_name
+            let gene_name = &a.feature.gene_name;
+            if refdata.name[feature_idx] != *gene_name
                 && !accept_inconsistent
                 && !exiting.swap(true, Ordering::Relaxed)
             {
@@ -395,12 +419,9 @@ fn parse_vector_entry_from_json(
                 ));
             }
             if region_type == "L-REGION+V-REGION" && ref_start == 0 {
-                let chain = a["feature"]["chain"]
-                    .to_string()
-                    .between("\"", "\"")
-                    .to_string();
+                let chain = a.feature.chain.clone().unwrap_or_default();
                 // if !chain.starts_with("IG") { continue; } // *******************
-                tig_start = a["contig_match_start"].as_i64().unwrap() as isize;
+                tig_start = a.contig_match_start as isize;
                 cdr3_start -= tig_start as usize;
                 chain_type = chain.clone();
                 if chain == *"IGH"
@@ -410,27 +431,27 @@ fn parse_vector_entry_from_json(
                     left = true;
                 }
                 v_ref_id = feature_idx;
-                cigarv = a["cigar"].to_string().between("\"", "\"").to_string();
+                cigarv = a.cigar.clone().unwrap_or_default();
             } else {
                 // also check for IG chain?????????????????????????????????????????
-                let ref_stop = a["annotation_match_end"].as_u64().unwrap() as usize;
-                let ref_len = a["annotation_length"].as_u64().unwrap() as usize;
+                let ref_stop = a.annotation_match_end;
+                let ref_len = a.annotation_length;
                 if region_type == "J-REGION" && ref_stop == ref_len {
-                    tig_stop = a["contig_match_end"].as_i64().unwrap() as isize;
+                    tig_stop = a.contig_match_end as isize;
                     j_ref_id = feature_idx;
-                    j_start = a["contig_match_start"].as_i64().unwrap() as usize;
-                    j_start_ref = a["annotation_match_start"].as_i64().unwrap() as usize;
+                    j_start = a.contig_match_start;
+                    j_start_ref = a.annotation_match_start;
                 }
                 if region_type == "5'UTR" {
                     u_ref_id = Some(feature_idx);
                 }
                 if region_type == "D-REGION" {
-                    d_start = Some(a["contig_match_start"].as_i64().unwrap() as usize);
+                    d_start = Some(a.contig_match_start);
                     d_ref_id = Some(feature_idx);
                 }
                 if region_type == "C-REGION" {
                     c_ref_id = Some(feature_idx);
-                    c_start = Some(a["contig_match_start"].as_i64().unwrap() as usize);
+                    c_start = Some(a.contig_match_start);
                 }
             }
         }
@@ -483,7 +504,7 @@ fn parse_vector_entry_from_json(
         let rt = &refdata.refs[v_ref_id];
         if annv.len() == 2 && annv[0].1 as usize > rt.len() {
             let msg = format!("annv[0].1 = {}, rt.len() = {}", annv[0].1, rt.len());
-            json_error(None, ctl, exiting, &msg)?;
+            json_error(None, offset, ctl, exiting, &msg)?;
         }
 
         // Check to see if the CDR3 sequence has changed.  This could happen if the cellranger
@@ -535,23 +556,10 @@ fn parse_vector_entry_from_json(
 
     if tig_start < 0 || tig_stop < 0 {
         let msg = format!("tig_start = {tig_start}, tig_stop = {tig_stop}");
-        json_error(Some(json), ctl, exiting, &msg)?;
+        json_error(Some(json), offset, ctl, exiting, &msg)?;
     }
     let (tig_start, tig_stop) = (tig_start as usize, tig_stop as usize);
-    let quals0 = v["quals"].to_string();
-    let quals0 = quals0.after("\"").as_bytes();
-    let mut quals = Vec::<u8>::new();
-    let mut slashed = false;
-    for &qual in quals0.iter().take(quals0.len() - 1) {
-        if !slashed && qual == b'\\'
-        /* && ( i == 0 || quals0[i-1] != b'\\' ) */
-        {
-            slashed = true;
-            continue;
-        }
-        slashed = false;
-        quals.push(qual);
-    }
+    let mut quals = v.quals.into_bytes();
     assert_eq!(full_seq.len(), quals.len());
     let seq = &full_seq[tig_start..tig_stop].to_string();
     for qual in quals.iter_mut() {
@@ -559,8 +567,8 @@ fn parse_vector_entry_from_json(
     }
     let full_quals = quals;
     let quals = full_quals[tig_start..tig_stop].to_vec();
-    let umi_count = v["umi_count"].as_i64().unwrap() as usize;
-    let read_count = v["read_count"].as_i64().unwrap() as usize;
+    let umi_count = v.umi_count;
+    let read_count = v.read_count;
     let origin = origin_info.origin_for_bc[li].get(&barcode).or_else(|| {
         // the way we use s1 here is flaky
         if !origin_info.origin_id[li].is_empty()
@@ -594,18 +602,6 @@ fn parse_vector_entry_from_json(
     if let Some(tag) = tag {
         tag_index = Some(bin_position(&origin_info.tag_list, tag) as usize);
     }
-    let mut valu = None;
-    if validated_umis_present {
-        valu = Some(validated_umis);
-    }
-    let mut non_valu = None;
-    if non_validated_umis_present {
-        non_valu = Some(non_validated_umis);
-    }
-    let mut invalu = None;
-    if invalidated_umis_present {
-        invalu = Some(invalidated_umis);
-    }
     tigs.push(TigData {
         cdr3_dna,
         len: seq.len(),
@@ -643,9 +639,9 @@ fn parse_vector_entry_from_json(
         read_count,
         chain_type,
         annv,
-        validated_umis: valu,
-        non_validated_umis: non_valu,
-        invalidated_umis: invalu,
+        validated_umis: v.validated_umis,
+        non_validated_umis: v.non_validated_umis,
+        invalidated_umis: v.invalidated_umis,
         frac_reads_used,
     });
     Ok(())
@@ -653,28 +649,114 @@ fn parse_vector_entry_from_json(
 
 // ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
 
+// The number of entries of a JSON annotations file that are held in memory and parsed in
+// parallel at one time.
+
+const JSON_CHUNK: usize = 5_000;
+
+// Reader for the entries of a JSON annotations file, which is an array of objects.  Each entry
+// is returned unparsed, as the bytes of one object, together with its byte offset in the
+// uncompressed file.  Only the nesting of the JSON is tracked, so this is much faster than
+// parsing.
+
+struct JsonEntryReader<R: BufRead> {
+    f: R,
+    offset: usize,  // number of bytes consumed
+    started: bool,  // seen the opening bracket of the array
+    finished: bool, // seen the closing bracket of the array
+}
+
+impl<R: BufRead> JsonEntryReader<R> {
+    fn new(f: R) -> Self {
+        JsonEntryReader {
+            f,
+            offset: 0,
+            started: false,
+            finished: false,
+        }
+    }
+
+    fn next_entry(&mut self) -> Result<Option<(usize, Vec<u8>)>, String> {
+        let mut entry = Vec::<u8>::new();
+        let (mut start, mut depth) = (0, 0);
+        let (mut in_string, mut escaped) = (false, false);
+        loop {
+            let buf = self.f.fill_buf().map_err(|e| e.to_string())?;
+            if buf.is_empty() {
+                if depth > 0 || !self.finished {
+                    return Err(format!(
+                        "the file ends unexpectedly at byte offset {}",
+                        self.offset
+                    ));
+                }
+                return Ok(None);
+            }
+            let mut used = 0;
+            let mut complete = false;
+            for &b in buf {
+                used += 1;
+                if depth == 0 {
+                    match b {
+                        b'[' if !self.started => self.started = true,
+                        b',' if self.started && !self.finished => {}
+                        b']' if self.started && !self.finished => self.finished = true,
+                        b'{' if self.started && !self.finished => {
+                            start = self.offset + used - 1;
+                            depth = 1;
+                            entry.push(b);
+                        }
+                        _ if b.is_ascii_whitespace() => {}
+                        _ => {
+                            return Err(format!(
+                                "unexpected character at byte offset {}",
+                                self.offset + used - 1
+                            ));
+                        }
+                    }
+                    continue;
+                }
+                entry.push(b);
+                if in_string {
+                    if escaped {
+                        escaped = false;
+                    } else if b == b'\\' {
+                        escaped = true;
+                    } else if b == b'"' {
+                        in_string = false;
+                    }
+                } else if b == b'"' {
+                    in_string = true;
+                } else if b == b'{' || b == b'[' {
+                    depth += 1;
+                } else if b == b'}' || b == b']' {
+                    depth -= 1;
+                    if depth == 0 {
+                        complete = true;
+                        break;
+                    }
+                }
+            }
+            self.f.consume(used);
+            self.offset += used;
+            if complete {
+                return Ok(Some((start, entry)));
+            }
+        }
+    }
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
 // Parse the JSON annotations file.
 //
-// In the future could be converted to LazyWrite:
-// https://martian-lang.github.io/martian-rust/doc/martian_filetypes/json_file/
-// index.html#lazy-readwrite-example.
-//
 // Tracking contigs using bc_cdr3_aa; could improve later.
 //
 // This section requires 3.1.  If you want to avoid that, do something to make tig_start
 // and tig_stop always nonnegative.  Or use the RE option.
 //
-// Computational performance.  It would appear that nearly all the time here is spent in
-// two lines:
-//
-// read_vector_entry_from_json(&mut f) {
-// let v: Value = serde_json::from_str(strme(&x)).unwrap();
-// (Should retest.)
-//
-// and simply reading the file lines is several times faster.  So the way we parse the
-// files is suboptimal.  If we want to make this faster, one option would be to speed up
-// this code.  Another would be to write out a binary version of the JSON file that contains
-// only the information that we need.
+// The file is streamed: entries are read in chunks of JSON_CHUNK, and each chunk is parsed in
+// parallel and then discarded, so that memory use does not grow with the size of the file.
+// Only the fields in JsonContig are deserialized.
 
 pub fn read_json(
     accept_inconsistent: bool,
@@ -690,8 +772,6 @@ pub fn read_json(
     gex_cells: &mut Vec<String>,
     gex_cells_specified: &mut bool,
 ) -> Result<Vec<Vec<TigData>>, String> {
-    *gex_cells_specified = false;
-    let mut tigs = Vec::<TigData>::new();
     let mut jsonx = json.clone();
     if !path_exists(json) {
         jsonx = format!("{json}.lz4");
@@ -715,22 +795,96 @@ pub fn read_json(
              input files to enclone, including the PRE argument.\n"
         ));
     }
-    let mut f = BufReader::new(open_maybe_compressed(&jsonx));
-    // ◼ This loop could be speeded up, see comments above.
-    let mut xs = Vec::<Vec<u8>>::new();
+    let mut f = JsonEntryReader::new(BufReader::new(open_maybe_compressed(&jsonx)));
+    *gex_cells_specified = false;
+    let mut tigs = Vec::<TigData>::new();
     loop {
-        let x = read_vector_entry_from_json(&mut f);
-        if x.is_err() {
-            eprintln!("\nProblem reading {jsonx}.\n");
-            return Err(x.err().unwrap());
-        }
-        match x.unwrap() {
-            None => break,
-            Some(x) => {
-                xs.push(x);
+        let mut entries = Vec::<(usize, Vec<u8>, bool)>::new();
+        while entries.len() < JSON_CHUNK {
+            match f.next_entry() {
+                Err(e) => return Err(format!("\nProblem reading {jsonx}: {e}.\n")),
+                Ok(None) => break,
+                Ok(Some((offset, x))) => entries.push((offset, x, reannotate)),
             }
         }
+        if entries.is_empty() {
+            break;
+        }
+        parse_json_chunk(
+            accept_inconsistent,
+            origin_info,
+            li,
+            json,
+            &entries,
+            refdata,
+            to_ref_index,
+            cr_version,
+            ctl,
+            vdj_cells,
+            gex_cells,
+            gex_cells_specified,
+            &mut tigs,
+        )?;
     }
+    Ok(group_tigs_by_barcode(tigs, ctl, vdj_cells, gex_cells))
+}
+
+// Parse the entries of a JSON annotations file, each of which is given as (byte offset of the
+// entry in the uncompressed file, entry, reannotate), where reannotate says whether its
+// annotations should be recomputed, and group the resulting contigs by barcode.  The entries
+// must be sorted by barcode.
+
+pub fn parse_json_entries(
+    accept_inconsistent: bool,
+    origin_info: &OriginInfo,
+    li: usize,
+    json: &str,
+    entries: &[(usize, Vec<u8>, bool)],
+    refdata: &RefData,
+    to_ref_index: &HashMap<usize, usize>,
+    cr_version: &mut String,
+    ctl: &EncloneControl,
+    vdj_cells: &mut Vec<String>,
+    gex_cells: &mut Vec<String>,
+    gex_cells_specified: &mut bool,
+) -> Result<Vec<Vec<TigData>>, String> {
+    *gex_cells_specified = false;
+    let mut tigs = Vec::<TigData>::new();
+    parse_json_chunk(
+        accept_inconsistent,
+        origin_info,
+        li,
+        json,
+        entries,
+        refdata,
+        to_ref_index,
+        cr_version,
+        ctl,
+        vdj_cells,
+        gex_cells,
+        gex_cells_specified,
+        &mut tigs,
+    )?;
+    Ok(group_tigs_by_barcode(tigs, ctl, vdj_cells, gex_cells))
+}
+
+// Parse a chunk of entries in parallel, appending the results.
+
+fn parse_json_chunk(
+    accept_inconsistent: bool,
+    origin_info: &OriginInfo,
+    li: usize,
+    json: &str,
+    entries: &[(usize, Vec<u8>, bool)],
+    refdata: &RefData,
+    to_ref_index: &HashMap<usize, usize>,
+    cr_version: &mut String,
+    ctl: &EncloneControl,
+    vdj_cells: &mut Vec<String>,
+    gex_cells: &mut Vec<String>,
+    gex_cells_specified: &mut bool,
+    tigs: &mut Vec<TigData>,
+) -> Result<(), String> {
     let mut results = Vec::<(
         usize,
         Vec<String>,
@@ -740,7 +894,7 @@ pub fn read_json(
         Vec<TigData>,
         String,
     )>::new();
-    for i in 0..xs.len() {
+    for i in 0..entries.len() {
         results.push((
             i,
             Vec::<String>::new(),
@@ -753,16 +907,17 @@ pub fn read_json(
     }
     let exiting = AtomicBool::new(false);
     results.par_iter_mut().for_each(|res| {
-        let i = res.0;
+        let (offset, x, reannotate) = &entries[res.0];
         let resx = parse_vector_entry_from_json(
-            &xs[i],
+            x,
+            *offset,
             json,
             accept_inconsistent,
             origin_info,
             li,
             refdata,
             to_ref_index,
-            reannotate,
+            *reannotate,
             ctl,
             &mut res.1,
             &mut res.2,
@@ -780,7 +935,7 @@ pub fn read_json(
             return Err(result.6.clone());
         }
     }
-    for result in results.iter_mut().take(xs.len()) {
+    for result in results.iter_mut() {
         vdj_cells.append(&mut result.1);
         gex_cells.append(&mut result.2);
         if result.3 {
@@ -791,6 +946,17 @@ pub fn read_json(
         }
         tigs.append(&mut result.5);
     }
+    Ok(())
+}
+
+// Group contigs, which are sorted by barcode, into cells, and subsample.
+
+fn group_tigs_by_barcode(
+    tigs: Vec<TigData>,
+    ctl: &EncloneControl,
+    vdj_cells: &mut Vec<String>,
+    gex_cells: &mut Vec<String>,
+) -> Vec<Vec<TigData>> {
     unique_sort(gex_cells);
     let mut tig_bc = Vec::<Vec<TigData>>::new();
     let mut r = 0;
@@ -843,22 +1009,24 @@ pub fn read_json(
 
     // Done.
 
-    Ok(tig_bc)
+    tig_bc
 }
 
 // ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
 
-// Parse the JSON annotations file(s).
+// Parse the JSON annotations file(s).  Datasets before first_dataset are not read, and are
+// given empty entries in vdj_cells, gex_cells and gex_cells_specified.
 
 pub fn parse_json_annotations_files(
     ctl: &EncloneControl,
+    first_dataset: usize,
     tig_bc: &mut Vec<Vec<TigData>>,
     refdata: &RefData,
     to_ref_index: &HashMap<usize, usize>,
     vdj_cells: &mut Vec<Vec<String>>,
     gex_cells: &mut Vec<Vec<String>>,
     gex_cells_specified: &mut Vec<bool>,
-    fate: &mut [HashMap<String, BarcodeFate>],
+    fate: &mut [FateLedger],
 ) -> Result<(), String> {
     // (origin index, contig name, V..J length): (?)
     let mut results = Vec::<(
@@ -893,26 +1061,44 @@ pub fn parse_json_annotations_files(
     };
     results.par_iter_mut().for_each(|res| {
         let li = res.0;
-        let json = format!("{}/{ann}", ctl.origin_info.dataset_path[li]);
-        let json_lz4 = format!("{}/{ann}.lz4", ctl.origin_info.dataset_path[li]);
-        if !path_exists(&json) && !path_exists(&json_lz4) {
-            res.8 = format!("\ncan't find {json} or {json_lz4}\n");
+        if li < first_dataset {
             return;
         }
-        let resx = read_json(
-            ctl.gen_opt.accept_inconsistent,
-            &ctl.origin_info,
-            li,
-            &json,
-            refdata,
-            to_ref_index,
-            ctl.gen_opt.reannotate,
-            &mut res.4,
-            ctl,
-            &mut res.5,
-            &mut res.6,
-            &mut res.7,
-        );
+        let json = format!("{}/{ann}", ctl.origin_info.dataset_path[li]);
+        let json_lz4 = format!("{}/{ann}.lz4", ctl.origin_info.dataset_path[li]);
+        let airr = format!("{}/{AIRR_REARRANGEMENT}", ctl.origin_info.dataset_path[li]);
+        let resx = if !path_exists(&json) && !path_exists(&json_lz4) {
+            if !path_exists(&airr) {
+                res.8 = format!("\ncan't find {json} or {json_lz4} or {airr}\n");
+                return;
+            }
+            read_airr(
+                &ctl.origin_info,
+                li,
+                &airr,
+                refdata,
+                to_ref_index,
+                ctl,
+                &mut res.5,
+                &mut res.6,
+                &mut res.7,
+            )
+        } else {
+            read_json(
+                ctl.gen_opt.accept_inconsistent,
+                &ctl.origin_info,
+                li,
+                &json,
+                refdata,
+                to_ref_index,
+                ctl.gen_opt.reannotate,
+                &mut res.4,
+                ctl,
+                &mut res.5,
+                &mut res.6,
+                &mut res.7,
+            )
+        };
         if let Ok(resx) = resx {
             let tig_bc: Vec<Vec<TigData>> = resx;
             res.5.sort();
@@ -950,7 +1136,7 @@ pub fn parse_json_annotations_files(
         }
         for j in 0..found.len() {
             if !found[j] {
-                fate[i].insert(cells[j].clone(), BarcodeFate::NonProductive);
+                fate[i].insert(cells[j].clone(), BarcodeFate::NonProductive, true);
             }
         }
     }
@@ -972,3 +1158,24 @@ pub fn parse_json_annotations_files(
     */
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_entry_reader() {
+        let json = b"[\n  {\"a\": \"}{\\\"\", \"b\": [1, {}]},\n  {\"c\": 2}\n]\n";
+        let mut f = JsonEntryReader::new(BufReader::with_capacity(4, &json[..]));
+        let (offset, x) = f.next_entry().unwrap().unwrap();
+        assert_eq!(offset, 4);
+        assert_eq!(x, b"{\"a\": \"}{\\\"\", \"b\": [1, {}]}");
+        let (offset, x) = f.next_entry().unwrap().unwrap();
+        assert_eq!(offset, 35);
+        assert_eq!(x, b"{\"c\": 2}");
+        assert!(f.next_entry().unwrap().is_none());
+        let mut f = JsonEntryReader::new(BufReader::new(&b"[{\"a\": 1}"[..]));
+        assert!(f.next_entry().unwrap().is_some());
+        assert!(f.next_entry().is_err());
+    }
+}
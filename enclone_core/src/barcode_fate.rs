@@ -1,11 +1,13 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Write;
 
 /// Different reasons why a barcode which have productive contig(s)
 /// are not called as cells by enclone
 ///
 /// For more explanation, see <https://10xgenomics.github.io/enclone/pages/auto/default_filters.html>
 /// and <https://10xgenomics.github.io/enclone/pages/auto/help.special.html>
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum BarcodeFate {
     Doublet,
     WeakChains,
@@ -63,9 +65,40 @@ pub enum BarcodeFate {
     /// No productive contigs for this barcode. This will only happen
     /// when certain default filters are turned off
     NonProductive,
+    /// In a clonotype having more than one chain, an exact subclonotype having only one chain,
+    /// which does not exactly match a chain in another exact subclonotype.  This is applied when
+    /// clonotypes are printed.
+    UnmatchedOnesie,
+    /// Exact subclonotypes having more than one heavy chain, deleted because MAX_HEAVIES=1
+    /// was specified.
+    MaxHeavies,
+    /// Cells not satisfying the constraints imposed by FCELL.
+    Fcell,
 }
 
 impl BarcodeFate {
+    /// All fates, in the order in which the corresponding filters are applied.
+    pub const PIPELINE_ORDER: [BarcodeFate; 18] = [
+        BarcodeFate::NonProductive,
+        BarcodeFate::NotAsmCell,
+        BarcodeFate::GraphFilter,
+        BarcodeFate::Cross,
+        BarcodeFate::GelBeadContamination,
+        BarcodeFate::DuplicatedBarcode,
+        BarcodeFate::FoursieKill,
+        BarcodeFate::MaxHeavies,
+        BarcodeFate::Improper,
+        BarcodeFate::Umi,
+        BarcodeFate::UmiRatio,
+        BarcodeFate::NotGexCell,
+        BarcodeFate::Fcell,
+        BarcodeFate::Doublet,
+        BarcodeFate::Signature,
+        BarcodeFate::WeakChains,
+        BarcodeFate::Qual,
+        BarcodeFate::UnmatchedOnesie,
+    ];
+
     pub fn label(&self) -> &'static str {
         match self {
             BarcodeFate::Doublet => "DOUBLET",
@@ -83,6 +116,249 @@ impl BarcodeFate {
             BarcodeFate::Improper => "IMPROPER",
             BarcodeFate::GraphFilter => "GRAPH_FILTER",
             BarcodeFate::NonProductive => "PRODUCTIVE",
+            BarcodeFate::UnmatchedOnesie => "ONESIE",
+            BarcodeFate::MaxHeavies => "MAX_HEAVIES",
+            BarcodeFate::Fcell => "FCELL",
+        }
+    }
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+/// The fates of the barcodes in one dataset.
+///
+/// For each barcode, this records every filter that acted on it, in the order in which the
+/// filters were applied, and separately, the first filter that removed it.  A filter may act on
+/// a barcode without removing it, either because it only marks barcodes (for example
+/// `NotAsmCell` when NCELL is specified, or the `*_MARK` filters), or because the barcode was
+/// kept for some other reason, so the first filter that acted on a barcode need not be the
+/// filter that removed it.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct FateLedger {
+    /// Number of barcodes having a contig that entered the pipeline, including those
+    /// that were filtered out because they had no productive contig.
+    pub num_barcodes: usize,
+    fates: HashMap<String, Vec<BarcodeFate>>,
+    removed: HashMap<String, BarcodeFate>,
+}
+
+impl FateLedger {
+    /// Record that a filter acted on a barcode, and whether it removed the barcode.  Repeated
+    /// records of the same fate for the same barcode are ignored, and only the first filter
+    /// that removed the barcode is kept.
+    pub fn insert(&mut self, barcode: String, fate: BarcodeFate, removed: bool) {
+        if removed && !self.removed.contains_key(&barcode) {
+            self.removed.insert(barcode.clone(), fate);
+        }
+        let fates = self.fates.entry(barcode).or_default();
+        if !fates.contains(&fate) {
+            fates.push(fate);
+        }
+    }
+
+    pub fn contains_key(&self, barcode: &str) -> bool {
+        self.fates.contains_key(barcode)
+    }
+
+    /// The filter that removed the barcode, if any.
+    pub fn get(&self, barcode: &str) -> Option<BarcodeFate> {
+        self.removed.get(barcode).copied()
+    }
+
+    /// Every filter that acted on the barcode, in pipeline order.
+    pub fn fates(&self, barcode: &str) -> &[BarcodeFate] {
+        self.fates.get(barcode).map_or(&[], |f| f.as_slice())
+    }
+
+    /// Number of barcodes that some filter acted on.
+    pub fn len(&self) -> usize {
+        self.fates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fates.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &[BarcodeFate])> {
+        self.fates.iter().map(|(bc, f)| (bc, f.as_slice()))
+    }
+
+    /// The map from each barcode to the filter that removed it, which is what FATE_FILE
+    /// contains.
+    pub fn removed_by(&self) -> HashMap<&str, BarcodeFate> {
+        self.removed
+            .iter()
+            .map(|(bc, f)| (bc.as_str(), *f))
+            .collect()
+    }
+}
+
+/// Summarize the fates of barcodes as a CSV table, having one line per dataset and filter.
+/// For each, `removed` is the number of barcodes that the filter removed, and `flagged` is the
+/// number of barcodes that the filter acted on, including those already removed or marked by an
+/// earlier filter.  Percentages are of the barcodes in the dataset.
+pub fn fate_summary(ledgers: &[FateLedger], dataset_ids: &[String]) -> String {
+    let mut csv =
+        "dataset,filter,barcodes,removed,removed_percent,flagged,flagged_percent\n".to_string();
+    for (ledger, id) in ledgers.iter().zip(dataset_ids.iter()) {
+        let mut removed = HashMap::<BarcodeFate, usize>::new();
+        let mut flagged = HashMap::<BarcodeFate, usize>::new();
+        for f in ledger.removed.values() {
+            *removed.entry(*f).or_default() += 1;
+        }
+        for (_, fates) in ledger.iter() {
+            for f in fates {
+                *flagged.entry(*f).or_default() += 1;
+            }
+        }
+        let n = ledger.num_barcodes;
+        let percent = |x: usize| {
+            if n == 0 {
+                0.0
+            } else {
+                100.0 * x as f64 / n as f64
+            }
+        };
+        for f in BarcodeFate::PIPELINE_ORDER {
+            let r = removed.get(&f).copied().unwrap_or(0);
+            let g = flagged.get(&f).copied().unwrap_or(0);
+            writeln!(
+                csv,
+                "{id},{},{n},{r},{:.2},{g},{:.2}",
+                f.label(),
+                percent(r),
+                percent(g)
+            )
+            .unwrap();
+        }
+    }
+    csv
+}
+
+/// Report, as a CSV table, the number of cells and clonotypes that each filter would delete, per
+/// dataset.  This is for FILTER_IMPACT, when nothing has been deleted, so that `clonotypes`
+/// contains every cell, each given by its dataset index and barcode.  A clonotype is counted
+/// for a filter if the filter would delete all of its cells, once for each dataset it has
+/// cells in.
+pub fn filter_impact(
+    ledgers: &[FateLedger],
+    dataset_ids: &[String],
+    clonotypes: &[Vec<(usize, &str)>],
+) -> String {
+    let n = ledgers.len();
+    let mut ncells = vec![HashMap::<BarcodeFate, usize>::new(); n];
+    for (ledger, counts) in ledgers.iter().zip(ncells.iter_mut()) {
+        for (_, fates) in ledger.iter() {
+            for f in fates {
+                *counts.entry(*f).or_default() += 1;
+            }
+        }
+    }
+    let mut nclonotypes = vec![HashMap::<BarcodeFate, usize>::new(); n];
+    for cells in clonotypes {
+        if cells.is_empty() {
+            continue;
         }
+        let mut datasets = cells.iter().map(|c| c.0).collect::<Vec<_>>();
+        datasets.sort_unstable();
+        datasets.dedup();
+        for f in BarcodeFate::PIPELINE_ORDER {
+            if cells.iter().all(|c| ledgers[c.0].fates(c.1).contains(&f)) {
+                for &li in &datasets {
+                    *nclonotypes[li].entry(f).or_default() += 1;
+                }
+            }
+        }
+    }
+    let mut csv = "dataset,filter,cells,cells_percent,clonotypes\n".to_string();
+    for li in 0..n {
+        let nbc = ledgers[li].num_barcodes;
+        for f in BarcodeFate::PIPELINE_ORDER {
+            let c = ncells[li].get(&f).copied().unwrap_or(0);
+            let percent = if nbc == 0 {
+                0.0
+            } else {
+                100.0 * c as f64 / nbc as f64
+            };
+            writeln!(
+                csv,
+                "{},{},{c},{percent:.2},{}",
+                dataset_ids[li],
+                f.label(),
+                nclonotypes[li].get(&f).copied().unwrap_or(0)
+            )
+            .unwrap();
+        }
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fate_ledger() {
+        let mut ledger = FateLedger {
+            num_barcodes: 4,
+            ..Default::default()
+        };
+        ledger.insert("A".to_string(), BarcodeFate::NotAsmCell, true);
+        ledger.insert("A".to_string(), BarcodeFate::Umi, true);
+        ledger.insert("A".to_string(), BarcodeFate::Umi, true);
+        ledger.insert("B".to_string(), BarcodeFate::Umi, true);
+        assert_eq!(ledger.get("A"), Some(BarcodeFate::NotAsmCell));
+        assert_eq!(
+            ledger.fates("A"),
+            &[BarcodeFate::NotAsmCell, BarcodeFate::Umi]
+        );
+        let summary = fate_summary(&[ledger], &["d1".to_string()]);
+        assert!(summary.contains("\nd1,CELL,4,1,25.00,1,25.00\n"));
+        assert!(summary.contains("\nd1,UMI,4,1,25.00,2,50.00\n"));
+    }
+
+    #[test]
+    fn test_marked_then_removed() {
+        // A is marked by NCELL and then removed by the UMI filter, and C is only marked.
+
+        let mut ledger = FateLedger {
+            num_barcodes: 4,
+            ..Default::default()
+        };
+        ledger.insert("A".to_string(), BarcodeFate::NotAsmCell, false);
+        ledger.insert("A".to_string(), BarcodeFate::Umi, true);
+        ledger.insert("A".to_string(), BarcodeFate::Doublet, true);
+        ledger.insert("C".to_string(), BarcodeFate::NotAsmCell, false);
+        assert_eq!(ledger.get("A"), Some(BarcodeFate::Umi));
+        assert_eq!(ledger.get("C"), None);
+        assert_eq!(
+            ledger.fates("A"),
+            &[
+                BarcodeFate::NotAsmCell,
+                BarcodeFate::Umi,
+                BarcodeFate::Doublet
+            ]
+        );
+        let removed_by = ledger.removed_by();
+        assert_eq!(removed_by.len(), 1);
+        assert_eq!(removed_by["A"], BarcodeFate::Umi);
+        let summary = fate_summary(&[ledger], &["d1".to_string()]);
+        assert!(summary.contains("\nd1,CELL,4,0,0.00,2,50.00\n"));
+        assert!(summary.contains("\nd1,UMI,4,1,25.00,1,25.00\n"));
+        assert!(summary.contains("\nd1,DOUBLET,4,0,0.00,1,25.00\n"));
+    }
+
+    #[test]
+    fn test_filter_impact() {
+        let mut ledger = FateLedger {
+            num_barcodes: 3,
+            ..Default::default()
+        };
+        ledger.insert("A".to_string(), BarcodeFate::Doublet, true);
+        ledger.insert("B".to_string(), BarcodeFate::Doublet, true);
+        let clonotypes = vec![vec![(0, "A"), (0, "B")], vec![(0, "C")]];
+        let report = filter_impact(&[ledger], &["d1".to_string()], &clonotypes);
+        assert!(report.contains("\nd1,DOUBLET,2,66.67,1\n"));
+        assert!(report.contains("\nd1,UMI,0,0.00,0\n"));
     }
 }
@@ -14,8 +14,9 @@ use perf_stats::elapsed;
 use perf_stats::peak_mem_usage_gb;
 
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::cmp::max;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use std::io::BufRead;
 use std::sync::atomic::AtomicBool;
@@ -53,7 +54,7 @@ pub const MAX_CDR3_DIFFS_TO_JOIN: usize = 5;
 
 // Clonotyping algorithm heuristics.
 
-#[derive(Default, PartialEq, Eq)]
+#[derive(Debug, Default, PartialEq, Eq)]
 pub struct ClonotypeHeuristics {
     pub max_diffs: usize,
     pub max_degradation: usize,
@@ -103,7 +104,7 @@ impl OriginInfo {
 
 // Miscellaneous general options.
 
-#[derive(Default, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct GeneralOpt {
     pub pre: Vec<String>,
     pub indels: bool,
@@ -133,7 +134,23 @@ pub struct GeneralOpt {
     pub exact: Option<usize>,
     pub binary: String,
     pub proto: String,
+    pub proto_index: String,
     pub fate_file: String,
+    pub fate_ledger: String,
+    pub fate_summary: String,
+    pub filter_impact: String,
+    pub checkpoint: String,
+    pub incremental: String,
+    pub incremental_report: String,
+    pub join_audit: String,
+    pub benchmark: String,
+    pub benchmark_truth: String,
+    pub airr_rearrangement: String,
+    pub airr_cell: String,
+    pub group_table: String,
+    // Retain the Loupe outputs in memory, for library callers.  Not settable by an argument.
+    pub proto_in_memory: bool,
+    pub proto_stream: bool,
     // Optional path to a json file containing metadata
     pub proto_metadata: Option<String>,
     pub h5: bool,
@@ -141,9 +158,12 @@ pub struct GeneralOpt {
     pub accept_reuse: bool,
     pub descrip: bool,
     pub ext: String,
-    pub extc: HashMap<(String, String), String>,
-    pub extn: HashMap<String, usize>,
+    pub extc: BTreeMap<(String, String), String>,
+    pub extn: BTreeMap<String, usize>,
     pub dref_file: String,
+    pub donor_genotype: String,
+    pub donor_genotype_only: bool,
+    pub donor_genotype_report: String,
     pub mouse: bool,
     pub refname: String,
     pub noprint: bool,
@@ -168,7 +188,7 @@ pub struct GeneralOpt {
     pub gene_scan_threshold: Option<LinearCondition>,
     pub gene_scan_exact: bool,
     pub clonotype_group_names: Option<String>,
-    pub origin_color_map: HashMap<String, String>,
+    pub origin_color_map: BTreeMap<String, String>,
     pub accept_inconsistent: bool, // TEMPORARY!
     pub current_ref: bool,         // TEMPORARY!
     pub internal_run: bool,
@@ -190,7 +210,7 @@ pub struct GeneralOpt {
     pub mark_stats2: bool,
     pub print_cpu: bool,
     pub print_cpu_info: bool,
-    pub newick: bool,
+    pub newick: String,
     pub tree_on: bool,
     pub tree: Vec<String>,
     pub allow_inconsistent: bool,
@@ -213,12 +233,12 @@ pub struct GeneralOpt {
     pub nopager: bool,
     pub info: Option<String>,
     pub info_fields: Vec<String>,
-    pub info_data: HashMap<String, Vec<String>>,
+    pub info_data: BTreeMap<String, Vec<String>>,
     pub info_resolve: bool,
     pub internal_data_dir: String,
     pub row_fill_verbose: bool,
     pub config_file: String,
-    pub config: HashMap<String, String>,
+    pub config: BTreeMap<String, String>,
     pub top_genes: bool,
     pub toy_com: bool,
     pub chains_to_align: Vec<usize>,
@@ -296,10 +316,11 @@ pub struct PlotOpt {
 
 // Allele-finding algorithmic options.
 
-#[derive(Default, PartialEq, Eq)]
+#[derive(Debug, Default, PartialEq, Eq)]
 pub struct AlleleAlgOpt {
     pub min_mult: usize,
     pub min_alt: usize,
+    pub j_alleles: bool, // also find alternate alleles of J segments
 }
 
 // Allele-finding print options.
@@ -312,7 +333,7 @@ pub struct AllelePrintOpt {
 
 // Data about alleles
 
-#[derive(Clone, Default)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct AlleleData {
     pub alt_refs: Vec<(usize, usize, DnaString, usize, bool)>,
     pub var_pos: Vec<Vec<usize>>,
@@ -333,7 +354,7 @@ pub struct JoinPrintOpt {
 
 // Join algorithmic options.
 
-#[derive(Default, PartialEq)]
+#[derive(Debug, Default, PartialEq)]
 pub struct JoinAlgOpt {
     pub max_score: f64,          // max score for join
     pub easy: bool,              // make joins even if core condition violated
@@ -341,6 +362,7 @@ pub struct JoinAlgOpt {
     pub merge_onesies_ctl: bool, // restriction on onesie merger
     pub bcjoin: bool,            // join only by barcode identity
     pub max_cdr3_diffs: usize,
+    pub max_cdr3_indel: usize,
     pub cdr3_mult: f64, // multiplier for checking CDR3 SHM concentration
     pub old_mult: bool,
     pub mult_pow: f64,
@@ -365,7 +387,7 @@ pub struct JoinAlgOpt {
 // These fall into 2 categories: 1) on by default and 2) user-specified.
 // Note that ClonoFiltOpt options are not allowed to affect intermediate computation.
 
-#[derive(Default, PartialEq)]
+#[derive(Debug, Default, PartialEq)]
 pub struct ClonoFiltOptDefault {
     pub marked_b: bool, // only print clonotypes having a mark and which are typed as B cells
     pub donor: bool,    // allow cells from different donors to be placed in the same clonotype
@@ -385,9 +407,25 @@ pub struct ClonoFiltOptDefault {
     pub bc_dup: bool,              // filter duplicated barcodes within an exact subclonotype
     pub signature: bool,           // signature filtering
     pub nmax: bool,                // turn off max contigs filter
+    pub mark_only: bool,           // evaluate default filters, but delete nothing
 }
 
-#[derive(Default)]
+// In mark-only mode (FILTER_IMPACT), every default filter is evaluated and its fate recorded,
+// whether or not it is turned on, but nothing is deleted.  Filters are coded as
+// "if eval(on) { find what to delete; if delete(on) { delete it } }", where on says whether the
+// filter is turned on.
+
+impl ClonoFiltOptDefault {
+    pub fn eval(&self, on: bool) -> bool {
+        on || self.mark_only
+    }
+
+    pub fn delete(&self, on: bool) -> bool {
+        on && !self.mark_only
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct ClonoFiltOpt {
     pub ncells_low: usize,   // only show clonotypes with at least this many cells
     pub ncells_high: usize,  // only show clonotypes with at most this many cells
@@ -669,7 +707,7 @@ impl TigData {
 // TigData0: data for each cell
 // TigData1: shared data
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct TigData0 {
     pub quals: Vec<u8>,                          // quality scores, truncated to V..J
     pub v_start: usize,                          // start of V on full contig sequence
@@ -692,7 +730,7 @@ pub struct TigData0 {
     pub v_ref_id: usize, // index of V segment reference sequence in ref file
 }
 
-#[derive(Clone, Default)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct Junction {
     pub hcomp: usize,                // junction alignment complexity
     pub matches: usize,              // matches
@@ -703,7 +741,7 @@ pub struct Junction {
     pub indels: Vec<(usize, isize)>, // indel tig start, size (+ ins, - del)
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct TigData1 {
     pub cdr3_dna: String,           // CDR3 DNA sequence
     pub seq: Vec<u8>,               // V..J contig subsequence
@@ -727,6 +765,9 @@ pub struct TigData1 {
     pub v_ref_id_donor_alt_id: Option<usize>, // alt ref id for donor id for v_ref_id_donor
     pub d_ref_id: Option<usize>,             // index of D segment reference sequence in ref file
     pub j_ref_id: usize,                     // index of J segment reference sequence in ref file
+    pub j_ref_id_donor: Option<usize>,       // optional index into alt_refs, for J
+    pub j_ref_id_donor_donor: Option<usize>, // donor id for j_ref_id_donor
+    pub j_ref_id_donor_alt_id: Option<usize>, // alt ref id for donor id for j_ref_id_donor
     pub c_ref_id: Option<usize>,             // index of C segment reference sequence in ref file
     pub fr1_start: usize,                    // start position in bases of FWR1 on V..J
     pub cdr1_start: Option<usize>,           // start position in bases of CDR1 on V..J
@@ -740,7 +781,7 @@ pub struct TigData1 {
     pub annv: Vec<(i32, i32, i32, i32, i32)>, // V annotation (one or two entries), for V..J
     pub vs: DnaString,      // reference V segment (possibly donor allele)
     pub vs_notesx: String,  // notes on reference V segment (probably to be replaced)
-    pub js: DnaString,      // reference J segment
+    pub js: DnaString,      // reference J segment (possibly donor allele)
     pub inkt_alpha_chain_gene_match: bool,
     pub inkt_alpha_chain_junction_match: bool,
     pub inkt_beta_chain_gene_match: bool,
@@ -762,7 +803,7 @@ impl TigData1 {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ExactClonotype {
     pub share: Vec<TigData1>,       // clone info that is shared
     pub clones: Vec<Vec<TigData0>>, // clone info, excluding shared stuff
@@ -800,7 +841,7 @@ impl ExactClonotype {
 // The vectors in a CloneInfo object mostly have length two.  The exceptions are in
 // improper clones (not having chains of both types).
 
-#[derive(Eq, Ord, PartialEq, PartialOrd, Default, Clone)] // not sure we need all these
+#[derive(Eq, Ord, PartialEq, PartialOrd, Default, Clone, Serialize, Deserialize)] // not sure we need all these
 pub struct CloneInfo {
     pub lens: Vec<usize>,   // V..J contig lengths (will sort by this)
     pub tigs: Vec<Vec<u8>>, // contigs, truncated to V..J (with possible - chars inserted)
@@ -871,6 +912,7 @@ pub struct ColInfo {
     pub vpids: Vec<Option<usize>>,
     pub dids: Vec<Option<usize>>,
     pub jids: Vec<usize>,
+    pub jpids: Vec<Option<usize>>,
     pub cids: Vec<Option<usize>>,
     pub fr1_starts: Vec<usize>,
     pub cdr1_starts: Vec<Option<usize>>,
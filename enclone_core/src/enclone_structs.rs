@@ -2,11 +2,12 @@
 
 use self::refx::RefData;
 use crate::{
-    barcode_fate::BarcodeFate,
+    barcode_fate::FateLedger,
     defs::{AlleleData, CloneInfo, EncloneControl, ExactClonotype, GexInfo},
 };
 use enclone_proto::types::DonorReferenceItem;
 use qd::Double;
+use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, time::Instant};
 use vdj_ann::refx;
 
@@ -49,18 +50,23 @@ pub struct EncloneIntermediates {
     pub ex: EncloneExacts,
 }
 
-#[derive(Default, Clone)]
+// The results of main_enclone_start.  These can be written to a checkpoint file (CHECKPOINT), except
+// for sr, which is recomputed when the checkpoint is read.
+
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct EncloneExacts {
     pub to_bc: HashMap<(usize, usize), Vec<String>>,
     pub exact_clonotypes: Vec<ExactClonotype>,
     pub raw_joins: Vec<Vec<usize>>,
     pub info: Vec<CloneInfo>,
     pub orbits: Vec<Vec<i32>>,
+    pub orbit_ids: Vec<usize>, // stable clonotype ids, parallel to orbits (see INCREMENTAL)
     pub vdj_cells: Vec<Vec<String>>,
     pub join_info: Vec<(usize, usize, bool, Vec<u8>)>,
     pub drefs: Vec<DonorReferenceItem>,
+    #[serde(skip)]
     pub sr: Vec<Vec<Double>>,
-    pub fate: Vec<HashMap<String, BarcodeFate>>, // GETS MODIFIED SUBSEQUENTLY
+    pub fate: Vec<FateLedger>, // GETS MODIFIED SUBSEQUENTLY
     pub is_bcr: bool,
     pub allele_data: AlleleData,
 }
@@ -92,7 +92,12 @@ pub fn heavy_complexity(
                 }
                 concat.extend(&drefx);
                 concat.extend(&d2ref);
-                let jref = refdata.refs[ex.share[r].j_ref_id].to_ascii_vec();
+                let mut jref = refdata.refs[ex.share[r].j_ref_id].to_ascii_vec();
+                if ex.share[r].j_ref_id_donor.is_some() {
+                    jref = dref[ex.share[r].j_ref_id_donor.unwrap()]
+                        .nt_sequence
+                        .clone();
+                }
                 let jend = jflank(seq, &jref);
                 let mut seq_start = vstart as isize;
                 // probably not exactly right
@@ -5,6 +5,7 @@ use crate::opt_d::jflank;
 use debruijn::{dna_string::ndiffs, Mer};
 use enclone_proto::types::DonorReferenceItem;
 use qd::{dd, Double};
+use rayon::prelude::*;
 use stats_utils::abs_diff;
 use std::cmp::min;
 use std::collections::HashMap;
@@ -44,6 +45,63 @@ pub fn p_at_most_m_distinct_in_sample_of_x_from_n_double(
 
 // ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
 
+// This is a copy of stirling2_ratio_table from the stirling_numbers crate, that has been modified
+// to use higher precision internal math.  This has also been speeded up, and in the process
+// made less readable.
+
+pub fn stirling2_ratio_table_double(n_max: usize) -> Vec<Vec<Double>> {
+    let mut s = Vec::<Vec<Double>>::new();
+    let zero = dd![0.0];
+    let one = dd![1.0];
+    for n in 0..=n_max {
+        s.push(vec![zero; n + 1]);
+    }
+    s[0][0] = one;
+    let mut z = Vec::<Double>::new();
+    let mut n2n1 = vec![dd![0.0]; n_max + 1];
+    for (n, nn) in n2n1.iter_mut().enumerate().skip(2) {
+        *nn = Double::from((n - 2) as u32) / Double::from((n - 1) as u32);
+    }
+    let mut k1k = vec![dd![0.0]; n_max];
+    for (k, kk) in k1k.iter_mut().enumerate().skip(1) {
+        *kk = Double::from((k - 1) as u32) / Double::from(k as u32);
+    }
+    let mut njn = Vec::<(usize, Double)>::new();
+    for i in 0..n_max + 1 {
+        njn.push((i, dd![0.0]));
+    }
+    njn.par_iter_mut().for_each(|res| {
+        let n = res.0;
+        if n >= 1 {
+            let mut p = one;
+            for j in 1..=n {
+                p *= Double::from(j as u32) / Double::from(n as u32);
+            }
+            res.1 = p;
+        }
+    });
+
+    // This is the slow part of the function.
+
+    for n in 1..=n_max {
+        s[n][0] = zero;
+        for k in 1..n - 1 {
+            z[k - 1] *= k1k[k];
+        }
+        if n >= 2 {
+            z.push(n2n1[n].powi((n - 1) as i32));
+        }
+        for k in 1..n {
+            let x = z[k - 1]; // = ((k-1)/k)^(n-1)
+            s[n][k] = s[n - 1][k] + s[n - 1][k - 1] * x;
+        }
+        s[n][n] = njn[n].1;
+    }
+    s
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
 // partial_bernoulli_sum( n, k ): return sum( choose(n,i), i = 0..=k ).
 //
 // Beware of overflow.
@@ -63,6 +121,72 @@ pub fn partial_bernoulli_sum(n: usize, k: usize) -> f64 {
 
 // ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
 
+/// The rules by which join_one rejects a pair of info entries, in the order in which they are
+/// applied, followed by the higher bar that join_exacts imposes on joins of two cells.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum JoinRejection {
+    /// An exact subclonotype does not have two or three chains.
+    Chains,
+    /// The CDR3s have different lengths, and do not differ by an indel that can be aligned.
+    Cdr3Length,
+    /// The V or J reference sequences differ, as required by some join algorithms, or have
+    /// different lengths.
+    Reference,
+    /// The CDR3 nucleotide identity is too low (JOIN_CDR3_IDENT, JOIN_BASIC, JOIN_BASIC_H or
+    /// BASICX).
+    Cdr3Ident,
+    /// The V..J amino acid identity is too low (JOIN_FULL_DIFF).
+    FullDiff,
+    /// Too many differences in V..J (MAX_DIFFS, or more than five for TCR).
+    MaxDiffs,
+    /// Too many CDR3 differences (MAX_CDR3_DIFFS, or any for TCR).
+    MaxCdr3Diffs,
+    /// The exact subclonotypes are from different donors.
+    Donor,
+    /// The two reference sequences are too different (MAX_DEGRADATION).
+    MaxDegradation,
+    /// The exact subclonotypes share a barcode.
+    BarcodeOverlap,
+    /// Somatic hypermutation is concentrated in the junction regions (CDR3_MULT).
+    Cdr3Mult,
+    /// The light chains were assigned different constant regions.
+    LightConstant,
+    /// The score is too high (MAX_SCORE), and there are too few shared mutations (AUTO_SHARE).
+    MaxScore,
+    /// The V genes differ, and so do their reference sequences.
+    VGene,
+    /// The heavy chain FWR1 identity exceeds the CDR1-2 identity by too much
+    /// (FWR1_CDR12_DELTA).
+    Fwr1Cdr12Delta,
+    /// A join of two cells that was rejected by the higher bar imposed by join_exacts.
+    TwoCellBar,
+}
+
+impl JoinRejection {
+    pub fn label(&self) -> &'static str {
+        match self {
+            JoinRejection::Chains => "CHAINS",
+            JoinRejection::Cdr3Length => "CDR3_LENGTH",
+            JoinRejection::Reference => "REFERENCE",
+            JoinRejection::Cdr3Ident => "CDR3_IDENT",
+            JoinRejection::FullDiff => "FULL_DIFF",
+            JoinRejection::MaxDiffs => "MAX_DIFFS",
+            JoinRejection::MaxCdr3Diffs => "MAX_CDR3_DIFFS",
+            JoinRejection::Donor => "DONOR",
+            JoinRejection::MaxDegradation => "MAX_DEGRADATION",
+            JoinRejection::BarcodeOverlap => "BARCODE_OVERLAP",
+            JoinRejection::Cdr3Mult => "CDR3_MULT",
+            JoinRejection::LightConstant => "LIGHT_CONSTANT",
+            JoinRejection::MaxScore => "MAX_SCORE",
+            JoinRejection::VGene => "V_GENE",
+            JoinRejection::Fwr1Cdr12Delta => "FWR1_CDR12_DELTA",
+            JoinRejection::TwoCellBar => "TWO_CELL_BAR",
+        }
+    }
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
 pub fn join_one<'a>(
     is_bcr: bool,
     k1: usize,
@@ -76,35 +200,74 @@ pub fn join_one<'a>(
     refdata: &RefData,
     dref: &[DonorReferenceItem],
 ) -> bool {
+    let mut pj = PotentialJoin {
+        k1,
+        k2,
+        ..Default::default()
+    };
+    let joined = join_one_explained(
+        is_bcr,
+        &info[k1],
+        &info[k2],
+        ctl,
+        exact_clonotypes,
+        to_bc,
+        sr,
+        refdata,
+        dref,
+        &mut pj,
+    )
+    .is_ok();
+    if joined {
+        pot.push(pj);
+    }
+    joined
+}
+
+// Same as join_one, but instead of saving the potential join, fill in pj, and if the join is
+// rejected, return the rule that rejected it.  In that case pj has only the evidence that was
+// gathered before the rule was applied.
+
+pub fn join_one_explained<'a>(
+    is_bcr: bool,
+    info1: &CloneInfo,
+    info2: &CloneInfo,
+    ctl: &EncloneControl,
+    exact_clonotypes: &[ExactClonotype],
+    to_bc: &'a HashMap<(usize, usize), Vec<String>>,
+    sr: &[Vec<Double>],
+    refdata: &RefData,
+    dref: &[DonorReferenceItem],
+    pj: &mut PotentialJoin<'a>,
+) -> Result<(), JoinRejection> {
     // Do not merge onesies or foursies with anything.  Deferred until later.
     // Note that perhaps some foursies should be declared doublets and deleted.
     // Note onesies merging above is turned off so this appears to be moot.
 
-    let (info1, info2) = (&info[k1], &info[k2]);
     let (clono1, clono2) = (info1.clonotype_id, info2.clonotype_id);
     let chains1 = exact_clonotypes[clono1].share.len();
     let chains2 = exact_clonotypes[clono2].share.len();
     if !(2..=3).contains(&chains1) || !(2..=3).contains(&chains2) {
-        return false;
+        return Err(JoinRejection::Chains);
     }
     // NEED FOR THIS SEEMS LIKE A BUG:
     if info1.vs.len() == 1 || info2.vs.len() == 4 {
-        return false;
+        return Err(JoinRejection::Chains);
     }
     if info1.vs.len() > 2 {
-        return false;
+        return Err(JoinRejection::Chains);
     }
 
     // Require that CDR3s have the same length.  Ugly.
     // First part should be a tautology.
 
-    let (x1, x2) = (&info[k1].cdr3s, &info[k2].cdr3s);
+    let (x1, x2) = (&info1.cdr3s, &info2.cdr3s);
     if x1.len() != x2.len() {
-        return false;
+        return Err(JoinRejection::Cdr3Length);
     }
     for i in 0..x1.len() {
         if x1[i].len() != x2[i].len() {
-            return false;
+            return Err(JoinRejection::Cdr3Length);
         }
     }
 
@@ -125,7 +288,7 @@ pub fn join_one<'a>(
             .take(chains)
         {
             if z1.len() != z2.len() || vs1 != vs2 || js1 != js2 {
-                return false;
+                return Err(JoinRejection::Reference);
             }
             let mut cd = 0;
             for (z1m, z2m) in z1.as_bytes().iter().zip(z2.as_bytes().iter()) {
@@ -139,29 +302,24 @@ pub fn join_one<'a>(
                 (100.0 - ctl.join_alg_opt.basic_h.unwrap()) / 100.0
             };
             if cd as f64 / (z1.len() as f64) > limit {
-                return false;
+                return Err(JoinRejection::Cdr3Ident);
             }
         }
-        pot.push(PotentialJoin {
-            k1,
-            k2,
-            ..Default::default()
-        });
-        return true;
+        return Ok(());
     }
 
     // Test for BASICX.
 
     if ctl.join_alg_opt.basicx {
-        let (x1, x2) = (&info[k1].cdr3s, &info[k2].cdr3s);
+        let (x1, x2) = (&info1.cdr3s, &info2.cdr3s);
         let mut cd = 0;
         let mut total = 0;
         for z in 0..2 {
             if x1[z].len() != x2[z].len() {
-                return false;
+                return Err(JoinRejection::Cdr3Length);
             }
-            if info[k1].vs[z] != info[k2].vs[z] || info[k1].js[z] != info[k2].js[z] {
-                return false;
+            if info1.vs[z] != info2.vs[z] || info1.js[z] != info2.js[z] {
+                return Err(JoinRejection::Reference);
             }
             for m in 0..x1[z].len() {
                 total += 1;
@@ -171,55 +329,45 @@ pub fn join_one<'a>(
             }
         }
         if cd as f64 / total as f64 > 0.1 {
-            return false;
+            return Err(JoinRejection::Cdr3Ident);
         }
-        pot.push(PotentialJoin {
-            k1,
-            k2,
-            ..Default::default()
-        });
-        return true;
+        return Ok(());
     }
 
     // Test for JOIN_FULL_DIFF.
 
     if ctl.join_alg_opt.join_full_diff {
-        let (x1, x2) = (&info[k1].cdr3s, &info[k2].cdr3s);
+        let (x1, x2) = (&info1.cdr3s, &info2.cdr3s);
         let (mut diffs, mut total) = (0, 0);
         for z in 0..2 {
             if x1[z].len() != x2[z].len() {
-                return false;
+                return Err(JoinRejection::Cdr3Length);
             }
-            if info[k1].vs[z] != info[k2].vs[z] || info[k1].js[z] != info[k2].js[z] {
-                return false;
+            if info1.vs[z] != info2.vs[z] || info1.js[z] != info2.js[z] {
+                return Err(JoinRejection::Reference);
             }
-            for p in 0..info[k1].tigs_amino[z].len() {
+            for p in 0..info1.tigs_amino[z].len() {
                 total += 1;
-                if info[k1].tigs_amino[z][p] != info[k2].tigs_amino[z][p] {
+                if info1.tigs_amino[z][p] != info2.tigs_amino[z][p] {
                     diffs += 1;
                 }
             }
         }
         if diffs as f64 / total as f64 > 0.1 {
-            return false;
+            return Err(JoinRejection::FullDiff);
         }
-        pot.push(PotentialJoin {
-            k1,
-            k2,
-            ..Default::default()
-        });
-        return true;
+        return Ok(());
     }
 
     // Put identity filter on CDR3s for BCR.
 
     if is_bcr {
-        let (x1, x2) = (&info[k1].cdr3s, &info[k2].cdr3s);
+        let (x1, x2) = (&info1.cdr3s, &info2.cdr3s);
         let mut cd = 0;
         let mut total = 0;
         for z in 0..2 {
             if x1[z].len() != x2[z].len() {
-                return false;
+                return Err(JoinRejection::Cdr3Length);
             }
             for m in 0..x1[z].len() {
                 if x1[z].as_bytes()[m] != x2[z].as_bytes()[m] {
@@ -229,17 +377,17 @@ pub fn join_one<'a>(
             total += x1[z].len();
         }
         if cd as f64 / total as f64 > 1.0 - ctl.join_alg_opt.join_cdr3_ident / 100.0 {
-            return false;
+            return Err(JoinRejection::Cdr3Ident);
         }
     }
 
     // Compute number of differences.  The default behavior is that this is applied only to TCR.
 
-    let (x1, x2) = (&info[k1].cdr3s, &info[k2].cdr3s);
+    let (x1, x2) = (&info1.cdr3s, &info2.cdr3s);
     if !is_bcr || ctl.heur.max_diffs < 1_000_000 {
         let mut diffs = 0_usize;
-        for x in 0..info[k1].lens.len() {
-            if !info[k1].has_del[x] && !info[k2].has_del[x] {
+        for x in 0..info1.lens.len() {
+            if !info1.has_del[x] && !info2.has_del[x] {
                 // A great deal of time is spent in the call to ndiffs.  Notes on this:
                 // 1. It is slower than if the computation is done outside
                 //    the ndiffs function.  This is mysterious but must have something to
@@ -249,44 +397,51 @@ pub fn join_one<'a>(
                 // 3. Adding a bounds test for diffs > ctl.heur.max_diffs inside the ndiffs
                 //    function doesn't help, whether placed in the inner loop or the other
                 //    loop.
-                diffs += ndiffs(&info[k1].tigsp[x], &info[k2].tigsp[x]);
+                diffs += ndiffs(&info1.tigsp[x], &info2.tigsp[x]);
             } else {
-                for j in 0..info[k1].tigs[x].len() {
-                    if info[k1].tigs[x][j] != info[k2].tigs[x][j] {
+                for j in 0..info1.tigs[x].len() {
+                    if info1.tigs[x][j] != info2.tigs[x][j] {
                         diffs += 1;
                     }
                 }
             }
         }
         if diffs > ctl.heur.max_diffs {
-            return false;
+            pj.diffs = diffs;
+            return Err(JoinRejection::MaxDiffs);
         }
         if !is_bcr && diffs > 5 {
-            return false;
+            pj.diffs = diffs;
+            return Err(JoinRejection::MaxDiffs);
         }
     }
 
-    // Compute junction diffs.
+    // Compute junction diffs.  These include one for each CDR3 indel, if join_one_indel
+    // aligned the CDR3s with a gap.
 
     let mut cd = 0_isize;
     let mut hcd = 0_isize;
     for l in 0..x1.len() {
-        for m in 0..x1[l].len() {
-            if x1[l].as_bytes()[m] != x2[l].as_bytes()[m] {
-                if l == 0 {
-                    hcd += 1;
-                }
-                cd += 1;
-            }
+        let diffs = cdr3_diffs(x1[l].as_bytes(), x2[l].as_bytes()) as isize;
+        if l == 0 {
+            hcd += diffs;
         }
+        cd += diffs;
     }
+    let indels: usize = x1
+        .iter()
+        .chain(x2.iter())
+        .map(|x| gap_runs(x.as_bytes()))
+        .sum();
+
+    pj.cd = cd;
 
     // Cap CDR3 diffs for TCR or as requested.
 
     if (ctl.join_alg_opt.max_cdr3_diffs < 1000 || !is_bcr)
         && (cd > ctl.join_alg_opt.max_cdr3_diffs as isize || (!is_bcr && cd > 0))
     {
-        return false;
+        return Err(JoinRejection::MaxCdr3Diffs);
     }
 
     // Unless MIX_DONORS specified, do not join across donors.
@@ -299,8 +454,8 @@ pub fn join_one<'a>(
     // exaggerated number of fails.
 
     let (mut donors1, mut donors2) = (Vec::<usize>::new(), Vec::<usize>::new());
-    let ex1 = &exact_clonotypes[info[k1].clonotype_index];
-    let ex2 = &exact_clonotypes[info[k2].clonotype_index];
+    let ex1 = &exact_clonotypes[info1.clonotype_index];
+    let ex2 = &exact_clonotypes[info2.clonotype_index];
     for j in 0..ex1.clones.len() {
         if ex1.clones[j][0].donor_index.is_some() {
             donors1.push(ex1.clones[j][0].donor_index.unwrap());
@@ -318,9 +473,10 @@ pub fn join_one<'a>(
         && !donors2.is_empty()
         && donors1 != donors2
     {
-        return false;
+        return Err(JoinRejection::Donor);
     }
     let err = donors1 != donors2 || donors1.len() != 1 || donors2.len() != 1;
+    pj.err = err;
 
     // Analyze the two clonotypes versus the reference.  First traverse the reference
     // sequences.  Either we use the references for k1 or the references for k2, but
@@ -328,7 +484,7 @@ pub fn join_one<'a>(
 
     let mut nrefs = 1;
     for m in 0..2 {
-        if info[k1].vs[m] != info[k2].vs[m] || info[k1].js[m] != info[k2].js[m] {
+        if info1.vs[m] != info2.vs[m] || info1.js[m] != info2.js[m] {
             nrefs = 2;
         }
     }
@@ -341,22 +497,18 @@ pub fn join_one<'a>(
     let mut share_pos_v = vec![Vec::<usize>::new(); 2];
     let mut share_pos_j = vec![Vec::<usize>::new(); 2];
     for u in 0..nrefs {
-        let k = if u == 0 { k1 } else { k2 };
+        let infok = if u == 0 { info1 } else { info2 };
 
         // Traverse the chains in the clonotype.
 
-        let nchains = info[k1].lens.len();
+        let nchains = info1.lens.len();
         for m in 0..nchains {
-            let (tig1, tig2) = (&info[k1].tigs[m], &info[k2].tigs[m]);
+            let (tig1, tig2) = (&info1.tigs[m], &info2.tigs[m]);
 
             // Traverse the two segments (V and J).
 
             for si in 0..2 {
-                let seg = if si == 0 {
-                    &info[k].vs[m]
-                } else {
-                    &info[k].js[m]
-                };
+                let seg = if si == 0 { &infok.vs[m] } else { &infok.js[m] };
                 let ref_trim = if si == 1 {
                     ctl.heur.ref_j_trim
                 } else {
@@ -369,7 +521,7 @@ pub fn join_one<'a>(
                         // Ugly bailout arising very rarely if the two reference
                         // sequences have different lengths.
                         if p >= tig1.len() || p >= tig2.len() {
-                            return false;
+                            return Err(JoinRejection::Reference);
                         }
                         t1 = tig1[p];
                         t2 = tig2[p];
@@ -399,6 +551,12 @@ pub fn join_one<'a>(
                             r = b'T';
                         }
                     }
+                    // Skip the gap put in a CDR3 by join_one_indel.  The indel is counted
+                    // once, as a CDR3 difference.
+
+                    if t1 == b'-' || t2 == b'-' {
+                        continue;
+                    }
                     if t1 == t2 && t1 != r {
                         shares[u] += 1;
                         if m == 1 {
@@ -428,37 +586,44 @@ pub fn join_one<'a>(
         }
     }
 
+    pj.nrefs = nrefs;
+    pj.shares = shares;
+    pj.indeps = indeps;
+    pj.shares_details = shares_details;
+    pj.share_pos_v = share_pos_v;
+    pj.share_pos_j = share_pos_j;
+
     // Don't allow different references if one is strongly favored.
     // (not documented)
 
     if nrefs == 2 {
         for m in 0..2 {
             if abs_diff(total[0][m], total[1][m]) > ctl.heur.max_degradation {
-                return false;
+                return Err(JoinRejection::MaxDegradation);
             }
         }
     }
 
     // Another test for acceptable join.  (not fully documented)
 
-    let min_shares = shares.iter().min().unwrap();
+    let min_shares = *pj.shares.iter().min().unwrap();
     let _min_shares1 = shares1.iter().min().unwrap();
     let _min_shares2 = shares2.iter().min().unwrap();
-    let min_indeps = indeps.iter().min().unwrap();
+    let min_indeps = *pj.indeps.iter().min().unwrap();
 
     // Reject if barcode overlap. (not documented)
 
     let (mut bcs1, mut bcs2) = (Vec::<&'a str>::new(), Vec::<&'a str>::new());
-    for origin in info[k1].origin.iter() {
+    for origin in info1.origin.iter() {
         bcs1.extend(
-            to_bc[&(*origin, info[k1].clonotype_id)]
+            to_bc[&(*origin, info1.clonotype_id)]
                 .iter()
                 .map(String::as_str),
         );
     }
-    for origin in info[k2].origin.iter() {
+    for origin in info2.origin.iter() {
         bcs2.extend(
-            to_bc[&(*origin, info[k2].clonotype_id)]
+            to_bc[&(*origin, info2.clonotype_id)]
                 .iter()
                 .map(String::as_str),
         );
@@ -466,28 +631,28 @@ pub fn join_one<'a>(
     unique_sort(&mut bcs1);
     unique_sort(&mut bcs2);
     if meet(&bcs1, &bcs2) {
-        return false;
+        return Err(JoinRejection::BarcodeOverlap);
     }
 
     // Test for concentration of SHM in the junction regions.
 
-    if cd as f64 >= ctl.join_alg_opt.cdr3_mult * std::cmp::max(1, *min_indeps) as f64 {
-        return false;
+    if cd as f64 >= ctl.join_alg_opt.cdr3_mult * std::cmp::max(1, min_indeps) as f64 {
+        return Err(JoinRejection::Cdr3Mult);
     }
 
     // Do not merge cells if they were assigned different light chain constant regions.
     // Unless cd = 0.
 
     if !ctl.join_alg_opt.old_light {
-        for i in 0..info[k1].cdr3s.len() {
-            let (j1, j2) = (info[k1].exact_cols[i], info[k2].exact_cols[i]);
+        for i in 0..info1.cdr3s.len() {
+            let (j1, j2) = (info1.exact_cols[i], info2.exact_cols[i]);
             if !ex1.share[j1].left
                 && ex1.share[j1].c_ref_id.is_some()
                 && ex2.share[j2].c_ref_id.is_some()
                 && ex1.share[j1].c_ref_id.unwrap() != ex2.share[j2].c_ref_id.unwrap()
                 && cd > 0
             {
-                return false;
+                return Err(JoinRejection::LightConstant);
             }
         }
     }
@@ -496,9 +661,9 @@ pub fn join_one<'a>(
     // objects from n = 3 * (sum of VJ contig lengths) yields d = min_shares or
     // more duplicates.
 
-    let n = 3 * (info[k1].tigs[0].len() + info[k1].tigs[1].len());
-    let k = *min_indeps + 2 * *min_shares;
-    let d = *min_shares;
+    let n = 3 * (info1.tigs[0].len() + info1.tigs[1].len());
+    let k = min_indeps + 2 * min_shares;
+    let d = min_shares;
     let p1 = p_at_most_m_distinct_in_sample_of_x_from_n_double((k - d) as usize, k as usize, n, sr);
     assert!(!p1.is_infinite()); // TODO: IS THIS SAFE?
 
@@ -514,20 +679,10 @@ pub fn join_one<'a>(
     } else {
         // mult = ctl.join_alg_opt.mult_pow.powi(cd as i32);
 
-        let mut cd1 = 0;
+        let cd1 = cdr3_diffs(x1[0].as_bytes(), x2[0].as_bytes());
         let n1 = x1[0].len();
-        for m in 0..x1[0].len() {
-            if x1[0].as_bytes()[m] != x2[0].as_bytes()[m] {
-                cd1 += 1;
-            }
-        }
-        let mut cd2 = 0;
+        let cd2 = cdr3_diffs(x1[1].as_bytes(), x2[1].as_bytes());
         let n2 = x1[1].len();
-        for m in 0..x1[1].len() {
-            if x1[1].as_bytes()[m] != x2[1].as_bytes()[m] {
-                cd2 += 1;
-            }
-        }
         let cdx = ctl.join_alg_opt.cdr3_normal_len;
         mult = ctl
             .join_alg_opt
@@ -542,21 +697,27 @@ pub fn join_one<'a>(
     // Compute score.
 
     let score = p1 * mult;
+    pj.score = score;
+    pj.p1 = p1;
+    pj.mult = mult;
+    pj.k = k;
+    pj.d = d;
+    pj.n = n;
 
     // Apply JUN_SHARE.
 
     let mut accept = false;
     if ctl.join_alg_opt.comp_filt < 1_000_000
         && score > ctl.join_alg_opt.max_score
-        && *min_shares < ctl.join_alg_opt.auto_share as isize
+        && min_shares < ctl.join_alg_opt.auto_share as isize
         && (ctl.join_alg_opt.comp_filt_bound == 0
-            || *min_indeps as usize <= ctl.join_alg_opt.comp_filt_bound)
+            || min_indeps as usize <= ctl.join_alg_opt.comp_filt_bound)
         && ex1.share.len() == 2
         && ex2.share.len() == 2
         && ex1.share[0].left != ex1.share[1].left
     {
-        let h1 = info[k1].exact_cols[0];
-        let h2 = info[k2].exact_cols[0];
+        let h1 = info1.exact_cols[0];
+        let h2 = info2.exact_cols[0];
         let comp = min(ex1.share[h1].jun.hcomp, ex2.share[h2].jun.hcomp);
         if comp as isize - cd >= ctl.join_alg_opt.comp_filt as isize {
             /*
@@ -565,7 +726,10 @@ pub fn join_one<'a>(
             println!("cdr3: {}", ex2.share[h2].cdr3_aa);
             */
             accept = true;
-        } else if ctl.join_alg_opt.super_comp_filt > 0 && score > ctl.join_alg_opt.max_score {
+        } else if ctl.join_alg_opt.super_comp_filt > 0
+            && score > ctl.join_alg_opt.max_score
+            && indels == 0
+        {
             let vstart = ex1.share[h1].jun.vstart;
             let indels = &ex1.share[h1].jun.indels;
             let v_ref_id = ex1.share[h1].v_ref_id;
@@ -605,7 +769,12 @@ pub fn join_one<'a>(
                         for &di in d {
                             concat.append(&mut refdata.refs[di].to_ascii_vec());
                         }
-                        let jref = refdata.refs[j_ref_id].to_ascii_vec();
+                        let mut jref = refdata.refs[j_ref_id].to_ascii_vec();
+                        if ex1.share[h1].j_ref_id_donor.is_some() {
+                            jref = dref[ex1.share[h1].j_ref_id_donor.unwrap()]
+                                .nt_sequence
+                                .clone();
+                        }
                         let jend = jflank(&seq1, &jref); // note using seq1
                         let jref = &jref[0..jend];
                         concat.extend(jref);
@@ -666,7 +835,7 @@ pub fn join_one<'a>(
                             fwriteln!(log, "cdr3: {}", ex1.share[h1].cdr3_aa);
                             fwriteln!(log, "cdr3: {}", ex2.share[h2].cdr3_aa);
 
-                            let (j1, j2) = (info[k1].exact_cols[0], info[k2].exact_cols[0]);
+                            let (j1, j2) = (info1.exact_cols[0], info2.exact_cols[0]);
                             let (x1, x2) = (&ex1.share[j1], &ex2.share[j2]);
                             let (v1, v2) = (x1.v_ref_id, x2.v_ref_id);
                             let (n1, n2) = (refdata.name[v1].clone(), refdata.name[v2].clone());
@@ -692,8 +861,8 @@ pub fn join_one<'a>(
                             fwriteln!(log, "concat = {}", strme(&concat));
                             */
                             fwriteln!(log, "heavy junction share = {}", share);
-                            fwriteln!(log, "non junction share = {}", *min_shares);
-                            fwriteln!(log, "indep mutations outside = {}", *min_indeps);
+                            fwriteln!(log, "non junction share = {}", min_shares);
+                            fwriteln!(log, "indep mutations outside = {}", min_indeps);
                             fwriteln!(log, "cd = {}", cd);
                             fwriteln!(log, "hcd = {}", hcd);
                             print!("{}", strme(&log));
@@ -709,9 +878,9 @@ pub fn join_one<'a>(
 
     if !accept
         && score > ctl.join_alg_opt.max_score
-        && *min_shares < ctl.join_alg_opt.auto_share as isize
+        && min_shares < ctl.join_alg_opt.auto_share as isize
     {
-        return false;
+        return Err(JoinRejection::MaxScore);
     }
 
     // If V gene names are different (after removing trailing *...), and either
@@ -719,8 +888,8 @@ pub fn join_one<'a>(
     // • or 5' UTR reference sequences are different, after truncation on left to the same length,
     // then the join is rejected.
 
-    for i in 0..info[k1].cdr3s.len() {
-        let (j1, j2) = (info[k1].exact_cols[i], info[k2].exact_cols[i]);
+    for i in 0..info1.cdr3s.len() {
+        let (j1, j2) = (info1.exact_cols[i], info2.exact_cols[i]);
         let (x1, x2) = (&ex1.share[j1], &ex2.share[j2]);
         let (v1, v2) = (x1.v_ref_id, x2.v_ref_id);
         let (mut n1, mut n2) = (refdata.name[v1].clone(), refdata.name[v2].clone());
@@ -734,13 +903,13 @@ pub fn join_one<'a>(
             let (y1, y2) = (&refdata.refs[v1], &refdata.refs[v2]);
             if y1.len() == y2.len() {
                 if y1 != y2 {
-                    return false;
+                    return Err(JoinRejection::VGene);
                 }
             } else {
                 let n = min(y1.len(), y2.len());
                 for m in 0..n {
                     if y1.get(m) != y2.get(m) {
-                        return false;
+                        return Err(JoinRejection::VGene);
                     }
                 }
             }
@@ -750,7 +919,7 @@ pub fn join_one<'a>(
                 let n = min(x1.len(), x2.len());
                 for m in 0..n {
                     if x1.get(x1.len() - 1 - m) != x2.get(x2.len() - 1 - m) {
-                        return false;
+                        return Err(JoinRejection::VGene);
                     }
                 }
             }
@@ -763,11 +932,11 @@ pub fn join_one<'a>(
     // percent heavy chain nuke identity on CDR12
     // is less than 20.
 
-    let nchains = info[k1].lens.len();
+    let nchains = info1.lens.len();
     let (mut fwr1_len, mut cdr1_len, mut cdr2_len) = (0, 0, 0);
     let (mut fwr1_diffs, mut cdr1_diffs, mut cdr2_diffs) = (0, 0, 0);
     for m in 0..nchains {
-        let (j1, j2) = (info[k1].exact_cols[m], info[k2].exact_cols[m]);
+        let (j1, j2) = (info1.exact_cols[m], info2.exact_cols[m]);
         let (x1, x2) = (&ex1.share[j1], &ex2.share[j2]);
         if x1.left {
             if x1.cdr1_start.is_some() && x2.cdr1_start.is_some() {
@@ -844,7 +1013,7 @@ pub fn join_one<'a>(
         let diffs = cdr1_diffs + cdr2_diffs;
         let cdr12_identity = 100.0 * (len - diffs) as f64 / len as f64;
         if fwr1_identity - cdr12_identity >= ctl.join_alg_opt.fwr1_cdr12_delta {
-            return false;
+            return Err(JoinRejection::Fwr1Cdr12Delta);
         }
     }
 
@@ -856,27 +1025,207 @@ pub fn join_one<'a>(
         bcs1.clear();
         bcs2.clear();
     }
-    let diffs = 0; // no longer computed
-    pot.push(PotentialJoin {
-        k1,
-        k2,
-        nrefs,
-        cd,
-        diffs,
-        bcs1,
-        bcs2,
-        shares,
-        indeps,
-        shares_details,
-        share_pos_v,
-        share_pos_j,
-        score,
-        err,
-        p1,
-        mult,
-        k,
-        d,
-        n,
-    });
-    true
+    pj.bcs1 = bcs1;
+    pj.bcs2 = bcs2;
+    Ok(())
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+// Count the positions at which two CDR3 nucleotide sequences of the same length differ, except
+// that a run of gap characters, representing an indel, counts as one difference.
+
+fn cdr3_diffs(x: &[u8], y: &[u8]) -> usize {
+    let mut diffs = 0;
+    for m in 0..x.len() {
+        if x[m] != y[m] {
+            let extends_gap =
+                m > 0 && ((x[m] == b'-' && x[m - 1] == b'-') || (y[m] == b'-' && y[m - 1] == b'-'));
+            if !extends_gap {
+                diffs += 1;
+            }
+        }
+    }
+    diffs
+}
+
+// Count the runs of gap characters in a CDR3 nucleotide sequence.
+
+fn gap_runs(x: &[u8]) -> usize {
+    (0..x.len())
+        .filter(|&m| x[m] == b'-' && (m == 0 || x[m - 1] != b'-'))
+        .count()
+}
+
+// Find the place to put a gap of length x.len() - y.len() in y, so as to minimize the number
+// of mismatches with x.  Return the position of the gap in y, and the number of mismatches.
+// Ties are broken in favor of the leftmost position.
+
+fn place_gap(x: &[u8], y: &[u8]) -> (usize, usize) {
+    let g = x.len() - y.len();
+
+    // mis_left[q] = mismatches between x[..q] and y[..q]
+    // mis_right[q] = mismatches between x[q + g..] and y[q..]
+
+    let mut mis_left = vec![0; y.len() + 1];
+    for q in 0..y.len() {
+        mis_left[q + 1] = mis_left[q] + usize::from(x[q] != y[q]);
+    }
+    let mut mis_right = vec![0; y.len() + 1];
+    for q in (0..y.len()).rev() {
+        mis_right[q] = mis_right[q + 1] + usize::from(x[q + g] != y[q]);
+    }
+    let mut best = (0, usize::MAX);
+    for q in 0..=y.len() {
+        let mis = mis_left[q] + mis_right[q];
+        if mis < best.1 {
+            best = (q, mis);
+        }
+    }
+    best
+}
+
+// Determine if two info entries could be joined across a CDR3 indel, and if so, return the
+// chain having the indel.  This requires that the CDR3s differ in length in exactly one chain,
+// by a nonzero multiple of three that is at most MAX_CDR3_INDEL, and that the contigs have the
+// same lengths outside the CDR3s, so that they can be aligned by putting a gap in the CDR3.
+
+pub fn cdr3_indel_chain(
+    ctl: &EncloneControl,
+    info1: &CloneInfo,
+    info2: &CloneInfo,
+) -> Option<usize> {
+    if info1.cdr3s.len() != 2 || info2.cdr3s.len() != 2 {
+        return None;
+    }
+    let mut chain = None;
+    for z in 0..2 {
+        let (c1, c2) = (info1.cdr3s[z].len(), info2.cdr3s[z].len());
+        if info1.lens[z] - c1 != info2.lens[z] - c2 {
+            return None;
+        }
+        if c1 != c2 {
+            let g = abs_diff(c1, c2);
+            if chain.is_some() || g % 3 != 0 || g > ctl.join_alg_opt.max_cdr3_indel {
+                return None;
+            }
+            chain = Some(z);
+        }
+    }
+    chain
+}
+
+// Same as join_one_explained, but for two info entries whose CDR3s differ by an indel, as
+// determined by cdr3_indel_chain.  The shorter CDR3 is aligned to the longer one by putting a
+// gap in it, and the gap is put in the same place in its contig.  Then join_one_explained
+// is applied to the gapped info entries, so that the indel is treated as a CDR3 difference.
+// This is only used for BCR.
+
+pub fn join_one_indel<'a>(
+    k1: usize,
+    k2: usize,
+    ctl: &EncloneControl,
+    exact_clonotypes: &[ExactClonotype],
+    info: &[CloneInfo],
+    to_bc: &'a HashMap<(usize, usize), Vec<String>>,
+    sr: &[Vec<Double>],
+    refdata: &RefData,
+    dref: &[DonorReferenceItem],
+    pj: &mut PotentialJoin<'a>,
+) -> Result<(), JoinRejection> {
+    let z = cdr3_indel_chain(ctl, &info[k1], &info[k2]).ok_or(JoinRejection::Cdr3Length)?;
+    let (long, short) = if info[k1].cdr3s[z].len() > info[k2].cdr3s[z].len() {
+        (k1, k2)
+    } else {
+        (k2, k1)
+    };
+    let (x, y) = (
+        info[long].cdr3s[z].as_bytes(),
+        info[short].cdr3s[z].as_bytes(),
+    );
+    let (q, mis) = place_gap(x, y);
+    let g = x.len() - y.len();
+
+    // Apply the CDR3 identity filter before copying anything.  This is the same as the filter
+    // in join_one_explained, which counts each gap position as a difference.
+
+    let w = 1 - z;
+    let (u, v) = (info[k1].cdr3s[w].as_bytes(), info[k2].cdr3s[w].as_bytes());
+    let cd = mis + g + u.iter().zip(v.iter()).filter(|(a, b)| a != b).count();
+    let total = x.len() + u.len();
+    if cd as f64 / total as f64 > 1.0 - ctl.join_alg_opt.join_cdr3_ident / 100.0 {
+        return Err(JoinRejection::Cdr3Ident);
+    }
+
+    // Locate the CDR3 in the contig of the shorter entry.  Whatever follows the CDR3 in the
+    // contig is as in the V..J sequence, but a V segment indel may precede it.
+
+    let s = &info[short];
+    let t = &exact_clonotypes[s.clonotype_index].share[s.exact_cols[z]];
+    let tail = t.seq.len().saturating_sub(t.cdr3_start + y.len());
+    let start = s.tigs[z].len().saturating_sub(tail + y.len());
+    if start + y.len() > s.tigs[z].len() || s.tigs[z][start..start + y.len()] != *y {
+        return Err(JoinRejection::Cdr3Length);
+    }
+
+    // Make a gapped copy of the shorter entry, having only the fields that the join rules use.
+
+    let gap = vec![b'-'; g];
+    let mut tigs = s.tigs.clone();
+    tigs[z].splice(start + q..start + q, gap.iter().copied());
+    let mut cdr3s = s.cdr3s.clone();
+    let mut cdr3 = y.to_vec();
+    cdr3.splice(q..q, gap);
+    cdr3s[z] = String::from_utf8(cdr3).unwrap();
+    let mut has_del = s.has_del.clone();
+    has_del[z] = true;
+    let gapped = CloneInfo {
+        lens: tigs.iter().map(Vec::len).collect(),
+        tigs,
+        tigsp: s.tigsp.clone(),
+        has_del,
+        clonotype_id: s.clonotype_id,
+        exact_cols: s.exact_cols.clone(),
+        clonotype_index: s.clonotype_index,
+        origin: s.origin.clone(),
+        vs: s.vs.clone(),
+        js: s.js.clone(),
+        cdr3s,
+        ..Default::default()
+    };
+
+    // Compare the two entries, in the original order.
+
+    let (info1, info2) = if short == k2 {
+        (&info[k1], &gapped)
+    } else {
+        (&gapped, &info[k2])
+    };
+    join_one_explained(
+        true,
+        info1,
+        info2,
+        ctl,
+        exact_clonotypes,
+        to_bc,
+        sr,
+        refdata,
+        dref,
+        pj,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cdr3_gap() {
+        let (x, y) = (b"TGTGCGAGAGGGTATTGG", b"TGTGCGAGATATTGG");
+        assert_eq!(place_gap(x, y), (9, 0));
+        assert_eq!(cdr3_diffs(x, b"TGTGCGAGA---TATTGG"), 1);
+        assert_eq!(cdr3_diffs(x, b"TGTGCGAGA---TATTGC"), 2);
+        assert_eq!(cdr3_diffs(x, x), 0);
+        assert_eq!(gap_runs(b"TGT---GCG-A"), 2);
+    }
 }
@@ -0,0 +1,439 @@
+// Copyright (c) 2023 10X Genomics, Inc. All rights reserved.
+
+// Write clonotypes in the AIRR Community formats: an AIRR Rearrangement TSV file
+// (AIRR_REARRANGEMENT), having one line per contig, and an AIRR Cell TSV file (AIRR_CELL),
+// having one line per cell.  See https://docs.airr-community.org.
+//
+// Clonotypes are numbered in the order in which they are given, so clone_id is clonotype1 for
+// the first, and so on.  Cells are identified by cell_id, which is the barcode, and
+// repertoire_id, which is the dataset short name.
+//
+// Coordinates are one-based and inclusive, on the contig sequence.  The sequence_alignment is
+// the V..J sequence of the exact subclonotype, with insertions relative to the reference removed
+// and deletions shown as gaps, so that it is aligned to the germline_alignment.  The latter is
+// made from the donor V and J alleles if they were found, and otherwise from the universal
+// reference, with N in the junction between V and J.
+//
+// The Cell file has one column, clone_id, that is not in the AIRR Cell schema.  It gives the
+// clonotype of the cell, as in the Rearrangement file, so that the two files can be joined on it
+// without going through the rearrangements column.
+
+use enclone_core::defs::{ColInfo, EncloneControl, ExactClonotype, TigData1};
+use enclone_proto::types::DonorReferenceItem;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use vdj_ann::refx::RefData;
+
+const REARRANGEMENT_FIELDS: [&str; 41] = [
+    "sequence_id",
+    "sequence",
+    "rev_comp",
+    "productive",
+    "locus",
+    "v_call",
+    "d_call",
+    "j_call",
+    "c_call",
+    "sequence_alignment",
+    "germline_alignment",
+    "junction",
+    "junction_aa",
+    "junction_length",
+    "v_cigar",
+    "d_cigar",
+    "j_cigar",
+    "v_sequence_start",
+    "v_sequence_end",
+    "j_sequence_start",
+    "j_sequence_end",
+    "c_sequence_start",
+    "fwr1_start",
+    "fwr1_end",
+    "cdr1_start",
+    "cdr1_end",
+    "fwr2_start",
+    "fwr2_end",
+    "cdr2_start",
+    "cdr2_end",
+    "fwr3_start",
+    "fwr3_end",
+    "cdr3_start",
+    "cdr3_end",
+    "fwr4_start",
+    "fwr4_end",
+    "cell_id",
+    "repertoire_id",
+    "clone_id",
+    "umi_count",
+    "consensus_count",
+];
+
+const CELL_FIELDS: [&str; 5] = [
+    "cell_id",
+    "repertoire_id",
+    "rearrangements",
+    "clone_id",
+    "virtual_pairing",
+];
+
+fn to_string(x: Option<usize>) -> String {
+    x.map(|x| x.to_string()).unwrap_or_default()
+}
+
+// Define the germline sequence of a chain, aligned to its seq_del.  This consists of the donor V
+// allele, or the reference V segment, up to the end of its alignment, then N for each base of
+// the junction that is not from V or J, then the aligned part of the donor or reference J.
+
+pub fn germline_alignment(refdata: &RefData, dref: &[DonorReferenceItem], x: &TigData1) -> Vec<u8> {
+    let mut vref = refdata.refs[x.v_ref_id].to_ascii_vec();
+    if let Some(v) = x.v_ref_id_donor {
+        vref = dref[v].nt_sequence.clone();
+    }
+    let mut jref = refdata.refs[x.j_ref_id].to_ascii_vec();
+    if let Some(j) = x.j_ref_id_donor {
+        jref = dref[j].nt_sequence.clone();
+    }
+    let n = x.seq_del.len();
+    let mut germline = vec![b'N'; n];
+    let vlen = x.v_stop_ref.min(vref.len()).min(n);
+    germline[..vlen].copy_from_slice(&vref[..vlen]);
+    let jlen = (jref.len() - x.j_start_ref).min(n);
+    germline[n - jlen..].copy_from_slice(&jref[jref.len() - jlen..]);
+    germline
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+pub fn write_airr(
+    ctl: &EncloneControl,
+    refdata: &RefData,
+    dref: &[DonorReferenceItem],
+    exact_clonotypes: &[ExactClonotype],
+    exacts: &[Vec<usize>],
+    rsi: &[ColInfo],
+) -> Result<(), String> {
+    let mut rearrangements = Vec::<String>::new();
+    let mut cells = Vec::<String>::new();
+    for (i, (exacts, rsi)) in exacts.iter().zip(rsi.iter()).enumerate() {
+        let clone_id = format!("clonotype{}", i + 1);
+        for (u, &e) in exacts.iter().enumerate() {
+            let ex = &exact_clonotypes[e];
+
+            // Find the chains of the exact subclonotype, in column order.
+
+            let ms = rsi
+                .mat
+                .iter()
+                .filter_map(|col| col[u])
+                .collect::<Vec<usize>>();
+            for clone in &ex.clones {
+                let mut ids = Vec::<&str>::new();
+                for &m in &ms {
+                    let x = &ex.share[m];
+                    let y = &clone[m];
+                    ids.push(&y.tigname);
+
+                    let germline = germline_alignment(refdata, dref, x);
+
+                    // Define coordinates on the contig.  The shared coordinates are on the
+                    // consensus contig, which may have a different start than this one.
+
+                    let pos = |p: usize| y.v_start + p - x.v_start;
+                    let vj = |p: usize| y.v_start + p;
+                    let junction_start = vj(x.cdr3_start);
+                    let junction_stop = junction_start + x.cdr3_dna.len();
+                    let fields = [
+                        y.tigname.clone(),
+                        String::from_utf8_lossy(&y.full_seq).to_string(),
+                        "F".to_string(),
+                        "T".to_string(),
+                        x.chain_type.clone(),
+                        refdata.name[x.v_ref_id].clone(),
+                        x.d_ref_id
+                            .map(|d| refdata.name[d].clone())
+                            .unwrap_or_default(),
+                        refdata.name[x.j_ref_id].clone(),
+                        x.c_ref_id
+                            .map(|c| refdata.name[c].clone())
+                            .unwrap_or_default(),
+                        String::from_utf8_lossy(&x.seq_del).to_string(),
+                        String::from_utf8_lossy(&germline).to_string(),
+                        x.cdr3_dna.clone(),
+                        x.cdr3_aa.clone(),
+                        x.cdr3_dna.len().to_string(),
+                        String::new(),
+                        String::new(),
+                        String::new(),
+                        (y.v_start + 1).to_string(),
+                        pos(x.v_stop).to_string(),
+                        (pos(x.j_start) + 1).to_string(),
+                        y.j_stop.to_string(),
+                        to_string(y.c_start.map(|c| c + 1)),
+                        (vj(x.fr1_start) + 1).to_string(),
+                        to_string(x.cdr1_start.map(vj)),
+                        to_string(x.cdr1_start.map(|p| vj(p) + 1)),
+                        to_string(x.fr2_start.map(vj)),
+                        to_string(x.fr2_start.map(|p| vj(p) + 1)),
+                        to_string(x.cdr2_start.map(vj)),
+                        to_string(x.cdr2_start.map(|p| vj(p) + 1)),
+                        to_string(x.fr3_start.map(vj)),
+                        to_string(x.fr3_start.map(|p| vj(p) + 1)),
+                        (junction_start + 3).to_string(),
+                        (junction_start + 4).to_string(),
+                        (junction_stop - 3).to_string(),
+                        (junction_stop - 2).to_string(),
+                        y.j_stop.to_string(),
+                        y.barcode.clone(),
+                        ctl.origin_info.dataset_id[y.dataset_index].clone(),
+                        clone_id.clone(),
+                        y.umi_count.to_string(),
+                        y.read_count.to_string(),
+                    ];
+                    rearrangements.push(fields.join("\t"));
+                }
+                let x = &clone[0];
+                cells.push(
+                    [
+                        x.barcode.as_str(),
+                        &ctl.origin_info.dataset_id[x.dataset_index],
+                        &ids.join(","),
+                        &clone_id,
+                        "F",
+                    ]
+                    .join("\t"),
+                );
+            }
+        }
+    }
+    if !ctl.gen_opt.airr_rearrangement.is_empty() {
+        write_tsv(
+            &ctl.gen_opt.airr_rearrangement,
+            "AIRR_REARRANGEMENT",
+            &REARRANGEMENT_FIELDS,
+            &rearrangements,
+        )?;
+    }
+    if !ctl.gen_opt.airr_cell.is_empty() {
+        write_tsv(&ctl.gen_opt.airr_cell, "AIRR_CELL", &CELL_FIELDS, &cells)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn write_tsv(
+    path: &str,
+    arg: &str,
+    header: &[&str],
+    lines: &[String],
+) -> Result<(), String> {
+    let err = |e: std::io::Error| format!("\nUnable to write {arg} file {path}: {e}.\n");
+    let mut f = BufWriter::new(File::create(path).map_err(err)?);
+    writeln!(f, "{}", header.join("\t")).map_err(err)?;
+    for line in lines {
+        writeln!(f, "{line}").map_err(err)?;
+    }
+    f.flush().map_err(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use debruijn::dna_string::DnaString;
+    use enclone_core::defs::{Junction, TigData0};
+    use std::fs::read_to_string;
+
+    const VREF: &str = "GATTACAGATTACAGATTACGATTACAGAT";
+    const JREF: &str = "TGGGGCCAGGGAACCCTGGTCACCGTCTCC";
+
+    fn test_refdata() -> RefData {
+        let mut refdata = RefData::new();
+        for (name, seq) in [("IGHV1", VREF), ("IGHJ1", JREF)] {
+            refdata.refs.push(DnaString::from_dna_string(seq));
+            refdata.name.push(name.to_string());
+        }
+        refdata
+    }
+
+    // The V..J sequence is the first 27 bases of V, three junction bases, and the last 27 bases
+    // of J.  The consensus contig has five bases before V, and the contig of the cell has seven,
+    // so that the coordinates of the latter are shifted by two.
+
+    fn test_exact() -> ExactClonotype {
+        let seq = [&VREF[..27], "CCC", &JREF[3..]].concat().into_bytes();
+        let share = TigData1 {
+            cdr3_dna: String::from_utf8(seq[20..38].to_vec()).unwrap(),
+            seq: seq.clone(),
+            seq_del: seq.clone(),
+            seq_del_amino: seq.clone(),
+            aa_mod_indel: Vec::new(),
+            ins: Vec::new(),
+            full_seq: [b"AAAAA".as_slice(), &seq, b"GCCTCC"].concat(),
+            v_start: 5,
+            v_stop: 32,
+            v_stop_ref: 27,
+            d_start: None,
+            j_start: 35,
+            j_start_ref: 3,
+            j_stop: 62,
+            u_ref_id: None,
+            v_ref_id: 0,
+            v_ref_id_donor: None,
+            v_ref_id_donor_donor: None,
+            v_ref_id_donor_alt_id: None,
+            d_ref_id: None,
+            j_ref_id: 1,
+            j_ref_id_donor: None,
+            j_ref_id_donor_donor: None,
+            j_ref_id_donor_alt_id: None,
+            c_ref_id: None,
+            fr1_start: 0,
+            cdr1_start: Some(6),
+            fr2_start: Some(9),
+            cdr2_start: Some(12),
+            fr3_start: Some(15),
+            cdr3_aa: "CARDYW".to_string(),
+            cdr3_start: 20,
+            left: true,
+            chain_type: "IGH".to_string(),
+            annv: Vec::new(),
+            vs: DnaString::new(),
+            vs_notesx: String::new(),
+            js: DnaString::new(),
+            inkt_alpha_chain_gene_match: false,
+            inkt_alpha_chain_junction_match: false,
+            inkt_beta_chain_gene_match: false,
+            inkt_beta_chain_junction_match: false,
+            mait_alpha_chain_gene_match: false,
+            mait_alpha_chain_junction_match: false,
+            mait_beta_chain_gene_match: false,
+            mait_beta_chain_junction_match: false,
+            jun: Junction::default(),
+        };
+        let clone = TigData0 {
+            quals: Vec::new(),
+            v_start: 7,
+            j_stop: 64,
+            c_start: Some(64),
+            full_seq: [b"TTAAAAA".as_slice(), &seq, b"GCCTCC"].concat(),
+            barcode: "AAAC-1".to_string(),
+            tigname: "AAAC-1_contig_1".to_string(),
+            dataset_index: 0,
+            origin_index: None,
+            donor_index: None,
+            tag_index: None,
+            umi_count: 4,
+            read_count: 100,
+            marked: false,
+            validated_umis: None,
+            non_validated_umis: None,
+            invalidated_umis: None,
+            frac_reads_used: None,
+            v_ref_id: 0,
+        };
+        ExactClonotype {
+            share: vec![share],
+            clones: vec![vec![clone]],
+        }
+    }
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("airr_test_{}_{name}.tsv", std::process::id()))
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_germline_alignment() {
+        let refdata = test_refdata();
+        let ex = test_exact();
+        let germline = germline_alignment(&refdata, &[], &ex.share[0]);
+        let expected = [&VREF[..27], "NNN", &JREF[3..]].concat();
+        assert_eq!(String::from_utf8(germline).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_write_airr() {
+        let mut ctl = EncloneControl::default();
+        ctl.origin_info.dataset_id = vec!["d1".to_string()];
+        ctl.gen_opt.airr_rearrangement = temp_path("rearrangement");
+        ctl.gen_opt.airr_cell = temp_path("cell");
+        let rsi = ColInfo {
+            mat: vec![vec![Some(0)]],
+            ..Default::default()
+        };
+        write_airr(
+            &ctl,
+            &test_refdata(),
+            &[],
+            &[test_exact()],
+            &[vec![0]],
+            &[rsi],
+        )
+        .unwrap();
+
+        // Check the Rearrangement file.
+
+        let r = read_to_string(&ctl.gen_opt.airr_rearrangement).unwrap();
+        std::fs::remove_file(&ctl.gen_opt.airr_rearrangement).unwrap();
+        let lines = r.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        let header = lines[0].split('\t').collect::<Vec<_>>();
+        let fields = lines[1].split('\t').collect::<Vec<_>>();
+        assert_eq!(header, REARRANGEMENT_FIELDS);
+        assert_eq!(header.len(), 41);
+        assert_eq!(fields.len(), 41);
+        let field = |name: &str| fields[header.iter().position(|h| *h == name).unwrap()];
+        assert_eq!(field("sequence_id"), "AAAC-1_contig_1");
+        assert_eq!(field("v_call"), "IGHV1");
+        assert_eq!(field("j_call"), "IGHJ1");
+        assert_eq!(field("d_call"), "");
+        assert_eq!(field("clone_id"), "clonotype1");
+        assert_eq!(field("repertoire_id"), "d1");
+        assert_eq!(field("junction_length"), "18");
+        let coords = [
+            ("v_sequence_start", "8"),
+            ("v_sequence_end", "34"),
+            ("j_sequence_start", "38"),
+            ("j_sequence_end", "64"),
+            ("c_sequence_start", "65"),
+            ("fwr1_start", "8"),
+            ("fwr1_end", "13"),
+            ("cdr1_start", "14"),
+            ("cdr1_end", "16"),
+            ("fwr2_start", "17"),
+            ("fwr2_end", "19"),
+            ("cdr2_start", "20"),
+            ("cdr2_end", "22"),
+            ("fwr3_start", "23"),
+            ("fwr3_end", "30"),
+            ("cdr3_start", "31"),
+            ("cdr3_end", "42"),
+            ("fwr4_start", "43"),
+            ("fwr4_end", "64"),
+        ];
+        for (name, value) in coords {
+            assert_eq!(field(name), value, "{name}");
+        }
+
+        // The coordinates are one-based and inclusive, on the contig of the cell.
+
+        let seq = field("sequence").as_bytes();
+        let start = field("v_sequence_start").parse::<usize>().unwrap();
+        let end = field("v_sequence_end").parse::<usize>().unwrap();
+        assert_eq!(&seq[start - 1..end], &VREF.as_bytes()[..27]);
+        let start = field("j_sequence_start").parse::<usize>().unwrap();
+        let end = field("j_sequence_end").parse::<usize>().unwrap();
+        assert_eq!(&seq[start - 1..end], &JREF.as_bytes()[3..]);
+
+        // Check the Cell file.
+
+        let c = read_to_string(&ctl.gen_opt.airr_cell).unwrap();
+        std::fs::remove_file(&ctl.gen_opt.airr_cell).unwrap();
+        assert_eq!(
+            c,
+            "cell_id\trepertoire_id\trearrangements\tclone_id\tvirtual_pairing\n\
+             AAAC-1\td1\tAAAC-1_contig_1\tclonotype1\tF\n"
+        );
+    }
+}
@@ -0,0 +1,817 @@
+// Copyright (c) 2023 10X Genomics, Inc. All rights reserved.
+
+// Grouping of clonotypes.
+//
+// Symmetric grouping (GROUP=... and its abbreviations such as GROUP_VJ_REFNAME) places two
+// clonotypes in the same group if they satisfy every condition that was specified, and then
+// closes up under transitivity.  Conditions on reference names and lengths are tested by sorting
+// the clonotypes on a key, and identity conditions are then tested on each pair of clonotypes
+// having the same key.  Identity is computed between the first heavy chains of the two
+// clonotypes, or between their first light chains, and requires the sequences to have the same
+// length, so those lengths are added to the key.
+//
+// Asymmetric grouping (AGROUP) is defined by centers, which are the clonotypes that pass the
+// filters.  Centers are considered in order, i.e. by decreasing size.  A center that is not
+// already in a group starts a new group, which takes in every clonotype that is not already in a
+// group and is within the distance bound AG_DIST_BOUND of the center.  The distance
+// (AG_DIST_FORMULA=cdr3_edit_distance) is the sum over the chains of the center of the edit
+// distance from its CDR3 amino acid sequence to the nearest one of a chain of the same type in
+// the other clonotype.  If AG_CENTER=from_filters, clonotypes that fail the filters may join a
+// group, but do not start one.
+//
+// Without a grouping option, each clonotype is a group by itself.  In that case,
+// group_clonotypes is called only for GROUP_TABLE, and otherwise the group filters are not
+// applied.
+//
+// In all cases, the groups are then filtered (MIN_GROUP, MIN_GROUP_DONORS, GROUP_CDR3H_LEN_VAR,
+// GROUP_CDR3, GROUP_DONOR, GROUP_NAIVE, GROUP_NO_NAIVE), and numbered from one, in order of
+// their largest clonotype.
+
+use crate::airr::write_tsv;
+use enclone_core::defs::{ClonoGroupOpt, ColInfo, EncloneControl, ExactClonotype, TigData1};
+use enclone_proto::types::DonorReferenceItem;
+use equiv::EquivRel;
+use rayon::prelude::*;
+use triple_accel::levenshtein;
+use vdj_ann::refx::RefData;
+use vector_utils::unique_sort;
+
+// The chain of a clonotype in a given column, taken from the first exact subclonotype that has
+// it.
+
+fn col_chain<'a>(
+    exact_clonotypes: &'a [ExactClonotype],
+    exacts: &[usize],
+    rsi: &ColInfo,
+    col: usize,
+) -> &'a TigData1 {
+    let (u, m) = rsi.mat[col]
+        .iter()
+        .enumerate()
+        .find_map(|(u, m)| m.map(|m| (u, m)))
+        .unwrap();
+    &exact_clonotypes[exacts[u]].share[m]
+}
+
+// Percent identity of two sequences, which is zero if they have different lengths.
+
+fn identity(a: &[u8], b: &[u8]) -> f64 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let same = a.iter().zip(b).filter(|(x, y)| x == y).count();
+    100.0 * same as f64 / a.len() as f64
+}
+
+// Percent similarity of two amino acid sequences, as for identity, but scoring each position by
+// an entry in a 20x20 matrix (GROUP=cdr3_aa_heavy≥n%:h:@f), whose rows and columns are in the
+// order of the one-letter codes ACDEFGHIKLMNPQRSTVWY.
+
+fn matrix_identity(a: &[u8], b: &[u8], m: &[Vec<f64>]) -> f64 {
+    const AA: &[u8; 20] = b"ACDEFGHIKLMNPQRSTVWY";
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let mut sum = 0.0;
+    for (x, y) in a.iter().zip(b) {
+        if let (Some(i), Some(j)) = (
+            AA.iter().position(|c| c == x),
+            AA.iter().position(|c| c == y),
+        ) {
+            sum += m[i][j];
+        }
+    }
+    100.0 * sum / a.len() as f64
+}
+
+// Determine if an exact subclonotype has no differences from the donor reference, computed as
+// for the variable dref.
+
+fn is_naive(
+    ctl: &EncloneControl,
+    refdata: &RefData,
+    dref: &[DonorReferenceItem],
+    ex: &ExactClonotype,
+    u: usize,
+    rsi: &ColInfo,
+) -> bool {
+    rsi.mat.iter().enumerate().all(|(m, col)| {
+        let Some(r) = col[u] else {
+            return true;
+        };
+        let seq = &ex.share[r].seq_del_amino;
+        let vref = match rsi.vpids[m] {
+            Some(v) => dref[v].nt_sequence.clone(),
+            None => refdata.refs[rsi.vids[m]].to_ascii_vec(),
+        };
+        let jref = refdata.refs[rsi.jids[m]].to_ascii_vec();
+        let vlen = vref.len().saturating_sub(ctl.heur.ref_v_trim);
+        let jlen = jref.len().saturating_sub(ctl.heur.ref_j_trim);
+        let z = seq.len();
+        (0..z).all(|p| {
+            (p >= vlen || seq[p] == vref[p])
+                && (p + jlen < z || seq[p] == jref[jref.len() - (z - p)])
+        })
+    })
+}
+
+const GROUP_TABLE_FIELDS: [&str; 5] = [
+    "group_id",
+    "clonotype_id",
+    "group_nclonotypes",
+    "group_ncells",
+    "clonotype_ncells",
+];
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+// The chains of a clonotype, and its first heavy and first light chains.
+
+struct Chains<'a> {
+    all: Vec<&'a TigData1>,
+    heavy: Option<&'a TigData1>,
+    light: Option<&'a TigData1>,
+}
+
+// Find the key for symmetric grouping.  Return None if the clonotype lacks a chain that one of
+// the conditions requires, in which case it is not grouped with any other clonotype.
+
+fn group_key(g: &ClonoGroupOpt, refdata: &RefData, x: &Chains) -> Option<Vec<String>> {
+    let name = |id: usize| refdata.name[id].as_str();
+    let refnames = |y: &TigData1, with_d: bool| {
+        if with_d {
+            let d = y.d_ref_id.map_or("", name);
+            format!("{}:{d}:{}", name(y.v_ref_id), name(y.j_ref_id))
+        } else {
+            format!("{}:{}", name(y.v_ref_id), name(y.j_ref_id))
+        }
+    };
+    let all =
+        |f: &dyn Fn(&TigData1) -> String| x.all.iter().map(|y| f(y)).collect::<Vec<_>>().join(",");
+    let mut key = Vec::<String>::new();
+    if g.vj_refname || g.vj_refname_strong {
+        key.push(all(&|y| refnames(y, false)));
+    }
+    if g.vdj_refname {
+        key.push(all(&|y| refnames(y, true)));
+    }
+    if g.v_heavy_refname {
+        key.push(name(x.heavy?.v_ref_id).to_string());
+    }
+    if g.vj_heavy_refname {
+        key.push(refnames(x.heavy?, false));
+    }
+    if g.vdj_heavy_refname {
+        key.push(refnames(x.heavy?, true));
+    }
+    if g.vj_len || g.vj_refname_strong {
+        key.push(all(&|y| y.seq_del.len().to_string()));
+    }
+    if g.cdr3_len || g.vj_refname_strong {
+        key.push(all(&|y| y.cdr3_aa.len().to_string()));
+    }
+    for (heavy, y) in [(true, x.heavy), (false, x.light)] {
+        let (len, nt_pc, aa_pc, cdr3_nt_pc, cdr3_aa_pc) = if heavy {
+            (
+                g.cdr3_heavy_len || g.cdr3_heavy_pc_hf.is_some(),
+                g.heavy_pc,
+                g.aa_heavy_pc,
+                g.cdr3_heavy_pc,
+                g.cdr3_aa_heavy_pc,
+            )
+        } else {
+            (
+                g.cdr3_light_len,
+                g.light_pc,
+                g.aa_light_pc,
+                g.cdr3_light_pc,
+                g.cdr3_aa_light_pc,
+            )
+        };
+        if len || cdr3_nt_pc.is_some() || cdr3_aa_pc.is_some() {
+            key.push(y?.cdr3_aa.len().to_string());
+        }
+        if nt_pc.is_some() {
+            key.push(y?.seq_del.len().to_string());
+        }
+        if aa_pc.is_some() {
+            key.push(y?.aa_mod_indel.len().to_string());
+        }
+    }
+    Some(key)
+}
+
+// Test the identity conditions for symmetric grouping, for two clonotypes having the same key.
+
+fn similar(g: &ClonoGroupOpt, x1: &Chains, x2: &Chains) -> bool {
+    let test = |pc: Option<f64>, heavy: bool, f: &dyn Fn(&TigData1) -> &[u8]| {
+        let Some(pc) = pc else {
+            return true;
+        };
+        let (y1, y2) = if heavy {
+            (x1.heavy, x2.heavy)
+        } else {
+            (x1.light, x2.light)
+        };
+        match (y1, y2) {
+            (Some(y1), Some(y2)) => identity(f(y1), f(y2)) >= pc,
+            _ => false,
+        }
+    };
+    if let Some((pc, m)) = &g.cdr3_heavy_pc_hf {
+        match (x1.heavy, x2.heavy) {
+            (Some(y1), Some(y2)) => {
+                if matrix_identity(y1.cdr3_aa.as_bytes(), y2.cdr3_aa.as_bytes(), m) < *pc {
+                    return false;
+                }
+            }
+            _ => return false,
+        }
+    }
+    [true, false].iter().all(|&heavy| {
+        let (nt_pc, aa_pc, cdr3_nt_pc, cdr3_aa_pc) = if heavy {
+            (
+                g.heavy_pc,
+                g.aa_heavy_pc,
+                g.cdr3_heavy_pc,
+                g.cdr3_aa_heavy_pc,
+            )
+        } else {
+            (
+                g.light_pc,
+                g.aa_light_pc,
+                g.cdr3_light_pc,
+                g.cdr3_aa_light_pc,
+            )
+        };
+        test(nt_pc, heavy, &|y| y.seq_del.as_slice())
+            && test(aa_pc, heavy, &|y| y.aa_mod_indel.as_slice())
+            && test(cdr3_nt_pc, heavy, &|y| y.cdr3_dna.as_bytes())
+            && test(cdr3_aa_pc, heavy, &|y| y.cdr3_aa.as_bytes())
+    })
+}
+
+fn symmetric_groups(g: &ClonoGroupOpt, refdata: &RefData, chains: &[Chains]) -> Vec<Vec<usize>> {
+    let n = chains.len();
+    let pairwise = [
+        g.heavy_pc,
+        g.aa_heavy_pc,
+        g.cdr3_heavy_pc,
+        g.cdr3_aa_heavy_pc,
+        g.light_pc,
+        g.aa_light_pc,
+        g.cdr3_light_pc,
+        g.cdr3_aa_light_pc,
+    ]
+    .iter()
+    .any(Option::is_some)
+        || g.cdr3_heavy_pc_hf.is_some();
+    let keys = chains
+        .iter()
+        .map(|x| group_key(g, refdata, x))
+        .collect::<Vec<_>>();
+    let mut order = (0..n).filter(|&i| keys[i].is_some()).collect::<Vec<_>>();
+    order.sort_by(|&i1, &i2| keys[i1].cmp(&keys[i2]));
+    let mut e = EquivRel::new(n as i32);
+    let mut i = 0;
+    while i < order.len() {
+        let mut j = i + 1;
+        while j < order.len() && keys[order[j]] == keys[order[i]] {
+            j += 1;
+        }
+        for k1 in i..j {
+            let c1 = order[k1];
+            if !pairwise {
+                e.join(order[i] as i32, c1 as i32);
+                continue;
+            }
+            for &c2 in &order[k1 + 1..j] {
+                if e.class_id(c1 as i32) != e.class_id(c2 as i32)
+                    && similar(g, &chains[c1], &chains[c2])
+                {
+                    e.join(c1 as i32, c2 as i32);
+                }
+            }
+        }
+        i = j;
+    }
+    let mut reps = Vec::<i32>::new();
+    e.orbit_reps(&mut reps);
+    reps.iter()
+        .map(|&r| {
+            let mut o = Vec::<i32>::new();
+            e.orbit(r, &mut o);
+            o.into_iter().map(|c| c as usize).collect()
+        })
+        .collect()
+}
+
+fn asymmetric_groups(g: &ClonoGroupOpt, chains: &[Chains], in_center: &[bool]) -> Vec<Vec<usize>> {
+    let n = chains.len();
+    let top = g
+        .asymmetric_dist_bound
+        .strip_prefix("top=")
+        .and_then(|x| x.parse::<usize>().ok());
+    let max = g
+        .asymmetric_dist_bound
+        .strip_prefix("max=")
+        .and_then(|x| x.parse::<f64>().ok());
+    let cdr3_edit_distance = |center: &Chains, other: &Chains| {
+        center
+            .all
+            .iter()
+            .map(|y1| {
+                other
+                    .all
+                    .iter()
+                    .filter(|y2| y2.chain_type == y1.chain_type)
+                    .map(|y2| levenshtein(y1.cdr3_aa.as_bytes(), y2.cdr3_aa.as_bytes()) as usize)
+                    .min()
+            })
+            .sum::<Option<usize>>()
+    };
+    let mut grouped = vec![false; n];
+    let mut groups = Vec::<Vec<usize>>::new();
+    for c in 0..n {
+        if !in_center[c] || grouped[c] {
+            continue;
+        }
+        grouped[c] = true;
+        let mut near = (0..n)
+            .into_par_iter()
+            .filter(|&i| !grouped[i])
+            .filter_map(|i| cdr3_edit_distance(&chains[c], &chains[i]).map(|d| (d, i)))
+            .collect::<Vec<_>>();
+        near.sort_unstable();
+        if let Some(top) = top {
+            near.truncate(top);
+        }
+        if let Some(max) = max {
+            near.retain(|&(d, _)| d as f64 <= max);
+        }
+        let mut group = vec![c];
+        for (_, i) in near {
+            grouped[i] = true;
+            group.push(i);
+        }
+        groups.push(group);
+    }
+    groups
+}
+
+// Apply the group filters.
+
+fn group_passes(
+    ctl: &EncloneControl,
+    refdata: &RefData,
+    dref: &[DonorReferenceItem],
+    exact_clonotypes: &[ExactClonotype],
+    exacts: &[Vec<usize>],
+    rsi: &[ColInfo],
+    chains: &[Chains],
+    group: &[usize],
+) -> bool {
+    let g = &ctl.clono_group_opt;
+    if group.len() < g.min_group {
+        return false;
+    }
+    let mut donors = Vec::<usize>::new();
+    for &i in group {
+        for &e in &exacts[i] {
+            for clone in &exact_clonotypes[e].clones {
+                donors.extend(clone[0].donor_index);
+            }
+        }
+    }
+    unique_sort(&mut donors);
+    if donors.len() < g.min_group_donors {
+        return false;
+    }
+    let donor_list = &ctl.origin_info.donor_list;
+    if !g
+        .donor
+        .iter()
+        .all(|d| donors.iter().any(|&k| donor_list[k] == *d))
+    {
+        return false;
+    }
+    if g.cdr3h_len_var {
+        let mut lens = group
+            .iter()
+            .filter_map(|&i| chains[i].heavy.map(|y| y.cdr3_aa.len()))
+            .collect::<Vec<_>>();
+        unique_sort(&mut lens);
+        if lens.len() < 2 {
+            return false;
+        }
+    }
+    if !g.cdr3.is_empty()
+        && !group.iter().any(|&i| {
+            exacts[i].iter().any(|&e| {
+                exact_clonotypes[e]
+                    .share
+                    .iter()
+                    .any(|y| y.cdr3_aa == g.cdr3)
+            })
+        })
+    {
+        return false;
+    }
+    if g.naive || g.no_naive {
+        let naive = group.iter().any(|&i| {
+            exacts[i]
+                .iter()
+                .enumerate()
+                .any(|(u, &e)| is_naive(ctl, refdata, dref, &exact_clonotypes[e], u, &rsi[i]))
+        });
+        if (g.naive && !naive) || (g.no_naive && naive) {
+            return false;
+        }
+    }
+    true
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+// Group the clonotypes, which are given by exacts, rsi and in_center, as for print_clonotypes.
+// Return the groups that pass the group filters, in order, each as a sorted list of clonotype
+// indices.
+
+pub fn group_clonotypes(
+    ctl: &EncloneControl,
+    refdata: &RefData,
+    dref: &[DonorReferenceItem],
+    exact_clonotypes: &[ExactClonotype],
+    exacts: &[Vec<usize>],
+    rsi: &[ColInfo],
+    in_center: &[bool],
+) -> Vec<Vec<usize>> {
+    let chains = exacts
+        .iter()
+        .zip(rsi.iter())
+        .map(|(exacts, rsi)| {
+            let all = (0..rsi.mat.len())
+                .map(|col| col_chain(exact_clonotypes, exacts, rsi, col))
+                .collect::<Vec<_>>();
+            Chains {
+                heavy: all.iter().copied().find(|y| y.left),
+                light: all.iter().copied().find(|y| !y.left),
+                all,
+            }
+        })
+        .collect::<Vec<_>>();
+    let g = &ctl.clono_group_opt;
+    let mut groups = match g.style.as_str() {
+        "symmetric" => symmetric_groups(g, refdata, &chains),
+        "asymmetric" => asymmetric_groups(g, &chains, in_center),
+        _ => (0..exacts.len()).map(|i| vec![i]).collect(),
+    };
+    for group in &mut groups {
+        group.sort_unstable();
+    }
+    groups.sort();
+    groups.retain(|group| {
+        group_passes(
+            ctl,
+            refdata,
+            dref,
+            exact_clonotypes,
+            exacts,
+            rsi,
+            &chains,
+            group,
+        )
+    });
+    groups
+}
+
+// Write the GROUP_TABLE file, having one line for each clonotype that is in a group.  Clonotypes
+// are numbered as for AIRR output.
+
+pub fn write_group_table(
+    ctl: &EncloneControl,
+    exact_clonotypes: &[ExactClonotype],
+    exacts: &[Vec<usize>],
+    groups: &[Vec<usize>],
+) -> Result<(), String> {
+    let mut lines = Vec::<String>::new();
+    for (g, group) in groups.iter().enumerate() {
+        let ncells = group
+            .iter()
+            .map(|&i| {
+                exacts[i]
+                    .iter()
+                    .map(|&e| exact_clonotypes[e].ncells())
+                    .sum::<usize>()
+            })
+            .collect::<Vec<_>>();
+        let total = ncells.iter().sum::<usize>();
+        for (&i, n) in group.iter().zip(ncells.iter()) {
+            lines.push(format!(
+                "{}\tclonotype{}\t{}\t{total}\t{n}",
+                g + 1,
+                i + 1,
+                group.len(),
+            ));
+        }
+    }
+    write_tsv(
+        &ctl.gen_opt.group_table,
+        "GROUP_TABLE",
+        &GROUP_TABLE_FIELDS,
+        &lines,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use debruijn::dna_string::DnaString;
+    use enclone_core::defs::{Junction, TigData0};
+
+    // Reference segments, as (name, sequence).
+
+    const REFS: [(&str, &str); 5] = [
+        ("IGHV1", "GATTACAGATTACAGATTACGATTACAGAT"),
+        ("IGHJ1", "TGGGGCCAGGGAACCCTGGTCACCGTCTCC"),
+        ("IGKV1", "GACATCCAGATGACCCAGTCTCCATCCTCC"),
+        ("IGKJ1", "GTGGACGTTCGGCCAAGGGACCAAGGTGGA"),
+        ("IGHV2", "CAGGTGCAGCTGGTGCAGTCTGGGGCTGAG"),
+    ];
+
+    fn test_refdata() -> RefData {
+        let mut refdata = RefData::new();
+        for (name, seq) in REFS {
+            refdata.refs.push(DnaString::from_dna_string(seq));
+            refdata.name.push(name.to_string());
+        }
+        refdata
+    }
+
+    // A chain whose V..J sequence is its V segment followed by its J segment, with the given
+    // positions mutated.
+
+    fn chain(v: usize, j: usize, cdr3_aa: &str, muts: &[usize]) -> TigData1 {
+        let mut seq = [REFS[v].1, REFS[j].1].concat().into_bytes();
+        for &p in muts {
+            seq[p] = if seq[p] == b'A' { b'C' } else { b'A' };
+        }
+        let left = REFS[v].0.starts_with("IGH");
+        TigData1 {
+            cdr3_dna: String::new(),
+            seq: seq.clone(),
+            seq_del: seq.clone(),
+            seq_del_amino: seq.clone(),
+            aa_mod_indel: Vec::new(),
+            ins: Vec::new(),
+            full_seq: seq,
+            v_start: 0,
+            v_stop: 0,
+            v_stop_ref: 0,
+            d_start: None,
+            j_start: 0,
+            j_start_ref: 0,
+            j_stop: 0,
+            u_ref_id: None,
+            v_ref_id: v,
+            v_ref_id_donor: None,
+            v_ref_id_donor_donor: None,
+            v_ref_id_donor_alt_id: None,
+            d_ref_id: None,
+            j_ref_id: j,
+            j_ref_id_donor: None,
+            j_ref_id_donor_donor: None,
+            j_ref_id_donor_alt_id: None,
+            c_ref_id: None,
+            fr1_start: 0,
+            cdr1_start: None,
+            fr2_start: None,
+            cdr2_start: None,
+            fr3_start: None,
+            cdr3_aa: cdr3_aa.to_string(),
+            cdr3_start: 0,
+            left,
+            chain_type: if left { "IGH" } else { "IGK" }.to_string(),
+            annv: Vec::new(),
+            vs: DnaString::new(),
+            vs_notesx: String::new(),
+            js: DnaString::new(),
+            inkt_alpha_chain_gene_match: false,
+            inkt_alpha_chain_junction_match: false,
+            inkt_beta_chain_gene_match: false,
+            inkt_beta_chain_junction_match: false,
+            mait_alpha_chain_gene_match: false,
+            mait_alpha_chain_junction_match: false,
+            mait_beta_chain_gene_match: false,
+            mait_beta_chain_junction_match: false,
+            jun: Junction::default(),
+        }
+    }
+
+    fn cell(donor: usize) -> TigData0 {
+        TigData0 {
+            quals: Vec::new(),
+            v_start: 0,
+            j_stop: 0,
+            c_start: None,
+            full_seq: Vec::new(),
+            barcode: String::new(),
+            tigname: String::new(),
+            dataset_index: 0,
+            origin_index: None,
+            donor_index: Some(donor),
+            tag_index: None,
+            umi_count: 0,
+            read_count: 0,
+            marked: false,
+            validated_umis: None,
+            non_validated_umis: None,
+            invalidated_umis: None,
+            frac_reads_used: None,
+            v_ref_id: 0,
+        }
+    }
+
+    // Clonotypes, each consisting of one exact subclonotype, having the given chains, and one
+    // cell from the given donor.
+
+    struct TestClonotypes {
+        exact_clonotypes: Vec<ExactClonotype>,
+        exacts: Vec<Vec<usize>>,
+        rsi: Vec<ColInfo>,
+    }
+
+    fn clonotypes(x: Vec<(Vec<TigData1>, usize)>) -> TestClonotypes {
+        let mut t = TestClonotypes {
+            exact_clonotypes: Vec::new(),
+            exacts: Vec::new(),
+            rsi: Vec::new(),
+        };
+        for (i, (share, donor)) in x.into_iter().enumerate() {
+            t.rsi.push(ColInfo {
+                mat: (0..share.len()).map(|m| vec![Some(m)]).collect(),
+                vids: share.iter().map(|y| y.v_ref_id).collect(),
+                vpids: vec![None; share.len()],
+                jids: share.iter().map(|y| y.j_ref_id).collect(),
+                ..Default::default()
+            });
+            t.exact_clonotypes.push(ExactClonotype {
+                share,
+                clones: vec![vec![cell(donor)]],
+            });
+            t.exacts.push(vec![i]);
+        }
+        t
+    }
+
+    fn test_ctl() -> EncloneControl {
+        let mut ctl = EncloneControl::default();
+        ctl.heur.ref_v_trim = 15;
+        ctl.heur.ref_j_trim = 15;
+        ctl.clono_group_opt.min_group = 1;
+        ctl.clono_group_opt.min_group_donors = 1;
+        ctl.origin_info.donor_list = vec!["d1".to_string(), "d2".to_string()];
+        ctl
+    }
+
+    fn groups(ctl: &EncloneControl, t: &TestClonotypes, in_center: &[bool]) -> Vec<Vec<usize>> {
+        group_clonotypes(
+            ctl,
+            &test_refdata(),
+            &[],
+            &t.exact_clonotypes,
+            &t.exacts,
+            &t.rsi,
+            in_center,
+        )
+    }
+
+    // Clonotypes 0 and 1 have the same genes and similar heavy chain CDR3s, clonotypes 2 and 3
+    // have the same genes, dissimilar heavy chain CDR3s, and mutations, and clonotype 4 has the
+    // heavy chain genes of clonotype 0 but lacks a light chain.
+
+    fn test_clonotypes() -> TestClonotypes {
+        clonotypes(vec![
+            (
+                vec![chain(0, 1, "CARDYW", &[]), chain(2, 3, "CQQYW", &[])],
+                0,
+            ),
+            (
+                vec![chain(0, 1, "CARDFW", &[]), chain(2, 3, "CQQYW", &[])],
+                0,
+            ),
+            (
+                vec![chain(4, 1, "CARDYW", &[0]), chain(2, 3, "CQQYW", &[])],
+                1,
+            ),
+            (
+                vec![chain(4, 1, "CTTTYW", &[0]), chain(2, 3, "CQQYW", &[])],
+                1,
+            ),
+            (vec![chain(0, 1, "CARDYYW", &[])], 0),
+        ])
+    }
+
+    #[test]
+    fn test_symmetric() {
+        let t = test_clonotypes();
+        let mut ctl = test_ctl();
+        let in_center = [true; 5];
+        assert_eq!(
+            groups(&ctl, &t, &in_center),
+            vec![vec![0], vec![1], vec![2], vec![3], vec![4]]
+        );
+
+        // Group by genes.
+
+        ctl.clono_group_opt.style = "symmetric".to_string();
+        ctl.clono_group_opt.vj_refname = true;
+        assert_eq!(
+            groups(&ctl, &t, &in_center),
+            vec![vec![0, 1], vec![2, 3], vec![4]]
+        );
+
+        // Also require at least 80% heavy chain CDR3 amino acid identity.
+
+        ctl.clono_group_opt.cdr3_aa_heavy_pc = Some(80.0);
+        assert_eq!(
+            groups(&ctl, &t, &in_center),
+            vec![vec![0, 1], vec![2], vec![3], vec![4]]
+        );
+
+        // A clonotype lacking a light chain is not grouped by light chain identity.
+
+        ctl.clono_group_opt.vj_refname = false;
+        ctl.clono_group_opt.cdr3_aa_heavy_pc = None;
+        ctl.clono_group_opt.cdr3_aa_light_pc = Some(100.0);
+        assert_eq!(
+            groups(&ctl, &t, &in_center),
+            vec![vec![0, 1, 2, 3], vec![4]]
+        );
+    }
+
+    // The distance from a center is defined only for clonotypes having every chain type of the
+    // center, so clonotype 4 can take in clonotype 0, but not conversely.
+
+    #[test]
+    fn test_asymmetric() {
+        let t = test_clonotypes();
+        let mut ctl = test_ctl();
+        let g = &mut ctl.clono_group_opt;
+        g.style = "asymmetric".to_string();
+        g.asymmetric_center = "from_filters".to_string();
+        g.asymmetric_dist_formula = "cdr3_edit_distance".to_string();
+        g.asymmetric_dist_bound = "max=1".to_string();
+        let all = [true; 5];
+        assert_eq!(
+            groups(&ctl, &t, &all),
+            vec![vec![0, 1, 2], vec![3], vec![4]]
+        );
+
+        // Clonotypes that are not centers do not start groups, and if not taken in by a center,
+        // are not in any group.
+
+        let in_center = [false, false, false, false, true];
+        assert_eq!(groups(&ctl, &t, &in_center), vec![vec![0, 2, 4]]);
+
+        // Take in only the nearest clonotype.
+
+        ctl.clono_group_opt.asymmetric_dist_bound = "top=1".to_string();
+        assert_eq!(
+            groups(&ctl, &t, &all),
+            vec![vec![0, 2], vec![1, 3], vec![4]]
+        );
+    }
+
+    // Grouping by heavy chain V gene yields the groups {0, 1, 4} and {2, 3}, and grouping by
+    // light chain identity yields the groups {0, 1, 2, 3} and {4}.
+
+    #[test]
+    fn test_group_passes() {
+        let t = test_clonotypes();
+        let in_center = [true; 5];
+        let test = |f: &dyn Fn(&mut ClonoGroupOpt)| {
+            let mut ctl = test_ctl();
+            ctl.clono_group_opt.style = "symmetric".to_string();
+            ctl.clono_group_opt.v_heavy_refname = true;
+            f(&mut ctl.clono_group_opt);
+            groups(&ctl, &t, &in_center)
+        };
+        assert_eq!(test(&|_| ()), vec![vec![0, 1, 4], vec![2, 3]]);
+        assert_eq!(test(&|g| g.min_group = 3), vec![vec![0, 1, 4]]);
+        assert_eq!(test(&|g| g.cdr3h_len_var = true), vec![vec![0, 1, 4]]);
+        assert_eq!(test(&|g| g.cdr3 = "CTTTYW".to_string()), vec![vec![2, 3]]);
+        assert_eq!(
+            test(&|g| g.donor = vec!["d2".to_string()]),
+            vec![vec![2, 3]]
+        );
+        assert_eq!(test(&|g| g.naive = true), vec![vec![0, 1, 4]]);
+        assert_eq!(test(&|g| g.no_naive = true), vec![vec![2, 3]]);
+        let by_light = |g: &mut ClonoGroupOpt| {
+            g.v_heavy_refname = false;
+            g.cdr3_aa_light_pc = Some(100.0);
+            g.min_group_donors = 2;
+        };
+        assert_eq!(test(&by_light), vec![vec![0, 1, 2, 3]]);
+    }
+}
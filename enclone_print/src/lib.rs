@@ -1,11 +1,16 @@
 // Copyright (c) 2021 10x Genomics, Inc. All rights reserved.
 
+pub mod airr;
 pub mod build_table_stuff;
 pub mod define_mat;
 pub mod filter;
 pub mod finish_table;
 pub mod gene_scan;
+pub mod group;
 pub mod loupe;
+pub mod msa;
+pub mod parseable;
+pub mod plot;
 pub mod print_clonotypes;
 pub mod print_utils1;
 pub mod print_utils2;
@@ -15,3 +20,5 @@ pub mod print_utils5;
 pub mod proc_cvar_auto;
 pub mod proc_lvar2;
 pub mod proc_lvar_auto;
+pub mod render;
+pub mod tree;
@@ -3,11 +3,15 @@
 // This set of functions writes a protobuf data structure that
 // Loupe uses to access clonotype data.
 
-use enclone_proto::proto_io::write_proto;
+use enclone_proto::proto_index::{write_proto_index, ProtoIndex};
+use enclone_proto::proto_io::{
+    write_proto, write_proto_and_index, ProtoReader, ProtoWriter, StreamingProtoWriter,
+};
 use enclone_proto::PROTO_VERSION;
 use vdj_ann::refx;
 
 use self::refx::RefData;
+use crate::tree::clonotype_tree;
 use amino::codon_to_aa;
 use bio_edit::alignment::pairwise::Aligner;
 
@@ -19,7 +23,10 @@ use enclone_proto::types::{
     InvariantTCellAnnotation, Metadata, Region, UniversalReference, UniversalReferenceItem,
 };
 use io_utils::write_obj;
+use std::collections::HashMap;
 use std::fmt::Write;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
 
 // Export donor reference/inferred alt allele sequences
 pub fn make_donor_refs(
@@ -68,7 +75,11 @@ pub fn make_donor_refs(
                 universal_idx: ref_id as u32,
                 donor_idx: donor_id as u32,
                 display_name: alt_name,
-                region: Region::V.into(),
+                region: if refdata.is_j(ref_id) {
+                    Region::J.into()
+                } else {
+                    Region::V.into()
+                },
                 nt_sequence: alt,
                 universal_aln: Alignment {
                     ref_start: 0,
@@ -125,7 +136,12 @@ pub fn make_loupe_clonotype(
         }
         let mut x = refdata.refs[rsi.jids[cx]].to_ascii_vec();
         concatu[cx].append(&mut x);
-        concatd[cx].append(&mut x);
+        if rsi.jpids[cx].is_none() {
+            concatd[cx].append(&mut x);
+        } else {
+            let mut y = dref[rsi.jpids[cx].unwrap()].nt_sequence.clone();
+            concatd[cx].append(&mut y);
+        }
         if rsi.cids[cx].is_some() {
             let mut x = refdata.refs[rsi.cids[cx].unwrap()].to_ascii_vec();
             concatu[cx].append(&mut x);
@@ -151,7 +167,7 @@ pub fn make_loupe_clonotype(
         let chain_type = ex.share[m0].chain_type.clone();
 
         let donor_v_idx = rsi.vpids[cx];
-        let donor_j_idx = None;
+        let donor_j_idx = rsi.jpids[cx];
         let score = |a: u8, b: u8| if a == b { 1i32 } else { -1i32 };
         let universal_reference = concatu[cx].clone();
         let donor_reference = concatd[cx].clone();
@@ -194,7 +210,7 @@ pub fn make_loupe_clonotype(
             j_idx: rsi.jids[cx] as u32,
             c_idx: rsi.cids[cx].map(|idx| idx as u32),
             donor_v_idx: donor_v_idx.map(|idx| idx as u32),
-            donor_j_idx,
+            donor_j_idx: donor_j_idx.map(|idx| idx as u32),
             universal_reference,
             universal_reference_aln,
             aa_sequence_universal,
@@ -294,7 +310,10 @@ pub fn make_loupe_clonotype(
             if xm.v_ref_id_donor_alt_id.is_some() {
                 vref = dref[xm.v_ref_id_donor.unwrap()].nt_sequence.clone();
             }
-            let jref = refdata.refs[xm.j_ref_id].to_ascii_vec();
+            let mut jref = refdata.refs[xm.j_ref_id].to_ascii_vec();
+            if xm.j_ref_id_donor_alt_id.is_some() {
+                jref = dref[xm.j_ref_id_donor.unwrap()].nt_sequence.clone();
+            }
             let z = seq.len();
             for p in 0..z {
                 let b = seq[p];
@@ -328,7 +347,10 @@ pub fn make_loupe_clonotype(
             if xm.v_ref_id_donor_alt_id.is_some() {
                 vref = dref[xm.v_ref_id_donor.unwrap()].nt_sequence.clone();
             }
-            let jref = refdata.refs[xm.j_ref_id].to_ascii_vec();
+            let mut jref = refdata.refs[xm.j_ref_id].to_ascii_vec();
+            if xm.j_ref_id_donor_alt_id.is_some() {
+                jref = dref[xm.j_ref_id_donor.unwrap()].nt_sequence.clone();
+            }
             let z = 3 * aa_seq.len() + 1;
             for p in 0..aa_seq.len() {
                 if aa_seq[p] == b'-' {
@@ -417,59 +439,261 @@ pub fn make_loupe_clonotype(
     // Build Clonotype.
 
     let n = ecl.iter().map(|e| e.cell_barcodes.len()).sum::<usize>();
+    let tree = if ctl.gen_opt.tree_on {
+        clonotype_tree(refdata, dref, exact_clonotypes, exacts, rsi)
+    } else {
+        None
+    };
     Clonotype {
         chains: xchains,
         exact_clonotypes: ecl,
         frequency: n as u32,
+        tree,
+        group_id: None, // set later, if grouping
     }
 }
 
-pub fn loupe_out(
+fn make_universal_ref(refdata: &RefData) -> UniversalReference {
+    let mut uref = Vec::new();
+    for i in 0..refdata.refs.len() {
+        uref.push(UniversalReferenceItem {
+            ref_idx: refdata.id[i] as u32,
+            display_name: refdata.name[i].clone(),
+            region: match refdata.segtype[i] {
+                "U" => Region::U.into(),
+                "V" => Region::V.into(),
+                "D" => Region::D.into(),
+                "J" => Region::J.into(),
+                "C" => Region::C.into(),
+                _ => unreachable!(),
+            },
+            nt_sequence: refdata.refs[i].to_ascii_vec(),
+        });
+    }
+    UniversalReference { items: uref }
+}
+
+fn read_proto_metadata(ctl: &EncloneControl) -> Metadata {
+    match &ctl.gen_opt.proto_metadata {
+        Some(fname) => serde_json::from_reader(
+            std::fs::File::open(fname).unwrap_or_else(|_| panic!("Error while reading {fname}")),
+        )
+        .unwrap_or_else(|_| panic!("Unable to deserialize Metadata from {fname}")),
+        None => Metadata::default(),
+    }
+}
+
+// Assemble the Loupe data structure from the clonotypes.
+
+pub fn make_enclone_outputs(
     ctl: &EncloneControl,
     all_loupe_clonotypes: Vec<Clonotype>,
     refdata: &RefData,
     dref: &[DonorReferenceItem],
-) {
-    if !ctl.gen_opt.binary.is_empty() || !ctl.gen_opt.proto.is_empty() {
-        let mut uref = Vec::new();
-        for i in 0..refdata.refs.len() {
-            uref.push(UniversalReferenceItem {
-                ref_idx: refdata.id[i] as u32,
-                display_name: refdata.name[i].clone(),
-                region: match refdata.segtype[i] {
-                    "U" => Region::U.into(),
-                    "V" => Region::V.into(),
-                    "D" => Region::D.into(),
-                    "J" => Region::J.into(),
-                    "C" => Region::C.into(),
-                    _ => unreachable!(),
-                },
-                nt_sequence: refdata.refs[i].to_ascii_vec(),
-            });
+) -> EncloneOutputs {
+    EncloneOutputs {
+        version: PROTO_VERSION.into(),
+        metadata: read_proto_metadata(ctl),
+        num_clonotypes: all_loupe_clonotypes.len() as u32,
+        clonotypes: all_loupe_clonotypes,
+        universal_reference: make_universal_ref(refdata),
+        donor_reference: DonorReference {
+            items: dref.to_vec(),
+        },
+    }
+}
+
+// Streaming output of clonotypes to the PROTO file, used if PROTO_STREAM is specified.  Orbits
+// are processed in parallel, and the clonotypes for each orbit are appended to a spool file as
+// soon as they are computed, so that they need not be held in memory.  Once all orbits are done,
+// the clonotypes are copied from the spool file to the PROTO file, in the same order as the
+// other outputs, so that clonotypes are numbered the same way in all of them.
+
+pub struct LoupeStream {
+    writer: StreamingProtoWriter<BufWriter<File>>,
+    index_path: String,
+    spool: ProtoWriter<BufWriter<File>>,
+    spool_path: String,
+    spool_pos: u64,
+    orbits: HashMap<usize, (u64, usize)>, // orbit -> (start in spool, number of clonotypes)
+    error: Option<String>,
+}
+
+impl LoupeStream {
+    pub fn start(
+        ctl: &EncloneControl,
+        refdata: &RefData,
+        dref: &[DonorReferenceItem],
+    ) -> Result<Option<LoupeStream>, String> {
+        if !ctl.gen_opt.proto_stream || ctl.gen_opt.proto.is_empty() {
+            return Ok(None);
         }
-        let metadata = match &ctl.gen_opt.proto_metadata {
-            Some(fname) => serde_json::from_reader(
-                std::fs::File::open(fname)
-                    .unwrap_or_else(|_| panic!("Error while reading {fname}")),
-            )
-            .unwrap_or_else(|_| panic!("Unable to deserialize Metadata from {fname}")),
-            None => Metadata::default(),
-        };
-        let enclone_outputs = EncloneOutputs {
-            version: PROTO_VERSION.into(),
-            metadata,
-            num_clonotypes: all_loupe_clonotypes.len() as u32,
-            clonotypes: all_loupe_clonotypes,
-            universal_reference: UniversalReference { items: uref },
-            donor_reference: DonorReference {
+        let mut writer = StreamingProtoWriter::create(
+            &ctl.gen_opt.proto,
+            PROTO_VERSION.into(),
+            read_proto_metadata(ctl),
+            make_universal_ref(refdata),
+            DonorReference {
                 items: dref.to_vec(),
             },
+        )
+        .map_err(|e| format!("\nFailed to write {}: {e}.\n", ctl.gen_opt.proto))?;
+        if !ctl.gen_opt.proto_index.is_empty() {
+            writer = writer.with_index();
+        }
+        let spool_path = format!("{}.spool", ctl.gen_opt.proto);
+        let spool = File::create(&spool_path)
+            .map_err(|e| format!("\nFailed to create {spool_path}: {e}.\n"))?;
+        Ok(Some(LoupeStream {
+            writer,
+            index_path: ctl.gen_opt.proto_index.clone(),
+            spool: ProtoWriter::with_writer(BufWriter::new(spool)),
+            spool_path,
+            spool_pos: 0,
+            orbits: HashMap::new(),
+            error: None,
+        }))
+    }
+
+    // Accept the clonotypes for an orbit, which may be empty.  Every orbit must be pushed
+    // exactly once.
+
+    pub fn push(&mut self, orbit: usize, clonotypes: Vec<Clonotype>) {
+        self.orbits
+            .insert(orbit, (self.spool_pos, clonotypes.len()));
+        for cl in clonotypes {
+            if self.error.is_none() {
+                match self.spool.encode_and_write(cl) {
+                    Ok(n) => self.spool_pos += n as u64,
+                    Err(e) => self.error = Some(e.to_string()),
+                }
+            }
+        }
+    }
+
+    // Write the clonotypes to the PROTO file, taking the orbits in the given order, and delete
+    // the spool file.
+
+    pub fn finish(self, order: &[usize]) -> Result<(), String> {
+        let result = match self.error {
+            Some(e) => Err(e),
+            None => copy_spool(
+                self.writer,
+                self.spool,
+                &self.spool_path,
+                &self.orbits,
+                order,
+            ),
         };
-        if !ctl.gen_opt.binary.is_empty() {
-            write_obj(&enclone_outputs, &ctl.gen_opt.binary);
+        let _ = std::fs::remove_file(&self.spool_path);
+        let index = result.map_err(|e| format!("\nFailed to write PROTO file: {e}.\n"))?;
+        if let Some(index) = index {
+            write_proto_index(index, &self.index_path)
+                .map_err(|e| format!("\nFailed to write {}: {e}.\n", self.index_path))?;
         }
-        if !ctl.gen_opt.proto.is_empty() {
-            write_proto(enclone_outputs, &ctl.gen_opt.proto).unwrap();
+        Ok(())
+    }
+}
+
+fn copy_spool(
+    mut writer: StreamingProtoWriter<BufWriter<File>>,
+    spool: ProtoWriter<BufWriter<File>>,
+    spool_path: &str,
+    orbits: &HashMap<usize, (u64, usize)>,
+    order: &[usize],
+) -> Result<Option<ProtoIndex>, String> {
+    spool.finish().into_inner().map_err(|e| e.to_string())?;
+    let spool = File::open(spool_path).map_err(|e| e.to_string())?;
+    let mut reader = ProtoReader::from_reader(BufReader::new(spool));
+    for orbit in order {
+        let (start, n) = orbits[orbit];
+        reader.seek(start).map_err(|e| e.to_string())?;
+        for _ in 0..n {
+            let cl: Clonotype = reader.read_and_decode().map_err(|e| e.to_string())?;
+            writer.write_clonotype(cl).map_err(|e| e.to_string())?;
         }
     }
+    let (_, index) = writer.finish().map_err(|e| e.to_string())?;
+    Ok(index)
+}
+
+// Write the Loupe data structure to the BINARY and PROTO files, if requested.  If
+// ctl.gen_opt.proto_in_memory is set, the data structure is also returned.  If PROTO_STREAM
+// was specified, the PROTO file has already been written by a LoupeStream.
+
+pub fn loupe_out(
+    ctl: &EncloneControl,
+    all_loupe_clonotypes: Vec<Clonotype>,
+    refdata: &RefData,
+    dref: &[DonorReferenceItem],
+) -> Option<EncloneOutputs> {
+    let write_proto_file = !ctl.gen_opt.proto.is_empty() && !ctl.gen_opt.proto_stream;
+    if ctl.gen_opt.binary.is_empty() && !write_proto_file && !ctl.gen_opt.proto_in_memory {
+        return None;
+    }
+    let enclone_outputs = make_enclone_outputs(ctl, all_loupe_clonotypes, refdata, dref);
+    if !ctl.gen_opt.binary.is_empty() {
+        write_obj(&enclone_outputs, &ctl.gen_opt.binary);
+    }
+    if ctl.gen_opt.proto_in_memory {
+        if write_proto_file {
+            write_proto_file_and_index(ctl, enclone_outputs.clone());
+        }
+        Some(enclone_outputs)
+    } else {
+        if write_proto_file {
+            write_proto_file_and_index(ctl, enclone_outputs);
+        }
+        None
+    }
+}
+
+fn write_proto_file_and_index(ctl: &EncloneControl, enclone_outputs: EncloneOutputs) {
+    if ctl.gen_opt.proto_index.is_empty() {
+        write_proto(enclone_outputs, &ctl.gen_opt.proto).unwrap();
+    } else {
+        write_proto_and_index(
+            enclone_outputs,
+            &ctl.gen_opt.proto,
+            &ctl.gen_opt.proto_index,
+        )
+        .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enclone_proto::proto_io::read_proto;
+
+    // Orbits that are pushed out of order are written in the order given to finish.
+
+    #[test]
+    fn test_loupe_stream_order() {
+        let path = std::env::temp_dir().join(format!("loupe_stream_{}", std::process::id()));
+        let mut ctl = EncloneControl::default();
+        ctl.gen_opt.proto = path.to_str().unwrap().to_string();
+        ctl.gen_opt.proto_stream = true;
+        let mut stream = LoupeStream::start(&ctl, &RefData::new(), &[])
+            .unwrap()
+            .unwrap();
+        let clonotype = |frequency| Clonotype {
+            frequency,
+            ..Default::default()
+        };
+        stream.push(1, vec![clonotype(5)]);
+        stream.push(2, Vec::new());
+        stream.push(0, vec![clonotype(3), clonotype(2)]);
+        stream.finish(&[1, 2, 0]).unwrap();
+        let outputs = read_proto(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let freqs = outputs
+            .clonotypes
+            .iter()
+            .map(|x| x.frequency)
+            .collect::<Vec<_>>();
+        assert_eq!(outputs.num_clonotypes, 3);
+        assert_eq!(freqs, [5, 3, 2]);
+        assert!(!std::path::Path::new(&format!("{}.spool", ctl.gen_opt.proto)).exists());
+    }
 }
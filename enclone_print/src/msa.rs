@@ -0,0 +1,310 @@
+// Copyright (c) 2023 10X Genomics, Inc. All rights reserved.
+
+// Multiple sequence alignments of clonotypes (FASTA, FASTA_AA, CLUSTAL_DNA, CLUSTAL_AA,
+// PHYLIP_DNA and PHYLIP_AA).
+//
+// The alignment of a clonotype has a record for the donor germline, named germline, followed by
+// a record for each exact subclonotype, named by its number in the clonotype, counting from one.
+// Each record is the concatenation, over the chains of the clonotype, of the V..J sequence of
+// the chain as in CloneInfo.tigs, i.e. seq_del, from which an insertion relative to the
+// reference has been removed, and in which a deletion is shown as '-' characters.  The germline
+// is as for AIRR output, with N for each base of the junction that is not from V or J.  If an
+// exact subclonotype lacks a chain, its part of the record is all '-'.  The sequences of a chain
+// normally have the same length, but if not, they are padded at the end with '-'.  Amino acid
+// alignments are the same, but use aa_mod_indel, and translate the germline, using X for codons
+// that contain N.
+//
+// FASTA and FASTA_AA write the alignments of all the clonotypes to one file, with records named
+// clonotype<n>.<record>, e.g. clonotype3.germline.  The CLUSTAL and PHYLIP outputs are tar files
+// having one file per clonotype, e.g. clonotype3.aln or clonotype3.phy, or are printed one after
+// another if the value is stdout.  The PHYLIP files are sequential, with names padded to ten
+// characters.
+//
+// Clonotypes are numbered as for AIRR output.
+
+use crate::airr::germline_alignment;
+use amino::codon_to_aa;
+use enclone_core::defs::{ColInfo, EncloneControl, ExactClonotype};
+use enclone_proto::types::DonorReferenceItem;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use string_utils::strme;
+use tar::{Builder, Header};
+use vdj_ann::refx::RefData;
+
+const CLUSTAL_WIDTH: usize = 60;
+const CLUSTAL_NAME_WIDTH: usize = 16;
+const PHYLIP_NAME_WIDTH: usize = 10;
+
+struct Msa {
+    names: Vec<String>,
+    seqs: Vec<Vec<u8>>,
+}
+
+fn clonotype_msa(
+    refdata: &RefData,
+    dref: &[DonorReferenceItem],
+    exact_clonotypes: &[ExactClonotype],
+    exacts: &[usize],
+    rsi: &ColInfo,
+    aa: bool,
+) -> Msa {
+    let mut seqs = vec![Vec::<u8>::new(); exacts.len() + 1];
+    for col in &rsi.mat {
+        let Some(u0) = col.iter().position(Option::is_some) else {
+            continue;
+        };
+        let mut germline = germline_alignment(
+            refdata,
+            dref,
+            &exact_clonotypes[exacts[u0]].share[col[u0].unwrap()],
+        );
+        if aa {
+            germline = germline
+                .chunks_exact(3)
+                .map(|c| {
+                    if c.contains(&b'N') {
+                        b'X'
+                    } else {
+                        codon_to_aa(c)
+                    }
+                })
+                .collect();
+        }
+        let rows = col
+            .iter()
+            .enumerate()
+            .map(|(u, m)| {
+                m.map(|m| {
+                    let x = &exact_clonotypes[exacts[u]].share[m];
+                    if aa {
+                        &x.aa_mod_indel
+                    } else {
+                        &x.seq_del
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        let len = rows
+            .iter()
+            .flatten()
+            .map(|s| s.len())
+            .chain([germline.len()])
+            .max()
+            .unwrap();
+        for (seq, row) in seqs
+            .iter_mut()
+            .zip([Some(&germline)].into_iter().chain(rows))
+        {
+            let start = seq.len();
+            seq.extend(row.into_iter().flatten());
+            seq.resize(start + len, b'-');
+        }
+    }
+    let names = ["germline".to_string()]
+        .into_iter()
+        .chain((1..=exacts.len()).map(|u| u.to_string()))
+        .collect();
+    Msa { names, seqs }
+}
+
+fn fasta(msa: &Msa, prefix: &str, out: &mut String) {
+    for (name, seq) in msa.names.iter().zip(msa.seqs.iter()) {
+        writeln!(out, ">{prefix}.{name}\n{}", strme(seq)).unwrap();
+    }
+}
+
+fn clustal(msa: &Msa) -> String {
+    let mut out = "CLUSTAL W multiple sequence alignment\n".to_string();
+    let len = msa.seqs[0].len();
+    for start in (0..len).step_by(CLUSTAL_WIDTH) {
+        let stop = (start + CLUSTAL_WIDTH).min(len);
+        out.push('\n');
+        for (name, seq) in msa.names.iter().zip(msa.seqs.iter()) {
+            let w = CLUSTAL_NAME_WIDTH;
+            writeln!(out, "{name:<w$}{}", strme(&seq[start..stop])).unwrap();
+        }
+        let conserved = (start..stop)
+            .map(|p| {
+                let b = msa.seqs[0][p];
+                if b != b'-' && msa.seqs.iter().all(|s| s[p] == b) {
+                    '*'
+                } else {
+                    ' '
+                }
+            })
+            .collect::<String>();
+        writeln!(out, "{:CLUSTAL_NAME_WIDTH$}{}", "", conserved.trim_end()).unwrap();
+    }
+    out
+}
+
+fn phylip(msa: &Msa) -> String {
+    let mut out = format!("{} {}\n", msa.seqs.len(), msa.seqs[0].len());
+    for (name, seq) in msa.names.iter().zip(msa.seqs.iter()) {
+        writeln!(out, "{name:<PHYLIP_NAME_WIDTH$}{}", strme(seq)).unwrap();
+    }
+    out
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+// Write a file, or print it if path is stdout.
+
+pub(crate) fn write_or_print(path: &str, arg: &str, data: &str) -> Result<(), String> {
+    if path == "stdout" {
+        print!("{data}");
+        return Ok(());
+    }
+    let err = |e: std::io::Error| format!("\nUnable to write {arg} file {path}: {e}.\n");
+    let mut f = BufWriter::new(File::create(path).map_err(err)?);
+    f.write_all(data.as_bytes()).map_err(err)?;
+    f.flush().map_err(err)
+}
+
+// Write a tar file containing the given (name, data) entries, or print the data if path is
+// stdout.
+
+fn write_tar(path: &str, arg: &str, entries: &[(String, String)]) -> Result<(), String> {
+    if path == "stdout" {
+        let data = entries.iter().map(|(_, data)| data.as_str());
+        print!("{}", data.collect::<Vec<_>>().join("\n"));
+        return Ok(());
+    }
+    let err = |e: std::io::Error| format!("\nUnable to write {arg} file {path}: {e}.\n");
+    let mut tar = Builder::new(BufWriter::new(File::create(path).map_err(err)?));
+    for (name, data) in entries {
+        let mut header = Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        tar.append_data(&mut header, name, data.as_bytes())
+            .map_err(err)?;
+    }
+    tar.into_inner().map_err(err)?.flush().map_err(err)
+}
+
+pub fn write_msas(
+    ctl: &EncloneControl,
+    refdata: &RefData,
+    dref: &[DonorReferenceItem],
+    exact_clonotypes: &[ExactClonotype],
+    exacts: &[Vec<usize>],
+    rsi: &[ColInfo],
+) -> Result<(), String> {
+    let g = &ctl.gen_opt;
+    for (path, arg, aa) in [
+        (&g.fasta_filename, "FASTA", false),
+        (&g.fasta_aa_filename, "FASTA_AA", true),
+    ] {
+        if !path.is_empty() {
+            let mut out = String::new();
+            for (i, (exacts, rsi)) in exacts.iter().zip(rsi.iter()).enumerate() {
+                let msa = clonotype_msa(refdata, dref, exact_clonotypes, exacts, rsi, aa);
+                fasta(&msa, &format!("clonotype{}", i + 1), &mut out);
+            }
+            write_or_print(path, arg, &out)?;
+        }
+    }
+    for (path, arg, aa, ext, format) in [
+        (
+            &g.clustal_dna,
+            "CLUSTAL_DNA",
+            false,
+            "aln",
+            clustal as fn(&Msa) -> String,
+        ),
+        (&g.clustal_aa, "CLUSTAL_AA", true, "aln", clustal),
+        (&g.phylip_dna, "PHYLIP_DNA", false, "phy", phylip),
+        (&g.phylip_aa, "PHYLIP_AA", true, "phy", phylip),
+    ] {
+        if !path.is_empty() {
+            let entries = exacts
+                .iter()
+                .zip(rsi.iter())
+                .enumerate()
+                .map(|(i, (exacts, rsi))| {
+                    let msa = clonotype_msa(refdata, dref, exact_clonotypes, exacts, rsi, aa);
+                    (format!("clonotype{}.{ext}", i + 1), format(&msa))
+                })
+                .collect::<Vec<_>>();
+            write_tar(path, arg, &entries)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msa(seqs: &[&str]) -> Msa {
+        Msa {
+            names: ["germline".to_string()]
+                .into_iter()
+                .chain((1..seqs.len()).map(|u| u.to_string()))
+                .collect(),
+            seqs: seqs.iter().map(|s| s.as_bytes().to_vec()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_clustal_short() {
+        let out = clustal(&msa(&["ACGTNN", "ACGTAC", "ACTT-C"]));
+        assert_eq!(
+            out,
+            "CLUSTAL W multiple sequence alignment\n\
+             \n\
+             germline        ACGTNN\n\
+             1               ACGTAC\n\
+             2               ACTT-C\n\
+             \x20               ** *\n"
+        );
+    }
+
+    #[test]
+    fn test_clustal_blocks() {
+        // Sequences of length 130 are wrapped into blocks of 60, 60 and 10 bases, each followed
+        // by a conservation line.  The sequences differ only at position 65.
+
+        let germline = "ACGT".repeat(33)[..130].to_string();
+        let mut other = germline.clone().into_bytes();
+        other[65] = b'A';
+        let other = String::from_utf8(other).unwrap();
+        let out = clustal(&msa(&[&germline, &other]));
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "CLUSTAL W multiple sequence alignment");
+        assert_eq!(lines.len(), 1 + 3 * 4);
+        for (b, (start, stop)) in [(0, 60), (60, 120), (120, 130)].into_iter().enumerate() {
+            let block = &lines[1 + 4 * b..1 + 4 * (b + 1)];
+            assert_eq!(block[0], "");
+            assert_eq!(
+                block[1],
+                format!("germline        {}", &germline[start..stop])
+            );
+            assert_eq!(block[2], format!("1               {}", &other[start..stop]));
+            let conserved = &block[3][CLUSTAL_NAME_WIDTH..];
+            let mut expected = "*".repeat(stop - start);
+            if b == 1 {
+                expected.replace_range(5..6, " ");
+            }
+            assert_eq!(conserved, expected);
+        }
+    }
+
+    #[test]
+    fn test_phylip() {
+        // The header gives the number of sequences and their length, and names are padded to
+        // ten characters.  Sequences are not wrapped.
+
+        let long = "ACGT".repeat(25);
+        let out = phylip(&msa(&[&long, &long]));
+        assert_eq!(out, format!("2 100\ngermline  {long}\n1         {long}\n"));
+        let out = phylip(&msa(&["ACG", "A-G", "ACC"]));
+        assert_eq!(out, "3 3\ngermline  ACG\n1         A-G\n2         ACC\n");
+        for line in out.lines().skip(1) {
+            assert_eq!(line.len(), PHYLIP_NAME_WIDTH + 3);
+        }
+    }
+}
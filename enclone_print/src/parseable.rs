@@ -0,0 +1,243 @@
+// Copyright (c) 2023 10X Genomics, Inc. All rights reserved.
+
+// Write parseable output (POUT), having one line per exact subclonotype, or with PCELL, one line
+// per cell.
+//
+// The values are those computed by print_clonotypes for each exact subclonotype, plus
+// clonotype_id, group_id and group_ncells, which are only known after grouping.  Clonotypes are
+// numbered as for AIRR output, and are listed by group.  With PCELL, a value that has one entry
+// per cell, separated by POUT_SEP, is split, and any other value is repeated on each line.
+//
+// The columns are those given by PCOLS, or if PCOLS is not given, every parseable field, and the
+// header line uses the names given by PCOLS_SHOW, if that is given.  The output is TSV if the
+// file name ends in .tsv, or if it is stdouth, and CSV otherwise, in which case a value that
+// contains a comma or a quote is quoted.
+
+use enclone_core::defs::{ColInfo, EncloneControl, ExactClonotype, POUT_SEP};
+use enclone_core::set_speakers::set_speakers;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+fn csv_field(x: &str) -> String {
+    if x.contains(',') || x.contains('"') {
+        format!("\"{}\"", x.replace('"', "\"\""))
+    } else {
+        x.to_string()
+    }
+}
+
+pub fn write_parseable(
+    ctl: &EncloneControl,
+    exact_clonotypes: &[ExactClonotype],
+    exacts: &[Vec<usize>],
+    rsi: &[ColInfo],
+    out_datas: &[Vec<HashMap<String, String>>],
+    groups: &[Vec<usize>],
+) -> Result<(), String> {
+    let popt = &ctl.parseable_opt;
+    let fields = if popt.pcols.is_empty() {
+        let max_chains = rsi.iter().map(|r| r.mat.len()).max().unwrap_or(0).max(4);
+        let mut fields = Vec::<String>::new();
+        set_speakers(ctl, &mut fields, max_chains);
+        fields
+    } else {
+        popt.pcols.clone()
+    };
+    let tsv = popt.pout == "stdouth" || popt.pout.ends_with(".tsv");
+    let join = |vals: &[&str]| {
+        if tsv {
+            vals.join("\t")
+        } else {
+            vals.iter()
+                .map(|x| csv_field(x))
+                .collect::<Vec<_>>()
+                .join(",")
+        }
+    };
+
+    // Generate the lines.
+
+    let mut lines = Vec::<String>::new();
+    if !popt.pno_header {
+        let header = if popt.pcols_show.is_empty() {
+            &fields
+        } else {
+            &popt.pcols_show
+        };
+        lines.push(join(&header.iter().map(String::as_str).collect::<Vec<_>>()));
+    }
+    for (g, group) in groups.iter().enumerate() {
+        let group_ncells = group
+            .iter()
+            .flat_map(|&i| exacts[i].iter())
+            .map(|&e| exact_clonotypes[e].ncells())
+            .sum::<usize>();
+        for &i in group {
+            let ids = [
+                ("clonotype_id", (i + 1).to_string()),
+                ("group_id", (g + 1).to_string()),
+                ("group_ncells", group_ncells.to_string()),
+            ];
+            for (u, data) in out_datas[i].iter().enumerate() {
+                let vals = fields
+                    .iter()
+                    .map(|f| match ids.iter().find(|(id, _)| id == f) {
+                        Some((_, val)) => val.as_str(),
+                        None => data.get(f).map_or("", String::as_str),
+                    })
+                    .collect::<Vec<_>>();
+                if !popt.pbarcode {
+                    lines.push(join(&vals));
+                    continue;
+                }
+                let vals = vals
+                    .iter()
+                    .map(|x| x.split(POUT_SEP).collect::<Vec<_>>())
+                    .collect::<Vec<_>>();
+                for b in 0..exact_clonotypes[exacts[i][u]].ncells() {
+                    let cell = vals
+                        .iter()
+                        .map(|x| {
+                            if x.len() > 1 {
+                                x.get(b).copied().unwrap_or("")
+                            } else {
+                                x[0]
+                            }
+                        })
+                        .collect::<Vec<_>>();
+                    lines.push(join(&cell));
+                }
+            }
+        }
+    }
+
+    // Write them.
+
+    if popt.pout == "stdout" || popt.pout == "stdouth" {
+        for line in &lines {
+            println!("{line}");
+        }
+        return Ok(());
+    }
+    let err = |e: std::io::Error| format!("\nUnable to write POUT file {}: {e}.\n", popt.pout);
+    let mut f = BufWriter::new(File::create(&popt.pout).map_err(err)?);
+    for line in &lines {
+        writeln!(f, "{line}").map_err(err)?;
+    }
+    f.flush().map_err(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{read_to_string, remove_file};
+
+    // Two clonotypes in one group, the first having exact subclonotypes with two cells and one
+    // cell, and the second having one exact subclonotype with three cells.
+
+    fn exact_clonotypes() -> Vec<ExactClonotype> {
+        [2, 1, 3]
+            .into_iter()
+            .map(|n| ExactClonotype {
+                share: Vec::new(),
+                clones: vec![Vec::new(); n],
+            })
+            .collect()
+    }
+
+    fn data(x: &[(&str, &str)]) -> HashMap<String, String> {
+        x.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn test_ctl(ext: &str, pcols: &[&str]) -> EncloneControl {
+        let mut ctl = EncloneControl::default();
+        ctl.parseable_opt.pout = std::env::temp_dir()
+            .join(format!("parseable_test_{}.{ext}", std::process::id()))
+            .to_str()
+            .unwrap()
+            .to_string();
+        ctl.parseable_opt.pcols = pcols.iter().map(ToString::to_string).collect();
+        ctl
+    }
+
+    fn write(ctl: &EncloneControl, out_datas: &[Vec<HashMap<String, String>>]) -> String {
+        let exacts = vec![vec![0, 1], vec![2]];
+        let groups = vec![vec![0, 1]];
+        write_parseable(ctl, &exact_clonotypes(), &exacts, &[], out_datas, &groups).unwrap();
+        let out = read_to_string(&ctl.parseable_opt.pout).unwrap();
+        remove_file(&ctl.parseable_opt.pout).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("CARDYW"), "CARDYW");
+        assert_eq!(csv_field(""), "");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn test_csv() {
+        let ctl = test_ctl(
+            "csv",
+            &["group_id", "clonotype_id", "group_ncells", "x", "y"],
+        );
+        let out_datas = vec![
+            vec![data(&[("x", "1,2"), ("y", "a")]), data(&[("x", "3")])],
+            vec![data(&[("x", "\"q\""), ("y", "b")])],
+        ];
+        assert_eq!(
+            write(&ctl, &out_datas),
+            "group_id,clonotype_id,group_ncells,x,y\n\
+             1,1,6,\"1,2\",a\n\
+             1,1,6,3,\n\
+             1,2,6,\"\"\"q\"\"\",b\n"
+        );
+    }
+
+    #[test]
+    fn test_tsv() {
+        let mut ctl = test_ctl("tsv", &["clonotype_id", "x"]);
+        ctl.parseable_opt.pcols_show = vec!["id".to_string(), "x,renamed".to_string()];
+        let out_datas = vec![
+            vec![data(&[("x", "1,2")]), data(&[("x", "3")])],
+            vec![data(&[("x", "\"q\"")])],
+        ];
+        assert_eq!(
+            write(&ctl, &out_datas),
+            "id\tx,renamed\n1\t1,2\n1\t3\n2\t\"q\"\n"
+        );
+        ctl.parseable_opt.pno_header = true;
+        assert_eq!(write(&ctl, &out_datas), "1\t1,2\n1\t3\n2\t\"q\"\n");
+    }
+
+    #[test]
+    fn test_pcell() {
+        // Values having one entry per cell are split, and other values are repeated.
+
+        let mut ctl = test_ctl("pcell.tsv", &["clonotype_id", "barcode", "x"]);
+        ctl.parseable_opt.pbarcode = true;
+        let sep = POUT_SEP;
+        let out_datas = vec![
+            vec![
+                data(&[("barcode", &format!("A-1{sep}B-1")), ("x", "u")]),
+                data(&[("barcode", "C-1"), ("x", "v")]),
+            ],
+            vec![data(&[("barcode", &format!("D-1{sep}E-1{sep}F-1"))])],
+        ];
+        assert_eq!(
+            write(&ctl, &out_datas),
+            "clonotype_id\tbarcode\tx\n\
+             1\tA-1\tu\n\
+             1\tB-1\tu\n\
+             1\tC-1\tv\n\
+             2\tD-1\t\n\
+             2\tE-1\t\n\
+             2\tF-1\t\n"
+        );
+    }
+}
@@ -0,0 +1,959 @@
+// Copyright (c) 2023 10X Genomics, Inc. All rights reserved.
+
+// Honeycomb plots of clonotypes (HONEY, PLOT, PLOT_BY_ISOTYPE, PLOT_BY_MARK) and scatter plots
+// of two variables (PLOTXY_EXACT), rendered headlessly as SVG or PNG.
+//
+// In a honeycomb plot, each cell is a circle, colored as specified by CellColor, and the cells
+// of a clonotype are packed hexagonally into a disk, in order of color.  The disks are placed
+// in order of decreasing size, each at the first point on a spiral about the origin at which it
+// does not overlap the disks already placed.  The search for each disk starts a little inside
+// the point at which the previous disk was placed, so that placement takes roughly linear time.
+// With SPLIT_PLOT_BY_DATASET or SPLIT_PLOT_BY_ORIGIN there is one such panel for each dataset
+// or origin, side by side.  The layout may be saved with HONEY_OUT and reused with HONEY_IN.
+//
+// The legend is given by LEGEND=..., or is made from the categories of the coloring, or for
+// coloring by a variable, is a color scale.  At most LEGEND_MAX categories are shown.
+//
+// The scatter plot has a point for each exact subclonotype for which both variables have
+// numeric values (and positive values, if on a log scale).
+
+use crate::airr::write_tsv;
+use crate::render::{text_width, Anchor, Canvas, Shape};
+use enclone_core::cell_color::CellColor;
+use enclone_core::defs::{EncloneControl, ExactClonotype, TigData0, POUT_SEP};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::io::Write;
+use vdj_ann::refx::RefData;
+
+const CELL_RADIUS: f64 = 4.0; // radius of a cell, in canvas units; layouts are in cell radii
+const CLUSTER_GAP: f64 = 1.0; // space between clonotypes, in cell radii
+const MARGIN: f64 = 20.0;
+const FONT_SIZE: f64 = 14.0;
+const LEGEND_MAX: usize = 40;
+const OTHER_COLOR: &str = "#c8c8c8";
+const PALETTE: [&str; 20] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
+    "#bcbd22", "#17becf", "#aec7e8", "#ffbb78", "#98df8a", "#ff9896", "#c5b0d5", "#c49c94",
+    "#f7b6d2", "#c7c7c7", "#dbdb8d", "#9edae5",
+];
+
+const HONEY_FIELDS: [&str; 5] = ["panel", "clonotype_id", "x", "y", "radius"];
+
+// The turbo color scheme, using the polynomial approximation of Anton Mikhailov's map, for x in
+// [0, 1].
+
+fn turbo(x: f64) -> String {
+    let x = x.clamp(0.0, 1.0);
+    let poly = |c: [f64; 6]| {
+        let v = c[0] + x * (c[1] + x * (c[2] + x * (c[3] + x * (c[4] + x * c[5]))));
+        (v.clamp(0.0, 1.0) * 255.0).round() as u8
+    };
+    let r = poly([
+        0.13572138,
+        4.6153926,
+        -42.66032258,
+        132.13108234,
+        -152.94239396,
+        59.28637943,
+    ]);
+    let g = poly([
+        0.09140261,
+        2.19418839,
+        4.84296658,
+        -14.18503333,
+        4.27729857,
+        2.82956604,
+    ]);
+    let b = poly([
+        0.1066733,
+        12.64194608,
+        -60.58204836,
+        110.36276771,
+        -89.90310912,
+        27.34824973,
+    ]);
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+fn format_number(x: f64) -> String {
+    if x == x.round() && x.abs() < 1e15 {
+        format!("{x:.0}")
+    } else {
+        let s = format!("{x:.3}");
+        s.trim_end_matches('0').trim_end_matches('.').to_string()
+    }
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+// A cell to be plotted.
+
+struct PlotCell {
+    clonotype: usize,
+    panel: String,
+    color: String,
+}
+
+enum Legend {
+    Categories(Vec<(String, String)>),
+    Scale { var: String, min: f64, max: f64 },
+}
+
+// Assign colors to categories, the most frequent first, except that if sorted is true, in
+// sorted order.  Colors are taken from fixed if given there, and otherwise from the palette.
+// Categories after the first maxcat are colored as other.
+
+fn color_categories(
+    keys: &[String],
+    maxcat: usize,
+    sorted: bool,
+    fixed: &dyn Fn(&str) -> Option<String>,
+) -> (Vec<String>, Vec<(String, String)>) {
+    let mut counts = HashMap::<&str, usize>::new();
+    for key in keys {
+        *counts.entry(key.as_str()).or_default() += 1;
+    }
+    let mut cats = counts.into_iter().collect::<Vec<_>>();
+    if sorted {
+        cats.sort_unstable();
+    } else {
+        cats.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    }
+    let mut color = HashMap::<&str, String>::new();
+    let mut legend = Vec::<(String, String)>::new();
+    for (j, &(cat, _)) in cats.iter().enumerate() {
+        if j < maxcat {
+            let c = fixed(cat).unwrap_or_else(|| PALETTE[j % PALETTE.len()].to_string());
+            legend.push((c.clone(), cat.to_string()));
+            color.insert(cat, c);
+        }
+    }
+    if cats.len() > maxcat {
+        legend.push((OTHER_COLOR.to_string(), "other".to_string()));
+    }
+    let colors = keys
+        .iter()
+        .map(|k| {
+            color
+                .get(k.as_str())
+                .cloned()
+                .unwrap_or_else(|| OTHER_COLOR.to_string())
+        })
+        .collect();
+    (colors, legend)
+}
+
+// Find the cells to plot, and their colors, and the legend.
+
+fn plot_cells(
+    ctl: &EncloneControl,
+    refdata: &RefData,
+    exact_clonotypes: &[ExactClonotype],
+    exacts: &[Vec<usize>],
+    out_datas: &[Vec<HashMap<String, String>>],
+) -> (Vec<PlotCell>, Option<Legend>) {
+    let popt = &ctl.plot_opt;
+    let oi = &ctl.origin_info;
+
+    // For each cell, find its clonotype, exact subclonotype and contigs.
+
+    let mut cells = Vec::new();
+    for (i, exacts) in exacts.iter().enumerate() {
+        for (u, &e) in exacts.iter().enumerate() {
+            let ex = &exact_clonotypes[e];
+            for (k, clone) in ex.clones.iter().enumerate() {
+                cells.push((i, u, k, ex, clone));
+            }
+        }
+    }
+    let origin = |tig: &TigData0| match tig.origin_index {
+        Some(o) => oi.origin_list[o].clone(),
+        None => oi.origin_id[tig.dataset_index].clone(),
+    };
+    let value = |i: usize, u: usize, k: usize, var: &str| {
+        let val = out_datas.get(i)?.get(u)?.get(var)?;
+        let vals = val.split(POUT_SEP).collect::<Vec<_>>();
+        let v = if vals.len() > 1 {
+            *vals.get(k)?
+        } else {
+            vals[0]
+        };
+        Some(v.to_string())
+    };
+    let isotypes = || {
+        cells
+            .iter()
+            .map(|(_, _, _, ex, _)| {
+                ex.share
+                    .iter()
+                    .find(|x| x.left)
+                    .and_then(|x| x.c_ref_id)
+                    .map_or_else(|| "unassigned".to_string(), |c| refdata.name[c].clone())
+            })
+            .collect::<Vec<_>>()
+    };
+    let by_isotype = |colors: &[String]| {
+        let keys = isotypes();
+        let mut names = keys.clone();
+        names.sort();
+        names.dedup();
+        color_categories(&keys, usize::MAX, true, &|cat| {
+            let j = names.iter().position(|n| n == cat)?;
+            colors.get(j).cloned()
+        })
+    };
+    let no_legend = || (vec!["black".to_string(); cells.len()], Vec::new());
+    let mut scale = None;
+    let (colors, categories) = match &popt.cell_color {
+        CellColor::ByIsotype(x) => by_isotype(&x.color),
+        CellColor::ByVariableValue(x) => {
+            let vals = cells
+                .iter()
+                .map(|&(i, u, k, _, _)| value(i, u, k, &x.var).and_then(|v| v.parse::<f64>().ok()))
+                .collect::<Vec<_>>();
+            let lo = vals.iter().flatten().copied().fold(f64::MAX, f64::min);
+            let hi = vals.iter().flatten().copied().fold(f64::MIN, f64::max);
+            let (min, max) = (x.min.unwrap_or(lo), x.max.unwrap_or(hi));
+            let colors = vals
+                .iter()
+                .map(|v| match v {
+                    Some(v) if max > min => turbo((v - min) / (max - min)),
+                    Some(_) => turbo(0.5),
+                    None => "black".to_string(),
+                })
+                .collect();
+            if min <= max {
+                scale = Some(Legend::Scale {
+                    var: x.display_var.clone(),
+                    min,
+                    max,
+                });
+            }
+            (colors, Vec::new())
+        }
+        CellColor::ByCategoricalVariableValue(x) => {
+            let keys = cells
+                .iter()
+                .map(|&(i, u, k, _, _)| {
+                    x.vars
+                        .iter()
+                        .map(|var| value(i, u, k, var).unwrap_or_default())
+                        .collect::<Vec<_>>()
+                        .join(",")
+                })
+                .collect::<Vec<_>>();
+            color_categories(&keys, x.maxcat, false, &|_| None)
+        }
+        CellColor::ByDataset(_) => {
+            let keys = cells
+                .iter()
+                .map(|(_, _, _, _, clone)| oi.dataset_id[clone[0].dataset_index].clone())
+                .collect::<Vec<_>>();
+            color_categories(&keys, usize::MAX, true, &|cat| {
+                let li = oi.dataset_id.iter().position(|d| d == cat)?;
+                oi.color.get(li).filter(|c| !c.is_empty()).cloned()
+            })
+        }
+        CellColor::BySample(x) => {
+            let keys = cells
+                .iter()
+                .map(|(_, _, _, _, clone)| origin(&clone[0]))
+                .collect::<Vec<_>>();
+            color_categories(&keys, usize::MAX, true, &|cat| {
+                x.specification.get(cat).cloned()
+            })
+        }
+        CellColor::ByBarcodeSpecification(_) => {
+            let colors = cells
+                .iter()
+                .map(|(_, _, _, _, clone)| {
+                    oi.barcode_color[clone[0].dataset_index]
+                        .get(&clone[0].barcode)
+                        .cloned()
+                        .unwrap_or_else(|| "black".to_string())
+                })
+                .collect();
+            (colors, Vec::new())
+        }
+        CellColor::Unspecified => {
+            if popt.plot_by_isotype {
+                by_isotype(&popt.plot_by_isotype_color)
+            } else if popt.plot_by_mark {
+                let keys = cells
+                    .iter()
+                    .map(|(_, _, _, _, clone)| {
+                        if clone.iter().any(|x| x.marked) {
+                            "marked".to_string()
+                        } else {
+                            "unmarked".to_string()
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                color_categories(&keys, usize::MAX, true, &|cat| {
+                    Some(if cat == "marked" { "red" } else { OTHER_COLOR }.to_string())
+                })
+            } else if !ctl.gen_opt.origin_color_map.is_empty() {
+                let keys = cells
+                    .iter()
+                    .map(|(_, _, _, _, clone)| origin(&clone[0]))
+                    .collect::<Vec<_>>();
+                color_categories(&keys, usize::MAX, true, &|cat| {
+                    Some(
+                        ctl.gen_opt
+                            .origin_color_map
+                            .get(cat)
+                            .cloned()
+                            .unwrap_or_else(|| OTHER_COLOR.to_string()),
+                    )
+                })
+            } else {
+                no_legend()
+            }
+        }
+    };
+
+    // Define the legend.
+
+    let show_legend = match &popt.cell_color {
+        CellColor::ByIsotype(x) => x.show_legend,
+        _ if popt.plot_by_isotype => !popt.plot_by_isotype_nolegend,
+        _ => popt.use_legend,
+    };
+    let legend = if !show_legend {
+        None
+    } else if !popt.legend.is_empty() {
+        Some(Legend::Categories(popt.legend.clone()))
+    } else if scale.is_some() {
+        scale
+    } else if !categories.is_empty() {
+        Some(Legend::Categories(
+            categories.into_iter().take(LEGEND_MAX).collect(),
+        ))
+    } else {
+        None
+    };
+    let cells = cells
+        .iter()
+        .zip(colors)
+        .map(|(&(i, _, _, _, clone), color)| PlotCell {
+            clonotype: i,
+            panel: if popt.split_plot_by_dataset {
+                oi.dataset_id[clone[0].dataset_index].clone()
+            } else if popt.split_plot_by_origin {
+                origin(&clone[0])
+            } else {
+                String::new()
+            },
+            color,
+        })
+        .collect();
+    (cells, legend)
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+// Find the centers of n cells of unit radius, packed hexagonally about the origin, in order of
+// distance from it.  The centers for fewer cells are a prefix of these.
+
+fn hex_positions(n: usize) -> Vec<(f64, f64)> {
+    let k = (n as f64).sqrt().ceil() as i64 + 2;
+    let mut pos = Vec::<(f64, f64)>::new();
+    for q in -k..=k {
+        for r in -k..=k {
+            pos.push((2.0 * q as f64 + r as f64, 3f64.sqrt() * r as f64));
+        }
+    }
+    let key = |&(x, y): &(f64, f64)| (x.hypot(y), y.atan2(x));
+    pos.sort_by(|a, b| key(a).partial_cmp(&key(b)).unwrap());
+    pos.truncate(n);
+    pos
+}
+
+// Place disks without overlap, each at the first free point on a spiral.  Disks are recorded in
+// a grid of square cells of side GRID, in every cell that their bounding box meets.  The spiral
+// is searched in steps, which skip over points that are known to be covered.
+
+const GRID: f64 = 4.0;
+const PITCH: f64 = 1.0; // distance between turns of the spiral
+
+#[derive(Default)]
+struct Packer {
+    disks: Vec<(f64, f64, f64)>,
+    grid: HashMap<(i64, i64), Vec<usize>>,
+    last: f64,       // distance from the origin of the last disk placed
+    last_theta: f64, // and its angle along the spiral
+    last_r: f64,     // and its radius
+}
+
+impl Packer {
+    fn cells(x: f64, y: f64, r: f64) -> impl Iterator<Item = (i64, i64)> {
+        let lo = move |v: f64| ((v - r) / GRID).floor() as i64;
+        let hi = move |v: f64| ((v + r) / GRID).floor() as i64;
+        (lo(x)..=hi(x)).flat_map(move |gx| (lo(y)..=hi(y)).map(move |gy| (gx, gy)))
+    }
+
+    // Return the greatest depth by which a disk would overlap those already placed, or zero.
+
+    fn overlap(&self, x: f64, y: f64, r: f64) -> f64 {
+        let mut depth = 0.0_f64;
+        for c in Packer::cells(x, y, r) {
+            for &d in self.grid.get(&c).into_iter().flatten() {
+                let (dx, dy, dr) = self.disks[d];
+                depth = depth.max(r + dr - (x - dx).hypot(y - dy));
+            }
+        }
+        depth
+    }
+
+    fn insert(&mut self, x: f64, y: f64, r: f64) {
+        let d = self.disks.len();
+        self.disks.push((x, y, r));
+        for c in Packer::cells(x, y, r) {
+            self.grid.entry(c).or_default().push(d);
+        }
+    }
+
+    fn place(&mut self, r: f64) -> (f64, f64) {
+        let mut theta = (self.last - 2.0 * r - PITCH).max(0.0) * 2.0 * PI / PITCH;
+
+        // A disk of the same radius as the last cannot be placed before it.
+
+        if r == self.last_r {
+            theta = theta.max(self.last_theta);
+        }
+        loop {
+            let rho = theta * PITCH / (2.0 * PI);
+            let (x, y) = (rho * theta.cos(), rho * theta.sin());
+            let depth = self.overlap(x, y, r);
+            if depth <= 0.0 {
+                (self.last, self.last_theta, self.last_r) = (rho, theta, r);
+                self.insert(x, y, r);
+                return (x, y);
+            }
+
+            // Advance along the spiral by at least a small step, and by as much as the depth,
+            // since no point closer than that can be free.
+
+            let speed = rho.hypot(PITCH / (2.0 * PI));
+            theta += depth.max(0.1) / speed.max(0.2);
+        }
+    }
+}
+
+// Read a layout written by HONEY_OUT.
+
+fn read_layout(path: &str) -> Result<HashMap<(String, usize), (f64, f64)>, String> {
+    let err = |e: String| format!("\nUnable to read HONEY_IN file {path}: {e}.\n");
+    let text = std::fs::read_to_string(path).map_err(|e| err(e.to_string()))?;
+    let mut layout = HashMap::new();
+    for line in text.lines().skip(1) {
+        let f = line.split('\t').collect::<Vec<_>>();
+        let parsed = (f.len() == HONEY_FIELDS.len())
+            .then(|| {
+                let id = f[1].strip_prefix("clonotype")?.parse::<usize>().ok()?;
+                Some((id, f[2].parse::<f64>().ok()?, f[3].parse::<f64>().ok()?))
+            })
+            .flatten();
+        let Some((id, x, y)) = parsed else {
+            return Err(err(format!("the line \"{line}\" is malformed")));
+        };
+        layout.insert((f[0].to_string(), id), (x, y));
+    }
+    Ok(layout)
+}
+
+// Draw the legend at the given position, returning its width and height.
+
+fn draw_legend(legend: &Legend, x0: f64, y0: f64, shapes: &mut Vec<Shape>) -> (f64, f64) {
+    let text = |x: f64, y: f64, s: &str| Shape::Text {
+        x,
+        y,
+        size: FONT_SIZE,
+        anchor: Anchor::Start,
+        fill: "black".to_string(),
+        text: s.to_string(),
+    };
+    match legend {
+        Legend::Categories(cats) => {
+            let line = FONT_SIZE * 1.5;
+            let mut width = 0.0_f64;
+            for (j, (color, name)) in cats.iter().enumerate() {
+                let y = y0 + j as f64 * line;
+                shapes.push(Shape::Circle {
+                    x: x0 + FONT_SIZE / 2.0,
+                    y: y + line / 2.0,
+                    r: FONT_SIZE / 2.5,
+                    fill: color.clone(),
+                });
+                shapes.push(text(
+                    x0 + FONT_SIZE * 1.5,
+                    y + line / 2.0 + FONT_SIZE / 3.0,
+                    name,
+                ));
+                width = width.max(FONT_SIZE * 1.5 + text_width(name, FONT_SIZE));
+            }
+            (width, cats.len() as f64 * line)
+        }
+        Legend::Scale { var, min, max } => {
+            const STEPS: usize = 64;
+            let (bar_w, bar_h) = (FONT_SIZE * 1.5, FONT_SIZE * 12.0);
+            shapes.push(text(x0, y0 + FONT_SIZE, var));
+            let top = y0 + FONT_SIZE * 1.5;
+            for s in 0..STEPS {
+                shapes.push(Shape::Rect {
+                    x: x0,
+                    y: top + bar_h * s as f64 / STEPS as f64,
+                    w: bar_w,
+                    h: bar_h / STEPS as f64 + 0.5,
+                    fill: turbo(1.0 - s as f64 / (STEPS - 1) as f64),
+                });
+            }
+            let (max, min) = (format_number(*max), format_number(*min));
+            let lx = x0 + bar_w + FONT_SIZE / 2.0;
+            shapes.push(text(lx, top + FONT_SIZE * 0.7, &max));
+            shapes.push(text(lx, top + bar_h, &min));
+            let width = text_width(var, FONT_SIZE).max(
+                bar_w
+                    + FONT_SIZE / 2.0
+                    + text_width(&max, FONT_SIZE).max(text_width(&min, FONT_SIZE)),
+            );
+            (width, top + bar_h - y0)
+        }
+    }
+}
+
+fn honeycomb(
+    ctl: &EncloneControl,
+    refdata: &RefData,
+    exact_clonotypes: &[ExactClonotype],
+    exacts: &[Vec<usize>],
+    out_datas: &[Vec<HashMap<String, String>>],
+) -> Result<Canvas, String> {
+    let popt = &ctl.plot_opt;
+    let (cells, legend) = plot_cells(ctl, refdata, exact_clonotypes, exacts, out_datas);
+    let layout_in = match &popt.honey_in {
+        Some(path) => read_layout(path)?,
+        None => HashMap::new(),
+    };
+
+    // Gather the cells of each clonotype in each panel, and sort them by color.
+
+    let mut panels = Vec::<(String, Vec<(usize, Vec<&str>)>)>::new();
+    {
+        let mut names = cells.iter().map(|c| c.panel.clone()).collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        for name in names {
+            let mut clusters = HashMap::<usize, Vec<&str>>::new();
+            for c in cells.iter().filter(|c| c.panel == name) {
+                clusters.entry(c.clonotype).or_default().push(&c.color);
+            }
+            let mut clusters = clusters.into_iter().collect::<Vec<_>>();
+            for (_, colors) in &mut clusters {
+                colors.sort_unstable();
+            }
+            clusters.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then(a.0.cmp(&b.0)));
+            panels.push((name, clusters));
+        }
+    }
+
+    // Lay out the panels.
+
+    let max_cells = panels
+        .iter()
+        .flat_map(|p| p.1.iter().map(|c| c.1.len()))
+        .max()
+        .unwrap_or(0);
+    let hex = hex_positions(max_cells);
+    let mut shapes = Vec::<Shape>::new();
+    let mut layout_out = Vec::<String>::new();
+    let mut x0 = MARGIN;
+    let mut height = 0.0_f64;
+    let title_h = if panels.len() > 1 {
+        FONT_SIZE * 2.0
+    } else {
+        0.0
+    };
+    for (name, clusters) in &panels {
+        let mut packer = Packer::default();
+        let mut placed = Vec::<(f64, f64, f64)>::new();
+        for (i, colors) in clusters {
+            let r = hex[colors.len() - 1].0.hypot(hex[colors.len() - 1].1) + 1.0;
+            let disk = r + CLUSTER_GAP / 2.0;
+            let (x, y) = match layout_in.get(&(name.clone(), i + 1)) {
+                Some(&(x, y)) => {
+                    packer.insert(x, y, disk);
+                    (x, y)
+                }
+                None => packer.place(disk),
+            };
+            placed.push((x, y, r));
+            layout_out.push(format!(
+                "{name}\tclonotype{}\t{x:.3}\t{y:.3}\t{r:.3}",
+                i + 1
+            ));
+        }
+        let min_x = placed.iter().map(|p| p.0 - p.2).fold(f64::MAX, f64::min);
+        let max_x = placed.iter().map(|p| p.0 + p.2).fold(f64::MIN, f64::max);
+        let min_y = placed.iter().map(|p| p.1 - p.2).fold(f64::MAX, f64::min);
+        let max_y = placed.iter().map(|p| p.1 + p.2).fold(f64::MIN, f64::max);
+        let w = ((max_x - min_x) * CELL_RADIUS).max(text_width(name, FONT_SIZE));
+        let h = (max_y - min_y) * CELL_RADIUS;
+        if !name.is_empty() {
+            shapes.push(Shape::Text {
+                x: x0 + w / 2.0,
+                y: MARGIN + FONT_SIZE,
+                size: FONT_SIZE,
+                anchor: Anchor::Middle,
+                fill: "black".to_string(),
+                text: name.clone(),
+            });
+        }
+        for ((_, colors), &(cx, cy, _)) in clusters.iter().zip(placed.iter()) {
+            for (color, &(hx, hy)) in colors.iter().zip(hex.iter()) {
+                shapes.push(Shape::Circle {
+                    x: x0 + (cx + hx - min_x) * CELL_RADIUS,
+                    y: MARGIN + title_h + (cy + hy - min_y) * CELL_RADIUS,
+                    r: CELL_RADIUS,
+                    fill: color.to_string(),
+                });
+            }
+        }
+        x0 += w + MARGIN;
+        height = height.max(title_h + h);
+    }
+    if let Some(legend) = &legend {
+        let (w, h) = draw_legend(legend, x0, MARGIN, &mut shapes);
+        x0 += w + MARGIN;
+        height = height.max(h);
+    }
+    if !popt.honey_out.is_empty() {
+        write_tsv(&popt.honey_out, "HONEY_OUT", &HONEY_FIELDS, &layout_out)?;
+    }
+    Ok(Canvas {
+        width: x0.max(2.0 * MARGIN),
+        height: height + 2.0 * MARGIN,
+        shapes,
+    })
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+// Choose about five round tick values in the range [lo, hi].
+
+fn ticks(lo: f64, hi: f64) -> Vec<f64> {
+    let raw = (hi - lo) / 5.0;
+    let mag = 10f64.powf(raw.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .iter()
+        .map(|m| m * mag)
+        .find(|&s| s >= raw)
+        .unwrap();
+    let first = (lo / step).ceil() as i64;
+    let last = (hi / step).floor() as i64;
+    (first..=last).map(|t| t as f64 * step).collect()
+}
+
+fn scatter(ctl: &EncloneControl, out_datas: &[Vec<HashMap<String, String>>]) -> Canvas {
+    const SIZE: f64 = 500.0;
+    const LEFT: f64 = 80.0;
+    const TOP: f64 = 40.0;
+    const BOTTOM: f64 = 50.0;
+    let popt = &ctl.plot_opt;
+    let coord = |data: &HashMap<String, String>, var: &str, log10: bool| {
+        let v = data.get(var)?.parse::<f64>().ok()?;
+        if !log10 {
+            Some(v)
+        } else if v > 0.0 {
+            Some(v.log10())
+        } else {
+            None
+        }
+    };
+    let points = out_datas
+        .iter()
+        .flatten()
+        .filter_map(|data| {
+            Some((
+                coord(data, &popt.plot_xy_xvar, popt.plot_xy_x_log10)?,
+                coord(data, &popt.plot_xy_yvar, popt.plot_xy_y_log10)?,
+            ))
+        })
+        .collect::<Vec<_>>();
+
+    // Define the ranges, padded by five percent.
+
+    let range = |vals: &mut dyn Iterator<Item = f64>| {
+        let (lo, hi) = vals.fold((f64::MAX, f64::MIN), |(lo, hi), v| (lo.min(v), hi.max(v)));
+        if lo > hi {
+            (0.0, 1.0)
+        } else if lo == hi {
+            (lo - 0.5, hi + 0.5)
+        } else {
+            (lo - (hi - lo) / 20.0, hi + (hi - lo) / 20.0)
+        }
+    };
+    let mut xr = range(&mut points.iter().map(|p| p.0));
+    let mut yr = range(&mut points.iter().map(|p| p.1));
+    if popt.plot_xy_sym {
+        let r = (xr.0.min(yr.0), xr.1.max(yr.1));
+        (xr, yr) = (r, r);
+    }
+    let px = |x: f64| LEFT + (x - xr.0) / (xr.1 - xr.0) * SIZE;
+    let py = |y: f64| TOP + SIZE - (y - yr.0) / (yr.1 - yr.0) * SIZE;
+
+    // Draw the axes, ticks and labels.
+
+    let mut shapes = Vec::<Shape>::new();
+    let line = |x1, y1, x2, y2| Shape::Line {
+        x1,
+        y1,
+        x2,
+        y2,
+        stroke: "black".to_string(),
+        width: 1.0,
+    };
+    let text = |x, y, anchor, s: String| Shape::Text {
+        x,
+        y,
+        size: FONT_SIZE,
+        anchor,
+        fill: "black".to_string(),
+        text: s,
+    };
+    shapes.push(line(LEFT, TOP + SIZE, LEFT + SIZE, TOP + SIZE));
+    shapes.push(line(LEFT, TOP, LEFT, TOP + SIZE));
+    for t in ticks(xr.0, xr.1) {
+        shapes.push(line(px(t), TOP + SIZE, px(t), TOP + SIZE + 5.0));
+        shapes.push(text(
+            px(t),
+            TOP + SIZE + 20.0,
+            Anchor::Middle,
+            format_number(t),
+        ));
+    }
+    for t in ticks(yr.0, yr.1) {
+        shapes.push(line(LEFT - 5.0, py(t), LEFT, py(t)));
+        shapes.push(text(LEFT - 8.0, py(t) + 5.0, Anchor::End, format_number(t)));
+    }
+    let label = |var: &str, log10: bool| {
+        if log10 {
+            format!("log10({var})")
+        } else {
+            var.to_string()
+        }
+    };
+    shapes.push(text(
+        LEFT + SIZE / 2.0,
+        TOP + SIZE + 42.0,
+        Anchor::Middle,
+        label(&popt.plot_xy_xvar, popt.plot_xy_x_log10),
+    ));
+    shapes.push(text(
+        LEFT,
+        TOP - 15.0,
+        Anchor::Middle,
+        label(&popt.plot_xy_yvar, popt.plot_xy_y_log10),
+    ));
+    for &(x, y) in &points {
+        shapes.push(Shape::Circle {
+            x: px(x),
+            y: py(y),
+            r: 3.0,
+            fill: PALETTE[0].to_string(),
+        });
+    }
+    Canvas {
+        width: LEFT + SIZE + MARGIN,
+        height: TOP + SIZE + BOTTOM,
+        shapes,
+    }
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+// Write a plot as PNG if the file name ends in .png, and otherwise as SVG.  The names stdout
+// and stdout.png denote standard output.
+
+fn write_plot(
+    canvas: &Canvas,
+    path: &str,
+    png_width: Option<usize>,
+    arg: &str,
+) -> Result<(), String> {
+    if path == "gui" || path == "gui_stdout" {
+        return Err(format!(
+            "\nThe {arg} plot cannot be sent to the GUI from here.\n"
+        ));
+    }
+    let data = if path.ends_with(".png") {
+        canvas.png(png_width)
+    } else {
+        canvas.svg().into_bytes()
+    };
+    let err = |e: std::io::Error| format!("\nUnable to write {arg} file {path}: {e}.\n");
+    if path == "stdout" || path == "stdouth" || path == "stdout.png" {
+        std::io::stdout().write_all(&data).map_err(err)
+    } else {
+        std::fs::write(path, data).map_err(err)
+    }
+}
+
+pub fn make_plots(
+    ctl: &EncloneControl,
+    refdata: &RefData,
+    exact_clonotypes: &[ExactClonotype],
+    exacts: &[Vec<usize>],
+    out_datas: &[Vec<HashMap<String, String>>],
+) -> Result<(), String> {
+    let popt = &ctl.plot_opt;
+    if !popt.plot_file.is_empty() {
+        let canvas = honeycomb(ctl, refdata, exact_clonotypes, exacts, out_datas)?;
+        write_plot(&canvas, &popt.plot_file, popt.png_width, "PLOT")?;
+    }
+    if !popt.plot_xy_filename.is_empty() {
+        let canvas = scatter(ctl, out_datas);
+        write_plot(
+            &canvas,
+            &popt.plot_xy_filename,
+            popt.png_width,
+            "PLOTXY_EXACT",
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(s: &[&str]) -> Vec<String> {
+        s.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_color_categories_by_frequency() {
+        // b is the most frequent, and a and c are tied, so are in sorted order.
+
+        let k = keys(&["c", "b", "a", "b", "c", "b", "a"]);
+        let (colors, legend) = color_categories(&k, 10, false, &|_| None);
+        assert_eq!(
+            legend,
+            [
+                (PALETTE[0].to_string(), "b".to_string()),
+                (PALETTE[1].to_string(), "a".to_string()),
+                (PALETTE[2].to_string(), "c".to_string()),
+            ]
+        );
+        let expected = [2, 0, 1, 0, 2, 0, 1].map(|j| PALETTE[j]);
+        assert_eq!(colors, expected);
+    }
+
+    #[test]
+    fn test_color_categories_sorted_and_fixed() {
+        let k = keys(&["z", "y", "y", "x"]);
+        let fixed = |cat: &str| (cat == "y").then(|| "red".to_string());
+        let (colors, legend) = color_categories(&k, 10, true, &fixed);
+        assert_eq!(
+            legend,
+            [
+                (PALETTE[0].to_string(), "x".to_string()),
+                ("red".to_string(), "y".to_string()),
+                (PALETTE[2].to_string(), "z".to_string()),
+            ]
+        );
+        assert_eq!(colors, [PALETTE[2], "red", "red", PALETTE[0]]);
+    }
+
+    #[test]
+    fn test_color_categories_other() {
+        let k = keys(&["a", "a", "a", "b", "b", "c", "d"]);
+        let (colors, legend) = color_categories(&k, 2, false, &|_| None);
+        assert_eq!(legend.len(), 3);
+        assert_eq!(legend[2], (OTHER_COLOR.to_string(), "other".to_string()));
+        assert_eq!(
+            colors,
+            [
+                PALETTE[0],
+                PALETTE[0],
+                PALETTE[0],
+                PALETTE[1],
+                PALETTE[1],
+                OTHER_COLOR,
+                OTHER_COLOR
+            ]
+        );
+
+        // The palette is reused after it runs out.
+
+        let k = (0..25).map(|i| format!("{i:02}")).collect::<Vec<_>>();
+        let (colors, legend) = color_categories(&k, LEGEND_MAX, true, &|_| None);
+        assert_eq!(legend.len(), 25);
+        assert_eq!(colors[20], PALETTE[0]);
+    }
+
+    #[test]
+    fn test_hex_positions() {
+        let hex = hex_positions(100);
+        assert_eq!(hex.len(), 100);
+        assert_eq!(hex[0], (0.0, 0.0));
+        for (i, a) in hex.iter().enumerate() {
+            for b in &hex[..i] {
+                assert!((a.0 - b.0).hypot(a.1 - b.1) >= 2.0 - 1e-9);
+            }
+        }
+        assert!(hex
+            .windows(2)
+            .all(|w| w[0].0.hypot(w[0].1) <= w[1].0.hypot(w[1].1) + 1e-9));
+        assert_eq!(hex_positions(10), hex[..10]);
+    }
+
+    // Lay out clonotypes as honeycomb does, and check that no two cells overlap.
+
+    #[test]
+    fn test_honeycomb_no_overlap() {
+        let sizes = [60, 25, 12, 12, 7, 4, 3, 3, 2, 1, 1, 1, 1, 1];
+        let hex = hex_positions(sizes[0]);
+        let mut packer = Packer::default();
+
+        // The largest clonotype is placed by HONEY_IN, off the origin, and the others must avoid
+        // it.
+
+        let mut cells = Vec::<(f64, f64)>::new();
+        for (i, &n) in sizes.iter().enumerate() {
+            let r = hex[n - 1].0.hypot(hex[n - 1].1) + 1.0;
+            let disk = r + CLUSTER_GAP / 2.0;
+            let (x, y) = if i == 0 {
+                packer.insert(3.0, 4.0, disk);
+                (3.0, 4.0)
+            } else {
+                packer.place(disk)
+            };
+            cells.extend(hex[..n].iter().map(|&(hx, hy)| (x + hx, y + hy)));
+        }
+        assert_eq!(cells.len(), sizes.iter().sum::<usize>());
+        for (i, a) in cells.iter().enumerate() {
+            for b in &cells[..i] {
+                let d = (a.0 - b.0).hypot(a.1 - b.1);
+                assert!(d >= 2.0 - 1e-9, "cells at {a:?} and {b:?} overlap");
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_layout() {
+        let path = std::env::temp_dir().join(format!("honey_{}.tsv", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(
+            path,
+            "panel\tclonotype_id\tx\ty\tradius\n\tclonotype3\t1.5\t-2.000\t1.000\n",
+        )
+        .unwrap();
+        let layout = read_layout(path).unwrap();
+        assert_eq!(layout[&(String::new(), 3)], (1.5, -2.0));
+        std::fs::write(path, "panel\tclonotype_id\tx\ty\tradius\n\t3\t1.5\t-2\t1\n").unwrap();
+        assert!(read_layout(path).unwrap_err().contains("malformed"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -5,11 +5,16 @@
 //
 // Problem: stack traces from this file consistently do not go back to the main program.
 
+use crate::airr::write_airr;
 use crate::define_mat::define_mat;
 use crate::filter::survives_filter;
 use crate::finish_table::finish_table;
 use crate::gene_scan::gene_scan_test;
-use crate::loupe::{loupe_out, make_loupe_clonotype};
+use crate::group::{group_clonotypes, write_group_table};
+use crate::loupe::{loupe_out, make_loupe_clonotype, LoupeStream};
+use crate::msa::write_msas;
+use crate::parseable::write_parseable;
+use crate::plot::make_plots;
 use crate::print_utils1::{compute_field_types, extra_args, start_gen};
 use crate::print_utils2::row_fill;
 use crate::print_utils3::{
@@ -17,13 +22,14 @@ use crate::print_utils3::{
 };
 use crate::print_utils4::{build_show_aa, compute_bu, compute_some_stats};
 use crate::print_utils5::{delete_weaks, vars_and_shares};
+use crate::tree::write_newick;
 use enclone_args::proc_args_check::involves_gex_fb;
 use enclone_core::allowed_vars::{CVARS_ALLOWED, CVARS_ALLOWED_PCELL, LVARS_ALLOWED};
-use enclone_core::barcode_fate::BarcodeFate;
+use enclone_core::barcode_fate::{fate_summary, filter_impact, BarcodeFate, FateLedger};
 use enclone_core::defs::{AlleleData, CloneInfo, ColInfo, EncloneControl, ExactClonotype, GexInfo};
 use enclone_core::mammalian_fixed_len::mammalian_fixed_len_peer_groups;
 use enclone_core::set_speakers::set_speakers;
-use enclone_proto::types::{Clonotype, DonorReferenceItem};
+use enclone_proto::types::{Clonotype, DonorReferenceItem, EncloneOutputs};
 use equiv::EquivRel;
 use hdf5::Reader;
 use itertools::izip;
@@ -33,6 +39,7 @@ use std::cmp::max;
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::BufWriter;
+use std::sync::Mutex;
 use string_utils::TextUtils;
 use vdj_ann::refx::RefData;
 use vector_utils::{bin_member, bin_position, erase_if, next_diff12_3, unique_sort};
@@ -73,8 +80,9 @@ pub fn print_clonotypes(
     out_datas: &mut Vec<Vec<HashMap<String, String>>>,
     tests: &mut Vec<usize>,
     controls: &mut Vec<usize>,
-    fate: &mut [HashMap<String, BarcodeFate>],
+    fate: &mut [FateLedger],
     allele_data: &AlleleData,
+    enclone_outputs: &mut Option<EncloneOutputs>,
 ) -> Result<(), String> {
     let lvars = &ctl.clono_print_opt.lvars;
 
@@ -188,6 +196,7 @@ pub fn print_clonotypes(
     //    [parallel to 1]
     // next to last three entries = whitelist contam, denominator for that, low gex count
     // added out_datas (used to be next to last three, now one more)
+    // last: first exact subclonotype of each Loupe clonotype [parallel to 6]
     let mut results = Vec::<(
         usize,
         Vec<String>,
@@ -203,6 +212,7 @@ pub fn print_clonotypes(
         Vec<(usize, String, BarcodeFate)>,
         Vec<bool>,
         String,
+        Vec<usize>,
     )>::new();
     for i in 0..orbits.len() {
         results.push((
@@ -220,8 +230,10 @@ pub fn print_clonotypes(
             Vec::new(),
             Vec::new(),
             String::new(),
+            Vec::new(),
         ));
     }
+    let stream = LoupeStream::start(ctl, refdata, dref)?.map(Mutex::new);
     results.par_iter_mut().for_each(|res| {
         let i = res.0;
         let o = &orbits[i];
@@ -358,7 +370,11 @@ pub fn print_clonotypes(
 
             // Generate Loupe data.
 
-            if (!ctl.gen_opt.binary.is_empty() || !ctl.gen_opt.proto.is_empty()) && pass == 2 {
+            if (!ctl.gen_opt.binary.is_empty()
+                || !ctl.gen_opt.proto.is_empty()
+                || ctl.gen_opt.proto_in_memory)
+                && pass == 2
+            {
                 loupe_clonotypes.push(make_loupe_clonotype(
                     exact_clonotypes,
                     &exacts,
@@ -367,6 +383,7 @@ pub fn print_clonotypes(
                     dref,
                     ctl,
                 ));
+                res.14.push(exacts[0]);
             }
 
             // Set up for parseable output.
@@ -420,7 +437,15 @@ pub fn print_clonotypes(
                 // Mark some weak exact subclonotypes for deletion.
 
                 if pass == 1 {
-                    delete_weaks(ctl, &exacts, exact_clonotypes, mat, refdata, &mut bads);
+                    delete_weaks(
+                        ctl,
+                        &exacts,
+                        exact_clonotypes,
+                        mat,
+                        refdata,
+                        &mut bads,
+                        &mut res.11,
+                    );
                 }
 
                 // Done unless on second pass.  Unless there are bounds or COMPLETE specified
@@ -896,14 +921,23 @@ pub fn print_clonotypes(
 
                 res.1.push(logz);
                 res.2.push((exacts.clone(), rsi.clone()));
+                res.7.push(out_data);
                 res.12.push(in_center);
                 for u in 0..exacts.len() {
                     res.8 += exact_clonotypes[exacts[u]].ncells() as isize;
                 }
             }
-            if pass == 2 {
-                res.7.push(out_data);
-            }
+        }
+
+        // Stream Loupe data.
+
+        if let Some(stream) = &stream {
+            let clonotypes = if !ctl.gen_opt.binary.is_empty() || ctl.gen_opt.proto_in_memory {
+                res.6.clone()
+            } else {
+                std::mem::take(&mut res.6)
+            };
+            stream.lock().unwrap().push(i, clonotypes);
         }
     });
     let exacts = exacts;
@@ -912,10 +946,10 @@ pub fn print_clonotypes(
             return Err(r.13.clone());
         }
     }
-
+    let removed = ctl.clono_filt_opt_def.delete(true);
     for ri in &results {
         for vj in &ri.11 {
-            fate[vj.0].insert(vj.1.clone(), vj.2.clone());
+            fate[vj.0].insert(vj.1.clone(), vj.2, removed);
         }
     }
 
@@ -923,21 +957,56 @@ pub fn print_clonotypes(
 
     results.sort_by_key(|x| -x.8);
 
-    // Write loupe output.
+    // Finish streaming Loupe data, in the same order.
 
-    let mut all_loupe_clonotypes = Vec::<Clonotype>::new();
-    for r in results.iter_mut() {
-        all_loupe_clonotypes.append(&mut r.6);
+    if let Some(stream) = stream {
+        let order = results.iter().map(|r| r.0).collect::<Vec<_>>();
+        stream.into_inner().unwrap().finish(&order)?;
     }
-    loupe_out(ctl, all_loupe_clonotypes, refdata, dref);
 
     // Write out the fate of each filtered barcode.
     if !ctl.gen_opt.fate_file.is_empty() {
         let mut wtr = BufWriter::new(
             File::create(&ctl.gen_opt.fate_file).expect("Unable to open FATE_FILE for writing"),
         );
+        let removed_by = fate.iter().map(FateLedger::removed_by).collect::<Vec<_>>();
+        serde_json::to_writer_pretty(&mut wtr, &removed_by).map_err(|e| e.to_string())?;
+    }
+    if !ctl.gen_opt.fate_ledger.is_empty() {
+        let mut wtr = BufWriter::new(
+            File::create(&ctl.gen_opt.fate_ledger).expect("Unable to open FATE_LEDGER for writing"),
+        );
         serde_json::to_writer_pretty(&mut wtr, fate).map_err(|e| e.to_string())?;
     }
+    if !ctl.gen_opt.fate_summary.is_empty() {
+        std::fs::write(
+            &ctl.gen_opt.fate_summary,
+            fate_summary(fate, &ctl.origin_info.dataset_id),
+        )
+        .map_err(|e| format!("\nUnable to write FATE_SUMMARY: {e}\n"))?;
+    }
+    if !ctl.gen_opt.filter_impact.is_empty() {
+        let mut clonotypes = Vec::<Vec<(usize, &str)>>::new();
+        for o in orbits {
+            let mut ids = o
+                .iter()
+                .map(|&id| info[id as usize].clonotype_index)
+                .collect::<Vec<_>>();
+            unique_sort(&mut ids);
+            let mut cells = Vec::new();
+            for id in ids {
+                for clone in &exact_clonotypes[id].clones {
+                    cells.push((clone[0].dataset_index, clone[0].barcode.as_str()));
+                }
+            }
+            clonotypes.push(cells);
+        }
+        std::fs::write(
+            &ctl.gen_opt.filter_impact,
+            filter_impact(fate, &ctl.origin_info.dataset_id, &clonotypes),
+        )
+        .map_err(|e| format!("\nUnable to write FILTER_IMPACT: {e}\n"))?;
+    }
 
     // Set up to group and print clonotypes.
 
@@ -951,6 +1020,61 @@ pub fn print_clonotypes(
         out_datas.append(&mut ri.7);
     }
 
+    // Group clonotypes.  This is done only if a grouping option or GROUP_TABLE was specified,
+    // and otherwise each clonotype is a group by itself.
+
+    let groups = if !ctl.clono_group_opt.style.is_empty() || !ctl.gen_opt.group_table.is_empty() {
+        group_clonotypes(ctl, refdata, dref, exact_clonotypes, exacts, rsi, in_center)
+    } else {
+        (0..exacts.len()).map(|i| vec![i]).collect()
+    };
+    let mut group_id = HashMap::<usize, u32>::new();
+    for (g, group) in groups.iter().enumerate() {
+        for &i in group {
+            group_id.insert(exacts[i][0], g as u32 + 1);
+        }
+    }
+    if !ctl.gen_opt.group_table.is_empty() {
+        write_group_table(ctl, exact_clonotypes, exacts, &groups)?;
+    }
+
+    // Write parseable output.
+
+    if !ctl.parseable_opt.pout.is_empty() {
+        write_parseable(ctl, exact_clonotypes, exacts, rsi, out_datas, &groups)?;
+    }
+
+    // Write loupe output.
+
+    let mut all_loupe_clonotypes = Vec::<Clonotype>::new();
+    for r in results.iter_mut() {
+        if !ctl.clono_group_opt.style.is_empty() {
+            for (x, e) in r.6.iter_mut().zip(r.14.iter()) {
+                x.group_id = group_id.get(e).copied();
+            }
+        }
+        all_loupe_clonotypes.append(&mut r.6);
+    }
+    *enclone_outputs = loupe_out(ctl, all_loupe_clonotypes, refdata, dref);
+
+    // Write AIRR output.
+
+    if !ctl.gen_opt.airr_rearrangement.is_empty() || !ctl.gen_opt.airr_cell.is_empty() {
+        write_airr(ctl, refdata, dref, exact_clonotypes, exacts, rsi)?;
+    }
+
+    // Write multiple sequence alignments.
+
+    write_msas(ctl, refdata, dref, exact_clonotypes, exacts, rsi)?;
+
+    // Write lineage trees.
+
+    write_newick(ctl, refdata, dref, exact_clonotypes, exacts, rsi)?;
+
+    // Make plots.
+
+    make_plots(ctl, refdata, exact_clonotypes, exacts, out_datas)?;
+
     // Gather some data for gene scan.
 
     if ctl.gen_opt.gene_scan_test.is_some() && !ctl.gen_opt.gene_scan_exact {
@@ -327,30 +327,35 @@ pub fn define_column_info(
     let mut vpids_a = vec![None; cols];
     let mut dids = vec![None; cols];
     let mut jids = vec![0; cols];
+    let mut jps = vec![(None, None, None); cols];
     let mut cids = vec![None; cols];
     let mut left = vec![false; cols];
-    for (m, (left, ((uids, (vids, (vpids, (vpids_d, vpids_a)))), (dids, (jids, cids))))) in mat
-        .iter()
-        .zip(
-            left.iter_mut().zip(
-                uids.iter_mut()
-                    .zip(
-                        vids.iter_mut().zip(
-                            vpids
-                                .iter_mut()
-                                .zip(vpids_d.iter_mut().zip(vpids_a.iter_mut())),
+    for (m, (left, ((uids, (vids, (vpids, (vpids_d, vpids_a)))), (dids, ((jids, jps), cids))))) in
+        mat.iter()
+            .zip(
+                left.iter_mut().zip(
+                    uids.iter_mut()
+                        .zip(
+                            vids.iter_mut().zip(
+                                vpids
+                                    .iter_mut()
+                                    .zip(vpids_d.iter_mut().zip(vpids_a.iter_mut())),
+                            ),
+                        )
+                        .zip(
+                            dids.iter_mut()
+                                .zip(jids.iter_mut().zip(jps.iter_mut()).zip(cids.iter_mut())),
                         ),
-                    )
-                    .zip(dids.iter_mut().zip(jids.iter_mut().zip(cids.iter_mut()))),
-            ),
-        )
-        .take(cols)
+                ),
+            )
+            .take(cols)
     {
         let mut u = Vec::<usize>::new();
         let mut v = Vec::<usize>::new();
         let mut vp = Vec::<(usize, Option<usize>, Option<usize>, Option<usize>)>::new();
         let mut d = Vec::<usize>::new();
         let mut j = Vec::<usize>::new();
+        let mut jp = Vec::<(usize, Option<usize>, Option<usize>, Option<usize>)>::new();
         let mut c = Vec::<usize>::new();
         for (&clonotype_id, &m) in exacts.iter().zip(m.iter()) {
             let ex = &exact_clonotypes[clonotype_id];
@@ -380,6 +385,15 @@ pub fn define_column_info(
                     d.resize(d.len() + ncells, d_ref_id);
                 }
                 j.resize(j.len() + ncells, x.j_ref_id);
+                jp.resize(
+                    jp.len() + ncells,
+                    (
+                        x.j_ref_id,
+                        x.j_ref_id_donor,
+                        x.j_ref_id_donor_donor,
+                        x.j_ref_id_donor_alt_id,
+                    ),
+                );
                 if let Some(c_ref_id) = x.c_ref_id {
                     c.resize(c.len() + ncells, c_ref_id);
                 }
@@ -390,6 +404,7 @@ pub fn define_column_info(
         vp.sort();
         d.sort_unstable();
         j.sort_unstable();
+        jp.sort();
         c.sort_unstable();
         let mut uf = Vec::<(u32, usize)>::new();
         make_freq(&u, &mut uf);
@@ -419,6 +434,10 @@ pub fn define_column_info(
         let mut jf = Vec::<(u32, usize)>::new();
         make_freq(&j, &mut jf);
         *jids = jf[0].1;
+        jp.retain(|x| x.0 == *jids);
+        let mut jpf = Vec::<(u32, (usize, Option<usize>, Option<usize>, Option<usize>))>::new();
+        make_freq(&jp, &mut jpf);
+        *jps = ((jpf[0].1).1, (jpf[0].1).2, (jpf[0].1).3);
         let mut cf = Vec::<(u32, usize)>::new();
         make_freq(&c, &mut cf);
         if !cf.is_empty() {
@@ -426,6 +445,8 @@ pub fn define_column_info(
         }
     }
 
+    let jpids = jps.iter().map(|x| x.0).collect::<Vec<_>>();
+
     // Compute seqss and seqss_amino.
 
     let mut seqss = Vec::<Vec<Vec<u8>>>::new();
@@ -472,12 +493,11 @@ pub fn define_column_info(
                 .unwrap();
             }
             let jid = jids[cx];
-            write!(
-                chain_descrip,
-                " ◆ {}|{}",
-                refdata.id[jid], refdata.name[jid]
-            )
-            .unwrap();
+            let mut jdescrip = format!("{}", refdata.id[jid]);
+            if let (Some(d), Some(a)) = (jps[cx].1, jps[cx].2) {
+                jdescrip = format!("{jdescrip}.{}.{}", d + 1, a + 1);
+            }
+            write!(chain_descrip, " ◆ {jdescrip}|{}", refdata.name[jid]).unwrap();
             chain_descrip
         })
         .collect();
@@ -491,6 +511,7 @@ pub fn define_column_info(
         vpids,
         dids,
         jids,
+        jpids,
         cids,
         fr1_starts,
         fr2_starts,
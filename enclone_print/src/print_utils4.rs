@@ -2,13 +2,12 @@
 
 use amino::codon_to_aa;
 use enclone_core::{
-    barcode_fate::BarcodeFate,
+    barcode_fate::FateLedger,
     defs::{ColInfo, EncloneControl, ExactClonotype, GexInfo},
 };
 use enclone_proto::types::DonorReferenceItem;
 use equiv::EquivRel;
 use itertools::Itertools;
-use std::collections::HashMap;
 use string_utils::TextUtils;
 use vdj_ann::refx::RefData;
 use vector_utils::{bin_member, bin_position, bin_position1_2, unique_sort};
@@ -503,7 +502,7 @@ pub fn compute_bu(
     gex_info: &GexInfo,
     rsi: &ColInfo,
     sr: &mut Vec<(Vec<String>, Vec<Vec<String>>, Vec<Vec<u8>>, usize)>,
-    fate: &[HashMap<String, BarcodeFate>],
+    fate: &[FateLedger],
     nd_fields: &[String],
     alt_bcs: &[String],
     cred: &[Vec<String>],
@@ -584,8 +583,8 @@ pub fn compute_bu(
                     row.push(format!("{n}"));
                 } else if var == "filter" {
                     let mut f = String::new();
-                    if fate[li].contains_key(bc) {
-                        f = fate[li][bc].label().to_string();
+                    if let Some(x) = fate[li].fates(bc).first() {
+                        f = x.label().to_string();
                     }
                     row.push(f);
                 } else if var == "n_other" {
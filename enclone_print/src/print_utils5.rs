@@ -3,6 +3,7 @@
 use crate::print_utils1::aa_classes;
 use amino::codon_to_aa;
 use ansi_escape::emit_end_escape;
+use enclone_core::barcode_fate::BarcodeFate;
 use enclone_core::defs::{ColInfo, EncloneControl, ExactClonotype};
 use enclone_core::print_tools::emit_codon_color_escape;
 use enclone_proto::types::DonorReferenceItem;
@@ -186,6 +187,7 @@ pub fn delete_weaks(
     mat: &[Vec<Option<usize>>],
     refdata: &RefData,
     bads: &mut [bool],
+    fate: &mut Vec<(usize, String, BarcodeFate)>,
 ) {
     // Mark for deletion exact subclonotypes that fail the MIN_CELLS_EXACT or MIN_CHAINS_EXACT
     // or CHAINS_EXACT tests.
@@ -275,7 +277,16 @@ pub fn delete_weaks(
                     }
                 }
                 if !perf {
-                    bads[u1] = true;
+                    if ctl.clono_filt_opt_def.delete(true) {
+                        bads[u1] = true;
+                    }
+                    for clone in &ex1.clones {
+                        fate.push((
+                            clone[0].dataset_index,
+                            clone[0].barcode.clone(),
+                            BarcodeFate::UnmatchedOnesie,
+                        ));
+                    }
                 }
             }
         }
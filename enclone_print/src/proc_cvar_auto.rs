@@ -63,7 +63,10 @@ pub fn proc_cvar_auto(
         if xm.v_ref_id_donor_alt_id.is_some() {
             vref = dref[xm.v_ref_id_donor.unwrap()].nt_sequence.clone();
         }
-        let jref = refdata.refs[xm.j_ref_id].to_ascii_vec();
+        let mut jref = refdata.refs[xm.j_ref_id].to_ascii_vec();
+        if xm.j_ref_id_donor_alt_id.is_some() {
+            jref = dref[xm.j_ref_id_donor.unwrap()].nt_sequence.clone();
+        }
         let z = 3 * aa_seq.len() + 1;
         for p in 0..aa_seq.len() {
             if aa_seq[p] == b'-' {
@@ -794,7 +797,10 @@ pub fn proc_cvar_auto(
         if xm.v_ref_id_donor_alt_id.is_some() {
             vref = dref[xm.v_ref_id_donor.unwrap()].nt_sequence.clone();
         }
-        let jref = refdata.refs[xm.j_ref_id].to_ascii_vec();
+        let mut jref = refdata.refs[xm.j_ref_id].to_ascii_vec();
+        if xm.j_ref_id_donor_alt_id.is_some() {
+            jref = dref[xm.j_ref_id_donor.unwrap()].nt_sequence.clone();
+        }
         let z = seq.len();
         for p in 0..z {
             let b = seq[p];
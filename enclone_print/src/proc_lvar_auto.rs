@@ -2,7 +2,7 @@
 // This file is auto-generated by the crate enclone_vars, please do not edit.
 
 use amino::{aa_seq, codon_to_aa};
-use enclone_core::barcode_fate::BarcodeFate;
+use enclone_core::barcode_fate::FateLedger;
 use enclone_core::defs::{ColInfo, EncloneControl, ExactClonotype, GexInfo, POUT_SEP};
 use enclone_core::median::{median_f64, rounded_median};
 use enclone_proto::types::DonorReferenceItem;
@@ -31,7 +31,7 @@ pub fn proc_lvar_auto(
     stats: &mut Vec<(String, Vec<String>)>,
     lvars: &[String],
     row: &mut Vec<String>,
-    fate: &[HashMap<String, BarcodeFate>],
+    fate: &[FateLedger],
     dref: &[DonorReferenceItem],
     varmat: &[Vec<Vec<u8>>],
     fp: &[Vec<usize>],
@@ -1110,8 +1110,8 @@ pub fn proc_lvar_auto(
             let mut f = "";
             let bc = &ex.clones[j][0].barcode;
             let li = ex.clones[j][0].dataset_index;
-            if fate[li].contains_key(&bc.clone()) {
-                f = fate[li][bc].label();
+            if let Some(x) = fate[li].fates(bc).first() {
+                f = x.label();
             }
             fates.push(f.to_string());
         }
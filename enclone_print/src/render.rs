@@ -0,0 +1,614 @@
+// Copyright (c) 2023 10X Genomics, Inc. All rights reserved.
+
+// Headless rendering of plots.  A plot is a canvas holding a list of shapes, in coordinates in
+// which the origin is at the top left.  It may be written as SVG, or as PNG, in which case the
+// shapes are rasterized here, with text drawn in a 5x7 bitmap font.  Text is always drawn in a
+// monospace font whose characters have width TEXT_WIDTH times the font size, so that its extent
+// is known when laying out a plot.
+//
+// Colors are given as in SVG, e.g. as a name like red, as #rgb or #rrggbb, or as rgb(r,g,b).
+// For PNG, only the common color names in COLOR_NAMES are recognized, and other colors are
+// drawn as black.
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::fmt::Write as _;
+use std::io::Write;
+
+pub const TEXT_WIDTH: f64 = 0.6;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    Start,
+    Middle,
+    End,
+}
+
+pub enum Shape {
+    Circle {
+        x: f64,
+        y: f64,
+        r: f64,
+        fill: String,
+    },
+    Rect {
+        x: f64,
+        y: f64,
+        w: f64,
+        h: f64,
+        fill: String,
+    },
+    Line {
+        x1: f64,
+        y1: f64,
+        x2: f64,
+        y2: f64,
+        stroke: String,
+        width: f64,
+    },
+    Text {
+        x: f64, // at the anchor
+        y: f64, // at the baseline
+        size: f64,
+        anchor: Anchor,
+        fill: String,
+        text: String,
+    },
+}
+
+pub struct Canvas {
+    pub width: f64,
+    pub height: f64,
+    pub shapes: Vec<Shape>,
+}
+
+pub fn text_width(text: &str, size: f64) -> f64 {
+    text.chars().count() as f64 * size * TEXT_WIDTH
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+impl Canvas {
+    pub fn svg(&self) -> String {
+        let mut s = String::new();
+        writeln!(
+            s,
+            "<svg version=\"1.1\" baseProfile=\"full\" width=\"{0:.1}\" height=\"{1:.1}\" \
+             viewBox=\"0 0 {0:.1} {1:.1}\" xmlns=\"http://www.w3.org/2000/svg\">",
+            self.width, self.height
+        )
+        .unwrap();
+        writeln!(s, "<rect width=\"100%\" height=\"100%\" fill=\"white\"/>").unwrap();
+        for shape in &self.shapes {
+            match shape {
+                Shape::Circle { x, y, r, fill } => writeln!(
+                    s,
+                    "<circle cx=\"{x:.2}\" cy=\"{y:.2}\" r=\"{r:.2}\" fill=\"{}\"/>",
+                    escape(fill)
+                ),
+                Shape::Rect { x, y, w, h, fill } => writeln!(
+                    s,
+                    "<rect x=\"{x:.2}\" y=\"{y:.2}\" width=\"{w:.2}\" height=\"{h:.2}\" \
+                     fill=\"{}\"/>",
+                    escape(fill)
+                ),
+                Shape::Line {
+                    x1,
+                    y1,
+                    x2,
+                    y2,
+                    stroke,
+                    width,
+                } => writeln!(
+                    s,
+                    "<line x1=\"{x1:.2}\" y1=\"{y1:.2}\" x2=\"{x2:.2}\" y2=\"{y2:.2}\" \
+                     stroke=\"{}\" stroke-width=\"{width:.2}\"/>",
+                    escape(stroke)
+                ),
+                Shape::Text {
+                    x,
+                    y,
+                    size,
+                    anchor,
+                    fill,
+                    text,
+                } => {
+                    let anchor = match anchor {
+                        Anchor::Start => "start",
+                        Anchor::Middle => "middle",
+                        Anchor::End => "end",
+                    };
+                    writeln!(
+                        s,
+                        "<text x=\"{x:.2}\" y=\"{y:.2}\" font-family=\"monospace\" \
+                         font-size=\"{size:.2}\" text-anchor=\"{anchor}\" fill=\"{}\">{}</text>",
+                        escape(fill),
+                        escape(text)
+                    )
+                }
+            }
+            .unwrap();
+        }
+        s.push_str("</svg>\n");
+        s
+    }
+
+    // Rasterize, with the given width in pixels, or by default, one pixel per unit.
+
+    pub fn png(&self, png_width: Option<usize>) -> Vec<u8> {
+        let scale = png_width.map_or(1.0, |w| w as f64 / self.width);
+        let mut image = Image {
+            width: ((self.width * scale).ceil() as usize).max(1),
+            height: ((self.height * scale).ceil() as usize).max(1),
+            pixels: Vec::new(),
+        };
+        image.pixels = vec![[255; 3]; image.width * image.height];
+        for shape in &self.shapes {
+            match shape {
+                Shape::Circle { x, y, r, fill } => {
+                    let c = parse_color(fill);
+                    image.fill(
+                        (x - r) * scale,
+                        (y - r) * scale,
+                        (x + r) * scale,
+                        (y + r) * scale,
+                        c,
+                        |px, py| r * scale - ((px - x * scale).hypot(py - y * scale)),
+                    );
+                }
+                Shape::Rect { x, y, w, h, fill } => {
+                    let c = parse_color(fill);
+                    let (x0, y0, x1, y1) = (x * scale, y * scale, (x + w) * scale, (y + h) * scale);
+                    image.fill(x0, y0, x1, y1, c, |px, py| {
+                        (px - x0).min(x1 - px).min(py - y0).min(y1 - py)
+                    });
+                }
+                Shape::Line {
+                    x1,
+                    y1,
+                    x2,
+                    y2,
+                    stroke,
+                    width,
+                } => {
+                    let c = parse_color(stroke);
+                    let (ax, ay, bx, by) = (x1 * scale, y1 * scale, x2 * scale, y2 * scale);
+                    let hw = (width * scale / 2.0).max(0.5);
+                    image.fill(
+                        ax.min(bx) - hw,
+                        ay.min(by) - hw,
+                        ax.max(bx) + hw,
+                        ay.max(by) + hw,
+                        c,
+                        |px, py| hw - segment_distance(px, py, ax, ay, bx, by),
+                    );
+                }
+                Shape::Text {
+                    x,
+                    y,
+                    size,
+                    anchor,
+                    fill,
+                    text,
+                } => {
+                    let c = parse_color(fill);
+                    let unit = size * scale * TEXT_WIDTH / 6.0;
+                    let w = text_width(text, *size) * scale;
+                    let mut left = match anchor {
+                        Anchor::Start => x * scale,
+                        Anchor::Middle => x * scale - w / 2.0,
+                        Anchor::End => x * scale - w,
+                    };
+                    let top = y * scale - 7.0 * unit;
+                    for ch in text.chars() {
+                        let glyph = glyph(ch);
+                        for (col, bits) in glyph.iter().enumerate() {
+                            for row in 0..7 {
+                                if (bits >> row) & 1 == 1 {
+                                    let (x0, y0) =
+                                        (left + col as f64 * unit, top + row as f64 * unit);
+                                    let (x1, y1) = (x0 + unit, y0 + unit);
+                                    image.fill(x0, y0, x1, y1, c, |px, py| {
+                                        (px - x0).min(x1 - px).min(py - y0).min(y1 - py)
+                                    });
+                                }
+                            }
+                        }
+                        left += 6.0 * unit;
+                    }
+                }
+            }
+        }
+        image.encode()
+    }
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<[u8; 3]>,
+}
+
+impl Image {
+    // Paint the pixels in a bounding box, where inside gives the signed distance of a pixel
+    // center from the boundary of the shape, positive inside.  Pixels within half a pixel of
+    // the boundary are blended, for antialiasing.
+
+    fn fill(
+        &mut self,
+        x0: f64,
+        y0: f64,
+        x1: f64,
+        y1: f64,
+        c: [u8; 3],
+        inside: impl Fn(f64, f64) -> f64,
+    ) {
+        let clip = |v: f64, n: usize| (v.max(0.0) as usize).min(n);
+        for py in clip(y0.floor(), self.height)..clip(y1.ceil(), self.height) {
+            for px in clip(x0.floor(), self.width)..clip(x1.ceil(), self.width) {
+                let alpha = (inside(px as f64 + 0.5, py as f64 + 0.5) + 0.5).clamp(0.0, 1.0);
+                if alpha > 0.0 {
+                    let p = &mut self.pixels[py * self.width + px];
+                    for (v, &cv) in p.iter_mut().zip(c.iter()) {
+                        *v = (*v as f64 * (1.0 - alpha) + cv as f64 * alpha).round() as u8;
+                    }
+                }
+            }
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut raw = Vec::<u8>::with_capacity((3 * self.width + 1) * self.height);
+        for row in self.pixels.chunks(self.width) {
+            raw.push(0); // filter type none
+            raw.extend(row.iter().flatten());
+        }
+        let mut z = ZlibEncoder::new(Vec::new(), Compression::default());
+        z.write_all(&raw).unwrap();
+        let idat = z.finish().unwrap();
+        let mut ihdr = Vec::<u8>::new();
+        ihdr.extend((self.width as u32).to_be_bytes());
+        ihdr.extend((self.height as u32).to_be_bytes());
+        ihdr.extend([8, 2, 0, 0, 0]); // 8 bit RGB, not interlaced
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        for (kind, data) in [(b"IHDR", ihdr), (b"IDAT", idat), (b"IEND", Vec::new())] {
+            png.extend((data.len() as u32).to_be_bytes());
+            let mut crc = crc32fast::Hasher::new();
+            crc.update(kind);
+            crc.update(&data);
+            png.extend(kind);
+            png.extend(data);
+            png.extend(crc.finalize().to_be_bytes());
+        }
+        png
+    }
+}
+
+fn segment_distance(px: f64, py: f64, ax: f64, ay: f64, bx: f64, by: f64) -> f64 {
+    let (dx, dy) = (bx - ax, by - ay);
+    let len2 = dx * dx + dy * dy;
+    let t = if len2 == 0.0 {
+        0.0
+    } else {
+        (((px - ax) * dx + (py - ay) * dy) / len2).clamp(0.0, 1.0)
+    };
+    (px - ax - t * dx).hypot(py - ay - t * dy)
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+const COLOR_NAMES: [(&str, [u8; 3]); 36] = [
+    ("aqua", [0, 255, 255]),
+    ("beige", [245, 245, 220]),
+    ("black", [0, 0, 0]),
+    ("blue", [0, 0, 255]),
+    ("brown", [165, 42, 42]),
+    ("coral", [255, 127, 80]),
+    ("cyan", [0, 255, 255]),
+    ("darkblue", [0, 0, 139]),
+    ("darkgreen", [0, 100, 0]),
+    ("darkred", [139, 0, 0]),
+    ("fuchsia", [255, 0, 255]),
+    ("gold", [255, 215, 0]),
+    ("gray", [128, 128, 128]),
+    ("green", [0, 128, 0]),
+    ("grey", [128, 128, 128]),
+    ("indigo", [75, 0, 130]),
+    ("lightblue", [173, 216, 230]),
+    ("lightgray", [211, 211, 211]),
+    ("lightgreen", [144, 238, 144]),
+    ("lime", [0, 255, 0]),
+    ("magenta", [255, 0, 255]),
+    ("maroon", [128, 0, 0]),
+    ("navy", [0, 0, 128]),
+    ("olive", [128, 128, 0]),
+    ("orange", [255, 165, 0]),
+    ("pink", [255, 192, 203]),
+    ("purple", [128, 0, 128]),
+    ("red", [255, 0, 0]),
+    ("salmon", [250, 128, 114]),
+    ("silver", [192, 192, 192]),
+    ("tan", [210, 180, 140]),
+    ("teal", [0, 128, 128]),
+    ("turquoise", [64, 224, 208]),
+    ("violet", [238, 130, 238]),
+    ("white", [255, 255, 255]),
+    ("yellow", [255, 255, 0]),
+];
+
+pub fn parse_color(c: &str) -> [u8; 3] {
+    let c = c.trim().to_ascii_lowercase();
+    if let Some(hex) = c.strip_prefix('#') {
+        let digits = hex
+            .chars()
+            .map(|d| d.to_digit(16).map(|d| d as u8))
+            .collect::<Option<Vec<u8>>>();
+        match digits.as_deref() {
+            Some(&[r, g, b]) => return [17 * r, 17 * g, 17 * b],
+            Some(&[r1, r2, g1, g2, b1, b2]) => {
+                return [16 * r1 + r2, 16 * g1 + g2, 16 * b1 + b2];
+            }
+            _ => {}
+        }
+    } else if let Some(rgb) = c.strip_prefix("rgb(").and_then(|x| x.strip_suffix(')')) {
+        let v = rgb
+            .split(',')
+            .map(|x| x.trim().parse::<u8>().ok())
+            .collect::<Option<Vec<u8>>>();
+        if let Some(&[r, g, b]) = v.as_deref() {
+            return [r, g, b];
+        }
+    } else if let Ok(i) = COLOR_NAMES.binary_search_by(|(name, _)| name.cmp(&c.as_str())) {
+        return COLOR_NAMES[i].1;
+    }
+    [0, 0, 0]
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+// A 5x7 font for the printable ASCII characters.  Each glyph is five columns, from left to
+// right, in each of which bit i is set if row i, counting from the top, is on.  Other
+// characters are drawn as ?.
+
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // space
+    [0x00, 0x00, 0x5f, 0x00, 0x00], // !
+    [0x00, 0x07, 0x00, 0x07, 0x00], // "
+    [0x14, 0x7f, 0x14, 0x7f, 0x14], // #
+    [0x24, 0x2a, 0x7f, 0x2a, 0x12], // $
+    [0x23, 0x13, 0x08, 0x64, 0x62], // %
+    [0x36, 0x49, 0x55, 0x22, 0x50], // &
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '
+    [0x00, 0x1c, 0x22, 0x41, 0x00], // (
+    [0x00, 0x41, 0x22, 0x1c, 0x00], // )
+    [0x08, 0x2a, 0x1c, 0x2a, 0x08], // *
+    [0x08, 0x08, 0x3e, 0x08, 0x08], // +
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ,
+    [0x08, 0x08, 0x08, 0x08, 0x08], // -
+    [0x00, 0x60, 0x60, 0x00, 0x00], // .
+    [0x20, 0x10, 0x08, 0x04, 0x02], // /
+    [0x3e, 0x51, 0x49, 0x45, 0x3e], // 0
+    [0x00, 0x42, 0x7f, 0x40, 0x00], // 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x45, 0x4b, 0x31], // 3
+    [0x18, 0x14, 0x12, 0x7f, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3c, 0x4a, 0x49, 0x49, 0x30], // 6
+    [0x01, 0x71, 0x09, 0x05, 0x03], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x06, 0x49, 0x49, 0x29, 0x1e], // 9
+    [0x00, 0x36, 0x36, 0x00, 0x00], // :
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ;
+    [0x08, 0x14, 0x22, 0x41, 0x00], // <
+    [0x14, 0x14, 0x14, 0x14, 0x14], // =
+    [0x00, 0x41, 0x22, 0x14, 0x08], // >
+    [0x02, 0x01, 0x51, 0x09, 0x06], // ?
+    [0x32, 0x49, 0x79, 0x41, 0x3e], // @
+    [0x7e, 0x11, 0x11, 0x11, 0x7e], // A
+    [0x7f, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3e, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7f, 0x41, 0x41, 0x22, 0x1c], // D
+    [0x7f, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7f, 0x09, 0x09, 0x09, 0x01], // F
+    [0x3e, 0x41, 0x49, 0x49, 0x7a], // G
+    [0x7f, 0x08, 0x08, 0x08, 0x7f], // H
+    [0x00, 0x41, 0x7f, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3f, 0x01], // J
+    [0x7f, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7f, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7f, 0x02, 0x0c, 0x02, 0x7f], // M
+    [0x7f, 0x04, 0x08, 0x10, 0x7f], // N
+    [0x3e, 0x41, 0x41, 0x41, 0x3e], // O
+    [0x7f, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3e, 0x41, 0x51, 0x21, 0x5e], // Q
+    [0x7f, 0x09, 0x19, 0x29, 0x46], // R
+    [0x46, 0x49, 0x49, 0x49, 0x31], // S
+    [0x01, 0x01, 0x7f, 0x01, 0x01], // T
+    [0x3f, 0x40, 0x40, 0x40, 0x3f], // U
+    [0x1f, 0x20, 0x40, 0x20, 0x1f], // V
+    [0x3f, 0x40, 0x38, 0x40, 0x3f], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x07, 0x08, 0x70, 0x08, 0x07], // Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], // Z
+    [0x00, 0x7f, 0x41, 0x41, 0x00], // [
+    [0x02, 0x04, 0x08, 0x10, 0x20], // backslash
+    [0x00, 0x41, 0x41, 0x7f, 0x00], // ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], // _
+    [0x00, 0x01, 0x02, 0x04, 0x00], // `
+    [0x20, 0x54, 0x54, 0x54, 0x78], // a
+    [0x7f, 0x48, 0x44, 0x44, 0x38], // b
+    [0x38, 0x44, 0x44, 0x44, 0x20], // c
+    [0x38, 0x44, 0x44, 0x48, 0x7f], // d
+    [0x38, 0x54, 0x54, 0x54, 0x18], // e
+    [0x08, 0x7e, 0x09, 0x01, 0x02], // f
+    [0x0c, 0x52, 0x52, 0x52, 0x3e], // g
+    [0x7f, 0x08, 0x04, 0x04, 0x78], // h
+    [0x00, 0x44, 0x7d, 0x40, 0x00], // i
+    [0x20, 0x40, 0x44, 0x3d, 0x00], // j
+    [0x7f, 0x10, 0x28, 0x44, 0x00], // k
+    [0x00, 0x41, 0x7f, 0x40, 0x00], // l
+    [0x7c, 0x04, 0x18, 0x04, 0x78], // m
+    [0x7c, 0x08, 0x04, 0x04, 0x78], // n
+    [0x38, 0x44, 0x44, 0x44, 0x38], // o
+    [0x7c, 0x14, 0x14, 0x14, 0x08], // p
+    [0x08, 0x14, 0x14, 0x18, 0x7c], // q
+    [0x7c, 0x08, 0x04, 0x04, 0x08], // r
+    [0x48, 0x54, 0x54, 0x54, 0x20], // s
+    [0x04, 0x3f, 0x44, 0x40, 0x20], // t
+    [0x3c, 0x40, 0x40, 0x20, 0x7c], // u
+    [0x1c, 0x20, 0x40, 0x20, 0x1c], // v
+    [0x3c, 0x40, 0x30, 0x40, 0x3c], // w
+    [0x44, 0x28, 0x10, 0x28, 0x44], // x
+    [0x0c, 0x50, 0x50, 0x50, 0x3c], // y
+    [0x44, 0x64, 0x54, 0x4c, 0x44], // z
+    [0x00, 0x08, 0x36, 0x41, 0x00], // {
+    [0x00, 0x00, 0x7f, 0x00, 0x00], // |
+    [0x00, 0x41, 0x36, 0x08, 0x00], // }
+    [0x08, 0x04, 0x08, 0x10, 0x08], // ~
+];
+
+fn glyph(c: char) -> &'static [u8; 5] {
+    match c {
+        ' '..='~' => &FONT[c as usize - ' ' as usize],
+        _ => &FONT['?' as usize - ' ' as usize],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+
+    // The CRC used by PNG, computed bitwise, independently of crc32fast.
+
+    fn crc(bytes: &[u8]) -> u32 {
+        let mut c = 0xffff_ffff_u32;
+        for &b in bytes {
+            c ^= b as u32;
+            for _ in 0..8 {
+                c = if c & 1 == 1 {
+                    0xedb8_8320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                };
+            }
+        }
+        !c
+    }
+
+    // Split a PNG file into its chunks, checking the signature and the CRC of each chunk.
+
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        let mut chunks = Vec::new();
+        let mut p = 8;
+        while p < png.len() {
+            let len = u32::from_be_bytes(png[p..p + 4].try_into().unwrap()) as usize;
+            let kind: [u8; 4] = png[p + 4..p + 8].try_into().unwrap();
+            let data = png[p + 8..p + 8 + len].to_vec();
+            let stored = u32::from_be_bytes(png[p + 8 + len..p + 12 + len].try_into().unwrap());
+            assert_eq!(stored, crc(&png[p + 4..p + 8 + len]));
+            chunks.push((kind, data));
+            p += 12 + len;
+        }
+        assert_eq!(p, png.len());
+        chunks
+    }
+
+    #[test]
+    fn test_crc() {
+        assert_eq!(crc(b"IEND"), 0xae42_6082);
+    }
+
+    #[test]
+    fn test_png() {
+        let canvas = Canvas {
+            width: 5.0,
+            height: 3.0,
+            shapes: vec![Shape::Rect {
+                x: 1.0,
+                y: 1.0,
+                w: 2.0,
+                h: 1.0,
+                fill: "red".to_string(),
+            }],
+        };
+        let chunks = chunks(&canvas.png(None));
+        let kinds = chunks.iter().map(|c| &c.0).collect::<Vec<_>>();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, [0, 0, 0, 5, 0, 0, 0, 3, 8, 2, 0, 0, 0]);
+        assert!(chunks[2].1.is_empty());
+
+        // Each row is a filter byte followed by the pixels, and exactly pixels (1, 1) and (2, 1)
+        // are red.
+
+        let mut raw = Vec::new();
+        ZlibDecoder::new(&chunks[1].1[..])
+            .read_to_end(&mut raw)
+            .unwrap();
+        assert_eq!(raw.len(), 3 * (1 + 3 * 5));
+        for (y, row) in raw.chunks(1 + 3 * 5).enumerate() {
+            assert_eq!(row[0], 0);
+            for (x, p) in row[1..].chunks(3).enumerate() {
+                let red = y == 1 && (x == 1 || x == 2);
+                let expected: &[u8] = if red { &[255, 0, 0] } else { &[255, 255, 255] };
+                assert_eq!(p, expected, "pixel ({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn test_png_width() {
+        let canvas = Canvas {
+            width: 100.0,
+            height: 50.0,
+            shapes: Vec::new(),
+        };
+        let chunks = chunks(&canvas.png(Some(50)));
+        assert_eq!(&chunks[0].1[..8], [0, 0, 0, 50, 0, 0, 0, 25]);
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("red"), [255, 0, 0]);
+        assert_eq!(parse_color(" Navy "), [0, 0, 128]);
+        assert_eq!(parse_color("#1f77b4"), [0x1f, 0x77, 0xb4]);
+        assert_eq!(parse_color("#f80"), [255, 136, 0]);
+        assert_eq!(parse_color("rgb(1, 2, 3)"), [1, 2, 3]);
+        assert_eq!(parse_color("rgb(1,2,300)"), [0, 0, 0]);
+        assert_eq!(parse_color("#12345"), [0, 0, 0]);
+        assert_eq!(parse_color("chartreuse"), [0, 0, 0]);
+        assert!(COLOR_NAMES.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn test_svg() {
+        let canvas = Canvas {
+            width: 10.0,
+            height: 20.0,
+            shapes: vec![Shape::Text {
+                x: 1.0,
+                y: 2.0,
+                size: 3.0,
+                anchor: Anchor::Middle,
+                fill: "black".to_string(),
+                text: "a<b".to_string(),
+            }],
+        };
+        let svg = canvas.svg();
+        assert!(svg.starts_with("<svg version=\"1.1\" baseProfile=\"full\" width=\"10.0\""));
+        assert!(svg.contains("text-anchor=\"middle\" fill=\"black\">a&lt;b</text>"));
+        assert!(svg.ends_with("</svg>\n"));
+    }
+}
@@ -0,0 +1,390 @@
+// Copyright (c) 2023 10X Genomics, Inc. All rights reserved.
+
+// Lineage trees of clonotypes (TREE and NEWICK).
+//
+// The tree of a clonotype has a leaf for each exact subclonotype, and is rooted at the donor
+// germline, as defined by germline_alignment.  Each node is represented by the concatenation of
+// the seq_del sequences of the chains of the clonotype, with N where a chain is absent or a base
+// is unknown, as in the junction of the germline.  The topology is found by neighbor joining,
+// using as distance the number of differences at positions where both bases are known.  Then
+// ancestral sequences are inferred by Fitch parsimony, so that each edge can be labelled by its
+// mutations.  Inferred ancestors that have no mutations relative to their parent are merged
+// into it.
+//
+// Neighbor joining is cubic in the number of exact subclonotypes, so no tree is made for a
+// clonotype having more than MAX_TREE_EXACTS of them.
+//
+// The tree is returned in Newick format, as described for the tree field of Clonotype in
+// types.proto.  NEWICK=<file> writes the trees of all the clonotypes to a file, and NEWICK or
+// NEWICK=stdout writes them to stdout, one line per clonotype, giving its name, e.g.
+// clonotype3, and its tree, separated by a tab.  Clonotypes are numbered as for AIRR output.
+
+use crate::airr::germline_alignment;
+use crate::msa::write_or_print;
+use enclone_core::defs::{ColInfo, EncloneControl, ExactClonotype};
+use enclone_proto::types::DonorReferenceItem;
+use std::fmt::Write;
+use vdj_ann::refx::RefData;
+
+pub const MAX_TREE_EXACTS: usize = 1000;
+
+// The bases of a node sequence, other than N.  A set of them is represented by a bit mask, as
+// used in Fitch parsimony.
+
+const BASES: &[u8; 5] = b"ACGT-";
+const ALL_BASES: u8 = 31;
+
+fn base_set(b: u8) -> u8 {
+    match BASES.iter().position(|&x| x == b) {
+        Some(i) => 1 << i,
+        None => ALL_BASES,
+    }
+}
+
+fn first_base(set: u8) -> u8 {
+    BASES[set.trailing_zeros() as usize]
+}
+
+// A rooted tree, in which node 0 is the germline, nodes 1..=n are the exact subclonotypes, and
+// higher nodes are inferred ancestors.
+
+struct Tree {
+    n: usize,
+    children: Vec<Vec<usize>>,
+    mutations: Vec<Vec<String>>, // on the edge from the parent
+}
+
+impl Tree {
+    // Find the children of a node, after merging inferred ancestors that have no mutations.
+
+    fn merged_children(&self, v: usize, kids: &mut Vec<usize>) {
+        for &w in &self.children[v] {
+            if w > self.n && self.mutations[w].is_empty() {
+                self.merged_children(w, kids);
+            } else {
+                kids.push(w);
+            }
+        }
+    }
+
+    fn newick(&self, v: usize, s: &mut String) {
+        let mut kids = Vec::<usize>::new();
+        self.merged_children(v, &mut kids);
+        if !kids.is_empty() {
+            s.push('(');
+            for (i, &w) in kids.iter().enumerate() {
+                if i > 0 {
+                    s.push(',');
+                }
+                self.newick(w, s);
+            }
+            s.push(')');
+        }
+        if v == 0 {
+            s.push_str("germline");
+            return;
+        }
+        if v <= self.n {
+            write!(s, "{v}").unwrap();
+        }
+        write!(s, ":{}", self.mutations[v].len()).unwrap();
+        if !self.mutations[v].is_empty() {
+            write!(s, "[{}]", self.mutations[v].join(",")).unwrap();
+        }
+    }
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+pub fn clonotype_tree(
+    refdata: &RefData,
+    dref: &[DonorReferenceItem],
+    exact_clonotypes: &[ExactClonotype],
+    exacts: &[usize],
+    rsi: &ColInfo,
+) -> Option<String> {
+    let n = exacts.len();
+    if n == 0 || n > MAX_TREE_EXACTS {
+        return None;
+    }
+
+    // Define the node sequences of the germline and the exact subclonotypes, and for each
+    // position, its chain type and its position on the chain.
+
+    let mut seqs = vec![Vec::<u8>::new(); n + 1];
+    let mut sites = Vec::<(&str, usize)>::new();
+    for col in &rsi.mat {
+        let Some(u0) = col.iter().position(Option::is_some) else {
+            continue;
+        };
+        let x = &exact_clonotypes[exacts[u0]].share[col[u0].unwrap()];
+        let germline = germline_alignment(refdata, dref, x);
+        let len = germline.len();
+        seqs[0].extend(&germline);
+        for (u, m) in col.iter().enumerate() {
+            let seq = &mut seqs[u + 1];
+            match m.map(|m| &exact_clonotypes[exacts[u]].share[m].seq_del) {
+                Some(s) if s.len() == len => seq.extend(s),
+                _ => seq.resize(seq.len() + len, b'N'),
+            }
+        }
+        sites.extend((0..len).map(|p| (x.chain_type.as_str(), p)));
+    }
+    Some(lineage_tree(&seqs, &sites))
+}
+
+// Find the tree for the given node sequences, the first of which is the germline, where sites
+// gives the chain type and position on the chain of each position.
+
+fn lineage_tree(seqs: &[Vec<u8>], sites: &[(&str, usize)]) -> String {
+    let n = seqs.len() - 1;
+
+    // Find the topology by neighbor joining.
+
+    let dist = |a: &[u8], b: &[u8]| {
+        a.iter()
+            .zip(b)
+            .filter(|&(&x, &y)| x != b'N' && y != b'N' && x != y)
+            .count() as f64
+    };
+    let mut d = vec![vec![0.0; 2 * n + 1]; 2 * n + 1];
+    for (i, si) in seqs.iter().enumerate() {
+        for (j, sj) in seqs.iter().enumerate().take(i) {
+            let x = dist(si, sj);
+            d[i][j] = x;
+            d[j][i] = x;
+        }
+    }
+    let mut adj = vec![Vec::<usize>::new(); 2 * n + 1];
+    let mut active = (0..=n).collect::<Vec<usize>>();
+    let mut nodes = n + 1;
+    while active.len() > 2 {
+        let r = active.len() as f64;
+        let s = active
+            .iter()
+            .map(|&i| active.iter().map(|&j| d[i][j]).sum::<f64>())
+            .collect::<Vec<f64>>();
+        let mut best = (f64::MAX, 0, 0);
+        for (a, &i) in active.iter().enumerate() {
+            for (b, &j) in active.iter().enumerate().skip(a + 1) {
+                let q = (r - 2.0) * d[i][j] - s[a] - s[b];
+                if q < best.0 {
+                    best = (q, a, b);
+                }
+            }
+        }
+        let (_, a, b) = best;
+        let (i, j) = (active[a], active[b]);
+        let k = nodes;
+        nodes += 1;
+        for &m in &active {
+            let x = (d[i][m] + d[j][m] - d[i][j]) / 2.0;
+            d[k][m] = x;
+            d[m][k] = x;
+        }
+        adj[k].extend([i, j]);
+        adj[i].push(k);
+        adj[j].push(k);
+        active.remove(b);
+        active.remove(a);
+        active.push(k);
+    }
+    if let [i, j] = active[..] {
+        adj[i].push(j);
+        adj[j].push(i);
+    }
+
+    // Root the tree at the germline.
+
+    let mut parent = vec![usize::MAX; nodes];
+    let mut children = vec![Vec::<usize>::new(); nodes];
+    let mut order = vec![0];
+    parent[0] = 0;
+    let mut i = 0;
+    while i < order.len() {
+        let v = order[i];
+        i += 1;
+        for &w in &adj[v] {
+            if parent[w] == usize::MAX {
+                parent[w] = v;
+                children[v].push(w);
+                order.push(w);
+            }
+        }
+    }
+
+    // Infer ancestral sequences by Fitch parsimony.  The root is the germline, except where
+    // the germline is unknown.
+
+    let len = seqs[0].len();
+    let mut sets = vec![Vec::<u8>::new(); nodes];
+    for (set, seq) in sets.iter_mut().zip(seqs.iter()) {
+        *set = seq.iter().map(|&b| base_set(b)).collect();
+    }
+    for &v in order.iter().rev() {
+        if v > n {
+            let mut inter = vec![ALL_BASES; len];
+            let mut union = vec![0; len];
+            for &w in &children[v] {
+                for ((x, y), &z) in inter.iter_mut().zip(union.iter_mut()).zip(&sets[w]) {
+                    *x &= z;
+                    *y |= z;
+                }
+            }
+            sets[v] = inter
+                .iter()
+                .zip(&union)
+                .map(|(&x, &y)| if x != 0 { x } else { y })
+                .collect();
+        }
+    }
+    let mut states = vec![Vec::<u8>::new(); nodes];
+    states[0] = seqs[0]
+        .iter()
+        .zip(&sets[children[0][0]])
+        .map(|(&g, &c)| if g != b'N' { g } else { first_base(c) })
+        .collect();
+    for &v in &order[1..] {
+        let state = states[parent[v]]
+            .iter()
+            .zip(&sets[v])
+            .map(|(&b, &s)| {
+                if s & base_set(b) != 0 {
+                    b
+                } else {
+                    first_base(s)
+                }
+            })
+            .collect();
+        states[v] = state;
+    }
+
+    // Find the mutations on each edge, and build the Newick string.
+
+    let mut mutations = vec![Vec::<String>::new(); nodes];
+    for &v in &order[1..] {
+        mutations[v] = states[parent[v]]
+            .iter()
+            .zip(&states[v])
+            .zip(sites)
+            .filter(|((a, b), _)| a != b)
+            .map(|((&a, &b), (chain, p))| format!("{chain}:{}{}{}", a as char, p + 1, b as char))
+            .collect();
+    }
+    let tree = Tree {
+        n,
+        children,
+        mutations,
+    };
+    let mut s = String::new();
+    tree.newick(0, &mut s);
+    s.push(';');
+    s
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+pub fn write_newick(
+    ctl: &EncloneControl,
+    refdata: &RefData,
+    dref: &[DonorReferenceItem],
+    exact_clonotypes: &[ExactClonotype],
+    exacts: &[Vec<usize>],
+    rsi: &[ColInfo],
+) -> Result<(), String> {
+    if ctl.gen_opt.newick.is_empty() {
+        return Ok(());
+    }
+    let mut out = String::new();
+    for (i, (exacts, rsi)) in exacts.iter().zip(rsi.iter()).enumerate() {
+        if let Some(tree) = clonotype_tree(refdata, dref, exact_clonotypes, exacts, rsi) {
+            writeln!(out, "clonotype{}\t{tree}", i + 1).unwrap();
+        }
+    }
+    write_or_print(&ctl.gen_opt.newick, "NEWICK", &out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V: &[u8] = b"GATTACAGATTACAGATTAC";
+    const J: &[u8] = b"TGGGGCCAGG";
+
+    // The germline of a heavy chain, whose junction is three unknown bases, and an exact
+    // subclonotype having the given substitutions and deletions relative to it.
+
+    fn germline() -> Vec<u8> {
+        [V, b"NNN", J].concat()
+    }
+
+    fn leaf(subs: &[usize], dels: &[usize]) -> Vec<u8> {
+        let mut s = [V, b"GGG", J].concat();
+        for &p in subs {
+            s[p] = match s[p] {
+                b'A' => b'C',
+                b'C' => b'G',
+                b'G' => b'T',
+                _ => b'A',
+            };
+        }
+        for &p in dels {
+            s[p] = b'-';
+        }
+        s
+    }
+
+    fn tree(seqs: &[Vec<u8>]) -> String {
+        let sites = (0..seqs[0].len()).map(|p| ("IGH", p)).collect::<Vec<_>>();
+        lineage_tree(seqs, &sites)
+    }
+
+    // Two clades, each having a leaf that is the ancestor of the other.  Neighbor joining
+    // pairs the leaves of each clade, and Fitch parsimony puts the mutations shared by a clade
+    // on the edge to its ancestor.  Positions count from one, and a deletion is shown as '-'.
+
+    #[test]
+    fn test_two_clades() {
+        let seqs = [
+            germline(),
+            leaf(&[2, 3], &[]),
+            leaf(&[2, 3, 10], &[]),
+            leaf(&[15, 16], &[]),
+            leaf(&[15, 16, 18], &[12]),
+        ];
+        assert_eq!(
+            tree(&seqs),
+            "((1:0,2:1[IGH:T11A]):2[IGH:T3A,IGH:T4A],\
+             (3:0,4:2[IGH:C13-,IGH:A19C]):2[IGH:A16C,IGH:T17A])germline;"
+        );
+    }
+
+    // Leaves having no mutations in common are children of the germline, because inferred
+    // ancestors having no mutations are merged into their parents.
+
+    #[test]
+    fn test_star() {
+        let seqs = [
+            germline(),
+            leaf(&[1], &[]),
+            leaf(&[5], &[]),
+            leaf(&[9], &[]),
+        ];
+        assert_eq!(
+            tree(&seqs),
+            "(1:1[IGH:A2C],2:1[IGH:C6G],3:1[IGH:T10A])germline;"
+        );
+        assert_eq!(tree(&[germline(), leaf(&[], &[])]), "(1:0)germline;");
+    }
+
+    // No tree is made for a clonotype having too many exact subclonotypes.
+
+    #[test]
+    fn test_max_tree_exacts() {
+        let refdata = RefData::new();
+        let rsi = ColInfo::default();
+        let tree = |n: usize| clonotype_tree(&refdata, &[], &[], &vec![0; n], &rsi);
+        assert_eq!(tree(0), None);
+        assert_eq!(tree(MAX_TREE_EXACTS + 1), None);
+    }
+}
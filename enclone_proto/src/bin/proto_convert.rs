@@ -0,0 +1,49 @@
+// Copyright (c) 2023 10X Genomics, Inc. All rights reserved.
+
+// Convert a proto file written by enclone to formats that can be read without enclone.
+//
+// Usage: proto_convert PROTO [JSON=out.json] [PARQUET=out.parquet]
+//
+// JSON mirrors the proto message.  PARQUET is a flat table having one row per
+// (clonotype, exact subclonotype, chain, barcode); see enclone_proto::export.
+
+use enclone_proto::export::{proto_to_json, proto_to_parquet};
+use std::env;
+use std::process::exit;
+
+fn fail(msg: &str) -> ! {
+    eprintln!("\n{msg}\n");
+    exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let (mut proto, mut json, mut parquet) = (None, None, None);
+    for arg in &args[1..] {
+        if let Some(path) = arg.strip_prefix("JSON=") {
+            json = Some(path.to_string());
+        } else if let Some(path) = arg.strip_prefix("PARQUET=") {
+            parquet = Some(path.to_string());
+        } else if proto.is_none() {
+            proto = Some(arg.clone());
+        } else {
+            fail(&format!("Unrecognized argument {arg}."));
+        }
+    }
+    let Some(proto) = proto else {
+        fail("Usage: proto_convert PROTO [JSON=out.json] [PARQUET=out.parquet]");
+    };
+    if json.is_none() && parquet.is_none() {
+        fail("Please specify at least one of JSON=... and PARQUET=....");
+    }
+    if let Some(json) = json {
+        if let Err(e) = proto_to_json(&proto, &json) {
+            fail(&format!("Failed to write {json}: {e}"));
+        }
+    }
+    if let Some(parquet) = parquet {
+        if let Err(e) = proto_to_parquet(&proto, &parquet) {
+            fail(&format!("Failed to write {parquet}: {e}"));
+        }
+    }
+}
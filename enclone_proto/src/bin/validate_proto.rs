@@ -0,0 +1,73 @@
+// Copyright (c) 2023 10X Genomics, Inc. All rights reserved.
+
+// Validate a proto file written by enclone.
+//
+// Usage: validate_proto PROTO [UPGRADE=OUT]
+//
+// Print each violation of the invariants described in types.proto, and exit with status 1 if
+// there are any.  Files written by other versions of enclone having the same major version are
+// read as they are, since within a major version, fields are only ever appended.  If UPGRADE=OUT
+// is given, also write a copy of the file, stamped with the current version, to OUT.  This is
+// refused for a file having a different major version.
+
+use enclone_proto::proto_io::{read_proto, read_proto_until_clonotypes, write_proto};
+use enclone_proto::types::Clonotype;
+use enclone_proto::validate::{upgrade, validate, validate_clonotype};
+use enclone_proto::PROTO_VERSION;
+use std::env;
+use std::process::exit;
+
+fn fail(msg: &str) -> ! {
+    eprintln!("\n{msg}\n");
+    exit(2);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let mut upgrade_path = None;
+    let mut proto_path = None;
+    for arg in &args[1..] {
+        if let Some(path) = arg.strip_prefix("UPGRADE=") {
+            upgrade_path = Some(path.to_string());
+        } else if proto_path.is_none() {
+            proto_path = Some(arg.clone());
+        } else {
+            fail(&format!("Unrecognized argument {arg}."));
+        }
+    }
+    let Some(proto_path) = proto_path else {
+        fail("Usage: validate_proto PROTO [UPGRADE=OUT]");
+    };
+
+    // Stream through the clonotypes, so that large files need not fit in memory.
+
+    let (header, mut proto_reader) = read_proto_until_clonotypes(&proto_path)
+        .unwrap_or_else(|e| fail(&format!("Failed to read {proto_path}: {e}")));
+    let mut violations = validate(&header);
+    for i in 0..header.num_clonotypes as usize {
+        let clonotype: Clonotype = proto_reader
+            .read_and_decode()
+            .unwrap_or_else(|e| fail(&format!("Failed to read clonotype {i}: {e}")));
+        violations.append(&mut validate_clonotype(&header, i, &clonotype));
+    }
+    for v in &violations {
+        println!("{v}");
+    }
+    println!(
+        "{proto_path}: version {}, {} clonotypes, {} violations",
+        header.version,
+        header.num_clonotypes,
+        violations.len()
+    );
+
+    if let Some(out) = upgrade_path {
+        let outputs = read_proto(&proto_path)
+            .unwrap_or_else(|e| fail(&format!("Failed to read {proto_path}: {e}")));
+        let outputs = upgrade(outputs).unwrap_or_else(|e| fail(&e));
+        write_proto(outputs, &out).unwrap_or_else(|e| fail(&format!("Failed to write {out}: {e}")));
+        println!("wrote {out} at version {PROTO_VERSION}");
+    }
+    if !violations.is_empty() {
+        exit(1);
+    }
+}
@@ -32,11 +32,13 @@ pub struct Alignment {
 pub struct ExactSubClonotypeChain {
     /// Nucleotide sequence of the chain. This will only contain ACGT alphabets
     #[prost(bytes = "vec", required, tag = "1")]
+    #[serde(with = "crate::types::bytes_as_string")]
     pub nt_sequence: ::prost::alloc::vec::Vec<u8>,
     /// Amino acid sequence from the start codon at the beginning of the V-REGION.
     /// This can be inferred from the `nt_sequence` and `v_start`, but stored for
     /// convenience
     #[prost(bytes = "vec", required, tag = "2")]
+    #[serde(with = "crate::types::bytes_as_string")]
     pub aa_sequence: ::prost::alloc::vec::Vec<u8>,
     /// Index of the start of the V-REGION in the `nt_sequence`.
     #[prost(uint32, required, tag = "3")]
@@ -171,6 +173,7 @@ pub struct ClonotypeChain {
     /// first exact subclonotype which has an entry for the given chain.  Over 99%
     /// of the time, this will be the first exact subclonotype.
     #[prost(bytes = "vec", required, tag = "1")]
+    #[serde(with = "crate::types::bytes_as_string")]
     pub nt_sequence: ::prost::alloc::vec::Vec<u8>,
     /// Index of the 5' UTR region in the universal reference. The region in the
     /// universal reference is guaranteed to be `Region::U`
@@ -211,6 +214,7 @@ pub struct ClonotypeChain {
     ///      `nt_sequence` of universal_reference\[j_idx\] +
     ///      `nt_sequence` of universal_reference\[c_idx\] if c_idx is not None.
     #[prost(bytes = "vec", required, tag = "9")]
+    #[serde(with = "crate::types::bytes_as_string")]
     pub universal_reference: ::prost::alloc::vec::Vec<u8>,
     /// Alignment of the `nt_sequence` to the nucleotide sequence of the
     /// concatenated universal reference of this chain.
@@ -224,6 +228,7 @@ pub struct ClonotypeChain {
     ///      `nt_sequence` of donor_reference\[donor_j_idx\] if donor_j_idx is not
     ///      None.
     #[prost(bytes = "vec", required, tag = "11")]
+    #[serde(with = "crate::types::bytes_as_string")]
     pub donor_reference: ::prost::alloc::vec::Vec<u8>,
     /// Alignment of the `nt_sequence` to the nucleotide sequence of the
     /// concatenated donor reference of this chain.
@@ -264,13 +269,16 @@ pub struct ClonotypeChain {
     pub chain_type: ::prost::alloc::string::String,
     /// AA sequence of the clonotype chain consensus
     #[prost(bytes = "vec", required, tag = "22")]
+    #[serde(with = "crate::types::bytes_as_string")]
     pub aa_sequence: ::prost::alloc::vec::Vec<u8>,
     /// AA sequence of the concatenated universal reference starting from the V
     /// regions
     #[prost(bytes = "vec", required, tag = "23")]
+    #[serde(with = "crate::types::bytes_as_string")]
     pub aa_sequence_universal: ::prost::alloc::vec::Vec<u8>,
     /// AA sequence of the concatenated donor reference starting from the V regions
     #[prost(bytes = "vec", required, tag = "24")]
+    #[serde(with = "crate::types::bytes_as_string")]
     pub aa_sequence_donor: ::prost::alloc::vec::Vec<u8>,
     /// Index of the start of the FWR1 sequence in the `nt_sequence`.
     #[prost(uint32, optional, tag = "25")]
@@ -317,6 +325,24 @@ pub struct Clonotype {
     /// subclonotype, but it is stored here for convenience.
     #[prost(uint32, required, tag = "3")]
     pub frequency: u32,
+    /// The lineage tree of the exact subclonotypes, in Newick format, present if
+    /// enclone was run with TREE, and absent in files written before version
+    /// 1.1.0. The root is labelled germline and is the donor germline sequence.
+    /// Each leaf is labelled by the number of an exact subclonotype, counting from
+    /// one, in the order of `exact_clonotypes`. Inferred ancestors are unlabelled.
+    /// The length of each branch is the number of mutations on it, and these are
+    /// listed in a comment following the length, e.g. \[IGH:A57G,IGK:C12T\], where
+    /// positions count from one on the V..J sequence of the chain, with gaps for
+    /// deletions relative to the reference.
+    #[prost(string, optional, tag = "4")]
+    pub tree: ::core::option::Option<::prost::alloc::string::String>,
+    /// The number of the group of clonotypes that this clonotype belongs to,
+    /// counting from one, present if enclone was run with a grouping option (e.g.
+    /// GROUP or AGROUP) and the group passed the group filters (e.g. MIN_GROUP),
+    /// and absent in files written before version 1.2.0. Clonotypes in the same
+    /// group are similar, for example because they use the same V and J genes.
+    #[prost(uint32, optional, tag = "5")]
+    pub group_id: ::core::option::Option<u32>,
 }
 /// A single donor reference sequence and metadata packaged in a convenient
 /// struct. In the current version of enclone, the donor reference is only
@@ -339,6 +365,7 @@ pub struct UniversalReferenceItem {
     pub region: i32,
     /// Nucleotide sequence associated with this reference item
     #[prost(bytes = "vec", required, tag = "4")]
+    #[serde(with = "crate::types::bytes_as_string")]
     pub nt_sequence: ::prost::alloc::vec::Vec<u8>,
 }
 /// List of all universal reference sequences and metadata packaged in a
@@ -354,9 +381,8 @@ pub struct UniversalReference {
     pub items: ::prost::alloc::vec::Vec<UniversalReferenceItem>,
 }
 /// A single donor reference sequence and metadata packaged in a convenient
-/// struct. In the current version of enclone, the donor reference is only
-/// inferred for the V-REGIONs. But we could extend it to J-REGIONs in the
-/// future.
+/// struct. The donor reference is inferred for the V-REGIONs and the
+/// J-REGIONs.
 #[derive(::serde::Serialize, ::serde::Deserialize)]
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -374,11 +400,12 @@ pub struct DonorReferenceItem {
     /// [Donor 0]? for now, like this: "TRAV-1, donor 1, alt allele 1", etc.
     #[prost(string, required, tag = "3")]
     pub display_name: ::prost::alloc::string::String,
-    /// Currently, the donor reference region will only be the V-REGION
+    /// The donor reference region is either the V-REGION or the J-REGION
     #[prost(enumeration = "Region", required, tag = "4")]
     pub region: i32,
     /// The nucleotide sequence associated with this reference item
     #[prost(bytes = "vec", required, tag = "5")]
+    #[serde(with = "crate::types::bytes_as_string")]
     pub nt_sequence: ::prost::alloc::vec::Vec<u8>,
     /// Alignment of the `nt-sequence` with the nucleotide sequence of the
     /// corresponding universal reference item.
@@ -393,8 +420,8 @@ pub struct DonorReferenceItem {
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct DonorReference {
     /// All the entries in this reference
-    /// The donor V-REGION associated with a clonotype chain is stored as an index
-    /// to this vector.
+    /// The donor V-REGION and J-REGION associated with a clonotype chain are stored
+    /// as indices to this vector.
     #[prost(message, repeated, tag = "1")]
     pub items: ::prost::alloc::vec::Vec<DonorReferenceItem>,
 }
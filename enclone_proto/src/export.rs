@@ -0,0 +1,311 @@
+// Copyright (c) 2023 10X Genomics, Inc. All rights reserved.
+
+//!
+//! Export of `EncloneOutputs` in formats that can be read without this crate: a JSON document
+//! that mirrors the proto message, and a flat columnar table written as Parquet.
+//!
+//! The flat table has one row per (clonotype, exact subclonotype, chain, barcode).  Gene names
+//! are the display names of the universal reference items.  Clonotypes are numbered as in the
+//! proto file, and exact subclonotypes and chains as within their clonotype.
+//!
+
+use crate::proto_io::{self, read_proto, read_proto_until_clonotypes};
+use crate::types::{Clonotype, EncloneOutputs};
+use arrow::array::{ArrayRef, Float32Array, StringArray, UInt32Array};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::Arc;
+
+// Number of clonotypes that are accumulated before a batch is written to a Parquet file.
+
+const CLONOTYPES_PER_BATCH: usize = 1000;
+
+/// The errors that may arise in exporting, including those in reading a proto file.
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+    #[error(transparent)]
+    Proto(#[from] proto_io::Error),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Arrow(#[from] arrow::error::ArrowError),
+
+    #[error(transparent)]
+    Parquet(#[from] parquet::errors::ParquetError),
+}
+
+/// Write `outputs` as a JSON document.  Sequences are written as strings.
+pub fn write_json(outputs: &EncloneOutputs, path: impl AsRef<Path>) -> Result<(), ExportError> {
+    let writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer(writer, outputs)?;
+    Ok(())
+}
+
+/// Convert a proto file to a JSON document.
+pub fn proto_to_json(
+    proto_path: impl AsRef<Path>,
+    path: impl AsRef<Path>,
+) -> Result<(), ExportError> {
+    write_json(&read_proto(proto_path)?, path)
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+/// The schema of the flat table.
+pub fn flat_schema() -> SchemaRef {
+    let u32_field = |name| Field::new(name, DataType::UInt32, false);
+    let str_field = |name, nullable| Field::new(name, DataType::Utf8, nullable);
+    let f32_field = |name| Field::new(name, DataType::Float32, false);
+    Arc::new(Schema::new(vec![
+        u32_field("clonotype_id"),
+        u32_field("exact_subclonotype_id"),
+        u32_field("chain_index"),
+        str_field("chain_type", false),
+        str_field("barcode", false),
+        str_field("contig_id", false),
+        str_field("cdr3_nt", false),
+        str_field("cdr3_aa", false),
+        str_field("v_gene", false),
+        str_field("d_gene", true),
+        str_field("j_gene", false),
+        str_field("c_gene", true),
+        u32_field("umi_count"),
+        u32_field("read_count"),
+        f32_field("dna_percent"),
+        f32_field("aa_percent"),
+    ]))
+}
+
+/// Rows of the flat table, accumulated column by column.
+#[derive(Default)]
+pub struct FlatRows {
+    clonotype_id: Vec<u32>,
+    exact_subclonotype_id: Vec<u32>,
+    chain_index: Vec<u32>,
+    chain_type: Vec<String>,
+    barcode: Vec<String>,
+    contig_id: Vec<String>,
+    cdr3_nt: Vec<String>,
+    cdr3_aa: Vec<String>,
+    v_gene: Vec<String>,
+    d_gene: Vec<Option<String>>,
+    j_gene: Vec<String>,
+    c_gene: Vec<Option<String>>,
+    umi_count: Vec<u32>,
+    read_count: Vec<u32>,
+    dna_percent: Vec<f32>,
+    aa_percent: Vec<f32>,
+}
+
+// Return the slice start..stop of s as a string, or the empty string if it is out of bounds.
+
+fn substring(s: &[u8], start: usize, stop: usize) -> String {
+    s.get(start..stop)
+        .map(|x| String::from_utf8_lossy(x).into_owned())
+        .unwrap_or_default()
+}
+
+impl FlatRows {
+    pub fn len(&self) -> usize {
+        self.clonotype_id.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clonotype_id.is_empty()
+    }
+
+    /// Add the rows for clonotype number `id`.  The reference names are taken from `header`,
+    /// which need not contain the clonotypes.
+    pub fn push_clonotype(&mut self, header: &EncloneOutputs, id: usize, clonotype: &Clonotype) {
+        let uref = &header.universal_reference.items;
+        let name = |idx: u32| {
+            uref.get(idx as usize)
+                .map(|item| item.display_name.clone())
+                .unwrap_or_default()
+        };
+        for (j, ex) in clonotype.exact_clonotypes.iter().enumerate() {
+            for info in &ex.chains {
+                let chain = &info.chain;
+                let cchain = &clonotype.chains[info.index as usize];
+                let (v_start, cdr3_start, cdr3_end) = (
+                    chain.v_start as usize,
+                    chain.cdr3_start as usize,
+                    chain.cdr3_end as usize,
+                );
+                let cdr3_nt = substring(&chain.nt_sequence, cdr3_start, cdr3_end);
+                let cdr3_aa = substring(
+                    &chain.aa_sequence,
+                    cdr3_start.saturating_sub(v_start) / 3,
+                    cdr3_end.saturating_sub(v_start) / 3,
+                );
+                let c_idx = chain.c_region_idx.or(cchain.c_idx);
+                for (k, bc) in ex.cell_barcodes.iter().enumerate() {
+                    self.clonotype_id.push(id as u32);
+                    self.exact_subclonotype_id.push(j as u32);
+                    self.chain_index.push(info.index);
+                    self.chain_type.push(cchain.chain_type.clone());
+                    self.barcode.push(bc.clone());
+                    self.contig_id
+                        .push(chain.contig_ids.get(k).cloned().unwrap_or_default());
+                    self.cdr3_nt.push(cdr3_nt.clone());
+                    self.cdr3_aa.push(cdr3_aa.clone());
+                    self.v_gene.push(name(cchain.v_idx));
+                    self.d_gene.push(cchain.d_idx.map(name));
+                    self.j_gene.push(name(cchain.j_idx));
+                    self.c_gene.push(c_idx.map(name));
+                    self.umi_count
+                        .push(chain.umi_counts.get(k).copied().unwrap_or_default());
+                    self.read_count
+                        .push(chain.read_counts.get(k).copied().unwrap_or_default());
+                    self.dna_percent.push(chain.dna_percent);
+                    self.aa_percent.push(chain.aa_percent);
+                }
+            }
+        }
+    }
+
+    /// Convert the accumulated rows to a record batch, leaving this empty.
+    pub fn take_record_batch(&mut self) -> Result<RecordBatch, ExportError> {
+        let rows = std::mem::take(self);
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(UInt32Array::from(rows.clonotype_id)),
+            Arc::new(UInt32Array::from(rows.exact_subclonotype_id)),
+            Arc::new(UInt32Array::from(rows.chain_index)),
+            Arc::new(StringArray::from(rows.chain_type)),
+            Arc::new(StringArray::from(rows.barcode)),
+            Arc::new(StringArray::from(rows.contig_id)),
+            Arc::new(StringArray::from(rows.cdr3_nt)),
+            Arc::new(StringArray::from(rows.cdr3_aa)),
+            Arc::new(StringArray::from(rows.v_gene)),
+            Arc::new(StringArray::from(rows.d_gene)),
+            Arc::new(StringArray::from(rows.j_gene)),
+            Arc::new(StringArray::from(rows.c_gene)),
+            Arc::new(UInt32Array::from(rows.umi_count)),
+            Arc::new(UInt32Array::from(rows.read_count)),
+            Arc::new(Float32Array::from(rows.dna_percent)),
+            Arc::new(Float32Array::from(rows.aa_percent)),
+        ];
+        Ok(RecordBatch::try_new(flat_schema(), columns)?)
+    }
+}
+
+/// Flatten `outputs` into a single record batch.
+pub fn to_record_batch(outputs: &EncloneOutputs) -> Result<RecordBatch, ExportError> {
+    let mut rows = FlatRows::default();
+    for (i, clonotype) in outputs.clonotypes.iter().enumerate() {
+        rows.push_clonotype(outputs, i, clonotype);
+    }
+    rows.take_record_batch()
+}
+
+/// Write `outputs` as a flat Parquet table.
+pub fn write_parquet(outputs: &EncloneOutputs, path: impl AsRef<Path>) -> Result<(), ExportError> {
+    let mut writer = ArrowWriter::try_new(File::create(path)?, flat_schema(), None)?;
+    let mut rows = FlatRows::default();
+    for (i, clonotype) in outputs.clonotypes.iter().enumerate() {
+        rows.push_clonotype(outputs, i, clonotype);
+        if (i + 1) % CLONOTYPES_PER_BATCH == 0 {
+            writer.write(&rows.take_record_batch()?)?;
+        }
+    }
+    if !rows.is_empty() {
+        writer.write(&rows.take_record_batch()?)?;
+    }
+    writer.close()?;
+    Ok(())
+}
+
+/// Convert a proto file to a flat Parquet table, streaming through the clonotypes so that
+/// they need not all be in memory at once.
+pub fn proto_to_parquet(
+    proto_path: impl AsRef<Path>,
+    path: impl AsRef<Path>,
+) -> Result<(), ExportError> {
+    let (header, mut proto_reader) = read_proto_until_clonotypes(proto_path)?;
+    let mut writer = ArrowWriter::try_new(File::create(path)?, flat_schema(), None)?;
+    let mut rows = FlatRows::default();
+    for i in 0..header.num_clonotypes as usize {
+        let clonotype: Clonotype = proto_reader.read_and_decode()?;
+        rows.push_clonotype(&header, i, &clonotype);
+        if (i + 1) % CLONOTYPES_PER_BATCH == 0 {
+            writer.write(&rows.take_record_batch()?)?;
+        }
+    }
+    if !rows.is_empty() {
+        writer.write(&rows.take_record_batch()?)?;
+    }
+    writer.close()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{
+        ClonotypeChain, ExactSubClonotype, ExactSubClonotypeChain, ExactSubClonotypeChainInfo,
+    };
+
+    #[test]
+    fn test_flat_rows() {
+        let chain = ExactSubClonotypeChain {
+            nt_sequence: b"ATGTGTGCAAGCTGG".to_vec(),
+            aa_sequence: b"MCASW".to_vec(),
+            cdr3_start: 3,
+            cdr3_end: 15,
+            umi_counts: vec![5, 7],
+            read_counts: vec![50, 70],
+            contig_ids: vec!["a_contig_1".to_string(), "b_contig_1".to_string()],
+            ..Default::default()
+        };
+        let clonotype = Clonotype {
+            chains: vec![ClonotypeChain {
+                chain_type: "TRB".to_string(),
+                ..Default::default()
+            }],
+            exact_clonotypes: vec![ExactSubClonotype {
+                chains: vec![ExactSubClonotypeChainInfo { index: 0, chain }],
+                cell_barcodes: vec!["a".to_string(), "b".to_string()],
+                ..Default::default()
+            }],
+            frequency: 2,
+            tree: None,
+            group_id: None,
+        };
+        let outputs = EncloneOutputs {
+            num_clonotypes: 1,
+            clonotypes: vec![clonotype],
+            ..Default::default()
+        };
+        let batch = to_record_batch(&outputs).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        let cdr3_aa = batch
+            .column(7)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(cdr3_aa.value(0), "CASW");
+    }
+
+    #[test]
+    fn test_json_sequences() {
+        let chain = ExactSubClonotypeChain {
+            nt_sequence: b"ATGTGTGCAAGCTGG".to_vec(),
+            aa_sequence: b"MCASW".to_vec(),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&chain).unwrap();
+        assert!(json.contains(r#""nt_sequence":"ATGTGTGCAAGCTGG""#));
+        assert!(json.contains(r#""aa_sequence":"MCASW""#));
+        let chain2: ExactSubClonotypeChain = serde_json::from_str(&json).unwrap();
+        assert_eq!(chain2, chain);
+    }
+}
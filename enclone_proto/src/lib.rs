@@ -2,7 +2,10 @@
 //! Definition of the proto file created by enclone and readers/writers for the proto file
 //!
 
+pub mod export;
+pub mod proto_index;
 pub mod proto_io;
 pub mod types;
+pub mod validate;
 
-pub const PROTO_VERSION: &str = "1.0.0";
+pub const PROTO_VERSION: &str = "1.2.0";
@@ -0,0 +1,277 @@
+// Copyright (c) 2023 10X Genomics, Inc. All rights reserved.
+
+//!
+//! Random access to the clonotypes in a proto file, by way of a sidecar index file.
+//!
+//! The index records the byte offset of each clonotype message in the proto file, together
+//! with a map from cell barcode to clonotype number.  It is itself stored as a single length
+//! delimited message, in the format described in `proto_io`.  The length of the proto file is
+//! recorded in the index, so that an index that does not belong to a proto file is detected
+//! when the two are opened together.
+//!
+
+use crate::proto_io::{read_proto_until_clonotypes, Error, ProtoReader, ProtoWriter};
+use crate::types::{Clonotype, EncloneOutputs};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+pub const PROTO_INDEX_VERSION: &str = "1.0.0";
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProtoIndex {
+    #[prost(string, tag = "1")]
+    pub version: ::prost::alloc::string::String,
+    /// Length in bytes of the proto file that this index refers to.
+    #[prost(uint64, tag = "2")]
+    pub proto_len: u64,
+    /// Byte offset of each clonotype message, starting at its length prefix.
+    #[prost(uint64, repeated, tag = "3")]
+    pub offsets: ::prost::alloc::vec::Vec<u64>,
+    /// Cell barcodes, sorted.  A barcode may appear more than once if it occurs in more than
+    /// one clonotype.
+    #[prost(string, repeated, tag = "4")]
+    pub barcodes: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// The clonotype number for each entry in `barcodes`.
+    #[prost(uint32, repeated, tag = "5")]
+    pub barcode_clonotypes: ::prost::alloc::vec::Vec<u32>,
+}
+
+impl ProtoIndex {
+    /// The numbers of the clonotypes that contain the given barcode.
+    pub fn clonotypes_with_barcode(&self, barcode: &str) -> &[u32] {
+        let start = self.barcodes.partition_point(|bc| bc.as_str() < barcode);
+        let stop = self.barcodes.partition_point(|bc| bc.as_str() <= barcode);
+        &self.barcode_clonotypes[start..stop]
+    }
+}
+
+/// Accumulates an index as clonotypes are written.
+#[derive(Default)]
+pub struct ProtoIndexBuilder {
+    offsets: Vec<u64>,
+    barcodes: Vec<(String, u32)>,
+}
+
+impl ProtoIndexBuilder {
+    /// Record a clonotype, which starts at byte `offset` of the proto file.
+    pub fn add(&mut self, offset: u64, clonotype: &Clonotype) {
+        let n = self.offsets.len() as u32;
+        self.offsets.push(offset);
+        for ex in &clonotype.exact_clonotypes {
+            for bc in &ex.cell_barcodes {
+                self.barcodes.push((bc.clone(), n));
+            }
+        }
+    }
+
+    pub fn finish(mut self, proto_len: u64) -> ProtoIndex {
+        self.barcodes.sort_unstable();
+        let (barcodes, barcode_clonotypes) = self.barcodes.into_iter().unzip();
+        ProtoIndex {
+            version: PROTO_INDEX_VERSION.to_string(),
+            proto_len,
+            offsets: self.offsets,
+            barcodes,
+            barcode_clonotypes,
+        }
+    }
+}
+
+pub fn write_proto_index(index: ProtoIndex, path: impl AsRef<Path>) -> Result<(), Error> {
+    let mut proto_writer = ProtoWriter::with_writer(BufWriter::new(File::create(path)?));
+    proto_writer.encode_and_write(index)?;
+    Ok(())
+}
+
+pub fn read_proto_index(path: impl AsRef<Path>) -> Result<ProtoIndex, Error> {
+    let mut proto_reader = ProtoReader::from_reader(BufReader::new(File::open(path)?));
+    proto_reader.read_and_decode()
+}
+
+/// Build an index for an existing proto file, by reading through it once.
+pub fn build_proto_index(path: impl AsRef<Path>) -> Result<ProtoIndex, Error> {
+    let (outputs, mut proto_reader) = read_proto_until_clonotypes(path)?;
+    let mut builder = ProtoIndexBuilder::default();
+    for _ in 0..outputs.num_clonotypes {
+        let offset = proto_reader.position();
+        let cl: Clonotype = proto_reader.read_and_decode()?;
+        builder.add(offset, &cl);
+    }
+    Ok(builder.finish(proto_reader.position()))
+}
+
+/// Reads individual clonotypes from a proto file, using its index.
+pub struct ProtoIndexReader {
+    header: EncloneOutputs,
+    index: ProtoIndex,
+    proto_reader: ProtoReader<BufReader<File>>,
+}
+
+impl ProtoIndexReader {
+    pub fn open(proto_path: impl AsRef<Path>, index_path: impl AsRef<Path>) -> Result<Self, Error> {
+        let index = read_proto_index(index_path)?;
+        let proto_len = std::fs::metadata(proto_path.as_ref())?.len();
+        if index.proto_len != proto_len {
+            return Err(Error::StaleIndex {
+                expected: index.proto_len,
+                got: proto_len,
+            });
+        }
+        let (header, _) = read_proto_until_clonotypes(proto_path.as_ref())?;
+        if header.num_clonotypes as usize != index.offsets.len() {
+            return Err(Error::StaleIndex {
+                expected: index.offsets.len() as u64,
+                got: header.num_clonotypes as u64,
+            });
+        }
+        let proto_reader = ProtoReader::from_reader(BufReader::new(File::open(proto_path)?));
+        Ok(ProtoIndexReader {
+            header,
+            index,
+            proto_reader,
+        })
+    }
+
+    /// Everything in the proto file except the clonotypes.
+    pub fn header(&self) -> &EncloneOutputs {
+        &self.header
+    }
+
+    pub fn index(&self) -> &ProtoIndex {
+        &self.index
+    }
+
+    pub fn num_clonotypes(&self) -> usize {
+        self.index.offsets.len()
+    }
+
+    /// Read clonotype `n`, without decoding any other clonotype.
+    pub fn read_clonotype(&mut self, n: usize) -> Result<Clonotype, Error> {
+        if n >= self.index.offsets.len() {
+            return Err(Error::ClonotypeOutOfRange {
+                index: n,
+                num_clonotypes: self.index.offsets.len(),
+            });
+        }
+        self.proto_reader.seek(self.index.offsets[n])?;
+        self.proto_reader.read_and_decode()
+    }
+
+    /// Read the clonotypes that contain the given barcode.
+    pub fn read_clonotypes_with_barcode(&mut self, barcode: &str) -> Result<Vec<Clonotype>, Error> {
+        let ids = self.index.clonotypes_with_barcode(barcode).to_vec();
+        ids.into_iter()
+            .map(|n| self.read_clonotype(n as usize))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto_io::{write_proto, write_proto_and_index, ClonotypeIter};
+    use crate::types::ExactSubClonotype;
+    use crate::PROTO_VERSION;
+    use std::fs::remove_file;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("proto_index_test_{}_{name}", std::process::id()))
+    }
+
+    // Clonotypes of different sizes, so that their offsets are not evenly spaced.  Barcode
+    // shared-1 is in clonotypes 1 and 3.
+
+    fn test_outputs() -> EncloneOutputs {
+        let clonotypes = (0..5u32)
+            .map(|n| {
+                let mut barcodes = (0..=n)
+                    .map(|c| format!("cell{n}-{c}-1"))
+                    .collect::<Vec<_>>();
+                if n % 2 == 1 {
+                    barcodes.push("shared-1".to_string());
+                }
+                Clonotype {
+                    frequency: barcodes.len() as u32,
+                    exact_clonotypes: vec![ExactSubClonotype {
+                        cell_barcodes: barcodes,
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }
+            })
+            .collect::<Vec<_>>();
+        EncloneOutputs {
+            version: PROTO_VERSION.to_string(),
+            num_clonotypes: clonotypes.len() as u32,
+            clonotypes,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let proto = temp_path("round_trip.proto");
+        let index = temp_path("round_trip.index");
+        write_proto_and_index(test_outputs(), &proto, &index).unwrap();
+        let sequential = ClonotypeIter::from_file(&proto)
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(sequential, test_outputs().clonotypes);
+
+        // Read the clonotypes by index, out of order, and compare them to the sequential read.
+
+        let mut reader = ProtoIndexReader::open(&proto, &index).unwrap();
+        assert_eq!(reader.num_clonotypes(), 5);
+        assert_eq!(reader.header().num_clonotypes, 5);
+        assert!(reader.header().clonotypes.is_empty());
+        for k in [3, 0, 4, 1, 1, 2] {
+            assert_eq!(reader.read_clonotype(k).unwrap(), sequential[k]);
+        }
+        assert!(matches!(
+            reader.read_clonotype(5),
+            Err(Error::ClonotypeOutOfRange {
+                index: 5,
+                num_clonotypes: 5
+            })
+        ));
+
+        // Look up clonotypes by barcode.
+
+        let found = reader.read_clonotypes_with_barcode("shared-1").unwrap();
+        assert_eq!(found, vec![sequential[1].clone(), sequential[3].clone()]);
+        let found = reader.read_clonotypes_with_barcode("cell2-1-1").unwrap();
+        assert_eq!(found, vec![sequential[2].clone()]);
+        assert!(reader
+            .read_clonotypes_with_barcode("missing-1")
+            .unwrap()
+            .is_empty());
+
+        // An index built from the proto file is the same as the one written with it.
+
+        assert_eq!(
+            build_proto_index(&proto).unwrap(),
+            read_proto_index(&index).unwrap()
+        );
+        remove_file(&proto).unwrap();
+        remove_file(&index).unwrap();
+    }
+
+    #[test]
+    fn test_stale_index() {
+        let proto = temp_path("stale.proto");
+        let index = temp_path("stale.index");
+        write_proto_and_index(test_outputs(), &proto, &index).unwrap();
+        let mut outputs = test_outputs();
+        outputs.clonotypes.pop();
+        outputs.num_clonotypes -= 1;
+        write_proto(outputs, &proto).unwrap();
+        assert!(matches!(
+            ProtoIndexReader::open(&proto, &index),
+            Err(Error::StaleIndex { .. })
+        ));
+        remove_file(&proto).unwrap();
+        remove_file(&index).unwrap();
+    }
+}
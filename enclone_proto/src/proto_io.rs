@@ -19,11 +19,14 @@
 //! - `Length` is an unsigned 32 bit integer stored in **Big endian** order.
 //! - If there are multiple messages, they are stored consecutively following the same format.
 
-use crate::types::{Clonotype, EncloneOutputs};
+use crate::proto_index::{write_proto_index, ProtoIndex, ProtoIndexBuilder};
+use crate::types::{Clonotype, DonorReference, EncloneOutputs, Metadata, UniversalReference};
+use crate::validate::is_supported_version;
+use crate::PROTO_VERSION;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use prost::Message;
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Read, Write};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::Path;
 
 const BUFFER_CAPACITY: usize = 1_000_000;
@@ -41,6 +44,33 @@ pub enum Error {
 
     #[error(transparent)]
     ProtoEncode(#[from] prost::EncodeError),
+
+    #[error("The index does not match the proto file: expected {expected}, got {got}")]
+    StaleIndex { expected: u64, got: u64 },
+
+    #[error("Requested clonotype {index}, but there are only {num_clonotypes} clonotypes")]
+    ClonotypeOutOfRange { index: usize, num_clonotypes: usize },
+
+    #[error(
+        "Unsupported proto version \"{version}\", this code reads versions having the same major \
+        version as {supported}"
+    )]
+    UnsupportedVersion {
+        version: String,
+        supported: &'static str,
+    },
+}
+
+/// Check that a file having this version can be read by this code.
+fn check_version(version: &str) -> Result<(), Error> {
+    if is_supported_version(version) {
+        Ok(())
+    } else {
+        Err(Error::UnsupportedVersion {
+            version: version.to_string(),
+            supported: PROTO_VERSION,
+        })
+    }
 }
 
 /// A helper struct to write a length delimited protobuf encoded message into the inner `writer`.
@@ -84,6 +114,8 @@ impl<W: Write> ProtoWriter<W> {
 pub struct ProtoReader<R: Read> {
     decode_buffer: Vec<u8>,
     reader: R,
+    // Number of bytes consumed from the reader
+    position: u64,
 }
 
 impl<R: Read> ProtoReader<R> {
@@ -91,8 +123,14 @@ impl<R: Read> ProtoReader<R> {
         ProtoReader {
             decode_buffer: Vec::with_capacity(BUFFER_CAPACITY),
             reader,
+            position: 0,
         }
     }
+    /// Number of bytes consumed from the underlying reader, which is the offset of the next
+    /// message if reading started at the beginning of the file
+    pub fn position(&self) -> u64 {
+        self.position
+    }
     // Clear the decode_buffer and fill it with `num_bytes` bytes from the reader
     fn read_exact(&mut self, num_bytes: usize) -> Result<(), Error> {
         self.decode_buffer.clear();
@@ -100,6 +138,7 @@ impl<R: Read> ProtoReader<R> {
             .by_ref()
             .take(num_bytes as u64)
             .read_to_end(&mut self.decode_buffer)?;
+        self.position += self.decode_buffer.len() as u64;
         // If we did not get num_bytes bytes, return an error
         if self.decode_buffer.len() != num_bytes {
             return Err(Error::Truncated {
@@ -131,6 +170,15 @@ impl<R: Read> ProtoReader<R> {
     }
 }
 
+impl<R: Read + Seek> ProtoReader<R> {
+    /// Move to the given byte offset, which should be the start of a message
+    pub fn seek(&mut self, offset: u64) -> Result<(), Error> {
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.position = offset;
+        Ok(())
+    }
+}
+
 /// The enclone outputs are stored in the protobuf file as follows:
 /// ```text
 /// +------------+----------------------------+
@@ -160,23 +208,156 @@ impl<R: Read> ProtoReader<R> {
 /// ```
 /// The newlines are only showed for illustration
 pub fn write_proto(enclone_outputs: EncloneOutputs, path: impl AsRef<Path>) -> Result<(), Error> {
+    write_proto_core(enclone_outputs, path, None)?;
+    Ok(())
+}
+
+/// Same as `write_proto`, but also write an index to `index_path`, see `proto_index`.
+pub fn write_proto_and_index(
+    enclone_outputs: EncloneOutputs,
+    path: impl AsRef<Path>,
+    index_path: impl AsRef<Path>,
+) -> Result<(), Error> {
+    let mut index = ProtoIndexBuilder::default();
+    let proto_len = write_proto_core(enclone_outputs, path, Some(&mut index))?;
+    write_proto_index(index.finish(proto_len), index_path)
+}
+
+// Write the proto file, returning its length.
+fn write_proto_core(
+    enclone_outputs: EncloneOutputs,
+    path: impl AsRef<Path>,
+    mut index: Option<&mut ProtoIndexBuilder>,
+) -> Result<u64, Error> {
     let writer = BufWriter::new(File::create(path)?);
     let mut proto_writer = ProtoWriter::with_writer(writer);
+    let mut pos = 0;
 
     // Write the version
-    proto_writer.encode_and_write(enclone_outputs.version)?;
+    pos += proto_writer.encode_and_write(enclone_outputs.version)?;
     // Write the metadata
-    proto_writer.encode_and_write(enclone_outputs.metadata)?;
+    pos += proto_writer.encode_and_write(enclone_outputs.metadata)?;
     // Write the universal reference
-    proto_writer.encode_and_write(enclone_outputs.universal_reference)?;
+    pos += proto_writer.encode_and_write(enclone_outputs.universal_reference)?;
     // Write the donor reference
-    proto_writer.encode_and_write(enclone_outputs.donor_reference)?;
+    pos += proto_writer.encode_and_write(enclone_outputs.donor_reference)?;
     // Write the number of clonotypes. Not bothering to write this raw
-    proto_writer.encode_and_write(enclone_outputs.clonotypes.len() as u32)?;
+    pos += proto_writer.encode_and_write(enclone_outputs.clonotypes.len() as u32)?;
     for cl in enclone_outputs.clonotypes {
-        proto_writer.encode_and_write(cl)?;
+        if let Some(index) = index.as_mut() {
+            index.add(pos as u64, &cl);
+        }
+        pos += proto_writer.encode_and_write(cl)?;
+    }
+    proto_writer.finish().flush()?;
+    Ok(pos as u64)
+}
+
+/// Number of bytes used for the number of clonotypes by `StreamingProtoWriter`: a four byte
+/// length, a one byte field key and a varint padded to five bytes.
+const PADDED_COUNT_LEN: usize = 10;
+
+/// Encode the number of clonotypes as a `u32` message whose varint is padded to a fixed width,
+/// so that it can be overwritten in place once the final count is known.  Protobuf decoders
+/// accept non-minimal varints, so this is read back by `ProtoReader` like any other `u32`.
+fn padded_count(num_clonotypes: u32) -> [u8; PADDED_COUNT_LEN] {
+    let mut buf = [0_u8; PADDED_COUNT_LEN];
+    buf[..4].copy_from_slice(&6_u32.to_be_bytes());
+    buf[4] = 0x08; // field 1, varint
+    let mut n = num_clonotypes;
+    for b in buf[5..].iter_mut() {
+        *b = (n & 0x7f) as u8 | 0x80;
+        n >>= 7;
+    }
+    buf[PADDED_COUNT_LEN - 1] &= 0x7f;
+    buf
+}
+
+/// Writes the same layout as `write_proto`, but one clonotype at a time, so that the caller
+/// need not hold all clonotypes in memory.  The number of clonotypes is written as a fixed
+/// width placeholder, and back-patched by `finish`.  The output can be read by `read_proto`
+/// and `ClonotypeIter`.
+pub struct StreamingProtoWriter<W: Write + Seek> {
+    proto_writer: ProtoWriter<W>,
+    count_pos: u64,
+    num_clonotypes: u32,
+    // Number of bytes written so far
+    pos: u64,
+    index: Option<ProtoIndexBuilder>,
+}
+
+impl StreamingProtoWriter<BufWriter<File>> {
+    pub fn create(
+        path: impl AsRef<Path>,
+        version: String,
+        metadata: Metadata,
+        universal_reference: UniversalReference,
+        donor_reference: DonorReference,
+    ) -> Result<Self, Error> {
+        StreamingProtoWriter::with_writer(
+            BufWriter::new(File::create(path)?),
+            version,
+            metadata,
+            universal_reference,
+            donor_reference,
+        )
+    }
+}
+
+impl<W: Write + Seek> StreamingProtoWriter<W> {
+    pub fn with_writer(
+        writer: W,
+        version: String,
+        metadata: Metadata,
+        universal_reference: UniversalReference,
+        donor_reference: DonorReference,
+    ) -> Result<Self, Error> {
+        let mut proto_writer = ProtoWriter::with_writer(writer);
+        proto_writer.encode_and_write(version)?;
+        proto_writer.encode_and_write(metadata)?;
+        proto_writer.encode_and_write(universal_reference)?;
+        proto_writer.encode_and_write(donor_reference)?;
+        let count_pos = proto_writer.writer.stream_position()?;
+        proto_writer.writer.write_all(&padded_count(0))?;
+        Ok(StreamingProtoWriter {
+            proto_writer,
+            count_pos,
+            num_clonotypes: 0,
+            pos: count_pos + PADDED_COUNT_LEN as u64,
+            index: None,
+        })
+    }
+
+    /// Also build an index of the clonotypes, which is returned by `finish`.
+    pub fn with_index(mut self) -> Self {
+        self.index = Some(ProtoIndexBuilder::default());
+        self
+    }
+
+    pub fn write_clonotype(&mut self, clonotype: Clonotype) -> Result<(), Error> {
+        if let Some(index) = self.index.as_mut() {
+            index.add(self.pos, &clonotype);
+        }
+        self.pos += self.proto_writer.encode_and_write(clonotype)? as u64;
+        self.num_clonotypes += 1;
+        Ok(())
+    }
+
+    pub fn num_clonotypes(&self) -> u32 {
+        self.num_clonotypes
+    }
+
+    /// Back-patch the number of clonotypes, flush, and return the inner writer, together with
+    /// the index if one was requested.
+    pub fn finish(self) -> Result<(W, Option<ProtoIndex>), Error> {
+        let mut writer = self.proto_writer.finish();
+        let end = writer.stream_position()?;
+        writer.seek(SeekFrom::Start(self.count_pos))?;
+        writer.write_all(&padded_count(self.num_clonotypes))?;
+        writer.seek(SeekFrom::Start(end))?;
+        writer.flush()?;
+        Ok((writer, self.index.map(|index| index.finish(self.pos))))
     }
-    Ok(())
 }
 
 /// A read that mirrors the write above. The fields until the list of clonotypes are read here.
@@ -188,7 +369,8 @@ pub fn read_proto_until_clonotypes(
     let mut proto_reader = ProtoReader::from_reader(reader);
 
     // Read the version
-    let version = proto_reader.read_and_decode()?;
+    let version: String = proto_reader.read_and_decode()?;
+    check_version(&version)?;
     // Read the metadata
     let metadata = proto_reader.read_and_decode()?;
     // Read the universal reference
@@ -239,8 +421,10 @@ impl ClonotypeIter<BufReader<File>> {
 impl<R: Read> ClonotypeIter<R> {
     pub fn from_reader(reader: R) -> Result<Self, Error> {
         let mut proto_reader = ProtoReader::from_reader(reader);
-        // Skip version, metadata, universal reference, donor reference
-        for _ in 0..4 {
+        let version: String = proto_reader.read_and_decode()?;
+        check_version(&version)?;
+        // Skip metadata, universal reference, donor reference
+        for _ in 0..3 {
             proto_reader.skip()?;
         }
         let num_clonotypes: u32 = proto_reader.read_and_decode()?;
@@ -268,3 +452,117 @@ impl<R: Read> Iterator for ClonotypeIter<R> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_padded_count() {
+        for n in [0, 1, 127, 128, 300, 1 << 20, u32::MAX] {
+            let buf = padded_count(n);
+            let mut reader = ProtoReader::from_reader(&buf[..]);
+            let m: u32 = reader.read_and_decode().unwrap();
+            assert_eq!(m, n);
+        }
+    }
+
+    #[test]
+    fn test_streaming_writer() {
+        let mut writer = StreamingProtoWriter::with_writer(
+            Cursor::new(Vec::new()),
+            "1.0.0".to_string(),
+            Metadata::default(),
+            UniversalReference::default(),
+            DonorReference::default(),
+        )
+        .unwrap();
+        for _ in 0..3 {
+            writer.write_clonotype(Clonotype::default()).unwrap();
+        }
+        let bytes = writer.finish().unwrap().0.into_inner();
+        let iter = ClonotypeIter::from_reader(&bytes[..]).unwrap();
+        assert_eq!(iter.count(), 3);
+    }
+
+    #[test]
+    fn test_streaming_index() {
+        let mut writer = StreamingProtoWriter::with_writer(
+            Cursor::new(Vec::new()),
+            "1.0.0".to_string(),
+            Metadata::default(),
+            UniversalReference::default(),
+            DonorReference::default(),
+        )
+        .unwrap()
+        .with_index();
+        for n in 1..=3 {
+            writer
+                .write_clonotype(Clonotype {
+                    frequency: n,
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        let (cursor, index) = writer.finish().unwrap();
+        let index = index.unwrap();
+        assert_eq!(index.proto_len, cursor.get_ref().len() as u64);
+        let mut reader = ProtoReader::from_reader(cursor);
+        for n in [2, 0, 1] {
+            reader.seek(index.offsets[n]).unwrap();
+            let cl: Clonotype = reader.read_and_decode().unwrap();
+            assert_eq!(cl.frequency, n as u32 + 1);
+        }
+    }
+
+    // Files having the same major version are read, whatever their minor version.
+
+    #[test]
+    fn test_version_on_read() {
+        for (version, ok) in [
+            ("1.0.0", true),
+            (PROTO_VERSION, true),
+            ("1.99.3", true),
+            ("2.0.0", false),
+            ("0.1.0", false),
+            ("latest", false),
+        ] {
+            let mut writer = StreamingProtoWriter::with_writer(
+                Cursor::new(Vec::new()),
+                version.to_string(),
+                Metadata::default(),
+                UniversalReference::default(),
+                DonorReference::default(),
+            )
+            .unwrap();
+            writer.write_clonotype(Clonotype::default()).unwrap();
+            let bytes = writer.finish().unwrap().0.into_inner();
+            match ClonotypeIter::from_reader(&bytes[..]) {
+                Ok(iter) => {
+                    assert!(ok, "{version}");
+                    assert_eq!(iter.count(), 1);
+                }
+                Err(Error::UnsupportedVersion { version: v, .. }) => {
+                    assert!(!ok, "{version}");
+                    assert_eq!(v, version);
+                }
+                Err(e) => panic!("{e}"),
+            }
+        }
+    }
+
+    // A clonotype written by a newer minor version, having a field that this code does not
+    // know about, is decoded with that field ignored.
+
+    #[test]
+    fn test_unknown_field() {
+        let cl = Clonotype {
+            frequency: 7,
+            ..Default::default()
+        };
+        let mut bytes = cl.encode_to_vec();
+        bytes.extend([0xa0, 0x06, 0x01]); // field 100, varint 1
+        assert_eq!(Clonotype::decode(&bytes[..]).unwrap(), cl);
+    }
+}
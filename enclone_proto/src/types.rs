@@ -9,6 +9,21 @@ use vdj_ann::annotate::Region as AnnRegion;
 
 include!("./enclone.types.rs");
 
+// Serialization of the bytes fields, which hold DNA and amino acid sequences, as strings rather
+// than as arrays of numbers.  This is set for those fields in build_enclone_proto.
+
+pub(crate) mod bytes_as_string {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&String::from_utf8_lossy(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        Ok(String::deserialize(deserializer)?.into_bytes())
+    }
+}
+
 impl From<&bio_edit::alignment::Alignment> for Alignment {
     fn from(al: &bio_edit::alignment::Alignment) -> Self {
         Alignment {
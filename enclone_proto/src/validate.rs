@@ -0,0 +1,511 @@
+// Copyright (c) 2023 10X Genomics, Inc. All rights reserved.
+
+//!
+//! Checks of the invariants that are described in `types.proto`, but which the protobuf
+//! encoding itself cannot enforce, and of the version of a file, and upgrading of files written
+//! by older versions of enclone.
+//!
+
+use crate::types::{Clonotype, ClonotypeChain, EncloneOutputs, ExactSubClonotypeChain, Region};
+use crate::PROTO_VERSION;
+use std::fmt;
+
+/// Parse a version string of the form major.minor.patch.
+pub fn parse_version(version: &str) -> Option<(u32, u32, u32)> {
+    let mut fields = version.split('.').map(str::parse::<u32>);
+    match (fields.next(), fields.next(), fields.next(), fields.next()) {
+        (Some(Ok(major)), Some(Ok(minor)), Some(Ok(patch)), None) => Some((major, minor, patch)),
+        _ => None,
+    }
+}
+
+/// True if a file having this version can be read by this code.  Within a major version, fields
+/// are only ever appended (see types.proto), so a file written by an older version decodes with
+/// the fields it lacks unset, and a file written by a newer version decodes with the fields this
+/// code does not know about ignored.
+pub fn is_supported_version(version: &str) -> bool {
+    match parse_version(version) {
+        Some(v) => v.0 == parse_version(PROTO_VERSION).unwrap().0,
+        None => false,
+    }
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+/// Where in `EncloneOutputs` a violation was found.  Unset fields mean that the violation is not
+/// specific to a clonotype, exact subclonotype or chain.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Location {
+    pub clonotype: Option<usize>,
+    pub exact_clonotype: Option<usize>,
+    pub chain: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Violation {
+    pub location: Location,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let loc = &self.location;
+        if let Some(i) = loc.clonotype {
+            write!(f, "clonotype {i}")?;
+            if let Some(j) = loc.exact_clonotype {
+                write!(f, ", exact subclonotype {j}")?;
+            }
+            if let Some(k) = loc.chain {
+                write!(f, ", chain {k}")?;
+            }
+            write!(f, ": ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+struct Checker<'a> {
+    outputs: &'a EncloneOutputs,
+    location: Location,
+    violations: Vec<Violation>,
+}
+
+impl<'a> Checker<'a> {
+    fn fail(&mut self, message: String) {
+        self.violations.push(Violation {
+            location: self.location.clone(),
+            message,
+        });
+    }
+
+    fn check(&mut self, cond: bool, message: impl FnOnce() -> String) {
+        if !cond {
+            self.fail(message());
+        }
+    }
+
+    // Check that idx points at a universal reference item of the given region.
+
+    fn check_uref(&mut self, name: &str, idx: u32, region: Region) {
+        let items = &self.outputs.universal_reference.items;
+        match items.get(idx as usize) {
+            None => self.fail(format!(
+                "{name} = {idx} is out of range, as the universal reference has {} items",
+                items.len()
+            )),
+            Some(item) => {
+                if item.region != region as i32 {
+                    self.fail(format!(
+                        "{name} = {idx} points at {}, which is not a {region:?} region",
+                        item.display_name
+                    ));
+                }
+            }
+        }
+    }
+
+    // Check that idx points at a donor reference item of the given region, derived from the
+    // given universal reference item.
+
+    fn check_dref(&mut self, name: &str, idx: u32, region: Region, universal_idx: u32) {
+        let items = &self.outputs.donor_reference.items;
+        match items.get(idx as usize) {
+            None => self.fail(format!(
+                "{name} = {idx} is out of range, as the donor reference has {} items",
+                items.len()
+            )),
+            Some(item) => {
+                if item.region != region as i32 {
+                    self.fail(format!(
+                        "{name} = {idx} points at {}, which is not a {region:?} region",
+                        item.display_name
+                    ));
+                }
+                if item.universal_idx != universal_idx {
+                    self.fail(format!(
+                        "{name} = {idx} is derived from universal reference item {}, \
+                         but the chain uses {universal_idx}",
+                        item.universal_idx
+                    ));
+                }
+            }
+        }
+    }
+
+    fn check_header(&mut self) {
+        let outputs = self.outputs;
+        self.check(is_supported_version(&outputs.version), || {
+            format!("unsupported version \"{}\"", outputs.version)
+        });
+        for item in &outputs.universal_reference.items {
+            self.check(Region::from_i32(item.region).is_some(), || {
+                format!(
+                    "universal reference item {} has no region",
+                    item.display_name
+                )
+            });
+        }
+        let ndonors = outputs.metadata.donors.len();
+        for (i, item) in outputs.donor_reference.items.iter().enumerate() {
+            self.check(
+                item.region == Region::V as i32 || item.region == Region::J as i32,
+                || format!("donor reference item {i} is not a V or J region"),
+            );
+            self.check(
+                (item.universal_idx as usize) < outputs.universal_reference.items.len(),
+                || format!("donor reference item {i} has universal_idx out of range"),
+            );
+            self.check(ndonors == 0 || (item.donor_idx as usize) < ndonors, || {
+                format!("donor reference item {i} has donor_idx out of range")
+            });
+        }
+        if !outputs.clonotypes.is_empty() || outputs.num_clonotypes == 0 {
+            self.check(
+                outputs.num_clonotypes as usize == outputs.clonotypes.len(),
+                || {
+                    format!(
+                        "num_clonotypes = {}, but there are {} clonotypes",
+                        outputs.num_clonotypes,
+                        outputs.clonotypes.len()
+                    )
+                },
+            );
+        }
+    }
+
+    fn check_clonotype_chain(&mut self, chain: &ClonotypeChain) {
+        if let Some(u) = chain.u_idx {
+            self.check_uref("u_idx", u, Region::U);
+        }
+        self.check_uref("v_idx", chain.v_idx, Region::V);
+        if let Some(d) = chain.d_idx {
+            self.check_uref("d_idx", d, Region::D);
+        }
+        self.check_uref("j_idx", chain.j_idx, Region::J);
+        if let Some(c) = chain.c_idx {
+            self.check_uref("c_idx", c, Region::C);
+        }
+        if let Some(dv) = chain.donor_v_idx {
+            self.check_dref("donor_v_idx", dv, Region::V, chain.v_idx);
+        }
+        if let Some(dj) = chain.donor_j_idx {
+            self.check_dref("donor_j_idx", dj, Region::J, chain.j_idx);
+        }
+        self.check_bounds(
+            chain.v_start,
+            chain.cdr3_start,
+            chain.cdr3_end,
+            chain.j_end,
+            chain.nt_sequence.len(),
+        );
+    }
+
+    fn check_exact_chain(&mut self, chain: &ExactSubClonotypeChain, nbarcodes: usize) {
+        for (name, n) in [
+            ("umi_counts", chain.umi_counts.len()),
+            ("read_counts", chain.read_counts.len()),
+            ("contig_ids", chain.contig_ids.len()),
+        ] {
+            self.check(n == nbarcodes, || {
+                format!("{name} has length {n}, but there are {nbarcodes} cell barcodes")
+            });
+        }
+        if let Some(c) = chain.c_region_idx {
+            self.check_uref("c_region_idx", c, Region::C);
+        }
+        self.check_bounds(
+            chain.v_start,
+            chain.cdr3_start,
+            chain.cdr3_end,
+            chain.j_end,
+            chain.nt_sequence.len(),
+        );
+    }
+
+    fn check_bounds(
+        &mut self,
+        v_start: u32,
+        cdr3_start: u32,
+        cdr3_end: u32,
+        j_end: u32,
+        len: usize,
+    ) {
+        self.check(
+            v_start <= cdr3_start && cdr3_start < cdr3_end && cdr3_end <= j_end,
+            || {
+                format!(
+                    "require v_start <= cdr3_start < cdr3_end <= j_end, but have \
+                     {v_start}, {cdr3_start}, {cdr3_end}, {j_end}"
+                )
+            },
+        );
+        self.check(j_end as usize <= len, || {
+            format!("j_end = {j_end} exceeds the sequence length {len}")
+        });
+    }
+
+    fn check_clonotype(&mut self, i: usize, clonotype: &Clonotype) {
+        self.location = Location {
+            clonotype: Some(i),
+            ..Default::default()
+        };
+        for (k, chain) in clonotype.chains.iter().enumerate() {
+            self.location.chain = Some(k);
+            self.check_clonotype_chain(chain);
+        }
+        self.location.chain = None;
+        let mut ncells = 0;
+        for (j, ex) in clonotype.exact_clonotypes.iter().enumerate() {
+            self.location.exact_clonotype = Some(j);
+            self.location.chain = None;
+            let nbarcodes = ex.cell_barcodes.len();
+            ncells += nbarcodes;
+            self.check(nbarcodes > 0, || "no cell barcodes".to_string());
+            let mut seen = vec![false; clonotype.chains.len()];
+            for info in &ex.chains {
+                let k = info.index as usize;
+                self.location.chain = Some(k);
+                if k >= clonotype.chains.len() {
+                    self.fail(format!(
+                        "chain index {k} is out of range, as the clonotype has {} chains",
+                        clonotype.chains.len()
+                    ));
+                    continue;
+                }
+                self.check(!seen[k], || "chain index appears twice".to_string());
+                seen[k] = true;
+                self.check_exact_chain(&info.chain, nbarcodes);
+            }
+        }
+        self.location.exact_clonotype = None;
+        self.location.chain = None;
+        self.check(clonotype.frequency as usize == ncells, || {
+            format!(
+                "frequency = {}, but there are {ncells} cell barcodes",
+                clonotype.frequency
+            )
+        });
+    }
+}
+
+/// Check the invariants of `EncloneOutputs` that are described in `types.proto`.  If the
+/// clonotypes have not been loaded (for example, if `outputs` came from
+/// `read_proto_until_clonotypes`), only the other fields are checked.
+pub fn validate(outputs: &EncloneOutputs) -> Vec<Violation> {
+    let mut checker = Checker {
+        outputs,
+        location: Location::default(),
+        violations: Vec::new(),
+    };
+    checker.check_header();
+    for (i, clonotype) in outputs.clonotypes.iter().enumerate() {
+        checker.check_clonotype(i, clonotype);
+    }
+    checker.violations
+}
+
+/// Check a single clonotype, which is clonotype `i` in the file whose other fields are in
+/// `header`.  This allows validation while streaming through a file.
+pub fn validate_clonotype(
+    header: &EncloneOutputs,
+    i: usize,
+    clonotype: &Clonotype,
+) -> Vec<Violation> {
+    let mut checker = Checker {
+        outputs: header,
+        location: Location::default(),
+        violations: Vec::new(),
+    };
+    checker.check_clonotype(i, clonotype);
+    checker.violations
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+/// Bring data written by an older version of enclone having the same major version up to
+/// `PROTO_VERSION`.  The fields added since 1.0.0 (`Clonotype.tree` in 1.1.0 and
+/// `Clonotype.group_id` in 1.2.0) are optional, and absent in older files because they were not
+/// computed, so upgrading leaves them unset, and only restamps the version.  Files having a
+/// different major version, or written by a newer version, are refused.
+pub fn upgrade(mut outputs: EncloneOutputs) -> Result<EncloneOutputs, String> {
+    let current = parse_version(PROTO_VERSION).unwrap();
+    match parse_version(&outputs.version) {
+        Some(v) if v.0 == current.0 && v <= current => {}
+        _ => {
+            return Err(format!(
+                "cannot upgrade from version \"{}\" to {PROTO_VERSION}, which requires the same \
+                 major version and no newer version",
+                outputs.version
+            ))
+        }
+    }
+    outputs.version = PROTO_VERSION.to_string();
+    outputs.num_clonotypes = outputs.clonotypes.len() as u32;
+    Ok(outputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{
+        ExactSubClonotype, ExactSubClonotypeChainInfo, UniversalReference, UniversalReferenceItem,
+    };
+
+    #[test]
+    fn test_versions() {
+        assert_eq!(parse_version("1.0.0"), Some((1, 0, 0)));
+        assert_eq!(parse_version("1.0"), None);
+        assert!(is_supported_version(PROTO_VERSION));
+        assert!(is_supported_version("1.0.0"));
+        assert!(is_supported_version("1.1.7"));
+        assert!(is_supported_version("1.1000.0"));
+        assert!(!is_supported_version("0.9.0"));
+        assert!(!is_supported_version("2.0.0"));
+        assert!(!is_supported_version("1000.0.0"));
+        assert!(!is_supported_version("1.2"));
+        assert!(!is_supported_version(""));
+    }
+
+    #[test]
+    fn test_frequency() {
+        let outputs = EncloneOutputs {
+            version: PROTO_VERSION.to_string(),
+            num_clonotypes: 1,
+            clonotypes: vec![Clonotype {
+                frequency: 1,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let violations = validate(&outputs);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].location.clonotype, Some(0));
+    }
+
+    // A file having one clonotype, with one chain and one exact subclonotype having one cell,
+    // which satisfies all the invariants.
+
+    fn valid() -> EncloneOutputs {
+        let item = |region: Region, name: &str| UniversalReferenceItem {
+            display_name: name.to_string(),
+            region: region as i32,
+            ..Default::default()
+        };
+        let chain = ExactSubClonotypeChain {
+            nt_sequence: vec![b'A'; 40],
+            v_start: 0,
+            cdr3_start: 10,
+            cdr3_end: 20,
+            j_end: 30,
+            umi_counts: vec![1],
+            read_counts: vec![2],
+            contig_ids: vec!["AAAC-1_contig_1".to_string()],
+            ..Default::default()
+        };
+        EncloneOutputs {
+            version: PROTO_VERSION.to_string(),
+            universal_reference: UniversalReference {
+                items: vec![item(Region::V, "IGHV1"), item(Region::J, "IGHJ1")],
+            },
+            num_clonotypes: 1,
+            clonotypes: vec![Clonotype {
+                chains: vec![ClonotypeChain {
+                    nt_sequence: vec![b'A'; 40],
+                    v_idx: 0,
+                    j_idx: 1,
+                    v_start: 0,
+                    cdr3_start: 10,
+                    cdr3_end: 20,
+                    j_end: 30,
+                    ..Default::default()
+                }],
+                exact_clonotypes: vec![ExactSubClonotype {
+                    chains: vec![ExactSubClonotypeChainInfo { index: 0, chain }],
+                    cell_barcodes: vec!["AAAC-1".to_string()],
+                    ..Default::default()
+                }],
+                frequency: 1,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    // The messages of the violations, after changing a valid file.
+
+    fn violations(change: impl FnOnce(&mut EncloneOutputs)) -> Vec<String> {
+        let mut outputs = valid();
+        change(&mut outputs);
+        validate(&outputs).into_iter().map(|v| v.message).collect()
+    }
+
+    #[test]
+    fn test_valid() {
+        assert_eq!(violations(|_| ()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_v_idx_region() {
+        let v = violations(|o| o.clonotypes[0].chains[0].v_idx = 1);
+        assert_eq!(v, ["v_idx = 1 points at IGHJ1, which is not a V region"]);
+        let v = violations(|o| o.clonotypes[0].chains[0].v_idx = 2);
+        assert_eq!(
+            v,
+            ["v_idx = 2 is out of range, as the universal reference has 2 items"]
+        );
+    }
+
+    #[test]
+    fn test_bounds() {
+        let msg = |a, b, c, d| {
+            format!(
+                "require v_start <= cdr3_start < cdr3_end <= j_end, but have \
+                 {a}, {b}, {c}, {d}"
+            )
+        };
+        let v = violations(|o| o.clonotypes[0].chains[0].cdr3_end = 10);
+        assert_eq!(v, [msg(0, 10, 10, 30)]);
+        let v = violations(|o| o.clonotypes[0].chains[0].j_end = 19);
+        assert_eq!(v, [msg(0, 10, 20, 19)]);
+        let v = violations(|o| o.clonotypes[0].exact_clonotypes[0].chains[0].chain.v_start = 11);
+        assert_eq!(v, [msg(11, 10, 20, 30)]);
+        let v = violations(|o| o.clonotypes[0].chains[0].j_end = 41);
+        assert_eq!(v, ["j_end = 41 exceeds the sequence length 40"]);
+    }
+
+    #[test]
+    fn test_per_cell_lengths() {
+        let v = violations(|o| {
+            let chain = &mut o.clonotypes[0].exact_clonotypes[0].chains[0].chain;
+            chain.umi_counts.push(1);
+            chain.read_counts.clear();
+            chain.contig_ids.push(String::new());
+        });
+        assert_eq!(
+            v,
+            [
+                "umi_counts has length 2, but there are 1 cell barcodes",
+                "read_counts has length 0, but there are 1 cell barcodes",
+                "contig_ids has length 2, but there are 1 cell barcodes",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_upgrade() {
+        for version in ["1.0.0", "1.1.0", PROTO_VERSION] {
+            let mut outputs = valid();
+            outputs.version = version.to_string();
+            outputs.num_clonotypes = 0;
+            let outputs = upgrade(outputs).unwrap();
+            assert_eq!(outputs.version, PROTO_VERSION);
+            assert_eq!(outputs.num_clonotypes, 1);
+            assert!(validate(&outputs).is_empty());
+        }
+        for version in ["0.9.0", "2.0.0", "1.99.0", "latest"] {
+            let mut outputs = valid();
+            outputs.version = version.to_string();
+            assert!(upgrade(outputs).is_err(), "{version}");
+        }
+    }
+}
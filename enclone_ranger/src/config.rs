@@ -0,0 +1,936 @@
+// Copyright (c) 2023 10X Genomics, Inc. All rights reserved.
+
+// Typed configuration for the cellranger entry point.
+//
+// A RangerConfig captures everything that cellranger is allowed to pass to enclone.  It is
+// validated up front, and then translated into the argument list understood by proc_args, so
+// that the argument handling code remains the single source of truth for how options are
+// applied to EncloneControl.  The old argv interface is retained via RangerConfig::from_args.
+
+use enclone_core::require_readable_file;
+use string_utils::TextUtils;
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+#[derive(Debug, thiserror::Error)]
+pub enum RangerError {
+    #[error("illegal argument {0} passed to main_enclone_ranger")]
+    IllegalArgument(String),
+    #[error("required argument {0} not passed to main_enclone_ranger")]
+    MissingArgument(&'static str),
+    #[error("argument {arg} passed to main_enclone_ranger has invalid value \"{value}\"")]
+    InvalidValue { arg: &'static str, value: String },
+    #[error("exactly one of BCR, TCR, TCRGD or META must be passed to main_enclone_ranger")]
+    AmbiguousInput,
+    #[error("{0}")]
+    UnreadableFile(String),
+    #[error("enclone setup failed: {0}")]
+    Setup(String),
+    #[error("enclone clonotyping failed: {0}")]
+    Start(String),
+    #[error("enclone output failed: {0}")]
+    Stop(String),
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+// The grouping arguments that may be passed.
+
+pub const GROUPING_ARGS: [&str; 16] = [
+    "AGROUP",
+    "AG_CENTER",
+    "AG_DIST_BOUND",
+    "AG_DIST_FORMULA",
+    "GROUP",
+    "GROUP_CDR3",
+    "GROUP_CDR3H_LEN_VAR",
+    "GROUP_DONOR",
+    "GROUP_NAIVE",
+    "GROUP_NO_NAIVE",
+    "GROUP_VDJ_REFNAME_HEAVY",
+    "GROUP_VJ_REFNAME",
+    "GROUP_VJ_REFNAME_HEAVY",
+    "GROUP_VJ_REFNAME_STRONG",
+    "MIN_GROUP",
+    "MIN_GROUP_DONORS",
+];
+
+// The plotting arguments that may be passed.
+
+pub const PLOT_ARGS: [&str; 12] = [
+    "HONEY",
+    "HONEY_IN",
+    "HONEY_OUT",
+    "LEGEND",
+    "PLOT",
+    "PLOT2",
+    "PLOTXY_EXACT",
+    "PLOT_BY_ISOTYPE",
+    "PLOT_BY_ISOTYPE_COLOR",
+    "PLOT_BY_MARK",
+    "SPLIT_PLOT_BY_DATASET",
+    "SPLIT_PLOT_BY_ORIGIN",
+];
+
+// The VDJ input.  The string is passed through verbatim, so it has the same syntax as the
+// corresponding command line argument.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RangerInput {
+    Bcr(String),
+    Tcr(String),
+    TcrGd(String),
+    Meta(String),
+}
+
+// Default filters that cellranger may turn off.  Each field is true if the filter is on, so
+// setting a field to false corresponds to passing the N* argument of the same name.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RangerFilters {
+    pub umi: bool,          // NUMI
+    pub umi_ratio: bool,    // NUMI_RATIO
+    pub graph: bool,        // NGRAPH_FILTER
+    pub weak_chains: bool,  // NWEAK_CHAINS
+    pub foursie_kill: bool, // NFOURSIE_KILL
+    pub doublet: bool,      // NDOUBLET
+    pub signature: bool,    // NSIG
+}
+
+impl Default for RangerFilters {
+    fn default() -> Self {
+        RangerFilters {
+            umi: true,
+            umi_ratio: true,
+            graph: true,
+            weak_chains: true,
+            foursie_kill: true,
+            doublet: true,
+            signature: true,
+        }
+    }
+}
+
+// Files that enclone writes.
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RangerOutputs {
+    pub proto: String,                         // PROTO
+    pub donor_ref_file: String,                // DONOR_REF_FILE
+    pub fate_file: Option<String>,             // FATE_FILE
+    pub fate_ledger: Option<String>,           // FATE_LEDGER
+    pub fate_summary: Option<String>,          // FATE_SUMMARY
+    pub filter_impact: Option<String>,         // FILTER_IMPACT
+    pub checkpoint: Option<String>,            // CHECKPOINT
+    pub incremental_report: Option<String>,    // INCREMENTAL_REPORT
+    pub join_audit: Option<String>,            // JOIN_AUDIT
+    pub donor_genotype_report: Option<String>, // DONOR_GENOTYPE_REPORT
+    pub airr_rearrangement: Option<String>,    // AIRR_REARRANGEMENT
+    pub airr_cell: Option<String>,             // AIRR_CELL
+    pub proto_index: Option<String>,           // PROTO_INDEX
+    pub stream_proto: bool,                    // PROTO_STREAM
+    pub tree: bool,                            // TREE
+    pub group_table: Option<String>,           // GROUP_TABLE
+    pub fasta: Option<String>,                 // FASTA
+    pub fasta_aa: Option<String>,              // FASTA_AA
+    pub clustal_dna: Option<String>,           // CLUSTAL_DNA
+    pub clustal_aa: Option<String>,            // CLUSTAL_AA
+    pub phylip_dna: Option<String>,            // PHYLIP_DNA
+    pub phylip_aa: Option<String>,             // PHYLIP_AA
+    pub pout: Option<String>,                  // POUT
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RangerConfig {
+    pub pre: Vec<String>,               // PRE
+    pub refname: String,                // REF
+    pub input: RangerInput,             // BCR, TCR, TCRGD or META
+    pub proto_metadata: Option<String>, // PROTO_METADATA
+    pub donor_genotype: Option<String>, // DONOR_GENOTYPE
+    pub donor_genotype_only: bool,      // DONOR_GENOTYPE_ONLY
+    pub incremental: Option<String>,    // INCREMENTAL
+    pub max_cores: usize,               // MAX_CORES
+    pub pretty: bool,                   // false = NOPRETTY
+    pub filters: RangerFilters,
+    pub split_max_chains: Option<usize>, // SPLIT_MAX_CHAINS
+    pub max_cdr3_indel: Option<usize>,   // MAX_CDR3_INDEL
+    pub grouping: Vec<String>,           // GROUP, AGROUP etc., verbatim (see GROUPING_ARGS)
+    pub pcols: Vec<String>,              // PCOLS
+    pub pcell: bool,                     // PCELL
+    pub plots: Vec<String>,              // HONEY, PLOT etc., verbatim (see PLOT_ARGS)
+    pub outputs: RangerOutputs,
+}
+
+impl RangerConfig {
+    pub fn builder(refname: &str, input: RangerInput) -> RangerConfigBuilder {
+        RangerConfigBuilder {
+            config: RangerConfig {
+                pre: Vec::new(),
+                refname: refname.to_string(),
+                input,
+                proto_metadata: None,
+                donor_genotype: None,
+                donor_genotype_only: false,
+                incremental: None,
+                max_cores: 0,
+                pretty: true,
+                filters: RangerFilters::default(),
+                split_max_chains: None,
+                max_cdr3_indel: None,
+                grouping: Vec::new(),
+                pcols: Vec::new(),
+                pcell: false,
+                plots: Vec::new(),
+                outputs: RangerOutputs::default(),
+            },
+        }
+    }
+
+    // Check the configuration, without running anything.
+
+    pub fn validate(&self) -> Result<(), RangerError> {
+        self.validate_core(true)
+    }
+
+    // Same, but for a run whose outputs are returned in memory, in which case PROTO is optional.
+
+    pub fn validate_in_memory(&self) -> Result<(), RangerError> {
+        self.validate_core(false)
+    }
+
+    fn validate_core(&self, require_proto: bool) -> Result<(), RangerError> {
+        if self.refname.is_empty() {
+            return Err(RangerError::MissingArgument("REF"));
+        }
+        require_readable_file(&self.refname, "REF").map_err(RangerError::UnreadableFile)?;
+        if let Some(meta) = &self.proto_metadata {
+            require_readable_file(meta, "PROTO_METADATA").map_err(RangerError::UnreadableFile)?;
+        }
+        if let Some(genotype) = &self.donor_genotype {
+            require_readable_file(genotype, "DONOR_GENOTYPE")
+                .map_err(RangerError::UnreadableFile)?;
+        } else if self.donor_genotype_only || self.outputs.donor_genotype_report.is_some() {
+            return Err(RangerError::MissingArgument("DONOR_GENOTYPE"));
+        }
+        if self.donor_genotype_only && self.outputs.donor_genotype_report.is_some() {
+            return Err(RangerError::InvalidValue {
+                arg: "DONOR_GENOTYPE_REPORT",
+                value: "DONOR_GENOTYPE_REPORT cannot be used with DONOR_GENOTYPE_ONLY".to_string(),
+            });
+        }
+        if let Some(previous) = &self.incremental {
+            require_readable_file(previous, "INCREMENTAL").map_err(RangerError::UnreadableFile)?;
+        } else if self.outputs.incremental_report.is_some() {
+            return Err(RangerError::MissingArgument("INCREMENTAL"));
+        }
+        let (arg, val) = self.input_arg();
+        if val.is_empty() {
+            return Err(RangerError::MissingArgument(arg));
+        }
+        if let RangerInput::Meta(meta) = &self.input {
+            require_readable_file(meta, "META").map_err(RangerError::UnreadableFile)?;
+        }
+        if self.max_cores == 0 {
+            return Err(RangerError::MissingArgument("MAX_CORES"));
+        }
+        if require_proto && self.outputs.proto.is_empty() {
+            return Err(RangerError::MissingArgument("PROTO"));
+        }
+        if self.outputs.donor_ref_file.is_empty() {
+            return Err(RangerError::MissingArgument("DONOR_REF_FILE"));
+        }
+        for (arg, path) in [
+            ("FATE_FILE", &self.outputs.fate_file),
+            ("FATE_LEDGER", &self.outputs.fate_ledger),
+            ("FATE_SUMMARY", &self.outputs.fate_summary),
+            ("FILTER_IMPACT", &self.outputs.filter_impact),
+            ("CHECKPOINT", &self.outputs.checkpoint),
+            ("INCREMENTAL_REPORT", &self.outputs.incremental_report),
+            ("JOIN_AUDIT", &self.outputs.join_audit),
+            ("DONOR_GENOTYPE_REPORT", &self.outputs.donor_genotype_report),
+            ("AIRR_REARRANGEMENT", &self.outputs.airr_rearrangement),
+            ("AIRR_CELL", &self.outputs.airr_cell),
+            ("GROUP_TABLE", &self.outputs.group_table),
+            ("FASTA", &self.outputs.fasta),
+            ("FASTA_AA", &self.outputs.fasta_aa),
+            ("POUT", &self.outputs.pout),
+        ] {
+            if path.as_deref() == Some("") {
+                return Err(RangerError::InvalidValue {
+                    arg,
+                    value: String::new(),
+                });
+            }
+        }
+        for (arg, path) in [
+            ("CLUSTAL_DNA", &self.outputs.clustal_dna),
+            ("CLUSTAL_AA", &self.outputs.clustal_aa),
+            ("PHYLIP_DNA", &self.outputs.phylip_dna),
+            ("PHYLIP_AA", &self.outputs.phylip_aa),
+        ] {
+            if let Some(path) = path {
+                if !path.ends_with(".tar") {
+                    return Err(RangerError::InvalidValue {
+                        arg,
+                        value: path.clone(),
+                    });
+                }
+            }
+        }
+        if self.outputs.proto_index.is_some() && self.outputs.proto.is_empty() {
+            return Err(RangerError::MissingArgument("PROTO"));
+        }
+        for arg in &self.grouping {
+            let name = arg.split('=').next().unwrap();
+            if !GROUPING_ARGS.contains(&name) {
+                return Err(RangerError::IllegalArgument(name.to_string()));
+            }
+        }
+        if !self.grouping.is_empty() && self.outputs.stream_proto {
+            return Err(RangerError::InvalidValue {
+                arg: "PROTO_STREAM",
+                value: "PROTO_STREAM cannot be used with grouping".to_string(),
+            });
+        }
+        for arg in &self.plots {
+            let name = arg.split('=').next().unwrap();
+            if !PLOT_ARGS.contains(&name) {
+                return Err(RangerError::IllegalArgument(name.to_string()));
+            }
+        }
+        if (!self.pcols.is_empty() || self.pcell) && self.outputs.pout.is_none() {
+            return Err(RangerError::MissingArgument("POUT"));
+        }
+        if self.split_max_chains == Some(0) {
+            return Err(RangerError::InvalidValue {
+                arg: "SPLIT_MAX_CHAINS",
+                value: "0".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    fn input_arg(&self) -> (&'static str, &str) {
+        match &self.input {
+            RangerInput::Bcr(x) => ("BCR", x),
+            RangerInput::Tcr(x) => ("TCR", x),
+            RangerInput::TcrGd(x) => ("TCRGD", x),
+            RangerInput::Meta(x) => ("META", x),
+        }
+    }
+
+    // Translate into the argument list understood by proc_args.  The first entry is the
+    // program name, which is ignored.
+
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = vec![
+            "enclone".to_string(),
+            "CELLRANGER".to_string(),
+            "FORCE_EXTERNAL".to_string(),
+            "NOPAGER".to_string(),
+            "NOPRINT".to_string(),
+            format!("PRE={}", self.pre.join(",")),
+            format!("REF={}", self.refname),
+            format!("MAX_CORES={}", self.max_cores),
+            format!("DONOR_REF_FILE={}", self.outputs.donor_ref_file),
+        ];
+        if !self.outputs.proto.is_empty() {
+            args.push(format!("PROTO={}", self.outputs.proto));
+        }
+        let (arg, val) = self.input_arg();
+        args.push(format!("{arg}={val}"));
+        if let RangerInput::TcrGd(_) = self.input {
+            args.push("GAMMA_DELTA".to_string());
+        }
+        if let Some(meta) = &self.proto_metadata {
+            args.push(format!("PROTO_METADATA={meta}"));
+        }
+        if let Some(fate_file) = &self.outputs.fate_file {
+            args.push(format!("FATE_FILE={fate_file}"));
+        }
+        if let Some(ledger) = &self.outputs.fate_ledger {
+            args.push(format!("FATE_LEDGER={ledger}"));
+        }
+        if let Some(summary) = &self.outputs.fate_summary {
+            args.push(format!("FATE_SUMMARY={summary}"));
+        }
+        if let Some(impact) = &self.outputs.filter_impact {
+            args.push(format!("FILTER_IMPACT={impact}"));
+        }
+        if let Some(checkpoint) = &self.outputs.checkpoint {
+            args.push(format!("CHECKPOINT={checkpoint}"));
+        }
+        if let Some(previous) = &self.incremental {
+            args.push(format!("INCREMENTAL={previous}"));
+        }
+        if let Some(report) = &self.outputs.incremental_report {
+            args.push(format!("INCREMENTAL_REPORT={report}"));
+        }
+        if let Some(audit) = &self.outputs.join_audit {
+            args.push(format!("JOIN_AUDIT={audit}"));
+        }
+        if let Some(genotype) = &self.donor_genotype {
+            args.push(format!("DONOR_GENOTYPE={genotype}"));
+        }
+        if self.donor_genotype_only {
+            args.push("DONOR_GENOTYPE_ONLY".to_string());
+        }
+        if let Some(report) = &self.outputs.donor_genotype_report {
+            args.push(format!("DONOR_GENOTYPE_REPORT={report}"));
+        }
+        if let Some(airr) = &self.outputs.airr_rearrangement {
+            args.push(format!("AIRR_REARRANGEMENT={airr}"));
+        }
+        if let Some(airr) = &self.outputs.airr_cell {
+            args.push(format!("AIRR_CELL={airr}"));
+        }
+        if let Some(index) = &self.outputs.proto_index {
+            args.push(format!("PROTO_INDEX={index}"));
+        }
+        if self.outputs.stream_proto {
+            args.push("PROTO_STREAM".to_string());
+        }
+        if self.outputs.tree {
+            args.push("TREE".to_string());
+        }
+        if let Some(table) = &self.outputs.group_table {
+            args.push(format!("GROUP_TABLE={table}"));
+        }
+        args.extend(self.grouping.iter().cloned());
+        args.extend(self.plots.iter().cloned());
+        for (arg, path) in [
+            ("FASTA", &self.outputs.fasta),
+            ("FASTA_AA", &self.outputs.fasta_aa),
+            ("CLUSTAL_DNA", &self.outputs.clustal_dna),
+            ("CLUSTAL_AA", &self.outputs.clustal_aa),
+            ("PHYLIP_DNA", &self.outputs.phylip_dna),
+            ("PHYLIP_AA", &self.outputs.phylip_aa),
+            ("POUT", &self.outputs.pout),
+        ] {
+            if let Some(path) = path {
+                args.push(format!("{arg}={path}"));
+            }
+        }
+        if !self.pcols.is_empty() {
+            args.push(format!("PCOLS={}", self.pcols.join(",")));
+        }
+        if self.pcell {
+            args.push("PCELL".to_string());
+        }
+        if !self.pretty {
+            args.push("NOPRETTY".to_string());
+        }
+        let f = &self.filters;
+        for (on, arg) in [
+            (f.umi, "NUMI"),
+            (f.umi_ratio, "NUMI_RATIO"),
+            (f.graph, "NGRAPH_FILTER"),
+            (f.weak_chains, "NWEAK_CHAINS"),
+            (f.foursie_kill, "NFOURSIE_KILL"),
+            (f.doublet, "NDOUBLET"),
+            (f.signature, "NSIG"),
+        ] {
+            if !on {
+                args.push(arg.to_string());
+            }
+        }
+        if let Some(n) = self.split_max_chains {
+            args.push(format!("SPLIT_MAX_CHAINS={n}"));
+        }
+        if let Some(n) = self.max_cdr3_indel {
+            args.push(format!("MAX_CDR3_INDEL={n}"));
+        }
+        args
+    }
+
+    // Parse the legacy argument list.  As for proc_args, the first argument is ignored.  PROTO
+    // is not required here, so that the argument list of an in-memory configuration can be
+    // parsed, but it is required by validate.
+
+    pub fn from_args(args: &[String]) -> Result<RangerConfig, RangerError> {
+        const REQUIRED_ARGS: [&str; 8] = [
+            "CELLRANGER",
+            "DONOR_REF_FILE",
+            "FORCE_EXTERNAL",
+            "MAX_CORES",
+            "NOPAGER",
+            "NOPRINT",
+            "PRE",
+            "REF",
+        ];
+        let mut found = [false; REQUIRED_ARGS.len()];
+        let mut input = None;
+        let mut gamma_delta = false;
+        let mut config = RangerConfig::builder("", RangerInput::Bcr(String::new())).config;
+        for arg in args.iter().skip(1) {
+            let (name, val) = if arg.contains('=') {
+                (arg.before("="), arg.after("="))
+            } else {
+                (arg.as_str(), "")
+            };
+            if let Some(i) = REQUIRED_ARGS.iter().position(|&x| x == name) {
+                found[i] = true;
+            }
+            match name {
+                "CELLRANGER" | "FORCE_EXTERNAL" | "NOPAGER" | "NOPRINT" => {}
+                "PRE" if val.is_empty() => config.pre.clear(),
+                "PRE" => {
+                    config.pre = val.split(',').map(str::to_string).collect();
+                }
+                "REF" => config.refname = val.to_string(),
+                "PROTO" => config.outputs.proto = val.to_string(),
+                "DONOR_REF_FILE" => config.outputs.donor_ref_file = val.to_string(),
+                "FATE_FILE" => config.outputs.fate_file = Some(val.to_string()),
+                "FATE_LEDGER" => config.outputs.fate_ledger = Some(val.to_string()),
+                "FATE_SUMMARY" => config.outputs.fate_summary = Some(val.to_string()),
+                "FILTER_IMPACT" => config.outputs.filter_impact = Some(val.to_string()),
+                "CHECKPOINT" => config.outputs.checkpoint = Some(val.to_string()),
+                "INCREMENTAL" => config.incremental = Some(val.to_string()),
+                "INCREMENTAL_REPORT" => config.outputs.incremental_report = Some(val.to_string()),
+                "JOIN_AUDIT" => config.outputs.join_audit = Some(val.to_string()),
+                "DONOR_GENOTYPE" => config.donor_genotype = Some(val.to_string()),
+                "DONOR_GENOTYPE_ONLY" => config.donor_genotype_only = true,
+                "AIRR_REARRANGEMENT" => config.outputs.airr_rearrangement = Some(val.to_string()),
+                "AIRR_CELL" => config.outputs.airr_cell = Some(val.to_string()),
+                "DONOR_GENOTYPE_REPORT" => {
+                    config.outputs.donor_genotype_report = Some(val.to_string())
+                }
+                "PROTO_INDEX" => config.outputs.proto_index = Some(val.to_string()),
+                "PROTO_STREAM" => config.outputs.stream_proto = true,
+                "TREE" => config.outputs.tree = true,
+                "GROUP_TABLE" => config.outputs.group_table = Some(val.to_string()),
+                "FASTA" => config.outputs.fasta = Some(val.to_string()),
+                "FASTA_AA" => config.outputs.fasta_aa = Some(val.to_string()),
+                "CLUSTAL_DNA" => config.outputs.clustal_dna = Some(val.to_string()),
+                "CLUSTAL_AA" => config.outputs.clustal_aa = Some(val.to_string()),
+                "PHYLIP_DNA" => config.outputs.phylip_dna = Some(val.to_string()),
+                "PHYLIP_AA" => config.outputs.phylip_aa = Some(val.to_string()),
+                "POUT" => config.outputs.pout = Some(val.to_string()),
+                "PCOLS" => config.pcols = val.split(',').map(str::to_string).collect(),
+                "PCELL" => config.pcell = true,
+                _ if GROUPING_ARGS.contains(&name) => config.grouping.push(arg.clone()),
+                _ if PLOT_ARGS.contains(&name) => config.plots.push(arg.clone()),
+                "PROTO_METADATA" => config.proto_metadata = Some(val.to_string()),
+                "MAX_CORES" => {
+                    config.max_cores = val.parse().map_err(|_| RangerError::InvalidValue {
+                        arg: "MAX_CORES",
+                        value: val.to_string(),
+                    })?;
+                }
+                "SPLIT_MAX_CHAINS" => {
+                    let n = val.parse().map_err(|_| RangerError::InvalidValue {
+                        arg: "SPLIT_MAX_CHAINS",
+                        value: val.to_string(),
+                    })?;
+                    config.split_max_chains = Some(n);
+                }
+                "MAX_CDR3_INDEL" => {
+                    let n = val.parse().map_err(|_| RangerError::InvalidValue {
+                        arg: "MAX_CDR3_INDEL",
+                        value: val.to_string(),
+                    })?;
+                    config.max_cdr3_indel = Some(n);
+                }
+                "BCR" | "TCR" | "TCRGD" | "META" => {
+                    if input.is_some() {
+                        return Err(RangerError::AmbiguousInput);
+                    }
+                    let val = val.to_string();
+                    input = Some(match name {
+                        "BCR" => RangerInput::Bcr(val),
+                        "TCR" => RangerInput::Tcr(val),
+                        "TCRGD" => RangerInput::TcrGd(val),
+                        _ => RangerInput::Meta(val),
+                    });
+                }
+                "GAMMA_DELTA" => gamma_delta = true,
+                "NOPRETTY" => config.pretty = false,
+                "NUMI" => config.filters.umi = false,
+                "NUMI_RATIO" => config.filters.umi_ratio = false,
+                "NGRAPH_FILTER" => config.filters.graph = false,
+                "NWEAK_CHAINS" => config.filters.weak_chains = false,
+                "NFOURSIE_KILL" => config.filters.foursie_kill = false,
+                "NDOUBLET" => config.filters.doublet = false,
+                "NSIG" => config.filters.signature = false,
+                _ => return Err(RangerError::IllegalArgument(name.to_string())),
+            }
+        }
+        for (found, arg) in found.into_iter().zip(REQUIRED_ARGS.into_iter()) {
+            if !found {
+                return Err(RangerError::MissingArgument(arg));
+            }
+        }
+        config.input = input.ok_or(RangerError::AmbiguousInput)?;
+        if gamma_delta != matches!(config.input, RangerInput::TcrGd(_)) {
+            return Err(RangerError::InvalidValue {
+                arg: "GAMMA_DELTA",
+                value: "GAMMA_DELTA must be passed if and only if TCRGD is passed".to_string(),
+            });
+        }
+        Ok(config)
+    }
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+pub struct RangerConfigBuilder {
+    config: RangerConfig,
+}
+
+impl RangerConfigBuilder {
+    pub fn pre(mut self, pre: &[String]) -> Self {
+        self.config.pre = pre.to_vec();
+        self
+    }
+
+    pub fn proto_metadata(mut self, path: &str) -> Self {
+        self.config.proto_metadata = Some(path.to_string());
+        self
+    }
+
+    // Use a known donor genotype, in addition to inferred alleles, or instead of them if
+    // only is true.  The file is FASTA, with headers >donor:gene, or in the form written by
+    // DONOR_REF_FILE, or TSV, with header line donor<tab>gene<tab>sequence.  For each donor and
+    // gene that it lists, its alleles replace the inferred alleles.  Each allele must have the
+    // same length as the reference segment, as alleles having indels are not supported.
+
+    pub fn donor_genotype(mut self, path: &str, only: bool) -> Self {
+        self.config.donor_genotype = Some(path.to_string());
+        self.config.donor_genotype_only = only;
+        self
+    }
+
+    pub fn max_cores(mut self, n: usize) -> Self {
+        self.config.max_cores = n;
+        self
+    }
+
+    pub fn pretty(mut self, pretty: bool) -> Self {
+        self.config.pretty = pretty;
+        self
+    }
+
+    pub fn filters(mut self, filters: RangerFilters) -> Self {
+        self.config.filters = filters;
+        self
+    }
+
+    pub fn split_max_chains(mut self, n: usize) -> Self {
+        self.config.split_max_chains = Some(n);
+        self
+    }
+
+    // Set the largest CDR3 indel across which BCR cells may be joined, or turn this off with 0.
+
+    pub fn max_cdr3_indel(mut self, n: usize) -> Self {
+        self.config.max_cdr3_indel = Some(n);
+        self
+    }
+
+    pub fn proto(mut self, path: &str) -> Self {
+        self.config.outputs.proto = path.to_string();
+        self
+    }
+
+    pub fn donor_ref_file(mut self, path: &str) -> Self {
+        self.config.outputs.donor_ref_file = path.to_string();
+        self
+    }
+
+    pub fn fate_file(mut self, path: &str) -> Self {
+        self.config.outputs.fate_file = Some(path.to_string());
+        self
+    }
+
+    pub fn fate_ledger(mut self, path: &str) -> Self {
+        self.config.outputs.fate_ledger = Some(path.to_string());
+        self
+    }
+
+    pub fn fate_summary(mut self, path: &str) -> Self {
+        self.config.outputs.fate_summary = Some(path.to_string());
+        self
+    }
+
+    // Evaluate every default filter but delete nothing, and report what each would delete.
+
+    pub fn filter_impact(mut self, path: &str) -> Self {
+        self.config.outputs.filter_impact = Some(path.to_string());
+        self
+    }
+
+    // Reuse the clonotypes computed by a previous run having the same inputs and algorithmic
+    // options, if they were saved to this checkpoint file, and otherwise save them there.
+
+    pub fn checkpoint(mut self, path: &str) -> Self {
+        self.config.outputs.checkpoint = Some(path.to_string());
+        self
+    }
+
+    // Extend the clonotypes saved to a checkpoint file by a previous run, whose datasets must
+    // come first, by the remaining datasets, and optionally list the resulting changes to the
+    // clonotypes.
+
+    pub fn incremental(mut self, previous: &str, report: Option<&str>) -> Self {
+        self.config.incremental = Some(previous.to_string());
+        self.config.outputs.incremental_report = report.map(str::to_string);
+        self
+    }
+
+    // Record each pair of exact subclonotypes considered for joining, and why it was or was not
+    // joined.
+
+    pub fn join_audit(mut self, path: &str) -> Self {
+        self.config.outputs.join_audit = Some(path.to_string());
+        self
+    }
+
+    // Compare the inferred alleles to the donor genotype, writing a TSV file having one line
+    // per inferred allele, with fields donor, gene, allele, support and status.  The status is
+    // match, conflict, or absent if the donor genotype does not list the gene for the donor.
+
+    pub fn donor_genotype_report(mut self, path: &str) -> Self {
+        self.config.outputs.donor_genotype_report = Some(path.to_string());
+        self
+    }
+
+    // Write the clonotypes as AIRR Rearrangement and AIRR Cell TSV files.
+
+    pub fn airr(mut self, rearrangement: &str, cell: &str) -> Self {
+        self.config.outputs.airr_rearrangement = Some(rearrangement.to_string());
+        self.config.outputs.airr_cell = Some(cell.to_string());
+        self
+    }
+
+    pub fn proto_index(mut self, path: &str) -> Self {
+        self.config.outputs.proto_index = Some(path.to_string());
+        self
+    }
+
+    pub fn stream_proto(mut self, stream: bool) -> Self {
+        self.config.outputs.stream_proto = stream;
+        self
+    }
+
+    // Group clonotypes, using arguments such as GROUP=vj_refname or MIN_GROUP=2, which are
+    // passed through verbatim.
+
+    pub fn grouping(mut self, args: &[&str]) -> Self {
+        self.config.grouping = args.iter().copied().map(str::to_string).collect();
+        self
+    }
+
+    pub fn group_table(mut self, path: &str) -> Self {
+        self.config.outputs.group_table = Some(path.to_string());
+        self
+    }
+
+    // Write the multiple sequence alignment of each clonotype, in DNA and amino acid space.  The
+    // CLUSTAL and PHYLIP outputs are tar files.
+
+    pub fn fasta(mut self, dna: &str, aa: &str) -> Self {
+        self.config.outputs.fasta = Some(dna.to_string());
+        self.config.outputs.fasta_aa = Some(aa.to_string());
+        self
+    }
+
+    pub fn clustal(mut self, dna: &str, aa: &str) -> Self {
+        self.config.outputs.clustal_dna = Some(dna.to_string());
+        self.config.outputs.clustal_aa = Some(aa.to_string());
+        self
+    }
+
+    pub fn phylip(mut self, dna: &str, aa: &str) -> Self {
+        self.config.outputs.phylip_dna = Some(dna.to_string());
+        self.config.outputs.phylip_aa = Some(aa.to_string());
+        self
+    }
+
+    // Write parseable output, with one line per exact subclonotype, or per cell if per_cell is
+    // true.  The columns are the given variables, or if none are given, all of them.  The file
+    // is TSV if its name ends in .tsv, and otherwise CSV.
+
+    pub fn parseable(mut self, path: &str, cols: &[&str], per_cell: bool) -> Self {
+        self.config.outputs.pout = Some(path.to_string());
+        self.config.pcols = cols.iter().copied().map(str::to_string).collect();
+        self.config.pcell = per_cell;
+        self
+    }
+
+    // Make plots, using arguments such as HONEY=out=plot.svg,color=iso or PLOTXY_EXACT=..., which
+    // are passed through verbatim.  A plot is written as PNG if its file name ends in .png, and
+    // otherwise as SVG.
+
+    pub fn plots(mut self, args: &[&str]) -> Self {
+        self.config.plots = args.iter().copied().map(str::to_string).collect();
+        self
+    }
+
+    // Add the lineage tree of each clonotype to the PROTO file.
+
+    pub fn tree(mut self, tree: bool) -> Self {
+        self.config.outputs.tree = tree;
+        self
+    }
+
+    // Validate and return the configuration.
+
+    pub fn build(self) -> Result<RangerConfig, RangerError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+
+    // Same, but for main_enclone_ranger_config_in_memory, in which case PROTO is optional.
+
+    pub fn build_in_memory(self) -> Result<RangerConfig, RangerError> {
+        self.config.validate_in_memory()?;
+        Ok(self.config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A configuration using every option, without validating it.
+
+    fn full_config() -> RangerConfig {
+        RangerConfig::builder("ref.fa", RangerInput::Bcr("bcr_dir".to_string()))
+            .pre(&["pre1".to_string(), "pre2".to_string()])
+            .proto_metadata("meta.json")
+            .donor_genotype("genotype.fa", false)
+            .max_cores(8)
+            .pretty(false)
+            .filters(RangerFilters {
+                umi: false,
+                doublet: false,
+                ..Default::default()
+            })
+            .split_max_chains(4)
+            .max_cdr3_indel(0)
+            .proto("out.proto")
+            .donor_ref_file("donor_ref.fa")
+            .fate_file("fate.json")
+            .fate_ledger("ledger.tsv")
+            .fate_summary("summary.tsv")
+            .filter_impact("impact.tsv")
+            .checkpoint("checkpoint.bin")
+            .incremental("previous.bin", Some("changes.tsv"))
+            .join_audit("audit.tsv")
+            .donor_genotype_report("genotype.tsv")
+            .airr("rearrangement.tsv", "cell.tsv")
+            .proto_index("out.index")
+            .tree(true)
+            .grouping(&["GROUP=vj_refname,cdr3_aa_heavy>=90%", "MIN_GROUP=2"])
+            .group_table("groups.tsv")
+            .fasta("msa.fa", "msa_aa.fa")
+            .clustal("msa.aln.tar", "msa_aa.aln.tar")
+            .phylip("msa.phy.tar", "msa_aa.phy.tar")
+            .parseable("pout.tsv", &["group_id", "clonotype_id"], true)
+            .plots(&["HONEY=out=plot.svg,color=iso", "LEGEND"])
+            .config
+    }
+
+    fn args(x: &[&str]) -> Vec<String> {
+        x.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let config = full_config();
+        assert_eq!(RangerConfig::from_args(&config.to_args()).unwrap(), config);
+
+        // Gamma/delta input, with PROTO_STREAM, default options, and no PROTO, as for an
+        // in-memory configuration.
+
+        let config = RangerConfig::builder("ref.fa", RangerInput::TcrGd("tcrgd_dir".to_string()))
+            .max_cores(1)
+            .donor_ref_file("donor_ref.fa")
+            .stream_proto(true)
+            .config;
+        let a = config.to_args();
+        assert!(a.contains(&"GAMMA_DELTA".to_string()));
+        assert!(!a.iter().any(|x| x.starts_with("PROTO=")));
+        assert_eq!(RangerConfig::from_args(&a).unwrap(), config);
+    }
+
+    #[test]
+    fn test_from_args_errors() {
+        let base = [
+            "enclone",
+            "CELLRANGER",
+            "FORCE_EXTERNAL",
+            "NOPAGER",
+            "NOPRINT",
+            "PRE=",
+            "REF=ref.fa",
+            "MAX_CORES=2",
+            "DONOR_REF_FILE=donor_ref.fa",
+            "PROTO=out.proto",
+            "TCR=tcr_dir",
+        ];
+        let parse = |extra: &[&str], omit: &str| {
+            let mut a = args(&base);
+            a.retain(|x| x != omit);
+            a.extend(args(extra));
+            RangerConfig::from_args(&a)
+        };
+        assert!(parse(&[], "").is_ok());
+        assert!(matches!(
+            parse(&["NCROSS"], ""),
+            Err(RangerError::IllegalArgument(x)) if x == "NCROSS"
+        ));
+        assert!(matches!(
+            parse(&["GROUP_VJ_REFNAME_HEAVY_STRONG=1"], ""),
+            Err(RangerError::IllegalArgument(_))
+        ));
+        assert!(matches!(
+            parse(&[], "NOPRINT"),
+            Err(RangerError::MissingArgument("NOPRINT"))
+        ));
+        assert!(matches!(
+            parse(&["BCR=bcr_dir"], ""),
+            Err(RangerError::AmbiguousInput)
+        ));
+        assert!(matches!(
+            parse(&["GAMMA_DELTA"], ""),
+            Err(RangerError::InvalidValue {
+                arg: "GAMMA_DELTA",
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse(&["MAX_CORES=x"], "MAX_CORES=2"),
+            Err(RangerError::InvalidValue {
+                arg: "MAX_CORES",
+                ..
+            })
+        ));
+    }
+
+    // PROTO is required, except for an in-memory run.
+
+    #[test]
+    fn test_build_in_memory() {
+        let dir = std::env::temp_dir();
+        let refname = dir.join(format!("ranger_config_test_{}.fa", std::process::id()));
+        let refname = refname.to_str().unwrap();
+        std::fs::write(refname, ">1|IGHV1\nACGT\n").unwrap();
+        let builder = || {
+            RangerConfig::builder(refname, RangerInput::Bcr("bcr_dir".to_string()))
+                .max_cores(1)
+                .donor_ref_file("donor_ref.fa")
+        };
+        assert!(matches!(
+            builder().build(),
+            Err(RangerError::MissingArgument("PROTO"))
+        ));
+        assert!(builder().build_in_memory().is_ok());
+        assert!(builder().proto("out.proto").build().is_ok());
+        assert!(matches!(
+            builder().proto_index("out.index").build_in_memory(),
+            Err(RangerError::MissingArgument("PROTO"))
+        ));
+        std::fs::remove_file(refname).unwrap();
+    }
+}
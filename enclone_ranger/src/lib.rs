@@ -1,5 +1,6 @@
 // Copyright (c) 2021 10x Genomics, Inc. All rights reserved.
 
+pub mod config;
 pub mod main_enclone;
 pub mod stop;
 
@@ -4,7 +4,10 @@
 // be passed are limited.  The code here is simplified and could be further simplified.
 
 use self::refx::{make_vdj_ref_data_core, RefData};
-use crate::stop::main_enclone_stop_ranger;
+use crate::config::{RangerConfig, RangerError};
+use crate::stop::{
+    main_enclone_stop_ranger, main_enclone_stop_ranger_in_memory, EncloneRangerOutputs,
+};
 use crate::USING_PAGER;
 use enclone::innate::species;
 use enclone_args::load_gex::get_gex_info;
@@ -23,61 +26,29 @@ use string_utils::TextUtils;
 use vdj_ann::refx;
 
 pub fn main_enclone_ranger(args: &[String]) -> Result<(), String> {
-    const REQUIRED_ARGS: [&str; 9] = [
-        "CELLRANGER",
-        "DONOR_REF_FILE",
-        "FORCE_EXTERNAL",
-        "MAX_CORES",
-        "NOPAGER",
-        "NOPRINT",
-        "PRE",
-        "PROTO",
-        "REF",
-    ];
-    const ALLOWED_ARGS: [&str; 16] = [
-        "BCR",
-        "META",
-        "NOPRETTY",
-        "PROTO_METADATA",
-        "TCR",
-        "TCRGD",
-        "GAMMA_DELTA",
-        "FATE_FILE",
-        "NUMI",
-        "NUMI_RATIO",
-        "NGRAPH_FILTER",
-        "NWEAK_CHAINS",
-        "NFOURSIE_KILL",
-        "NDOUBLET",
-        "NSIG",
-        "SPLIT_MAX_CHAINS",
-    ];
-    let mut found = [false; REQUIRED_ARGS.len()];
-    for arg in args.iter().skip(1) {
-        let mut arg = arg.as_str();
-        if arg.contains('=') {
-            arg = arg.before("=");
-        }
-        let mut ok = false;
-        for (f, &x) in found.iter_mut().zip(REQUIRED_ARGS.iter()) {
-            if arg == x {
-                ok = true;
-                *f = true;
-            }
-        }
-        ok = ok || ALLOWED_ARGS.contains(&arg);
-        if !ok {
-            panic!("Illegal argument {arg} passed to main_enclone_ranger.");
-        }
-    }
-    for (found, arg) in found.into_iter().zip(REQUIRED_ARGS.into_iter()) {
-        if !found {
-            panic!("Required argument {arg} not passed to main_enclone_ranger");
-        }
-    }
-    let setup = main_enclone_setup_ranger(args)?;
-    let inter = main_enclone_start(setup)?;
-    main_enclone_stop_ranger(inter)
+    let config = RangerConfig::from_args(args).map_err(|e| e.to_string())?;
+    main_enclone_ranger_config(&config).map_err(|e| e.to_string())
+}
+
+// Run enclone from a typed configuration.
+
+pub fn main_enclone_ranger_config(config: &RangerConfig) -> Result<(), RangerError> {
+    config.validate()?;
+    let setup = main_enclone_setup_ranger(&config.to_args()).map_err(RangerError::Setup)?;
+    let inter = main_enclone_start(setup).map_err(RangerError::Start)?;
+    main_enclone_stop_ranger(inter).map_err(RangerError::Stop)
+}
+
+// Run enclone from a typed configuration, returning the clonotypes and barcode fates rather than
+// only writing them to disk.
+
+pub fn main_enclone_ranger_config_in_memory(
+    config: &RangerConfig,
+) -> Result<EncloneRangerOutputs, RangerError> {
+    config.validate_in_memory()?;
+    let setup = main_enclone_setup_ranger(&config.to_args()).map_err(RangerError::Setup)?;
+    let inter = main_enclone_start(setup).map_err(RangerError::Start)?;
+    main_enclone_stop_ranger_in_memory(inter).map_err(RangerError::Stop)
 }
 
 pub fn main_enclone_setup_ranger(args: &[String]) -> Result<EncloneSetup, String> {
@@ -113,10 +84,11 @@ pub fn main_enclone_setup_ranger(args: &[String]) -> Result<EncloneSetup, String
 
     let mut refx = String::new();
     let ann = "contig_annotations.json";
-    let fx = File::open(&ctl.gen_opt.refname);
-    let f = BufReader::new(fx.unwrap());
+    let fx = File::open(&ctl.gen_opt.refname)
+        .map_err(|e| format!("\nCould not open {}: {e}.\n", ctl.gen_opt.refname))?;
+    let f = BufReader::new(fx);
     for line in f.lines() {
-        let s = line.unwrap();
+        let s = line.map_err(|e| format!("\nCould not read {}: {e}.\n", ctl.gen_opt.refname))?;
         refx += &s;
         refx += "\n";
     }
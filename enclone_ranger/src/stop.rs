@@ -1,13 +1,44 @@
 // Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
 
+use enclone_core::barcode_fate::FateLedger;
 use enclone_core::defs::ColInfo;
 use enclone_core::enclone_structs::EncloneIntermediates;
 use enclone_print::print_clonotypes::print_clonotypes;
+use enclone_proto::types::EncloneOutputs;
 use hdf5::Reader;
 use rayon::prelude::*;
 use std::collections::HashMap;
 
-pub fn main_enclone_stop_ranger(mut inter: EncloneIntermediates) -> Result<(), String> {
+// What main_enclone_stop_ranger_in_memory returns: the data structure that is otherwise written
+// to the PROTO file, and the fate of each barcode that was filtered out, per dataset.
+
+pub struct EncloneRangerOutputs {
+    pub outputs: EncloneOutputs,
+    pub fate: Vec<FateLedger>,
+}
+
+pub fn main_enclone_stop_ranger(inter: EncloneIntermediates) -> Result<(), String> {
+    main_enclone_stop_ranger_core(inter)?;
+    Ok(())
+}
+
+// Same as main_enclone_stop_ranger, but return the clonotypes to the caller.  The PROTO file is
+// still written if one was specified.
+
+pub fn main_enclone_stop_ranger_in_memory(
+    mut inter: EncloneIntermediates,
+) -> Result<EncloneRangerOutputs, String> {
+    inter.setup.ctl.gen_opt.proto_in_memory = true;
+    let (outputs, fate) = main_enclone_stop_ranger_core(inter)?;
+    Ok(EncloneRangerOutputs {
+        outputs: outputs.unwrap(),
+        fate,
+    })
+}
+
+fn main_enclone_stop_ranger_core(
+    mut inter: EncloneIntermediates,
+) -> Result<(Option<EncloneOutputs>, Vec<FateLedger>), String> {
     // Unpack inputs.
 
     let to_bc = &inter.ex.to_bc;
@@ -64,6 +95,7 @@ pub fn main_enclone_stop_ranger(mut inter: EncloneIntermediates) -> Result<(), S
     let mut out_datas = Vec::<Vec<HashMap<String, String>>>::new();
     let mut tests = Vec::<usize>::new();
     let mut controls = Vec::<usize>::new();
+    let mut enclone_outputs = None;
     print_clonotypes(
         is_bcr,
         to_bc,
@@ -89,6 +121,7 @@ pub fn main_enclone_stop_ranger(mut inter: EncloneIntermediates) -> Result<(), S
         &mut controls,
         fate,
         allele_data,
+        &mut enclone_outputs,
     )?;
-    Ok(())
+    Ok((enclone_outputs, std::mem::take(fate)))
 }
@@ -56,11 +56,14 @@ pub fn analyze_donor_ref(
             }
         }
 
-        // Store the donor reference alleles;
+        // Store the donor reference alleles.  Only V segments are analyzed.
 
         for (i, ar) in alt_refs.iter().enumerate() {
             let donor = ar.0;
             let ref_id = ar.1;
+            if !refdata.is_v(ref_id) {
+                continue;
+            }
             let name = &refdata.name[ref_id];
             let alt_seq = &ar.2;
             refs.push((
@@ -0,0 +1,343 @@
+// Copyright (c) 2023 10X Genomics, Inc. All rights reserved.
+
+// Clonotyping accuracy benchmark (BENCHMARK).  The clonotypes are compared to truth labels on
+// cells, and pairwise precision and recall, the false merge rate and the split rate are written
+// as JSON, overall and broken down by chain types and clonotype size, so that clonotyping
+// quality can be tracked as the join algorithm changes.
+//
+// The truth labels are either lineages, given by BENCHMARK_TRUTH, or donors, which serve as a
+// negative control: cells from different donors cannot be clonally related, but cells from the
+// same donor need not be, so only false merges are measured.
+//
+// The BENCHMARK_TRUTH file is a CSV file, whose first line names its fields.  These must
+// include barcode and lineage, and may include dataset, which is a dataset short name.  If
+// there is no dataset field, a barcode is labelled in every dataset in which it appears.
+
+use enclone_core::defs::{EncloneControl, ExactClonotype};
+use enclone_core::enclone_structs::EncloneIntermediates;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+// Metrics for a set of cells, each of which is in a clonotype and has a truth label.  A pair
+// of cells is a true pair if the cells have the same label.  Rates are None if they would be
+// 0/0, and recall and split rate are None if the labels are donors.
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct BenchmarkMetrics {
+    pub cells: usize,
+    pub clonotypes: usize,
+    pub labels: usize,
+    pub clonotype_pairs: usize,   // pairs of cells in the same clonotype
+    pub label_pairs: usize,       // pairs of cells having the same label
+    pub true_pairs: usize,        // pairs of cells in the same clonotype and having the same label
+    pub precision: Option<f64>,   // true_pairs / clonotype_pairs
+    pub recall: Option<f64>,      // true_pairs / label_pairs
+    pub mixing_rate: Option<f64>, // fraction of pairs having different labels that are merged
+    pub false_merge_rate: Option<f64>, // fraction of clonotypes of 2+ cells that mix labels
+    pub split_rate: Option<f64>,  // fraction of labels of 2+ cells that are split
+}
+
+fn pairs(n: usize) -> usize {
+    n * n.saturating_sub(1) / 2
+}
+
+fn ratio(num: usize, den: usize) -> Option<f64> {
+    if den == 0 {
+        None
+    } else {
+        Some(num as f64 / den as f64)
+    }
+}
+
+// Compute the metrics for cells given as {(clonotype, label)}.
+
+pub fn evaluate(cells: &[(usize, usize)], lineage: bool) -> BenchmarkMetrics {
+    let mut by_clonotype = HashMap::<usize, usize>::new();
+    let mut by_label = HashMap::<usize, usize>::new();
+    let mut by_both = HashMap::<(usize, usize), usize>::new();
+    for &(c, l) in cells {
+        *by_clonotype.entry(c).or_default() += 1;
+        *by_label.entry(l).or_default() += 1;
+        *by_both.entry((c, l)).or_default() += 1;
+    }
+    let clonotype_pairs = by_clonotype.values().map(|&n| pairs(n)).sum::<usize>();
+    let label_pairs = by_label.values().map(|&n| pairs(n)).sum::<usize>();
+    let true_pairs = by_both.values().map(|&n| pairs(n)).sum::<usize>();
+
+    // Count the clonotypes that mix labels, and the labels that are split across clonotypes.
+    // A clonotype mixes labels if its cells are not all in one (clonotype, label) class.
+
+    let mut classes_by_clonotype = HashMap::<usize, usize>::new();
+    let mut classes_by_label = HashMap::<usize, usize>::new();
+    for &(c, l) in by_both.keys() {
+        *classes_by_clonotype.entry(c).or_default() += 1;
+        *classes_by_label.entry(l).or_default() += 1;
+    }
+    let multi_clonotypes = by_clonotype.values().filter(|&&n| n >= 2).count();
+    let mixed = classes_by_clonotype.values().filter(|&&n| n > 1).count();
+    let multi_labels = by_label.values().filter(|&&n| n >= 2).count();
+    let split = classes_by_label.values().filter(|&&n| n > 1).count();
+    BenchmarkMetrics {
+        cells: cells.len(),
+        clonotypes: by_clonotype.len(),
+        labels: by_label.len(),
+        clonotype_pairs,
+        label_pairs,
+        true_pairs,
+        precision: ratio(true_pairs, clonotype_pairs),
+        recall: if lineage {
+            ratio(true_pairs, label_pairs)
+        } else {
+            None
+        },
+        mixing_rate: ratio(
+            clonotype_pairs - true_pairs,
+            pairs(cells.len()) - label_pairs,
+        ),
+        false_merge_rate: ratio(mixed, multi_clonotypes),
+        split_rate: if lineage {
+            ratio(split, multi_labels)
+        } else {
+            None
+        },
+    }
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+// Read BENCHMARK_TRUTH.  Return a map from (dataset index, barcode) to lineage index, where
+// the dataset index is None if the file does not have a dataset field.
+
+fn read_truth(ctl: &EncloneControl) -> Result<HashMap<(Option<usize>, String), usize>, String> {
+    let path = &ctl.gen_opt.benchmark_truth;
+    let f = File::open(path)
+        .map_err(|e| format!("\nCould not open BENCHMARK_TRUTH file {path}: {e}.\n"))?;
+    let mut lines = Vec::<String>::new();
+    for line in BufReader::new(f).lines() {
+        let line =
+            line.map_err(|e| format!("\nFailed to read BENCHMARK_TRUTH file {path}: {e}.\n"))?;
+        if !line.trim().is_empty() {
+            lines.push(line.trim_end().to_string());
+        }
+    }
+    let fields = lines.first().map_or(Vec::new(), |x| x.split(',').collect());
+    let field = |name: &str| fields.iter().position(|x| *x == name);
+    let (Some(bc_field), Some(lineage_field)) = (field("barcode"), field("lineage")) else {
+        return Err(format!(
+            "\nThe first line of the BENCHMARK_TRUTH file {path} should be a CSV header that \
+             includes the fields barcode and lineage.\n"
+        ));
+    };
+    let dataset_field = field("dataset");
+    let mut lineages = HashMap::<String, usize>::new();
+    let mut truth = HashMap::<(Option<usize>, String), usize>::new();
+    for (i, line) in lines.iter().enumerate().skip(1) {
+        let vals = line.split(',').collect::<Vec<&str>>();
+        if vals.len() != fields.len() {
+            return Err(format!(
+                "\nIn the BENCHMARK_TRUTH file {path}, line {} does not have {} fields.\n",
+                i + 1,
+                fields.len()
+            ));
+        }
+        let dataset = match dataset_field {
+            None => None,
+            Some(d) => {
+                let id = &ctl.origin_info.dataset_id;
+                let Some(li) = id.iter().position(|x| x == vals[d]) else {
+                    return Err(format!(
+                        "\nIn the BENCHMARK_TRUTH file {path}, line {} refers to the dataset \
+                         {}, which is not one of the datasets.\n",
+                        i + 1,
+                        vals[d]
+                    ));
+                };
+                Some(li)
+            }
+        };
+        let n = lineages.len();
+        let lineage = *lineages.entry(vals[lineage_field].to_string()).or_insert(n);
+        truth.insert((dataset, vals[bc_field].to_string()), lineage);
+    }
+    Ok(truth)
+}
+
+// The clonotype size bins used in the breakdown.
+
+const SIZE_BINS: [(usize, &str); 4] = [(1, "1"), (2, "2-9"), (10, "10-99"), (100, "100+")];
+
+fn size_bin(n: usize) -> &'static str {
+    SIZE_BINS.iter().rev().find(|x| n >= x.0).unwrap().1
+}
+
+// The chain types of an exact subclonotype, with heavy or beta chains first, e.g. IGH+IGK.
+
+fn chain_types(ex: &ExactClonotype) -> String {
+    let mut types = ex
+        .share
+        .iter()
+        .map(|x| (!x.left, x.chain_type.as_str()))
+        .collect::<Vec<_>>();
+    types.sort_unstable();
+    types.iter().map(|x| x.1).collect::<Vec<_>>().join("+")
+}
+
+#[derive(Serialize)]
+struct Breakdown {
+    category: String,
+    #[serde(flatten)]
+    metrics: BenchmarkMetrics,
+}
+
+#[derive(Serialize)]
+struct BenchmarkReport {
+    truth: &'static str,        // lineage or donor
+    labelled_cells: usize,      // cells in clonotypes that have a truth label
+    unclonotyped_labels: usize, // BENCHMARK_TRUTH lines that match no cell in a clonotype
+    join_alg_opt: String,
+    overall: BenchmarkMetrics,
+    by_chain_type: Vec<Breakdown>,
+    by_clonotype_size: Vec<Breakdown>,
+}
+
+// Evaluate the clonotypes and write the report to BENCHMARK.
+
+pub fn write_benchmark(inter: &EncloneIntermediates) -> Result<(), String> {
+    let ctl = &inter.setup.ctl;
+    let ex = &inter.ex;
+    let lineage = !ctl.gen_opt.benchmark_truth.is_empty();
+    if !lineage && (ctl.origin_info.donor_list.len() < 2 || !ctl.clono_filt_opt_def.donor) {
+        return Err(
+            "\nWithout BENCHMARK_TRUTH, BENCHMARK uses donors as truth labels, so it requires \
+             datasets from more than\none donor, and MIX_DONORS.\n"
+                .to_string(),
+        );
+    }
+    let truth = if lineage {
+        read_truth(ctl)?
+    } else {
+        HashMap::new()
+    };
+
+    // Gather the labelled cells, as {(clonotype, label, chain types, size bin)}.
+
+    let mut cells = Vec::<(usize, usize, String, &'static str)>::new();
+    let mut matched = HashSet::<(Option<usize>, String)>::new();
+    for (c, o) in ex.orbits.iter().enumerate() {
+        let mut exacts = o
+            .iter()
+            .map(|&k| ex.info[k as usize].clonotype_id)
+            .collect::<Vec<_>>();
+        exacts.sort_unstable();
+        exacts.dedup();
+        let n = exacts
+            .iter()
+            .map(|&e| ex.exact_clonotypes[e].ncells())
+            .sum::<usize>();
+        for &e in &exacts {
+            let x = &ex.exact_clonotypes[e];
+            let types = chain_types(x);
+            for clone in &x.clones {
+                let cell = &clone[0];
+                let label = if lineage {
+                    let bc = &cell.barcode;
+                    [(Some(cell.dataset_index), bc.clone()), (None, bc.clone())]
+                        .into_iter()
+                        .find(|key| truth.contains_key(key))
+                        .map(|key| {
+                            let label = truth[&key];
+                            matched.insert(key);
+                            label
+                        })
+                } else {
+                    cell.donor_index
+                };
+                if let Some(label) = label {
+                    cells.push((c, label, types.clone(), size_bin(n)));
+                }
+            }
+        }
+    }
+
+    // Compute the metrics.
+
+    let pairs = cells.iter().map(|x| (x.0, x.1)).collect::<Vec<_>>();
+    let overall = evaluate(&pairs, lineage);
+    let mut types = cells.iter().map(|x| x.2.as_str()).collect::<Vec<_>>();
+    types.sort_unstable();
+    types.dedup();
+    let by_chain_type = types
+        .iter()
+        .map(|&t| Breakdown {
+            category: t.to_string(),
+            metrics: evaluate(
+                &cells
+                    .iter()
+                    .filter(|x| x.2 == t)
+                    .map(|x| (x.0, x.1))
+                    .collect::<Vec<_>>(),
+                lineage,
+            ),
+        })
+        .collect::<Vec<_>>();
+    let by_clonotype_size = SIZE_BINS
+        .iter()
+        .filter(|b| cells.iter().any(|x| x.3 == b.1))
+        .map(|b| Breakdown {
+            category: b.1.to_string(),
+            metrics: evaluate(
+                &cells
+                    .iter()
+                    .filter(|x| x.3 == b.1)
+                    .map(|x| (x.0, x.1))
+                    .collect::<Vec<_>>(),
+                lineage,
+            ),
+        })
+        .collect::<Vec<_>>();
+    let report = BenchmarkReport {
+        truth: if lineage { "lineage" } else { "donor" },
+        labelled_cells: cells.len(),
+        unclonotyped_labels: truth.len() - matched.len(),
+        join_alg_opt: format!("{:?}", ctl.join_alg_opt),
+        overall,
+        by_chain_type,
+        by_clonotype_size,
+    };
+
+    // Write the report.
+
+    let path = &ctl.gen_opt.benchmark;
+    let f = File::create(path)
+        .map_err(|e| format!("\nCould not create BENCHMARK file {path}: {e}.\n"))?;
+    let mut f = BufWriter::new(f);
+    serde_json::to_writer_pretty(&mut f, &report)
+        .map_err(|e| format!("\nFailed to write BENCHMARK file {path}: {e}.\n"))?;
+    writeln!(f).map_err(|e| format!("\nFailed to write BENCHMARK file {path}: {e}.\n"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        // Clonotype 0 merges lineages 0 and 1, and lineage 2 is split across clonotypes 1 and 2.
+
+        let cells = [(0, 0), (0, 0), (0, 1), (1, 2), (1, 2), (2, 2)];
+        let m = evaluate(&cells, true);
+        assert_eq!((m.cells, m.clonotypes, m.labels), (6, 3, 3));
+        assert_eq!((m.clonotype_pairs, m.label_pairs, m.true_pairs), (4, 4, 2));
+        assert_eq!(m.precision, Some(0.5));
+        assert_eq!(m.recall, Some(0.5));
+        assert_eq!(m.mixing_rate, Some(2.0 / 11.0));
+        assert_eq!(m.false_merge_rate, Some(0.5));
+        assert_eq!(m.split_rate, Some(0.5));
+        let m = evaluate(&cells, false);
+        assert_eq!((m.recall, m.split_rate), (None, None));
+        assert_eq!(evaluate(&[], true).precision, None);
+    }
+}
@@ -0,0 +1,347 @@
+// Copyright (c) 2023 10X Genomics, Inc. All rights reserved.
+
+// Checkpointing of the results of main_enclone_start (CHECKPOINT=path).
+//
+// The checkpoint file consists of a magic string, then a header, followed by the EncloneExacts,
+// the last two encoded using bincode.  The header contains a key that describes the input files,
+// including when they were last modified, and the options that may affect the computation.  If
+// the checkpoint exists and its key agrees with the current one, the EncloneExacts are read from
+// it rather than recomputed, so that only the output phase is rerun.  Otherwise they are
+// recomputed, and the checkpoint is rewritten.
+//
+// The key includes the option structs that may affect the computation in their entirety, so that
+// an option added to one of them is part of the key without further work.  Output options, such
+// as the clonotype printing and parseable output options, and the general options that name
+// output files, are not part of the key, and may be changed freely between runs.
+//
+// The header also records the datasets, donors, origins and tags that the indices in the
+// EncloneExacts refer to, so that a checkpoint can be extended by INCREMENTAL, see
+// incremental.rs.
+
+use crate::filter_pipeline::FilterPipeline;
+use enclone_args::read_airr::AIRR_REARRANGEMENT;
+use enclone_core::defs::{EncloneControl, GeneralOpt};
+use enclone_core::enclone_structs::{EncloneExacts, EncloneSetup};
+use io_utils::path_exists;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::time::UNIX_EPOCH;
+
+const CHECKPOINT_MAGIC: &[u8] = b"enclone checkpoint\n";
+
+// Increment this if EncloneExacts or anything in it changes.
+
+pub const CHECKPOINT_VERSION: u32 = 4;
+
+#[derive(Serialize, Deserialize)]
+struct CheckpointHeader {
+    version: u32,
+    enclone_version: String,
+    key: String,
+    origin: CheckpointOrigin,
+}
+
+// The names behind the dataset, donor, origin and tag indices in a checkpoint.
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct CheckpointOrigin {
+    pub dataset_path: Vec<String>,
+    pub donor_list: Vec<String>,
+    pub origin_list: Vec<String>,
+    pub tag_list: Vec<String>,
+}
+
+impl CheckpointOrigin {
+    pub fn new(ctl: &EncloneControl) -> Self {
+        let origin = &ctl.origin_info;
+        CheckpointOrigin {
+            dataset_path: origin.dataset_path.clone(),
+            donor_list: origin.donor_list.clone(),
+            origin_list: origin.origin_list.clone(),
+            tag_list: origin.tag_list.clone(),
+        }
+    }
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+// Find the input files, and record when they were last modified in ctl.last_modified.
+
+pub fn set_last_modified(ctl: &mut EncloneControl) {
+    let ann = if !ctl.gen_opt.cellranger {
+        "all_contig_annotations.json"
+    } else {
+        "contig_annotations.json"
+    };
+    let mut paths = Vec::<String>::new();
+    for path in &ctl.origin_info.dataset_path {
+        let json = format!("{path}/{ann}");
+        let airr = format!("{path}/{AIRR_REARRANGEMENT}");
+        if path_exists(&json) {
+            paths.push(json);
+        } else if path_exists(&format!("{json}.lz4")) || !path_exists(&airr) {
+            paths.push(format!("{json}.lz4"));
+        } else {
+            paths.push(airr);
+        }
+    }
+    if !ctl.gen_opt.refname.is_empty() {
+        paths.push(ctl.gen_opt.refname.clone());
+    }
+    if !ctl.gen_opt.donor_genotype.is_empty() {
+        paths.push(ctl.gen_opt.donor_genotype.clone());
+    }
+    if !ctl.gen_opt.incremental.is_empty() {
+        paths.push(ctl.gen_opt.incremental.clone());
+    }
+    for path in &paths {
+        if !ctl.pathlist.contains(path) {
+            ctl.pathlist.push(path.clone());
+        }
+    }
+    ctl.last_modified = ctl
+        .pathlist
+        .iter()
+        .map(|p| {
+            fs::metadata(p)
+                .and_then(|m| m.modified())
+                .unwrap_or(UNIX_EPOCH)
+        })
+        .collect();
+}
+
+// The general options, except for those that name output files or checkpoints, or record the
+// CPU time at the start of the run.  None of these affect main_enclone_start, and the
+// checkpoints are described by the input lines of the key.
+
+fn start_gen_opt(g: &GeneralOpt) -> GeneralOpt {
+    GeneralOpt {
+        fasta: String::new(),
+        fasta_filename: String::new(),
+        fasta_aa_filename: String::new(),
+        clustal_aa: String::new(),
+        clustal_dna: String::new(),
+        phylip_aa: String::new(),
+        phylip_dna: String::new(),
+        binary: String::new(),
+        proto: String::new(),
+        proto_index: String::new(),
+        fate_file: String::new(),
+        fate_ledger: String::new(),
+        fate_summary: String::new(),
+        checkpoint: String::new(),
+        incremental: String::new(),
+        incremental_report: String::new(),
+        benchmark: String::new(),
+        airr_rearrangement: String::new(),
+        airr_cell: String::new(),
+        group_table: String::new(),
+        newick: String::new(),
+        cpu_all_start: 0,
+        cpu_this_start: 0,
+        ..g.clone()
+    }
+}
+
+// Describe everything that main_enclone_start depends on.  Two runs having the same key
+// compute the same EncloneExacts.
+
+pub fn checkpoint_key(setup: &EncloneSetup, filters: &FilterPipeline) -> String {
+    let ctl = &setup.ctl;
+    let mut key = String::new();
+    for (path, t) in ctl.pathlist.iter().zip(ctl.last_modified.iter()) {
+        let t = t.duration_since(UNIX_EPOCH).unwrap_or_default();
+        writeln!(key, "input {path} {}.{:09}", t.as_secs(), t.subsec_nanos()).unwrap();
+    }
+    let origin = &ctl.origin_info;
+    writeln!(key, "datasets {:?}", origin.dataset_path).unwrap();
+    writeln!(key, "gex {:?}", origin.gex_path).unwrap();
+    writeln!(key, "ids {:?}", origin.dataset_id).unwrap();
+    writeln!(key, "donors {:?}", origin.donor_id).unwrap();
+    writeln!(key, "origins {:?}", origin.origin_id).unwrap();
+    writeln!(key, "heur {:?}", ctl.heur).unwrap();
+    writeln!(key, "allele {:?}", ctl.allele_alg_opt).unwrap();
+    writeln!(key, "join {:?}", ctl.join_alg_opt).unwrap();
+    writeln!(key, "filt {:?}", ctl.clono_filt_opt_def).unwrap();
+    writeln!(key, "filt2 {:?}", ctl.clono_filt_opt).unwrap();
+    writeln!(key, "impropers {}", ctl.merge_all_impropers).unwrap();
+    writeln!(key, "gen {:?}", start_gen_opt(&ctl.gen_opt)).unwrap();
+    writeln!(key, "is_bcr {}", setup.is_bcr).unwrap();
+    let names = filters.exact.iter().map(|f| f.name()).collect::<Vec<_>>();
+    writeln!(key, "exact filters {names:?}").unwrap();
+    let names = filters.orbit.iter().map(|f| f.name()).collect::<Vec<_>>();
+    writeln!(key, "orbit filters {names:?}").unwrap();
+    key
+}
+
+// Reduce a key to the lines that describe the options, dropping those that describe the input.
+// A checkpoint may only be extended by INCREMENTAL if its options agree with the current ones.
+
+fn options_key(key: &str) -> String {
+    const INPUT: [&str; 6] = ["input ", "datasets ", "gex ", "ids ", "donors ", "origins "];
+    key.lines()
+        .filter(|line| !INPUT.iter().any(|p| line.starts_with(p)))
+        .map(|line| format!("{line}\n"))
+        .collect()
+}
+
+// Open a checkpoint and read its header.
+
+fn read_header(path: &str) -> Result<(BufReader<File>, CheckpointHeader), String> {
+    let f =
+        File::open(path).map_err(|e| format!("\nCould not open checkpoint file {path}: {e}.\n"))?;
+    let mut f = BufReader::new(f);
+    let mut magic = vec![0; CHECKPOINT_MAGIC.len()];
+    if f.read_exact(&mut magic).is_err() || magic != CHECKPOINT_MAGIC {
+        return Err(format!(
+            "\nThe file {path} is not an enclone checkpoint.  Please delete it, or specify \
+             a different path for CHECKPOINT.\n"
+        ));
+    }
+    let header: CheckpointHeader = bincode::deserialize_from(&mut f)
+        .map_err(|e| format!("\nThe checkpoint file {path} is damaged: {e}.\n"))?;
+    Ok((f, header))
+}
+
+// Read the EncloneExacts from a checkpoint.  Return None if there is no checkpoint, or if it is
+// out of date.  The caller must recompute sr.
+
+pub fn read_checkpoint(path: &str, key: &str) -> Result<Option<EncloneExacts>, String> {
+    if !path_exists(path) {
+        return Ok(None);
+    }
+    let (mut f, header) = read_header(path)?;
+    if header.version != CHECKPOINT_VERSION
+        || header.enclone_version != env!("CARGO_PKG_VERSION")
+        || header.key != key
+    {
+        return Ok(None);
+    }
+    let ex: EncloneExacts = bincode::deserialize_from(&mut f)
+        .map_err(|e| format!("\nThe checkpoint file {path} is damaged: {e}.\n"))?;
+    Ok(Some(ex))
+}
+
+// Read the EncloneExacts from a previous checkpoint that is to be extended (INCREMENTAL), along
+// with the names behind its indices.  Unlike read_checkpoint, it is an error if the checkpoint
+// cannot be used.  The caller must recompute sr.
+
+pub fn read_previous(path: &str, key: &str) -> Result<(EncloneExacts, CheckpointOrigin), String> {
+    if !path_exists(path) {
+        return Err(format!(
+            "\nThe checkpoint file {path} given by INCREMENTAL does not exist.\n"
+        ));
+    }
+    let (mut f, header) = read_header(path)?;
+    if header.version != CHECKPOINT_VERSION || header.enclone_version != env!("CARGO_PKG_VERSION") {
+        return Err(format!(
+            "\nThe checkpoint file {path} given by INCREMENTAL was written by a different \
+             version of enclone.\nPlease recompute it.\n"
+        ));
+    }
+    if options_key(&header.key) != options_key(key) {
+        return Err(format!(
+            "\nThe checkpoint file {path} given by INCREMENTAL was computed using different \
+             options than those\nspecified now.  Only the datasets may differ.\n"
+        ));
+    }
+    let ex: EncloneExacts = bincode::deserialize_from(&mut f)
+        .map_err(|e| format!("\nThe checkpoint file {path} is damaged: {e}.\n"))?;
+    Ok((ex, header.origin))
+}
+
+pub fn write_checkpoint(
+    path: &str,
+    key: &str,
+    origin: &CheckpointOrigin,
+    ex: &EncloneExacts,
+) -> Result<(), String> {
+    let header = CheckpointHeader {
+        version: CHECKPOINT_VERSION,
+        enclone_version: env!("CARGO_PKG_VERSION").to_string(),
+        key: key.to_string(),
+        origin: origin.clone(),
+    };
+    let f = File::create(path)
+        .map_err(|e| format!("\nCould not create checkpoint file {path}: {e}.\n"))?;
+    let mut f = BufWriter::new(f);
+    f.write_all(CHECKPOINT_MAGIC)
+        .map_err(|e| format!("\nFailed to write checkpoint file {path}: {e}.\n"))?;
+    bincode::serialize_into(&mut f, &header)
+        .and_then(|_| bincode::serialize_into(&mut f, ex))
+        .map_err(|e| format!("\nFailed to write checkpoint file {path}: {e}.\n"))?;
+    f.flush()
+        .map_err(|e| format!("\nFailed to write checkpoint file {path}: {e}.\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let path = std::env::temp_dir().join(format!("checkpoint_test_{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let ex = EncloneExacts {
+            orbits: vec![vec![0, 2], vec![1]],
+            vdj_cells: vec![vec!["AAAC-1".to_string()]],
+            is_bcr: true,
+            ..Default::default()
+        };
+        let origin = CheckpointOrigin {
+            dataset_path: vec!["d1".to_string()],
+            donor_list: vec!["donor".to_string()],
+            ..Default::default()
+        };
+        write_checkpoint(path, "input a 1\njoin x\n", &origin, &ex).unwrap();
+        let ex2 = read_checkpoint(path, "input a 1\njoin x\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(ex2.orbits, ex.orbits);
+        assert_eq!(ex2.vdj_cells, ex.vdj_cells);
+        assert!(ex2.is_bcr);
+        assert!(read_checkpoint(path, "input a 2\njoin x\n")
+            .unwrap()
+            .is_none());
+        let (ex3, origin3) = read_previous(path, "input b 2\njoin x\n").unwrap();
+        assert_eq!(ex3.orbits, ex.orbits);
+        assert_eq!(origin3.dataset_path, origin.dataset_path);
+        assert_eq!(origin3.donor_list, origin.donor_list);
+        assert!(read_previous(path, "input a 1\njoin y\n").is_err());
+        fs::write(path, "not a checkpoint").unwrap();
+        assert!(read_checkpoint(path, "input a 1\njoin x\n").is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_checkpoint_key() {
+        let filters = FilterPipeline::default();
+        let mut setup = EncloneSetup::default();
+        let key = checkpoint_key(&setup, &filters);
+
+        // Options that affect the computation change the key.
+
+        setup.ctl.merge_all_impropers = true;
+        assert_ne!(checkpoint_key(&setup, &filters), key);
+        setup.ctl.merge_all_impropers = false;
+        setup.ctl.clono_filt_opt.qual_filter = true;
+        assert_ne!(checkpoint_key(&setup, &filters), key);
+        setup.ctl.clono_filt_opt.qual_filter = false;
+        setup.ctl.clono_filt_opt_def.weak_onesies = true;
+        assert_ne!(checkpoint_key(&setup, &filters), key);
+        setup.ctl.clono_filt_opt_def.weak_onesies = false;
+        setup.ctl.gen_opt.max_heavies = 1;
+        assert_ne!(checkpoint_key(&setup, &filters), key);
+        setup.ctl.gen_opt.max_heavies = 0;
+
+        // Output files and checkpoints do not.
+
+        setup.ctl.gen_opt.proto = "out.proto".to_string();
+        setup.ctl.gen_opt.airr_rearrangement = "airr.tsv".to_string();
+        setup.ctl.gen_opt.checkpoint = "checkpoint".to_string();
+        setup.ctl.gen_opt.incremental = "previous".to_string();
+        assert_eq!(checkpoint_key(&setup, &filters), key);
+    }
+}
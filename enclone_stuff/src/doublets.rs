@@ -1,11 +1,9 @@
 // Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
 
 // Delete exact subclonotypes that appear to represent doublets.
-//
-// THIS FILTER DOESN'T PROPERLY TRACK FATE.
 
 use enclone_core::{
-    barcode_fate::BarcodeFate,
+    barcode_fate::{BarcodeFate, FateLedger},
     defs::{CloneInfo, EncloneControl, ExactClonotype},
 };
 use enclone_print::define_mat::{define_mat, setup_define_mat};
@@ -29,9 +27,9 @@ pub fn delete_doublets(
     raw_joins: &[Vec<usize>],
     refdata: &RefData,
     dref: &[DonorReferenceItem],
-    fate: &mut [HashMap<String, BarcodeFate>],
+    fate: &mut [FateLedger],
 ) {
-    if ctl.clono_filt_opt_def.doublet {
+    if ctl.clono_filt_opt_def.eval(ctl.clono_filt_opt_def.doublet) {
         let t = Instant::now();
         // Define pure subclonotypes.  To do this we break each clonotype up by chain signature.
         // Note duplication of code with print_clonotypes.rs.  And this is doing some
@@ -227,27 +225,40 @@ pub fn delete_doublets(
                 to_delete[*m] = true;
             }
         }
-        let mut orbits2 = Vec::<Vec<i32>>::new();
-        for o in orbits.iter() {
-            let mut o = o.clone();
-            let mut del2 = vec![false; o.len()];
-            for j in 0..o.len() {
-                let id = info[o[j] as usize].clonotype_index;
-                if to_delete[id] {
-                    del2[j] = true;
-                    let x: &CloneInfo = &info[o[j] as usize];
-                    let ex = &exact_clonotypes[x.clonotype_index];
-                    for k in 0..ex.ncells() {
-                        let li = ex.clones[k][0].dataset_index;
-                        let bc = &ex.clones[k][0].barcode;
-                        fate[li].insert(bc.clone(), BarcodeFate::Doublet);
+
+        // Record the fate of each cell in a deleted exact subclonotype, once per exact
+        // subclonotype, as is done for the other filters.
+
+        let removed = ctl
+            .clono_filt_opt_def
+            .delete(ctl.clono_filt_opt_def.doublet);
+        for (id, ex) in exact_clonotypes.iter().enumerate() {
+            if to_delete[id] {
+                for clone in &ex.clones {
+                    fate[clone[0].dataset_index].insert(
+                        clone[0].barcode.clone(),
+                        BarcodeFate::Doublet,
+                        removed,
+                    );
+                }
+            }
+        }
+        if removed {
+            let mut orbits2 = Vec::<Vec<i32>>::new();
+            for o in orbits.iter() {
+                let mut o = o.clone();
+                let mut del2 = vec![false; o.len()];
+                for j in 0..o.len() {
+                    let id = info[o[j] as usize].clonotype_index;
+                    if to_delete[id] {
+                        del2[j] = true;
                     }
                 }
+                erase_if(&mut o, &del2);
+                orbits2.push(o);
             }
-            erase_if(&mut o, &del2);
-            orbits2.push(o);
+            *orbits = orbits2;
         }
-        *orbits = orbits2;
         ctl.perf_stats(&t, "doublet filtering tail");
     }
 }
@@ -2,6 +2,7 @@
 
 // Filter using constraints imposed by FCELL.
 
+use enclone_core::barcode_fate::{BarcodeFate, FateLedger};
 use enclone_core::defs::{CloneInfo, EncloneControl, ExactClonotype, GexInfo};
 use enclone_print::print_utils4::get_gex_matrix_entry;
 use evalexpr::{ContextWithMutableVariables, HashMapContext};
@@ -21,6 +22,7 @@ pub fn filter_by_fcell(
     info: &[CloneInfo],
     exact_clonotypes: &mut [ExactClonotype],
     gex_info: &GexInfo,
+    fate: &mut [FateLedger],
 ) -> Result<(), String> {
     if !ctl.clono_filt_opt_def.fcell.is_empty() {
         // Load the GEX and FB data.  This is quite horrible: the code and computation are
@@ -105,8 +107,9 @@ pub fn filter_by_fcell(
         });
         ctl.perf_stats(&tdi, "setting up readers, zero");
 
-        // Proceed.
+        // Proceed.  Under FILTER_IMPACT, cells are only marked.
 
+        let removed = ctl.clono_filt_opt_def.delete(true);
         let mut orbits2 = Vec::<Vec<i32>>::new();
         for o in orbits.iter() {
             let mut o = o.clone();
@@ -202,7 +205,8 @@ pub fn filter_by_fcell(
                         }
                     }
                     if !keep {
-                        *d = true;
+                        *d = removed;
+                        fate[li].insert(bc.clone(), BarcodeFate::Fcell, removed);
                     }
                 }
                 erase_if(&mut ex.clones, &to_delete);
@@ -0,0 +1,781 @@
+// Copyright (c) 2023 10X Genomics, Inc. All rights reserved.
+
+// The filters applied by main_enclone_start, as a pipeline of trait objects.
+//
+// There are two kinds of filters.  An ExactFilter acts before exact subclonotypes are joined,
+// either on the contigs of each barcode, or on the exact subclonotypes.  An OrbitFilter acts on
+// the orbits of the join equivalence relation, i.e. on the clonotypes before they are printed.
+// The default pipeline consists of the built-in filters, in the order in which they have
+// always been applied.  Further filters may be inserted anywhere in it, and are then run by
+// main_enclone_start_with_filters.
+//
+// Every filter is responsible for recording the fate of each barcode it acts on, and for
+// respecting mark-only mode (FILTER_IMPACT), via ctl.clono_filt_opt_def.delete.
+
+use crate::fcell::filter_by_fcell;
+use crate::filter_umi::filter_umi;
+use crate::some_filters::some_filters;
+use enclone::graph_filter::graph_filter;
+use enclone::misc1::cross_filter;
+use enclone_core::barcode_fate::{BarcodeFate, FateLedger};
+use enclone_core::defs::{CloneInfo, EncloneControl, ExactClonotype, GexInfo, TigData};
+use enclone_print::define_mat::{define_mat, setup_define_mat};
+use enclone_proto::types::DonorReferenceItem;
+use equiv::EquivRel;
+use itertools::Itertools;
+use qd::Double;
+use std::collections::HashMap;
+use std::time::Instant;
+use vdj_ann::refx::RefData;
+use vector_utils::{bin_member, erase_if, unique_sort};
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+/// What every filter can see.
+pub struct FilterContext<'a> {
+    pub ctl: &'a EncloneControl,
+    pub refdata: &'a RefData,
+    pub gex_info: &'a GexInfo,
+    pub is_bcr: bool,
+    /// For each dataset, the sorted barcodes that the VDJ pipeline called as cells.
+    pub vdj_cells: &'a [Vec<String>],
+    /// For each dataset, the sorted barcodes that were called as cells by GEX, if
+    /// gex_cells_specified is set for the dataset.
+    pub gex_cells: &'a [Vec<String>],
+    pub gex_cells_specified: &'a [bool],
+}
+
+/// What an orbit filter can additionally see, once exact subclonotypes have been joined.
+pub struct JoinContext<'a> {
+    pub info: &'a [CloneInfo],
+    pub eq: &'a EquivRel,
+    pub to_bc: &'a HashMap<(usize, usize), Vec<String>>,
+    pub sr: &'a [Vec<Double>],
+    pub raw_joins: &'a [Vec<usize>],
+    pub disintegrated: &'a [bool],
+    pub drefs: &'a [DonorReferenceItem],
+}
+
+/// A filter applied before exact subclonotypes are joined.  Implement whichever of the two
+/// stages the filter belongs to.
+pub trait ExactFilter {
+    /// A short name, used to locate the filter in a pipeline and for PERF output.
+    fn name(&self) -> &str;
+
+    /// Filter barcodes, each given by its productive contigs, before they are grouped into
+    /// exact subclonotypes.
+    fn filter_barcodes(
+        &self,
+        _ctx: &FilterContext,
+        _tig_bc: &mut Vec<Vec<TigData>>,
+        _fate: &mut [FateLedger],
+    ) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Filter exact subclonotypes.
+    fn filter_exacts(
+        &self,
+        _ctx: &FilterContext,
+        _exact_clonotypes: &mut Vec<ExactClonotype>,
+        _fate: &mut [FateLedger],
+    ) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// A filter applied to orbits, each of which is a list of indices into join.info.  Exact
+/// subclonotypes are referred to by index, so a filter may remove cells from them, but must not
+/// remove them from exact_clonotypes; instead it removes them from the orbits.
+pub trait OrbitFilter {
+    /// A short name, used to locate the filter in a pipeline and for PERF output.
+    fn name(&self) -> &str;
+
+    fn filter_orbits(
+        &self,
+        ctx: &FilterContext,
+        join: &JoinContext,
+        orbits: &mut Vec<Vec<i32>>,
+        exact_clonotypes: &mut [ExactClonotype],
+        fate: &mut [FateLedger],
+    ) -> Result<(), String>;
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+/// The filters, in the order in which they are applied.
+pub struct FilterPipeline {
+    pub exact: Vec<Box<dyn ExactFilter>>,
+    pub orbit: Vec<Box<dyn OrbitFilter>>,
+}
+
+impl Default for FilterPipeline {
+    fn default() -> Self {
+        FilterPipeline {
+            exact: vec![
+                Box::new(GraphFilter),
+                Box::new(CrossFilter),
+                Box::new(FoursieKill),
+                Box::new(MaxHeavies),
+            ],
+            orbit: vec![
+                Box::new(UmiFilter),
+                Box::new(GexCellFilter),
+                Box::new(FcellFilter),
+                Box::new(SplitMaxChains),
+                Box::new(SomeFilters),
+            ],
+        }
+    }
+}
+
+impl FilterPipeline {
+    /// Insert an exact filter right after the one having the given name, or at the end if
+    /// there is no such filter.
+    pub fn insert_exact_after(&mut self, name: &str, filter: Box<dyn ExactFilter>) {
+        let pos = self.exact.iter().position(|f| f.name() == name);
+        let pos = pos.map_or(self.exact.len(), |p| p + 1);
+        self.exact.insert(pos, filter);
+    }
+
+    /// Insert an orbit filter right after the one having the given name, or at the end if
+    /// there is no such filter.
+    pub fn insert_orbit_after(&mut self, name: &str, filter: Box<dyn OrbitFilter>) {
+        let pos = self.orbit.iter().position(|f| f.name() == name);
+        let pos = pos.map_or(self.orbit.len(), |p| p + 1);
+        self.orbit.insert(pos, filter);
+    }
+
+    pub fn filter_barcodes(
+        &self,
+        ctx: &FilterContext,
+        tig_bc: &mut Vec<Vec<TigData>>,
+        fate: &mut [FateLedger],
+    ) -> Result<(), String> {
+        for f in &self.exact {
+            let t = Instant::now();
+            f.filter_barcodes(ctx, tig_bc, fate)?;
+            ctx.ctl
+                .perf_stats(&t, &format!("in {} barcode filter", f.name()));
+        }
+        Ok(())
+    }
+
+    pub fn filter_exacts(
+        &self,
+        ctx: &FilterContext,
+        exact_clonotypes: &mut Vec<ExactClonotype>,
+        fate: &mut [FateLedger],
+    ) -> Result<(), String> {
+        for f in &self.exact {
+            let t = Instant::now();
+            f.filter_exacts(ctx, exact_clonotypes, fate)?;
+            ctx.ctl
+                .perf_stats(&t, &format!("in {} exact filter", f.name()));
+        }
+        Ok(())
+    }
+
+    pub fn filter_orbits(
+        &self,
+        ctx: &FilterContext,
+        join: &JoinContext,
+        orbits: &mut Vec<Vec<i32>>,
+        exact_clonotypes: &mut [ExactClonotype],
+        fate: &mut [FateLedger],
+    ) -> Result<(), String> {
+        for f in &self.orbit {
+            let t = Instant::now();
+            f.filter_orbits(ctx, join, orbits, exact_clonotypes, fate)?;
+            ctx.ctl
+                .perf_stats(&t, &format!("in {} orbit filter", f.name()));
+        }
+        Ok(())
+    }
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+// The built-in filters.
+
+/// Filter using the light --> heavy graph.
+pub struct GraphFilter;
+
+impl ExactFilter for GraphFilter {
+    fn name(&self) -> &str {
+        "graph"
+    }
+
+    fn filter_barcodes(
+        &self,
+        ctx: &FilterContext,
+        tig_bc: &mut Vec<Vec<TigData>>,
+        fate: &mut [FateLedger],
+    ) -> Result<(), String> {
+        graph_filter(ctx.ctl, tig_bc, ctx.ctl.gen_opt.graph, fate);
+        Ok(())
+    }
+}
+
+/// Filter out V..J segments that appear to have spread across datasets.
+pub struct CrossFilter;
+
+impl ExactFilter for CrossFilter {
+    fn name(&self) -> &str {
+        "cross"
+    }
+
+    fn filter_barcodes(
+        &self,
+        ctx: &FilterContext,
+        tig_bc: &mut Vec<Vec<TigData>>,
+        fate: &mut [FateLedger],
+    ) -> Result<(), String> {
+        cross_filter(ctx.ctl, tig_bc, fate);
+        Ok(())
+    }
+}
+
+/// Filter out some foursie artifacts: exact subclonotypes having four chains, two of which
+/// form a two-chain exact subclonotype having at least ten cells.
+pub struct FoursieKill;
+
+impl ExactFilter for FoursieKill {
+    fn name(&self) -> &str {
+        "foursie_kill"
+    }
+
+    fn filter_exacts(
+        &self,
+        ctx: &FilterContext,
+        exact_clonotypes: &mut Vec<ExactClonotype>,
+        fate: &mut [FateLedger],
+    ) -> Result<(), String> {
+        let opt = &ctx.ctl.clono_filt_opt_def;
+        let removed = opt.delete(opt.weak_foursies);
+        let mut to_delete = vec![false; exact_clonotypes.len()];
+        let mut twosies = Vec::<(&[u8], &[u8])>::new();
+        for ex in exact_clonotypes.iter() {
+            if ex.share.len() == 2 && (ex.share[0].left ^ ex.share[1].left) && ex.ncells() >= 10 {
+                twosies.push((ex.share[0].seq.as_ref(), ex.share[1].seq.as_ref()));
+            }
+        }
+        unique_sort(&mut twosies);
+        for (ex, d) in exact_clonotypes.iter().zip(to_delete.iter_mut()) {
+            if ex.share.len() == 4 {
+                for (i1, s1) in ex.share.iter().enumerate() {
+                    for s2 in &ex.share[i1 + 1..4] {
+                        if s1.left ^ s2.left {
+                            let p = (s1.seq.as_ref(), s2.seq.as_ref());
+                            if bin_member(&twosies, &p) {
+                                *d = true;
+                                for clone in &ex.clones {
+                                    fate[clone[0].dataset_index].insert(
+                                        clone[0].barcode.clone(),
+                                        BarcodeFate::FoursieKill,
+                                        removed,
+                                    );
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        if removed {
+            erase_if(exact_clonotypes, &to_delete);
+        }
+        Ok(())
+    }
+}
+
+/// If MAX_HEAVIES=1 is specified, filter out exact subclonotypes having more than one heavy
+/// chain.
+pub struct MaxHeavies;
+
+impl ExactFilter for MaxHeavies {
+    fn name(&self) -> &str {
+        "max_heavies"
+    }
+
+    fn filter_exacts(
+        &self,
+        ctx: &FilterContext,
+        exact_clonotypes: &mut Vec<ExactClonotype>,
+        fate: &mut [FateLedger],
+    ) -> Result<(), String> {
+        let opt = &ctx.ctl.clono_filt_opt_def;
+        let on = ctx.ctl.gen_opt.max_heavies == 1;
+        if opt.eval(on) {
+            let removed = opt.delete(on);
+            let mut to_delete = vec![false; exact_clonotypes.len()];
+            for (ex, d) in exact_clonotypes.iter().zip(to_delete.iter_mut()) {
+                let heavies = ex.share.iter().filter(|s| s.left).count();
+                if heavies > 1 {
+                    *d = true;
+                    for clone in &ex.clones {
+                        fate[clone[0].dataset_index].insert(
+                            clone[0].barcode.clone(),
+                            BarcodeFate::MaxHeavies,
+                            removed,
+                        );
+                    }
+                }
+            }
+            if removed {
+                erase_if(exact_clonotypes, &to_delete);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Filter B cells based on UMI counts and UMI count ratios.
+pub struct UmiFilter;
+
+impl OrbitFilter for UmiFilter {
+    fn name(&self) -> &str {
+        "umi"
+    }
+
+    fn filter_orbits(
+        &self,
+        ctx: &FilterContext,
+        join: &JoinContext,
+        orbits: &mut Vec<Vec<i32>>,
+        exact_clonotypes: &mut [ExactClonotype],
+        fate: &mut [FateLedger],
+    ) -> Result<(), String> {
+        filter_umi(orbits, ctx.ctl, exact_clonotypes, join.info, fate);
+        let ctl = ctx.ctl;
+        if !ctl.gen_opt.trace_barcode.is_empty() {
+            for ex in exact_clonotypes.iter() {
+                for clone in &ex.clones {
+                    if clone[0].barcode == ctl.gen_opt.trace_barcode {
+                        println!(
+                            "\nfound {} in an post-umi-filter exact subclonotype having {} cells",
+                            ctl.gen_opt.trace_barcode,
+                            ex.ncells(),
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Remove cells that are not called cells by GEX or feature barcodes.
+pub struct GexCellFilter;
+
+impl OrbitFilter for GexCellFilter {
+    fn name(&self) -> &str {
+        "gex"
+    }
+
+    fn filter_orbits(
+        &self,
+        ctx: &FilterContext,
+        join: &JoinContext,
+        orbits: &mut Vec<Vec<i32>>,
+        exact_clonotypes: &mut [ExactClonotype],
+        fate: &mut [FateLedger],
+    ) -> Result<(), String> {
+        let ctl = ctx.ctl;
+        let mut orbits2 = Vec::<Vec<i32>>::new();
+        for mut o in std::mem::take(orbits) {
+            let mut to_deletex = vec![false; o.len()];
+            for (&x, dx) in o.iter().zip(to_deletex.iter_mut()) {
+                let x: &CloneInfo = &join.info[x as usize];
+                let ex = &mut exact_clonotypes[x.clonotype_index];
+                let mut to_delete = vec![false; ex.ncells()];
+                for (clone, d) in ex.clones.iter().take(ex.ncells()).zip(to_delete.iter_mut()) {
+                    let li = clone[0].dataset_index;
+                    let bc = &clone[0].barcode;
+                    if ctl.gen_opt.cellranger {
+                        if ctx.gex_cells_specified[li] && !bin_member(&ctx.gex_cells[li], bc) {
+                            *d = ctl.clono_filt_opt_def.delete(true);
+                            fate[li].insert(bc.clone(), BarcodeFate::NotGexCell, *d);
+                        }
+                    } else if !ctl.origin_info.gex_path[li].is_empty() {
+                        let gbc = &ctx.gex_info.gex_cell_barcodes[li];
+                        if !bin_member(gbc, bc) {
+                            *d = ctl.clono_filt_opt_def.delete(!ctl.clono_filt_opt_def.ngex);
+                            fate[li].insert(bc.clone(), BarcodeFate::NotGexCell, *d);
+                        }
+                    }
+                }
+                erase_if(&mut ex.clones, &to_delete);
+                if ex.ncells() == 0 {
+                    *dx = true;
+                }
+            }
+            erase_if(&mut o, &to_deletex);
+            if !o.is_empty() {
+                orbits2.push(o);
+            }
+        }
+        *orbits = orbits2;
+        Ok(())
+    }
+}
+
+/// Filter using constraints imposed by FCELL.
+pub struct FcellFilter;
+
+impl OrbitFilter for FcellFilter {
+    fn name(&self) -> &str {
+        "fcell"
+    }
+
+    fn filter_orbits(
+        &self,
+        ctx: &FilterContext,
+        join: &JoinContext,
+        orbits: &mut Vec<Vec<i32>>,
+        exact_clonotypes: &mut [ExactClonotype],
+        fate: &mut [FateLedger],
+    ) -> Result<(), String> {
+        filter_by_fcell(
+            ctx.ctl,
+            orbits,
+            join.info,
+            exact_clonotypes,
+            ctx.gex_info,
+            fate,
+        )
+    }
+}
+
+/// Break up clonotypes containing a large number of chains.  These are very likely to be false
+/// merges.  No cells are removed, so no fates are recorded.
+pub struct SplitMaxChains;
+
+impl OrbitFilter for SplitMaxChains {
+    fn name(&self) -> &str {
+        "split_max_chains"
+    }
+
+    fn filter_orbits(
+        &self,
+        ctx: &FilterContext,
+        join: &JoinContext,
+        orbits: &mut Vec<Vec<i32>>,
+        exact_clonotypes: &mut [ExactClonotype],
+        _fate: &mut [FateLedger],
+    ) -> Result<(), String> {
+        let exact_clonotypes = &*exact_clonotypes;
+        *orbits = std::mem::take(orbits)
+            .into_iter()
+            .flat_map(|orbit| {
+                let (od, exacts) = setup_define_mat(&orbit, join.info);
+                let mat = define_mat(
+                    ctx.is_bcr,
+                    join.to_bc,
+                    join.sr,
+                    ctx.ctl,
+                    exact_clonotypes,
+                    &exacts,
+                    &od,
+                    join.info,
+                    join.raw_joins,
+                    ctx.refdata,
+                    join.drefs,
+                );
+                let num_chains = mat.len();
+                if num_chains < ctx.ctl.join_alg_opt.split_max_chains {
+                    vec![orbit]
+                } else {
+                    let exacts_of_chains = mat
+                        .iter()
+                        .enumerate()
+                        .flat_map(|(chain_num, chain_in_exact)| {
+                            exacts
+                                .iter()
+                                .zip_eq(chain_in_exact.iter())
+                                .filter_map(move |(e, chain)| chain.map(|_| (e, chain_num)))
+                        })
+                        .into_group_map()
+                        .into_iter()
+                        .map(|(k, v)| (v, *k))
+                        .into_group_map();
+
+                    let mut group_of_exacts = HashMap::new();
+                    let mut group_num = 0;
+                    for (chains, chain_exacts) in exacts_of_chains {
+                        if chains.len() == 1 {
+                            for e in chain_exacts {
+                                group_of_exacts.insert(e, group_num);
+                                group_num += 1;
+                            }
+                        } else {
+                            for e in chain_exacts {
+                                group_of_exacts.insert(e, group_num);
+                            }
+                            group_num += 1;
+                        }
+                    }
+
+                    let mut groups = vec![vec![]; group_num];
+
+                    for (_, exact_clonotype_id, val) in &od {
+                        groups[group_of_exacts[exact_clonotype_id]].push(*val);
+                    }
+
+                    // To split every subclonotype
+                    // od
+                    //     .into_iter()
+                    //     .group_by(|o| o.1)
+                    //     .into_iter()
+                    //     .map(|(_, vals)| vals.map(|v| v.2).collect())
+                    //     .collect();
+                    groups
+                }
+            })
+            .collect();
+        Ok(())
+    }
+}
+
+/// The doublet, signature, weak chain and qual filters, together with merging of onesies and
+/// splitting of orbits.  See some_filters.
+pub struct SomeFilters;
+
+impl OrbitFilter for SomeFilters {
+    fn name(&self) -> &str {
+        "some_filters"
+    }
+
+    fn filter_orbits(
+        &self,
+        ctx: &FilterContext,
+        join: &JoinContext,
+        orbits: &mut Vec<Vec<i32>>,
+        exact_clonotypes: &mut [ExactClonotype],
+        fate: &mut [FateLedger],
+    ) -> Result<(), String> {
+        some_filters(
+            orbits,
+            ctx.is_bcr,
+            join.to_bc,
+            join.sr,
+            ctx.ctl,
+            exact_clonotypes,
+            join.info,
+            join.raw_joins,
+            join.eq,
+            join.disintegrated,
+            fate,
+            ctx.refdata,
+            join.drefs,
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enclone::join_test_data::{test_ctl, JoinTestData, TestChain, TestExact, HEAVY_V, LIGHT_V};
+
+    fn exact(ins: &[u8], ncells: usize) -> TestExact {
+        TestExact {
+            heavy: TestChain {
+                v: HEAVY_V,
+                muts: Vec::new(),
+                ins: ins.to_vec(),
+            },
+            light: TestChain {
+                v: LIGHT_V,
+                muts: Vec::new(),
+                ins: b"CAG".to_vec(),
+            },
+            ncells,
+        }
+    }
+
+    // A filter that does nothing, other than having a name.
+
+    struct Named(&'static str);
+
+    impl ExactFilter for Named {
+        fn name(&self) -> &str {
+            self.0
+        }
+    }
+
+    impl OrbitFilter for Named {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn filter_orbits(
+            &self,
+            _ctx: &FilterContext,
+            _join: &JoinContext,
+            _orbits: &mut Vec<Vec<i32>>,
+            _exact_clonotypes: &mut [ExactClonotype],
+            _fate: &mut [FateLedger],
+        ) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    // A custom filter that removes exact subclonotypes having fewer than the given number of
+    // cells, recording the fate of their barcodes as a built-in filter would.
+
+    struct MinCells(usize);
+
+    impl ExactFilter for MinCells {
+        fn name(&self) -> &str {
+            "min_cells"
+        }
+
+        fn filter_exacts(
+            &self,
+            ctx: &FilterContext,
+            exact_clonotypes: &mut Vec<ExactClonotype>,
+            fate: &mut [FateLedger],
+        ) -> Result<(), String> {
+            let removed = ctx.ctl.clono_filt_opt_def.delete(true);
+            let mut to_delete = vec![false; exact_clonotypes.len()];
+            for (ex, d) in exact_clonotypes.iter().zip(to_delete.iter_mut()) {
+                if ex.ncells() < self.0 {
+                    *d = true;
+                    for clone in &ex.clones {
+                        fate[clone[0].dataset_index].insert(
+                            clone[0].barcode.clone(),
+                            BarcodeFate::Umi,
+                            removed,
+                        );
+                    }
+                }
+            }
+            if removed {
+                erase_if(exact_clonotypes, &to_delete);
+            }
+            Ok(())
+        }
+    }
+
+    fn exact_names(p: &FilterPipeline) -> Vec<&str> {
+        p.exact.iter().map(|f| f.name()).collect()
+    }
+
+    fn orbit_names(p: &FilterPipeline) -> Vec<&str> {
+        p.orbit.iter().map(|f| f.name()).collect()
+    }
+
+    // The default pipeline applies the built-in filters in the order in which main_enclone_start
+    // applied them before they were made into a pipeline, which is also the order of
+    // BarcodeFate::PIPELINE_ORDER.
+
+    #[test]
+    fn test_default_order() {
+        let p = FilterPipeline::default();
+        assert_eq!(
+            exact_names(&p),
+            ["graph", "cross", "foursie_kill", "max_heavies"]
+        );
+        assert_eq!(
+            orbit_names(&p),
+            ["umi", "gex", "fcell", "split_max_chains", "some_filters"]
+        );
+        let order = |f: BarcodeFate| BarcodeFate::PIPELINE_ORDER.iter().position(|&g| g == f);
+        let fates = [
+            BarcodeFate::GraphFilter,
+            BarcodeFate::Cross,
+            BarcodeFate::FoursieKill,
+            BarcodeFate::MaxHeavies,
+            BarcodeFate::Umi,
+            BarcodeFate::NotGexCell,
+            BarcodeFate::Fcell,
+            BarcodeFate::Doublet,
+        ];
+        assert!(fates.windows(2).all(|w| order(w[0]) < order(w[1])));
+    }
+
+    #[test]
+    fn test_insert_after() {
+        let mut p = FilterPipeline::default();
+        p.insert_exact_after("cross", Box::new(Named("custom1")));
+        p.insert_exact_after("max_heavies", Box::new(Named("custom2")));
+        p.insert_exact_after("no_such_filter", Box::new(Named("custom3")));
+        assert_eq!(
+            exact_names(&p),
+            [
+                "graph",
+                "cross",
+                "custom1",
+                "foursie_kill",
+                "max_heavies",
+                "custom2",
+                "custom3"
+            ]
+        );
+        p.insert_orbit_after("gex", Box::new(Named("custom4")));
+        p.insert_orbit_after("custom4", Box::new(Named("custom5")));
+        p.insert_orbit_after("no_such_filter", Box::new(Named("custom6")));
+        assert_eq!(
+            orbit_names(&p),
+            [
+                "umi",
+                "gex",
+                "custom4",
+                "custom5",
+                "fcell",
+                "split_max_chains",
+                "some_filters",
+                "custom6"
+            ]
+        );
+    }
+
+    // A custom filter inserted into the default pipeline records the fate of the barcodes it
+    // removes, or under FILTER_IMPACT, only marks.
+
+    #[test]
+    fn test_custom_filter_fate() {
+        for mark_only in [false, true] {
+            let mut ctl = test_ctl();
+            ctl.clono_filt_opt_def.mark_only = mark_only;
+            let d = JoinTestData::new(
+                ctl,
+                &[
+                    exact(b"CGAGGGTATAGC", 2),
+                    exact(b"CGAGGGTATAGA", 1),
+                    exact(b"CGAGGGTATAGG", 3),
+                ],
+            );
+            let gex_info = GexInfo::default();
+            let ctx = FilterContext {
+                ctl: &d.ctl,
+                refdata: &d.refdata,
+                gex_info: &gex_info,
+                is_bcr: true,
+                vdj_cells: &[],
+                gex_cells: &[],
+                gex_cells_specified: &[],
+            };
+            let mut p = FilterPipeline::default();
+            p.insert_exact_after("foursie_kill", Box::new(MinCells(2)));
+            let mut exact_clonotypes = d.exact_clonotypes.clone();
+            let mut fate = vec![FateLedger::default()];
+            p.filter_exacts(&ctx, &mut exact_clonotypes, &mut fate)
+                .unwrap();
+            let ncells = exact_clonotypes
+                .iter()
+                .map(ExactClonotype::ncells)
+                .collect::<Vec<_>>();
+            if mark_only {
+                assert_eq!(ncells, [2, 1, 3]);
+                assert_eq!(fate[0].get("1-0-1"), None);
+            } else {
+                assert_eq!(ncells, [2, 3]);
+                assert_eq!(fate[0].get("1-0-1"), Some(BarcodeFate::Umi));
+            }
+            assert_eq!(fate[0].fates("1-0-1"), [BarcodeFate::Umi]);
+            assert_eq!(fate[0].len(), 1);
+        }
+    }
+}
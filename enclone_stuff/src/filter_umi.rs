@@ -3,22 +3,19 @@
 // Filter B cells based on UMI counts.
 
 use enclone_core::{
-    barcode_fate::BarcodeFate,
+    barcode_fate::{BarcodeFate, FateLedger},
     defs::{CloneInfo, EncloneControl, ExactClonotype},
 };
-use equiv::EquivRel;
 use stats_utils::binomial_sum;
 use std::cmp::max;
-use std::collections::HashMap;
 use vector_utils::{erase_if, next_diff1_5, reverse_sort, VecUtils};
 
 pub fn filter_umi(
-    eq: &EquivRel,
     orbits: &mut Vec<Vec<i32>>,
     ctl: &EncloneControl,
     exact_clonotypes: &mut [ExactClonotype],
     info: &[CloneInfo],
-    fate: &mut [HashMap<String, BarcodeFate>],
+    fate: &mut [FateLedger],
 ) {
     let (mut is_tcr, mut is_bcr) = (true, true);
     if ctl.gen_opt.tcr {
@@ -39,20 +36,10 @@ pub fn filter_umi(
     // which the sum of its umitot values is greatest, and then in it, find the cell having
     // highest umitot value.  Protect this cell, so long as it has at least two chains.
 
-    *orbits = Vec::<Vec<i32>>::new();
-    let mut reps = Vec::<i32>::new();
-    eq.orbit_reps(&mut reps);
-    if is_tcr {
-        for rep in reps {
-            let mut o = Vec::<i32>::new();
-            eq.orbit(rep, &mut o);
-            orbits.push(o);
-        }
-    } else {
+    if !is_tcr {
+        let orbits0 = std::mem::take(orbits);
         let mut umis = vec![Vec::<usize>::new(); ctl.origin_info.n()];
-        for &rep in &reps {
-            let mut o = Vec::<i32>::new();
-            eq.orbit(rep, &mut o);
+        for o in &orbits0 {
             if o.solo() {
                 let x: &CloneInfo = &info[o[0] as usize];
                 let ex = &exact_clonotypes[x.clonotype_index];
@@ -92,9 +79,7 @@ pub fn filter_umi(
         }
         // if ctl.clono_filt_opt_def.umi_filt || ctl.clono_filt_opt_def.umi_filt_mark {
         const MIN_BASELINE_CELLS: usize = 20;
-        for rep in reps {
-            let mut o = Vec::<i32>::new();
-            eq.orbit(rep, &mut o);
+        for mut o in orbits0 {
             let mut ncells = 0;
             for j in 0..o.len() {
                 let x: &CloneInfo = &info[o[j] as usize];
@@ -185,13 +170,19 @@ pub fn filter_umi(
                             best_ex_sum = ex_sum;
                         }
                         if pass == 3 {
+                            let removed = ctl
+                                .clono_filt_opt_def
+                                .delete(ctl.clono_filt_opt_def.umi_filt);
                             for i in 0..ex.clones.len() {
                                 if to_delete[i] {
-                                    fate[ex.clones[i][0].dataset_index]
-                                        .insert(ex.clones[i][0].barcode.clone(), BarcodeFate::Umi);
+                                    fate[ex.clones[i][0].dataset_index].insert(
+                                        ex.clones[i][0].barcode.clone(),
+                                        BarcodeFate::Umi,
+                                        removed,
+                                    );
                                 }
                             }
-                            if ctl.clono_filt_opt_def.umi_filt {
+                            if removed {
                                 erase_if(&mut ex.clones, &to_delete);
                             }
                         }
@@ -207,7 +198,7 @@ pub fn filter_umi(
                 erase_if(&mut o, &to_deletex);
             }
             if !o.is_empty() {
-                orbits.push(o.clone());
+                orbits.push(o);
             }
         }
         // }
@@ -293,13 +284,19 @@ pub fn filter_umi(
                     }
 
                     if pass == 2 {
+                        let removed = ctl
+                            .clono_filt_opt_def
+                            .delete(ctl.clono_filt_opt_def.umi_ratio_filt);
                         for i in 0..ex.clones.len() {
                             if to_delete[j][i] {
-                                fate[ex.clones[i][0].dataset_index]
-                                    .insert(ex.clones[i][0].barcode.clone(), BarcodeFate::UmiRatio);
+                                fate[ex.clones[i][0].dataset_index].insert(
+                                    ex.clones[i][0].barcode.clone(),
+                                    BarcodeFate::UmiRatio,
+                                    removed,
+                                );
                             }
                         }
-                        if ctl.clono_filt_opt_def.umi_ratio_filt {
+                        if removed {
                             erase_if(&mut ex.clones, &to_delete[j]);
                             if ex.ncells() == 0 {
                                 to_deletex[j] = true;
@@ -308,7 +305,10 @@ pub fn filter_umi(
                     }
                 }
                 if pass == 2 {
-                    if ctl.clono_filt_opt_def.umi_ratio_filt {
+                    if ctl
+                        .clono_filt_opt_def
+                        .delete(ctl.clono_filt_opt_def.umi_ratio_filt)
+                    {
                         erase_if(&mut o, &to_deletex);
                     }
                     if !o.is_empty() {
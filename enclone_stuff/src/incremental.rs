@@ -0,0 +1,784 @@
+// Copyright (c) 2023 10X Genomics, Inc. All rights reserved.
+
+// Incremental clonotyping (INCREMENTAL=checkpoint).  The datasets of a previous run, whose
+// results were saved using CHECKPOINT, must be given first, in the same order, followed by new
+// datasets.  Only the new datasets are read.  Their exact subclonotypes are joined to the
+// previous clonotypes and to each other using join_one, and the clonotypes that gain new exact
+// subclonotypes are refiltered by the orbit filters, whereas the other previous clonotypes are
+// kept as they are.
+//
+// Each clonotype has a stable id (EncloneExacts.orbit_ids).  A previous clonotype keeps its id,
+// and if new data cause several previous clonotypes to be merged, the merged clonotype gets the
+// smallest of their ids.  New clonotypes get ids larger than all previous ones.  The changes are
+// summarized, and listed in full by INCREMENTAL_REPORT.
+//
+// This is faster than recomputing everything, but not identical to it:
+// • donor alleles are not rederived, so those of the previous run are used for new data;
+// • onesie clonotypes containing new data are not disintegrated;
+// • the higher bar that join_exacts imposes on joins of two cells is only imposed on joins
+//   across CDR3 indels;
+// • the previous run must have used the same options, apart from the datasets.
+
+use crate::checkpoint::CheckpointOrigin;
+use crate::filter_pipeline::{FilterContext, FilterPipeline, JoinContext};
+use crate::start::{load_exacts, mark_vdj_noncells, write_dref_file, LoadedExacts};
+use enclone::allele::sub_alts;
+use enclone::info::build_info;
+use enclone::join_indel::{indel_candidates, join_indels, joins_indels};
+use enclone::join_index::JoinIndex;
+use enclone_core::defs::{CloneInfo, EncloneControl, ExactClonotype, PotentialJoin};
+use enclone_core::enclone_structs::{EncloneExacts, EncloneIntermediates, EncloneSetup};
+use enclone_core::hcomp::heavy_complexity;
+use enclone_core::join_one::{join_one, stirling2_ratio_table_double};
+use enclone_print::loupe::make_donor_refs;
+use enclone_proto::types::DonorReferenceItem;
+use equiv::EquivRel;
+use io_utils::fwriteln;
+use qd::Double;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::Instant;
+use vdj_ann::refx::RefData;
+use vector_utils::unique_sort;
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+// A change to the clonotypes: event is "grow", "merge", "new" or "drop", and into is the id
+// of the clonotype that a merged clonotype became part of.
+
+struct Change {
+    event: &'static str,
+    clonotype_id: usize,
+    into: Option<usize>,
+    new_cells: usize,
+}
+
+// Map the names behind the indices of a previous run to their indices now.
+
+fn remap(old: &[String], new: &[String], what: &str) -> Result<Vec<usize>, String> {
+    old.iter()
+        .map(|name| {
+            new.binary_search(name).map_err(|_| {
+                format!(
+                    "\nThe {what} {name} of the checkpoint given by INCREMENTAL is not \
+                     assigned to any dataset now.\n"
+                )
+            })
+        })
+        .collect()
+}
+
+// Count the cells in the exact subclonotypes of an orbit that are at least first_exact.
+
+fn new_cells(
+    orbit: &[i32],
+    first_exact: usize,
+    info: &[CloneInfo],
+    exact_clonotypes: &[ExactClonotype],
+) -> usize {
+    let mut exacts = orbit
+        .iter()
+        .map(|&k| info[k as usize].clonotype_id)
+        .filter(|&e| e >= first_exact)
+        .collect::<Vec<_>>();
+    unique_sort(&mut exacts);
+    exacts.iter().map(|&e| exact_clonotypes[e].ncells()).sum()
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+// Join the new info entries, which are those from n_old_info on, to the previous clonotypes
+// and to each other.  Return the resulting equivalence relation on info entries, and the new
+// joins.
+
+fn join_new(
+    ctl: &EncloneControl,
+    refdata: &RefData,
+    is_bcr: bool,
+    exact_clonotypes: &[ExactClonotype],
+    info: &[CloneInfo],
+    n_old_info: usize,
+    old_orbits: &[Vec<i32>],
+    to_bc: &HashMap<(usize, usize), Vec<String>>,
+    sr: &[Vec<Double>],
+    drefs: &[DonorReferenceItem],
+) -> (EquivRel, Vec<(usize, usize)>) {
+    // Start from the previous clonotypes, and info entries of the same new exact subclonotype.
+    // Info entries of the previous run that are in no clonotype were filtered out, and are
+    // not used.
+
+    let mut eq = EquivRel::new(info.len() as i32);
+    let mut used = vec![false; info.len()];
+    for o in old_orbits {
+        for &k in o {
+            eq.join(o[0], k);
+            used[k as usize] = true;
+        }
+    }
+    let mut first_info = HashMap::<usize, usize>::new();
+    for (k, x) in info.iter().enumerate().skip(n_old_info) {
+        let k0 = *first_info.entry(x.clonotype_id).or_insert(k);
+        eq.join(k0 as i32, k as i32);
+        used[k] = true;
+    }
+
+    // Examine pairs of info entries having the same contig lengths, at least one of which is
+    // new, as join_exacts would.
+
+    let mut buckets = HashMap::<&[usize], Vec<usize>>::new();
+    for (k, x) in info.iter().enumerate() {
+        if used[k] {
+            let lens = if ctl.join_alg_opt.basic_h.is_some() {
+                &x.lens[..1]
+            } else {
+                &x.lens[..]
+            };
+            buckets.entry(lens).or_default().push(k);
+        }
+    }
+    let mut buckets = buckets
+        .into_values()
+        .filter(|b| *b.last().unwrap() >= n_old_info)
+        .collect::<Vec<_>>();
+    buckets.sort_unstable();
+    let mut new_joins = Vec::<(usize, usize)>::new();
+    {
+        // The potential joins recorded by join_one are not needed here.
+
+        let mut pot = Vec::<PotentialJoin>::new();
+        for entries in buckets {
+            let index = JoinIndex::new(is_bcr, ctl, info, entries.clone());
+            for &k1 in &entries {
+                for k2 in index.candidates(k1) {
+                    if k2 < n_old_info
+                        || (!ctl.force && eq.class_id(k1 as i32) == eq.class_id(k2 as i32))
+                    {
+                        continue;
+                    }
+                    if join_one(
+                        is_bcr,
+                        k1,
+                        k2,
+                        ctl,
+                        exact_clonotypes,
+                        info,
+                        to_bc,
+                        sr,
+                        &mut pot,
+                        refdata,
+                        drefs,
+                    ) {
+                        eq.join(k1 as i32, k2 as i32);
+                        new_joins.push((k1, k2));
+                    }
+                }
+            }
+        }
+    }
+
+    // Examine pairs whose CDR3s differ by an indel, at least one of which is new.
+
+    if joins_indels(is_bcr, ctl) {
+        let mut blocks = HashMap::<&[usize], Vec<usize>>::new();
+        for (k, x) in info.iter().enumerate() {
+            if used[k] {
+                blocks.entry(x.lens.as_slice()).or_default().push(k);
+            }
+        }
+        let mut blocks = blocks.into_values().collect::<Vec<_>>();
+        blocks.sort_unstable();
+        let candidates = indel_candidates(ctl, info, &blocks)
+            .into_iter()
+            .filter(|&(_, k2)| k2 >= n_old_info)
+            .collect::<Vec<_>>();
+        let (joins, _) = join_indels(
+            ctl,
+            exact_clonotypes,
+            info,
+            to_bc,
+            sr,
+            refdata,
+            drefs,
+            &candidates,
+            &mut eq,
+            &mut Vec::new(),
+        );
+        new_joins.extend(joins);
+    }
+    (eq, new_joins)
+}
+
+// The previous clonotypes, divided into those that are unchanged, and those whose classes
+// contain new info entries, which are to be refiltered.
+
+struct Touched {
+    orbits: Vec<(usize, Vec<i32>)>, // unchanged clonotypes, as {(position, orbit)}
+    old_id_of: HashMap<i32, usize>, // info entry => previous clonotype id
+    position: HashMap<usize, usize>, // previous clonotype id => position
+    touched_ids: Vec<usize>,        // ids of previous clonotypes that are touched
+    changed: Vec<Vec<i32>>,         // classes containing new info entries
+}
+
+fn find_touched(
+    old_orbits: Vec<Vec<i32>>,
+    old_ids: &[usize],
+    eq: &EquivRel,
+    n_old_info: usize,
+    n_info: usize,
+) -> Touched {
+    let mut touched = HashMap::<i32, Vec<i32>>::new();
+    for k in n_old_info..n_info {
+        touched
+            .entry(eq.class_id(k as i32))
+            .or_default()
+            .push(k as i32);
+    }
+    let mut t = Touched {
+        orbits: Vec::new(),
+        old_id_of: HashMap::new(),
+        position: HashMap::new(),
+        touched_ids: Vec::new(),
+        changed: Vec::new(),
+    };
+    for (pos, (o, &id)) in old_orbits.into_iter().zip(old_ids.iter()).enumerate() {
+        match touched.get_mut(&eq.class_id(o[0])) {
+            None => t.orbits.push((pos, o)),
+            Some(class) => {
+                for &k in &o {
+                    t.old_id_of.insert(k, id);
+                }
+                t.position.insert(id, pos);
+                t.touched_ids.push(id);
+                class.extend(o);
+            }
+        }
+    }
+    t.changed = touched.into_values().collect();
+    for o in &mut t.changed {
+        o.sort_unstable();
+    }
+    t.changed.sort_unstable();
+    t
+}
+
+// Assign ids to the refiltered clonotypes.  One that contains previous clonotypes gets the
+// smallest of their ids that has not already been given out, and takes its place.  Return the
+// clonotypes, their ids, and the changes.
+
+fn assign_ids(
+    t: Touched,
+    old_ids: &[usize],
+    n_old_exacts: usize,
+    info: &[CloneInfo],
+    exact_clonotypes: &[ExactClonotype],
+) -> (Vec<Vec<i32>>, Vec<usize>, Vec<Change>) {
+    let Touched {
+        mut orbits,
+        old_id_of,
+        position,
+        touched_ids,
+        changed,
+    } = t;
+    let mut next_id = old_ids.iter().map(|&id| id + 1).max().unwrap_or(0);
+    let mut given = HashSet::<usize>::new();
+    let mut ids = HashMap::<usize, usize>::new(); // position => id
+    for &(pos, _) in &orbits {
+        ids.insert(pos, old_ids[pos]);
+    }
+    let mut changes = Vec::<Change>::new();
+    let n_old_orbits = old_ids.len();
+    for o in changed {
+        let added = new_cells(&o, n_old_exacts, info, exact_clonotypes);
+        let mut prev_ids = o
+            .iter()
+            .filter_map(|k| old_id_of.get(k).copied())
+            .filter(|id| !given.contains(id))
+            .collect::<Vec<_>>();
+        unique_sort(&mut prev_ids);
+        let (pos, id) = match prev_ids.first() {
+            Some(&id) => {
+                for &other in &prev_ids[1..] {
+                    changes.push(Change {
+                        event: "merge",
+                        clonotype_id: other,
+                        into: Some(id),
+                        new_cells: 0,
+                    });
+                }
+                if added > 0 {
+                    changes.push(Change {
+                        event: "grow",
+                        clonotype_id: id,
+                        into: None,
+                        new_cells: added,
+                    });
+                }
+                (position[&id], id)
+            }
+            None => {
+                next_id += 1;
+                changes.push(Change {
+                    event: "new",
+                    clonotype_id: next_id - 1,
+                    into: None,
+                    new_cells: added,
+                });
+                (n_old_orbits + orbits.len(), next_id - 1)
+            }
+        };
+        given.extend(&prev_ids);
+        ids.insert(pos, id);
+        orbits.push((pos, o));
+    }
+    for &id in &touched_ids {
+        if !given.contains(&id) {
+            changes.push(Change {
+                event: "drop",
+                clonotype_id: id,
+                into: None,
+                new_cells: 0,
+            });
+        }
+    }
+    orbits.sort_by_key(|(pos, _)| *pos);
+    let orbit_ids = orbits.iter().map(|(pos, _)| ids[pos]).collect::<Vec<_>>();
+    let orbits = orbits.into_iter().map(|(_, o)| o).collect::<Vec<_>>();
+    (orbits, orbit_ids, changes)
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+pub fn main_enclone_start_incremental(
+    setup: EncloneSetup,
+    filters: &FilterPipeline,
+    prev: EncloneExacts,
+    prev_origin: &CheckpointOrigin,
+) -> Result<EncloneIntermediates, String> {
+    let ctl = &setup.ctl;
+    let refdata = &setup.refdata;
+    let is_bcr = setup.is_bcr;
+    let origin = &ctl.origin_info;
+    let EncloneExacts {
+        mut exact_clonotypes,
+        mut info,
+        orbits: old_orbits,
+        orbit_ids: old_ids,
+        mut raw_joins,
+        vdj_cells: old_vdj_cells,
+        fate: old_fate,
+        join_info,
+        mut allele_data,
+        ..
+    } = prev;
+
+    // The previous datasets must come first.  Translate the donor, origin and tag indices of the
+    // previous run into those of this run.  Because the lists of names are sorted, the order of
+    // alt_refs is preserved.
+
+    let tremap = Instant::now();
+    let n_old_datasets = prev_origin.dataset_path.len();
+    if !origin.dataset_path.starts_with(&prev_origin.dataset_path) {
+        return Err(format!(
+            "\nTo use INCREMENTAL, the {n_old_datasets} datasets of the checkpoint it names must \
+             be given first, in the same\norder, followed by the new datasets.\n"
+        ));
+    }
+    let donors = remap(&prev_origin.donor_list, &origin.donor_list, "donor")?;
+    let origins = remap(&prev_origin.origin_list, &origin.origin_list, "origin")?;
+    let tags = remap(&prev_origin.tag_list, &origin.tag_list, "tag")?;
+    for ex in &mut exact_clonotypes {
+        for clone in &mut ex.clones {
+            for x in clone {
+                x.donor_index = x.donor_index.map(|d| donors[d]);
+                x.origin_index = x.origin_index.map(|o| origins[o]);
+                x.tag_index = x.tag_index.map(|t| tags[t]);
+            }
+        }
+    }
+    for alt in &mut allele_data.alt_refs {
+        alt.0 = donors[alt.0];
+    }
+    let alt_refs = &allele_data.alt_refs;
+    write_dref_file(ctl, refdata, alt_refs);
+    let drefs = make_donor_refs(alt_refs, refdata);
+    ctl.perf_stats(&tremap, "remapping previous results");
+
+    // Find the exact subclonotypes of the new datasets, and build their info, using the donor
+    // alleles of the previous run.
+
+    let Some(LoadedExacts {
+        exact_clonotypes: mut new_exacts,
+        mut vdj_cells,
+        gex_cells,
+        gex_cells_specified,
+        mut fate,
+    }) = load_exacts(&setup, filters, n_old_datasets)?
+    else {
+        return Ok(EncloneIntermediates::default());
+    };
+    let tinfo = Instant::now();
+    let mut new_info = build_info(refdata, ctl, &mut new_exacts, &mut fate);
+    sub_alts(refdata, ctl, alt_refs, &mut new_info, &mut new_exacts);
+    if ctl.join_alg_opt.comp_filt < 1_000_000 {
+        let jun = heavy_complexity(refdata, &new_exacts, ctl, &drefs);
+        for (ex, jun) in new_exacts.iter_mut().zip(jun) {
+            for m in 0..ex.share.len() {
+                if ex.share.len() == 2 && ex.share[m].left {
+                    ex.share[m].jun = jun.clone();
+                }
+            }
+        }
+    }
+    ctl.perf_stats(&tinfo, "building info for new data");
+
+    // Append the new exact subclonotypes and info entries to the previous ones.
+
+    let n_old_exacts = exact_clonotypes.len();
+    let n_old_info = info.len();
+    for x in &mut new_info {
+        x.clonotype_id += n_old_exacts;
+        x.clonotype_index += n_old_exacts;
+    }
+    exact_clonotypes.append(&mut new_exacts);
+    info.append(&mut new_info);
+    for (li, (cells, f)) in old_vdj_cells.into_iter().zip(old_fate).enumerate() {
+        vdj_cells[li] = cells;
+        fate[li] = f;
+    }
+    let mut to_bc = HashMap::<(usize, usize), Vec<String>>::new();
+    for (i, ex) in exact_clonotypes.iter().enumerate() {
+        for clone in &ex.clones {
+            let x = &clone[0];
+            to_bc
+                .entry((x.dataset_index, i))
+                .or_default()
+                .push(x.barcode.clone());
+        }
+    }
+    let sr = stirling2_ratio_table_double(3000);
+
+    // Join the new info entries to the previous clonotypes and to each other.
+
+    let tjoin = Instant::now();
+    let (eq, new_joins) = join_new(
+        ctl,
+        refdata,
+        is_bcr,
+        &exact_clonotypes,
+        &info,
+        n_old_info,
+        &old_orbits,
+        &to_bc,
+        &sr,
+        &drefs,
+    );
+    raw_joins.resize(info.len(), Vec::new());
+    for &(k1, k2) in &new_joins {
+        raw_joins[k1].push(k2);
+        raw_joins[k2].push(k1);
+    }
+    ctl.perf_stats(&tjoin, "joining new data");
+
+    // Refilter the classes that contain new info entries.
+
+    let tumi = Instant::now();
+    let mut touched = find_touched(old_orbits, &old_ids, &eq, n_old_info, info.len());
+    let fctx = FilterContext {
+        ctl,
+        refdata,
+        gex_info: &setup.gex_info,
+        is_bcr,
+        vdj_cells: &vdj_cells,
+        gex_cells: &gex_cells,
+        gex_cells_specified: &gex_cells_specified,
+    };
+    let disintegrated = vec![false; exact_clonotypes.len()];
+    let join = JoinContext {
+        info: &info,
+        eq: &eq,
+        to_bc: &to_bc,
+        sr: &sr,
+        raw_joins: &raw_joins,
+        disintegrated: &disintegrated,
+        drefs: &drefs,
+    };
+    filters.filter_orbits(
+        &fctx,
+        &join,
+        &mut touched.changed,
+        &mut exact_clonotypes,
+        &mut fate,
+    )?;
+    ctl.perf_stats(&tumi, "in orbit filters for new data");
+    let (orbits, orbit_ids, changes) =
+        assign_ids(touched, &old_ids, n_old_exacts, &info, &exact_clonotypes);
+
+    // Report the changes.
+
+    let count = |event: &str| changes.iter().filter(|c| c.event == event).count();
+    if !ctl.silent {
+        let added = changes.iter().map(|c| c.new_cells).sum::<usize>();
+        println!(
+            "\nadded {} cells from {} new datasets: {} clonotypes grew, {} were merged into \
+             others, {} were dropped, and {} are new",
+            added,
+            origin.n() - n_old_datasets,
+            count("grow"),
+            count("merge"),
+            count("drop"),
+            count("new"),
+        );
+    }
+    if !ctl.gen_opt.incremental_report.is_empty() {
+        let path = &ctl.gen_opt.incremental_report;
+        let f = File::create(path)
+            .map_err(|e| format!("\nCould not create INCREMENTAL_REPORT file {path}: {e}.\n"))?;
+        let mut f = BufWriter::new(f);
+        fwriteln!(f, "event\tclonotype_id\tinto\tnew_cells");
+        for c in &changes {
+            let into = match c.into {
+                Some(id) => id.to_string(),
+                None => String::new(),
+            };
+            fwriteln!(
+                f,
+                "{}\t{}\t{}\t{}",
+                c.event,
+                c.clonotype_id,
+                into,
+                c.new_cells
+            );
+        }
+    }
+
+    // Mark VDJ noncells.
+
+    mark_vdj_noncells(ctl, &vdj_cells, &mut exact_clonotypes);
+    Ok(EncloneIntermediates {
+        setup,
+        ex: EncloneExacts {
+            to_bc,
+            exact_clonotypes,
+            raw_joins,
+            info,
+            orbits,
+            orbit_ids,
+            vdj_cells,
+            join_info,
+            drefs,
+            sr,
+            fate,
+            is_bcr,
+            allele_data,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enclone::join_test_data::{
+        mutate, test_ctl, JoinTestData, TestChain, TestExact, HEAVY_V, LIGHT_V,
+    };
+
+    // Make an exact subclonotype having one cell, whose heavy chain junction insertion is
+    // mutated at the given positions, and whose heavy chain has V segment mutations shared with
+    // the others, and one more.
+
+    fn exact(heavy_muts: &[usize], light_ins: &[u8], m: usize) -> TestExact {
+        let mut heavy_ins = b"CGAGGGTATAGC".to_vec();
+        for &p in heavy_muts {
+            heavy_ins[p] = mutate(heavy_ins[p]);
+        }
+        TestExact {
+            heavy: TestChain {
+                v: HEAVY_V,
+                muts: vec![10, 20, 30, 40, 50 + m],
+                ins: heavy_ins,
+            },
+            light: TestChain {
+                v: LIGHT_V,
+                muts: vec![5, 15],
+                ins: light_ins.to_vec(),
+            },
+            ncells: 1,
+        }
+    }
+
+    // Find the clonotypes of a run on the first n info entries, by examining every pair.
+
+    fn full_run(d: &JoinTestData, n: usize) -> Vec<Vec<i32>> {
+        let mut eq = EquivRel::new(n as i32);
+        let mut pot = Vec::<PotentialJoin>::new();
+        for k1 in 0..n {
+            for k2 in k1 + 1..n {
+                if join_one(
+                    true,
+                    k1,
+                    k2,
+                    &d.ctl,
+                    &d.exact_clonotypes,
+                    &d.info,
+                    &d.to_bc,
+                    &d.sr,
+                    &mut pot,
+                    &d.refdata,
+                    &[],
+                ) {
+                    eq.join(k1 as i32, k2 as i32);
+                }
+            }
+        }
+        let mut reps = Vec::<i32>::new();
+        eq.orbit_reps(&mut reps);
+        let mut orbits = reps
+            .iter()
+            .map(|&r| {
+                let mut o = Vec::<i32>::new();
+                eq.orbit(r, &mut o);
+                o.sort_unstable();
+                o
+            })
+            .collect::<Vec<_>>();
+        orbits.sort();
+        orbits
+    }
+
+    // The exact subclonotypes of each clonotype.
+
+    fn exacts_of(info: &[CloneInfo], orbits: &[Vec<i32>]) -> Vec<Vec<usize>> {
+        orbits
+            .iter()
+            .map(|o| {
+                let mut e = o
+                    .iter()
+                    .map(|&k| info[k as usize].clonotype_id)
+                    .collect::<Vec<_>>();
+                unique_sort(&mut e);
+                e
+            })
+            .collect()
+    }
+
+    fn changes_of(changes: &[Change]) -> Vec<(&str, usize, Option<usize>, usize)> {
+        let mut x = changes
+            .iter()
+            .map(|c| (c.event, c.clonotype_id, c.into, c.new_cells))
+            .collect::<Vec<_>>();
+        x.sort_unstable();
+        x
+    }
+
+    #[test]
+    fn test_incremental() {
+        // Allow one CDR3 difference.  The previous datasets have exact subclonotypes 0-4, and
+        // the new datasets have exact subclonotypes 5-7.  Exact subclonotypes 0, 1 and 5 have
+        // the same CDR3s.  Exact subclonotypes 2 and 3 differ at two bases, and so are not
+        // joined, but each differs from 6 at one base.  Exact subclonotypes 4 and 7 differ from
+        // all others at every base of their junction insertions.
+
+        let mut ctl = test_ctl();
+        ctl.join_alg_opt.max_cdr3_diffs = 1;
+        let light = b"CAG".to_vec();
+        let light4 = light.iter().map(|&b| mutate(b)).collect::<Vec<_>>();
+        let light7 = light4.iter().map(|&b| mutate(b)).collect::<Vec<_>>();
+        let all = (0..12).collect::<Vec<_>>();
+        let all2 = (0..12).chain(0..12).collect::<Vec<_>>();
+        let exacts = [
+            exact(&[], &light, 0),
+            exact(&[], &light, 1),
+            exact(&[0, 1, 2, 3, 6], &light, 2),
+            exact(&[0, 1, 2, 3, 8], &light, 3),
+            exact(&all, &light4, 4),
+            exact(&[], &light, 5),
+            exact(&[0, 1, 2, 3], &light, 6),
+            exact(&all2, &light7, 7),
+        ];
+        let mut d = JoinTestData::new(ctl, &exacts);
+
+        // Order the info entries as the exact subclonotypes, so that those of the previous
+        // datasets come first, as for an incremental run.
+
+        d.info.sort_by_key(|x| x.clonotype_id);
+        let (n_old_exacts, n_old_info) = (5, 5);
+        assert_eq!(d.info.len(), exacts.len());
+
+        // Find the clonotypes of the previous run, and give them ids.
+
+        let old_orbits = full_run(&d, n_old_info);
+        assert_eq!(
+            exacts_of(&d.info, &old_orbits),
+            vec![vec![0, 1], vec![2], vec![3], vec![4]]
+        );
+        let old_ids = vec![0, 1, 2, 3];
+
+        // The incremental run has the same clonotypes as a full run.  Previous clonotypes keep
+        // their ids, the merged clonotype gets the smaller id, and the new one gets a new id.
+
+        let (eq, new_joins) = join_new(
+            &d.ctl,
+            &d.refdata,
+            true,
+            &d.exact_clonotypes,
+            &d.info,
+            n_old_info,
+            &old_orbits,
+            &d.to_bc,
+            &d.sr,
+            &[],
+        );
+        assert!(new_joins.iter().all(|&(k1, k2)| k1.max(k2) >= n_old_info));
+        let touched = find_touched(old_orbits.clone(), &old_ids, &eq, n_old_info, d.info.len());
+        assert_eq!(touched.touched_ids, vec![0, 1, 2]);
+        let (orbits, ids, changes) = assign_ids(
+            touched,
+            &old_ids,
+            n_old_exacts,
+            &d.info,
+            &d.exact_clonotypes,
+        );
+        let full = full_run(&d, d.info.len());
+        assert_eq!(exacts_of(&d.info, &orbits), exacts_of(&d.info, &full));
+        assert_eq!(
+            exacts_of(&d.info, &orbits),
+            vec![vec![0, 1, 5], vec![2, 3, 6], vec![4], vec![7]]
+        );
+        assert_eq!(ids, vec![0, 1, 3, 4]);
+        assert_eq!(
+            changes_of(&changes),
+            vec![
+                ("grow", 0, None, 1),
+                ("grow", 1, None, 1),
+                ("merge", 2, Some(1), 0),
+                ("new", 4, None, 1),
+            ]
+        );
+
+        // If the refiltering deletes a grown clonotype, it is dropped.
+
+        let mut touched = find_touched(old_orbits, &old_ids, &eq, n_old_info, d.info.len());
+        touched.changed.retain(|o| !o.contains(&0));
+        let (orbits, ids, changes) = assign_ids(
+            touched,
+            &old_ids,
+            n_old_exacts,
+            &d.info,
+            &d.exact_clonotypes,
+        );
+        assert_eq!(
+            exacts_of(&d.info, &orbits),
+            vec![vec![2, 3, 6], vec![4], vec![7]]
+        );
+        assert_eq!(ids, vec![1, 3, 4]);
+        assert_eq!(
+            changes_of(&changes),
+            vec![
+                ("drop", 0, None, 0),
+                ("grow", 1, None, 1),
+                ("merge", 2, Some(1), 0),
+                ("new", 4, None, 1),
+            ]
+        );
+    }
+}
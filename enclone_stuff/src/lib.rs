@@ -1,12 +1,16 @@
 // Copyright (c) 2021 10x Genomics, Inc. All rights reserved.
 
 pub mod analyze_dref;
+pub mod benchmark;
+pub mod checkpoint;
 pub mod disintegrate;
 pub mod doublets;
 pub mod fcell;
+pub mod filter_pipeline;
 pub mod filter_umi;
 pub mod flag_defective;
 pub mod inconsistent;
+pub mod incremental;
 pub mod merge_onesies;
 pub mod populate_features;
 pub mod some_filters;
@@ -4,7 +4,7 @@ use crate::doublets::delete_doublets;
 use crate::merge_onesies::merge_onesies;
 use crate::split_orbits::split_orbits;
 use crate::weak_chains::weak_chains;
-use enclone_core::barcode_fate::BarcodeFate;
+use enclone_core::barcode_fate::{BarcodeFate, FateLedger};
 use enclone_core::defs::{CloneInfo, EncloneControl, ExactClonotype};
 use enclone_print::define_mat::{define_mat, setup_define_mat};
 use enclone_print::print_utils3::define_column_info;
@@ -29,7 +29,7 @@ pub fn some_filters(
     raw_joins: &[Vec<usize>],
     eq: &EquivRel,
     disintegrated: &[bool],
-    fate: &mut [HashMap<String, BarcodeFate>],
+    fate: &mut [FateLedger],
     refdata: &RefData,
     dref: &[DonorReferenceItem],
 ) {
@@ -150,8 +150,17 @@ pub fn some_filters(
                     t.push(col);
                 }
             }
-            if dels.contains(&t) && ctl.clono_filt_opt_def.signature {
-                res.2.push(exacts[u]);
+            if dels.contains(&t)
+                && ctl
+                    .clono_filt_opt_def
+                    .eval(ctl.clono_filt_opt_def.signature)
+            {
+                if ctl
+                    .clono_filt_opt_def
+                    .delete(ctl.clono_filt_opt_def.signature)
+                {
+                    res.2.push(exacts[u]);
+                }
                 let ex = &exact_clonotypes[exacts[u]];
                 for i in 0..ex.ncells() {
                     res.1.push((
@@ -164,9 +173,13 @@ pub fn some_filters(
         }
     });
     let mut to_delete = vec![false; exact_clonotypes.len()];
+    let removed = ctl
+        .clono_filt_opt_def
+        .delete(ctl.clono_filt_opt_def.signature);
     for i in 0..results.len() {
         for j in 0..results[i].1.len() {
-            fate[results[i].1[j].0].insert(results[i].1[j].1.clone(), results[i].1[j].2.clone());
+            let x = &results[i].1[j];
+            fate[x.0].insert(x.1.clone(), x.2, removed);
         }
         for j in 0..results[i].2.len() {
             to_delete[results[i].2[j]] = true;
@@ -396,7 +409,10 @@ pub fn some_filters(
             }
             if !q60 && q40 < 2 {
                 let u = vquals[j].4;
-                if ctl.clono_filt_opt.qual_filter {
+                if ctl
+                    .clono_filt_opt_def
+                    .delete(ctl.clono_filt_opt.qual_filter)
+                {
                     res.2.push(exacts[u]);
                 }
                 let ex = &exact_clonotypes[exacts[u]];
@@ -413,9 +429,13 @@ pub fn some_filters(
     });
     let mut to_delete = vec![false; exact_clonotypes.len()];
     let mut dels = Vec::<i32>::new();
+    let removed = ctl
+        .clono_filt_opt_def
+        .delete(ctl.clono_filt_opt.qual_filter);
     for i in 0..results.len() {
         for j in 0..results[i].1.len() {
-            fate[results[i].1[j].0].insert(results[i].1[j].1.clone(), results[i].1[j].2.clone());
+            let x = &results[i].1[j];
+            fate[x.0].insert(x.1.clone(), x.2, removed);
         }
         for x in results[i].2.iter() {
             to_delete[*x] = true;
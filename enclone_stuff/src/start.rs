@@ -3,32 +3,37 @@
 // See README for documentation.
 
 use crate::analyze_dref::analyze_donor_ref;
+use crate::benchmark::write_benchmark;
+use crate::checkpoint::{
+    checkpoint_key, read_checkpoint, read_previous, set_last_modified, write_checkpoint,
+    CheckpointOrigin,
+};
 use crate::disintegrate::disintegrate_onesies;
-use crate::fcell::filter_by_fcell;
-use crate::filter_umi::filter_umi;
+use crate::filter_pipeline::{FilterContext, FilterPipeline, JoinContext};
 use crate::flag_defective::flag_defective;
 use crate::inconsistent::test_vdj_gex_inconsistent;
+use crate::incremental::main_enclone_start_incremental;
 use crate::populate_features::populate_features;
-use crate::some_filters::some_filters;
 use debruijn::dna_string::DnaString;
-use enclone::allele::{find_alleles, sub_alts};
-use enclone::graph_filter::graph_filter;
+use enclone::allele::sub_alts;
+use enclone::genotype::donor_alleles;
 use enclone::info::build_info;
 use enclone::join::join_exacts;
-use enclone::misc1::{cross_filter, lookup_heavy_chain_reuse};
+use enclone::misc1::lookup_heavy_chain_reuse;
 use enclone::misc2::{check_for_barcode_reuse, find_exact_subclonotypes, search_for_shm_indels};
 use enclone::misc3::sort_tig_bc;
 use enclone_args::read_json::parse_json_annotations_files;
-use enclone_core::barcode_fate::BarcodeFate;
-use enclone_core::defs::{AlleleData, CloneInfo, TigData};
+use enclone_core::barcode_fate::{BarcodeFate, FateLedger};
+use enclone_core::defs::{AlleleData, CloneInfo, EncloneControl, ExactClonotype, TigData};
 use enclone_core::enclone_structs::{EncloneExacts, EncloneIntermediates, EncloneSetup};
 use enclone_core::hcomp::heavy_complexity;
+use enclone_core::join_one::stirling2_ratio_table_double;
 use enclone_print::define_mat::{define_mat, setup_define_mat};
 use enclone_print::loupe::make_donor_refs;
 use equiv::EquivRel;
 use io_utils::{fwriteln, open_for_read};
 use itertools::Itertools;
-use qd::dd;
+use rayon::prelude::*;
 use std::{
     collections::HashMap,
     env,
@@ -37,71 +42,157 @@ use std::{
     time::Instant,
 };
 use string_utils::{add_commas, TextUtils};
-use vector_utils::{bin_member, erase_if, next_diff12_3, sort_sync2, unique_sort};
+use vdj_ann::refx::RefData;
+use vector_utils::{bin_member, erase_if, next_diff12_3, sort_sync2};
 
 // ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
 
-// This is a copy of stirling2_ratio_table from the stirling_numbers crate, that has been modified
-// to use higher precision internal math.  This has also been speeded up, and in the process
-// made less readable.
-
-use qd::Double;
-use rayon::prelude::*;
+// Write the donor reference sequences (DONOR_REF_FILE).
 
-pub fn stirling2_ratio_table_double(n_max: usize) -> Vec<Vec<Double>> {
-    let mut s = Vec::<Vec<Double>>::new();
-    let zero = dd![0.0];
-    let one = dd![1.0];
-    for n in 0..=n_max {
-        s.push(vec![zero; n + 1]);
+pub(crate) fn write_dref_file(
+    ctl: &EncloneControl,
+    refdata: &RefData,
+    alt_refs: &[(usize, usize, DnaString, usize, bool)],
+) {
+    if !ctl.gen_opt.dref_file.is_empty() {
+        let f = File::create(&ctl.gen_opt.dref_file);
+        if f.is_err() {
+            eprintln!(
+                "\nError trying to write ctl.gen_opt.dref_file = {}.",
+                ctl.gen_opt.dref_file
+            );
+        }
+        let mut f = BufWriter::new(f.unwrap());
+        let mut count = 0;
+        for i in 0..alt_refs.len() {
+            let donor = alt_refs[i].0;
+            let ref_id = alt_refs[i].1;
+            if i > 0 && (donor != alt_refs[i - 1].0 || ref_id != alt_refs[i - 1].1) {
+                count = 0;
+            }
+            let alt_seq = &alt_refs[i].2;
+            fwriteln!(
+                f,
+                ">{}:{}:{}:{} (reference record id : donor name : allele number : gene name)\n{}",
+                refdata.id[ref_id],
+                ctl.origin_info.donor_list[donor],
+                count + 1,
+                refdata.name[ref_id],
+                alt_seq.to_string()
+            );
+            count += 1;
+        }
     }
-    s[0][0] = one;
-    let mut z = Vec::<Double>::new();
-    let mut n2n1 = vec![dd![0.0]; n_max + 1];
-    for (n, nn) in n2n1.iter_mut().enumerate().skip(2) {
-        *nn = Double::from((n - 2) as u32) / Double::from((n - 1) as u32);
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+// Mark the cells that were not called as cells by the VDJ pipeline (NON_CELL_MARK).
+
+pub(crate) fn mark_vdj_noncells(
+    ctl: &EncloneControl,
+    vdj_cells: &[Vec<String>],
+    exact_clonotypes: &mut [ExactClonotype],
+) {
+    if ctl.clono_filt_opt_def.non_cell_mark {
+        for ex in exact_clonotypes.iter_mut() {
+            for clone in ex.clones.iter_mut() {
+                let di = clone[0].dataset_index;
+                if !bin_member(&vdj_cells[di], &clone[0].barcode) {
+                    clone[0].marked = true;
+                }
+            }
+        }
     }
-    let mut k1k = vec![dd![0.0]; n_max];
-    for (k, kk) in k1k.iter_mut().enumerate().skip(1) {
-        *kk = Double::from((k - 1) as u32) / Double::from(k as u32);
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+pub fn main_enclone_start(setup: EncloneSetup) -> Result<EncloneIntermediates, String> {
+    main_enclone_start_with_filters(setup, &FilterPipeline::default())
+}
+
+// Same as main_enclone_start, but applying the given filters in place of the default ones.
+//
+// If CHECKPOINT is specified and the checkpoint is up to date, the results are read from it.
+// Otherwise they are computed, and if CHECKPOINT is specified, written to it.  If INCREMENTAL is
+// specified, they are computed by extending a previous checkpoint, see incremental.rs.  If
+// BENCHMARK is specified, the resulting clonotypes are evaluated, see benchmark.rs.
+
+pub fn main_enclone_start_with_filters(
+    setup: EncloneSetup,
+    filters: &FilterPipeline,
+) -> Result<EncloneIntermediates, String> {
+    let inter = start_with_checkpoint(setup, filters)?;
+    if !inter.setup.ctl.gen_opt.benchmark.is_empty() && !inter.ex.exact_clonotypes.is_empty() {
+        let tbench = Instant::now();
+        write_benchmark(&inter)?;
+        inter.setup.ctl.perf_stats(&tbench, "benchmarking");
     }
-    let mut njn = Vec::<(usize, Double)>::new();
-    for i in 0..n_max + 1 {
-        njn.push((i, dd![0.0]));
+    Ok(inter)
+}
+
+fn start_with_checkpoint(
+    mut setup: EncloneSetup,
+    filters: &FilterPipeline,
+) -> Result<EncloneIntermediates, String> {
+    let path = setup.ctl.gen_opt.checkpoint.clone();
+    let previous = setup.ctl.gen_opt.incremental.clone();
+    if path.is_empty() && previous.is_empty() {
+        return main_enclone_start_core(setup, filters);
     }
-    njn.par_iter_mut().for_each(|res| {
-        let n = res.0;
-        if n >= 1 {
-            let mut p = one;
-            for j in 1..=n {
-                p *= Double::from(j as u32) / Double::from(n as u32);
-            }
-            res.1 = p;
+    set_last_modified(&mut setup.ctl);
+    let key = checkpoint_key(&setup, filters);
+    let tcheck = Instant::now();
+    if !path.is_empty() {
+        if let Some(mut ex) = read_checkpoint(&path, &key)? {
+            ex.sr = stirling2_ratio_table_double(3000);
+            write_dref_file(&setup.ctl, &setup.refdata, &ex.allele_data.alt_refs);
+            setup.ctl.perf_stats(&tcheck, "reading checkpoint");
+            return Ok(EncloneIntermediates { setup, ex });
         }
-    });
+    }
+    let inter = if previous.is_empty() {
+        main_enclone_start_core(setup, filters)?
+    } else {
+        let (prev, prev_origin) = read_previous(&previous, &key)?;
+        setup.ctl.perf_stats(&tcheck, "reading previous checkpoint");
+        main_enclone_start_incremental(setup, filters, prev, &prev_origin)?
+    };
 
-    // This is the slow part of the function.
+    // Don't write a checkpoint if main_enclone_start stopped early, as it does for some
+    // special options.
 
-    for n in 1..=n_max {
-        s[n][0] = zero;
-        for k in 1..n - 1 {
-            z[k - 1] *= k1k[k];
-        }
-        if n >= 2 {
-            z.push(n2n1[n].powi((n - 1) as i32));
-        }
-        for k in 1..n {
-            let x = z[k - 1]; // = ((k-1)/k)^(n-1)
-            s[n][k] = s[n - 1][k] + s[n - 1][k - 1] * x;
-        }
-        s[n][n] = njn[n].1;
+    if !path.is_empty() && !inter.ex.exact_clonotypes.is_empty() {
+        let twrite = Instant::now();
+        let origin = CheckpointOrigin::new(&inter.setup.ctl);
+        write_checkpoint(&path, &key, &origin, &inter.ex)?;
+        inter.setup.ctl.perf_stats(&twrite, "writing checkpoint");
     }
-    s
+    Ok(inter)
 }
 
 // ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
 
-pub fn main_enclone_start(setup: EncloneSetup) -> Result<EncloneIntermediates, String> {
+// The exact subclonotypes found by load_exacts, and what was learned about the barcodes on the
+// way.
+
+pub(crate) struct LoadedExacts {
+    pub exact_clonotypes: Vec<ExactClonotype>,
+    pub vdj_cells: Vec<Vec<String>>,
+    pub gex_cells: Vec<Vec<String>>,
+    pub gex_cells_specified: Vec<bool>,
+    pub fate: Vec<FateLedger>,
+}
+
+// Read the datasets starting at first_dataset, and find their filtered exact subclonotypes.
+// Return None if a special option stops the computation here.
+
+pub(crate) fn load_exacts(
+    setup: &EncloneSetup,
+    filters: &FilterPipeline,
+    first_dataset: usize,
+) -> Result<Option<LoadedExacts>, String> {
     let tr = Instant::now();
     let ctl = &setup.ctl;
     let gex_info = &setup.gex_info;
@@ -109,8 +200,6 @@ pub fn main_enclone_start(setup: EncloneSetup) -> Result<EncloneIntermediates, S
     let is_bcr = setup.is_bcr;
     let to_ref_index = &setup.to_ref_index;
 
-    // ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
-
     // Flag defective reference sequences.
 
     let mut log = Vec::<u8>::new();
@@ -125,9 +214,10 @@ pub fn main_enclone_start(setup: EncloneSetup) -> Result<EncloneIntermediates, S
     let mut vdj_cells = Vec::<Vec<String>>::new();
     let mut gex_cells = Vec::<Vec<String>>::new();
     let mut gex_cells_specified = Vec::<bool>::new();
-    let mut fate = vec![HashMap::<String, BarcodeFate>::new(); ctl.origin_info.n()];
+    let mut fate = vec![FateLedger::default(); ctl.origin_info.n()];
     parse_json_annotations_files(
         ctl,
+        first_dataset,
         &mut tig_bc,
         refdata,
         to_ref_index,
@@ -138,6 +228,16 @@ pub fn main_enclone_start(setup: EncloneSetup) -> Result<EncloneIntermediates, S
     )?;
     ctl.perf_stats(&tparse, "loading from json");
 
+    // Count the barcodes in each dataset, for FATE_SUMMARY.  Those having no productive contig
+    // are not in tig_bc, but already have a fate.
+
+    for tigi in &tig_bc {
+        fate[tigi[0].dataset_index].num_barcodes += 1;
+    }
+    for f in fate.iter_mut() {
+        f.num_barcodes += f.len();
+    }
+
     // Populate features.
 
     let tpop = Instant::now();
@@ -158,7 +258,7 @@ pub fn main_enclone_start(setup: EncloneSetup) -> Result<EncloneIntermediates, S
         &mut log,
     )?;
     if ctl.gen_opt.require_unbroken_ok {
-        return Ok(EncloneIntermediates::default());
+        return Ok(None);
     }
     for tigi in &mut tig_bc {
         for x in tigi {
@@ -182,7 +282,7 @@ pub fn main_enclone_start(setup: EncloneSetup) -> Result<EncloneIntermediates, S
 
     search_for_shm_indels(ctl, &tig_bc);
     if ctl.gen_opt.indels {
-        return Ok(EncloneIntermediates::default());
+        return Ok(None);
     }
 
     // Record fate of non-cells.
@@ -192,22 +292,28 @@ pub fn main_enclone_start(setup: EncloneSetup) -> Result<EncloneIntermediates, S
             let bc = &tigi[0].barcode;
             let li = tigi[0].dataset_index;
             if !bin_member(&vdj_cells[li], bc) {
-                fate[li].insert(bc.clone(), BarcodeFate::NotAsmCell);
+                fate[li].insert(bc.clone(), BarcodeFate::NotAsmCell, false);
             }
         }
     }
 
-    // Filter using light --> heavy graph.
-
-    graph_filter(ctl, &mut tig_bc, ctl.gen_opt.graph, &mut fate);
-
     // Sort tig_bc.
 
     sort_tig_bc(ctl, &mut tig_bc, refdata);
 
-    // Cross filter.
+    // Filter barcodes, using the light --> heavy graph, the cross filter, and any further
+    // filters that have been added to the pipeline.
 
-    cross_filter(ctl, &mut tig_bc, &mut fate);
+    let fctx = FilterContext {
+        ctl,
+        refdata,
+        gex_info,
+        is_bcr,
+        vdj_cells: &vdj_cells,
+        gex_cells: &gex_cells,
+        gex_cells_specified: &gex_cells_specified,
+    };
+    filters.filter_barcodes(&fctx, &mut tig_bc, &mut fate)?;
 
     // Look for barcode reuse.
 
@@ -218,7 +324,7 @@ pub fn main_enclone_start(setup: EncloneSetup) -> Result<EncloneIntermediates, S
 
     let mut exact_clonotypes = find_exact_subclonotypes(ctl, &tig_bc, refdata, &mut fate);
     if ctl.gen_opt.utr_con || ctl.gen_opt.con_con {
-        return Ok(EncloneIntermediates::default());
+        return Ok(None);
     }
     if !ctl.gen_opt.trace_barcode.is_empty() {
         for ex in &exact_clonotypes {
@@ -238,58 +344,50 @@ pub fn main_enclone_start(setup: EncloneSetup) -> Result<EncloneIntermediates, S
 
     test_vdj_gex_inconsistent(ctl, &tig_bc, &exact_clonotypes, &vdj_cells, gex_info)?;
 
-    // Filter out some foursie artifacts.
+    // Filter exact subclonotypes, including some foursie artifacts.
 
-    let t = Instant::now();
-    let mut to_delete = vec![false; exact_clonotypes.len()];
-    let mut twosies = Vec::<(&[u8], &[u8])>::new();
-    for ex in &exact_clonotypes {
-        if ex.share.len() == 2 && (ex.share[0].left ^ ex.share[1].left) && ex.ncells() >= 10 {
-            twosies.push((ex.share[0].seq.as_ref(), ex.share[1].seq.as_ref()));
-        }
-    }
-    unique_sort(&mut twosies);
-    for (ex, d) in exact_clonotypes.iter().zip(to_delete.iter_mut()) {
-        if ex.share.len() == 4 {
-            for (i1, s1) in ex.share.iter().enumerate() {
-                for s2 in &ex.share[i1 + 1..4] {
-                    if s1.left ^ s2.left {
-                        let p = (s1.seq.as_ref(), s2.seq.as_ref());
-                        if bin_member(&twosies, &p) {
-                            *d = true;
-                            for clone in &ex.clones {
-                                fate[clone[0].dataset_index]
-                                    .insert(clone[0].barcode.clone(), BarcodeFate::FoursieKill);
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
-    if ctl.clono_filt_opt_def.weak_foursies {
-        erase_if(&mut exact_clonotypes, &to_delete);
-    }
+    filters.filter_exacts(&fctx, &mut exact_clonotypes, &mut fate)?;
+    Ok(Some(LoadedExacts {
+        exact_clonotypes,
+        vdj_cells,
+        gex_cells,
+        gex_cells_specified,
+        fate,
+    }))
+}
 
-    // Filter if MAX_HEAVIES = 1 set.
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
 
-    if ctl.gen_opt.max_heavies == 1 {
-        let mut to_delete = vec![false; exact_clonotypes.len()];
-        for i in 0..exact_clonotypes.len() {
-            let ex = &exact_clonotypes[i];
-            let mut heavies = 0;
-            for j in 0..ex.share.len() {
-                if ex.share[j].left {
-                    heavies += 1;
-                }
-            }
-            if heavies > 1 {
-                to_delete[i] = true;
-            }
-        }
-        erase_if(&mut exact_clonotypes, &to_delete);
-    }
-    ctl.perf_stats(&t, "filtering foursies");
+fn main_enclone_start_core(
+    setup: EncloneSetup,
+    filters: &FilterPipeline,
+) -> Result<EncloneIntermediates, String> {
+    let ctl = &setup.ctl;
+    let gex_info = &setup.gex_info;
+    let refdata = &setup.refdata;
+    let is_bcr = setup.is_bcr;
+
+    // Find the exact subclonotypes.
+
+    let Some(LoadedExacts {
+        mut exact_clonotypes,
+        vdj_cells,
+        gex_cells,
+        gex_cells_specified,
+        mut fate,
+    }) = load_exacts(&setup, filters, 0)?
+    else {
+        return Ok(EncloneIntermediates::default());
+    };
+    let fctx = FilterContext {
+        ctl,
+        refdata,
+        gex_info,
+        is_bcr,
+        vdj_cells: &vdj_cells,
+        gex_cells: &gex_cells,
+        gex_cells_specified: &gex_cells_specified,
+    };
 
     // Build info about clonotypes.  Note that this edits the V reference sequence to perform
     // an indel in some cases.
@@ -298,45 +396,15 @@ pub fn main_enclone_start(setup: EncloneSetup) -> Result<EncloneIntermediates, S
     let mut info: Vec<CloneInfo> = build_info(refdata, ctl, &mut exact_clonotypes, &mut fate);
     ctl.perf_stats(&tinfo, "building info");
 
-    // Derive consensus sequences for alternate alleles of V segments.  Then create donor
-    // reference sequences for Loupe.
+    // Derive consensus sequences for alternate alleles of V and J segments, and combine them
+    // with the donor genotype, if one was given.  Then create donor reference sequences for
+    // Loupe.
 
     let talt = Instant::now();
     // {(donor, ref id, alt seq, support, is_ref)}:
-    let mut alt_refs = Vec::<(usize, usize, DnaString, usize, bool)>::new();
-    if !ctl.gen_opt.no_alt_alleles {
-        alt_refs = find_alleles(refdata, ctl, &exact_clonotypes);
-    }
+    let alt_refs = donor_alleles(refdata, ctl, &exact_clonotypes)?;
     ctl.perf_stats(&talt, "finding alt alleles");
-    if !ctl.gen_opt.dref_file.is_empty() {
-        let f = File::create(&ctl.gen_opt.dref_file);
-        if f.is_err() {
-            eprintln!(
-                "\nError trying to write ctl.gen_opt.dref_file = {}.",
-                ctl.gen_opt.dref_file
-            );
-        }
-        let mut f = BufWriter::new(f.unwrap());
-        let mut count = 0;
-        for i in 0..alt_refs.len() {
-            let donor = alt_refs[i].0;
-            let ref_id = alt_refs[i].1;
-            if i > 0 && (donor != alt_refs[i - 1].0 || ref_id != alt_refs[i - 1].1) {
-                count = 0;
-            }
-            let alt_seq = &alt_refs[i].2;
-            fwriteln!(
-                f,
-                ">{}:{}:{}:{} (reference record id : donor name : allele number : gene name)\n{}",
-                refdata.id[ref_id],
-                ctl.origin_info.donor_id[donor],
-                count + 1,
-                refdata.name[ref_id],
-                alt_seq.to_string()
-            );
-            count += 1;
-        }
-    }
+    write_dref_file(ctl, refdata, &alt_refs);
     let tdonor = Instant::now();
     let drefs = make_donor_refs(&alt_refs, refdata);
     ctl.perf_stats(&tdonor, "making donor refs");
@@ -345,7 +413,7 @@ pub fn main_enclone_start(setup: EncloneSetup) -> Result<EncloneIntermediates, S
 
     analyze_donor_ref(refdata, ctl, &alt_refs);
 
-    // Update reference sequences for V segments by substituting in alt alleles if better.
+    // Update reference sequences for V and J segments by substituting in alt alleles if better.
 
     sub_alts(refdata, ctl, &alt_refs, &mut info, &mut exact_clonotypes);
 
@@ -403,7 +471,7 @@ pub fn main_enclone_start(setup: EncloneSetup) -> Result<EncloneIntermediates, S
         &mut raw_joins,
         &sr,
         &drefs,
-    );
+    )?;
 
     // If NWEAK_ONESIES is not specified, disintegrate certain onesie clonotypes into single cell
     // clonotypes.  This requires editing of exact_clonotypes, info, eq, join_info and raw_joins.
@@ -470,166 +538,30 @@ pub fn main_enclone_start(setup: EncloneSetup) -> Result<EncloneIntermediates, S
     }
     ctl.perf_stats(&txxx, "in some odds and ends");
 
-    // Filter B cells based on UMI counts.
+    // Run the orbit filters, starting from the orbits of the join equivalence relation.  By
+    // default these filter B cells based on UMI counts, remove cells that are not GEX cells,
+    // apply FCELL, break up clonotypes having too many chains, and then run some_filters.
 
     let tumi = Instant::now();
     let mut orbits = Vec::<Vec<i32>>::new();
-    filter_umi(
-        &eq,
-        &mut orbits,
-        ctl,
-        &mut exact_clonotypes,
-        info,
-        &mut fate,
-    );
-    if !ctl.gen_opt.trace_barcode.is_empty() {
-        for ex in &exact_clonotypes {
-            for clone in &ex.clones {
-                if clone[0].barcode == ctl.gen_opt.trace_barcode {
-                    println!(
-                        "\nfound {} in an post-umi-filter exact subclonotype having {} cells",
-                        ctl.gen_opt.trace_barcode,
-                        ex.ncells(),
-                    );
-                }
-            }
-        }
+    let mut reps = Vec::<i32>::new();
+    eq.orbit_reps(&mut reps);
+    for rep in reps {
+        let mut o = Vec::<i32>::new();
+        eq.orbit(rep, &mut o);
+        orbits.push(o);
     }
-
-    // Remove cells that are not called cells by GEX or feature barcodes.
-
-    let mut orbits = {
-        let mut orbits2 = Vec::<Vec<i32>>::new();
-        for mut o in orbits {
-            let mut to_deletex = vec![false; o.len()];
-            for (&x, dx) in o.iter().zip(to_deletex.iter_mut()) {
-                let x: &CloneInfo = &info[x as usize];
-                let ex = &mut exact_clonotypes[x.clonotype_index];
-                let mut to_delete = vec![false; ex.ncells()];
-                for (clone, d) in ex.clones.iter().take(ex.ncells()).zip(to_delete.iter_mut()) {
-                    let li = clone[0].dataset_index;
-                    let bc = &clone[0].barcode;
-                    if ctl.gen_opt.cellranger {
-                        if gex_cells_specified[li] && !bin_member(&gex_cells[li], bc) {
-                            *d = true;
-                            fate[li].insert(bc.clone(), BarcodeFate::NotGexCell);
-                        }
-                    } else if !ctl.origin_info.gex_path[li].is_empty() {
-                        let gbc = &gex_info.gex_cell_barcodes[li];
-                        if !bin_member(gbc, bc) {
-                            fate[li].insert(bc.clone(), BarcodeFate::NotGexCell);
-                            if !ctl.clono_filt_opt_def.ngex {
-                                *d = true;
-                            }
-                        }
-                    }
-                }
-                erase_if(&mut ex.clones, &to_delete);
-                if ex.ncells() == 0 {
-                    *dx = true;
-                }
-            }
-            erase_if(&mut o, &to_deletex);
-            if !o.is_empty() {
-                orbits2.push(o);
-            }
-        }
-        orbits2
-    };
-
-    // Filter using constraints imposed by FCELL.
-
-    filter_by_fcell(ctl, &mut orbits, info, &mut exact_clonotypes, gex_info)?;
-    ctl.perf_stats(&tumi, "umi filtering and such");
-
-    // Break up clonotypes containing a large number of chains. These are
-    // very likely to be false merges
-    let mut orbits: Vec<Vec<i32>> = orbits
-        .into_iter()
-        .flat_map(|orbit| {
-            let (od, exacts) = setup_define_mat(&orbit, info);
-            let mat = define_mat(
-                is_bcr,
-                &to_bc,
-                &sr,
-                ctl,
-                &exact_clonotypes,
-                &exacts,
-                &od,
-                info,
-                &raw_joins,
-                refdata,
-                &drefs,
-            );
-            let num_chains = mat.len();
-            if num_chains < ctl.join_alg_opt.split_max_chains {
-                vec![orbit]
-            } else {
-                let exacts_of_chains = mat
-                    .iter()
-                    .enumerate()
-                    .flat_map(|(chain_num, chain_in_exact)| {
-                        exacts
-                            .iter()
-                            .zip_eq(chain_in_exact.iter())
-                            .filter_map(move |(e, chain)| chain.map(|_| (e, chain_num)))
-                    })
-                    .into_group_map()
-                    .into_iter()
-                    .map(|(k, v)| (v, *k))
-                    .into_group_map();
-
-                let mut group_of_exacts = HashMap::new();
-                let mut group_num = 0;
-                for (chains, chain_exacts) in exacts_of_chains {
-                    if chains.len() == 1 {
-                        for e in chain_exacts {
-                            group_of_exacts.insert(e, group_num);
-                            group_num += 1;
-                        }
-                    } else {
-                        for e in chain_exacts {
-                            group_of_exacts.insert(e, group_num);
-                        }
-                        group_num += 1;
-                    }
-                }
-
-                let mut groups = vec![vec![]; group_num];
-
-                for (_, exact_clonotype_id, val) in &od {
-                    groups[group_of_exacts[exact_clonotype_id]].push(*val);
-                }
-
-                // To split every subclonotype
-                // od
-                //     .into_iter()
-                //     .group_by(|o| o.1)
-                //     .into_iter()
-                //     .map(|(_, vals)| vals.map(|v| v.2).collect())
-                //     .collect();
-                groups
-            }
-        })
-        .collect();
-
-    // Run some filters.
-
-    some_filters(
-        &mut orbits,
-        is_bcr,
-        &to_bc,
-        &sr,
-        ctl,
-        &exact_clonotypes,
+    let join = JoinContext {
         info,
-        &raw_joins,
-        &eq,
-        &disintegrated,
-        &mut fate,
-        refdata,
-        &drefs,
-    );
+        eq: &eq,
+        to_bc: &to_bc,
+        sr: &sr,
+        raw_joins: &raw_joins,
+        disintegrated: &disintegrated,
+        drefs: &drefs,
+    };
+    filters.filter_orbits(&fctx, &join, &mut orbits, &mut exact_clonotypes, &mut fate)?;
+    ctl.perf_stats(&tumi, "in orbit filters");
 
     // Pre evaluate (PRE_EVAL).
 
@@ -883,16 +815,7 @@ pub fn main_enclone_start(setup: EncloneSetup) -> Result<EncloneIntermediates, S
     // Mark VDJ noncells.
 
     let tmark = Instant::now();
-    if ctl.clono_filt_opt_def.non_cell_mark {
-        for ex in exact_clonotypes.iter_mut() {
-            for clone in ex.clones.iter_mut() {
-                let di = clone[0].dataset_index;
-                if !bin_member(&vdj_cells[di], &clone[0].barcode) {
-                    clone[0].marked = true;
-                }
-            }
-        }
-    }
+    mark_vdj_noncells(ctl, &vdj_cells, &mut exact_clonotypes);
     ctl.perf_stats(&tmark, "marking vdj noncells");
     if !ctl.gen_opt.trace_barcode.is_empty() {
         for ex in &exact_clonotypes {
@@ -914,6 +837,7 @@ pub fn main_enclone_start(setup: EncloneSetup) -> Result<EncloneIntermediates, S
             exact_clonotypes,
             raw_joins,
             info: info.to_vec(),
+            orbit_ids: (0..orbits.len()).collect(),
             orbits,
             vdj_cells,
             join_info,
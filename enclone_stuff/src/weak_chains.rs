@@ -4,7 +4,7 @@
 // look like junk.  Preliminary heuristic.
 
 use enclone_core::{
-    barcode_fate::BarcodeFate,
+    barcode_fate::{BarcodeFate, FateLedger},
     defs::{CloneInfo, EncloneControl, ExactClonotype},
 };
 use enclone_print::define_mat::{define_mat, setup_define_mat};
@@ -24,7 +24,7 @@ pub fn weak_chains(
     exact_clonotypes: &[ExactClonotype],
     info: &[CloneInfo],
     raw_joins: &[Vec<usize>],
-    fate: &mut [HashMap<String, BarcodeFate>],
+    fate: &mut [FateLedger],
     refdata: &RefData,
     dref: &[DonorReferenceItem],
 ) {
@@ -76,7 +76,10 @@ pub fn weak_chains(
             for j in 0..cols {
                 if ncells[j] <= 20 && 8 * ncells[j] < total_cells {
                     for d in col_entries[j].iter() {
-                        if ctl.clono_filt_opt_def.weak_chains {
+                        if ctl
+                            .clono_filt_opt_def
+                            .delete(ctl.clono_filt_opt_def.weak_chains)
+                        {
                             res.2.push(exacts[*d]);
                         }
                         let ex = &exact_clonotypes[exacts[*d]];
@@ -94,9 +97,13 @@ pub fn weak_chains(
     });
     let mut to_delete = vec![false; exact_clonotypes.len()];
     let mut dels = Vec::<i32>::new();
+    let removed = ctl
+        .clono_filt_opt_def
+        .delete(ctl.clono_filt_opt_def.weak_chains);
     for i in 0..results.len() {
         for j in 0..results[i].1.len() {
-            fate[results[i].1[j].0].insert(results[i].1[j].1.clone(), results[i].1[j].2.clone());
+            let x = &results[i].1[j];
+            fate[x.0].insert(x.1.clone(), x.2, removed);
         }
         for x in results[i].2.iter() {
             to_delete[*x] = true;